@@ -0,0 +1,99 @@
+//! Throughput of dataset loading, training and evaluation on the bundled
+//! validation images.
+//!
+//! ```text
+//! cargo bench --bench training
+//! cargo bench --bench training -- 64
+//! ```
+//!
+//! The optional argument is the image size in pixels (28 by default). On a
+//! single x86-64 core, at 28x28 RGB with 153 images:
+//!
+//! ```text
+//! load folder                         851.965 ms
+//! load cache                            0.247 ms
+//! train_step                            0.020 ms
+//! epoch batch 1                         3.009 ms
+//! epoch batch 32                        0.459 ms
+//! evaluate                              0.207 ms
+//! ```
+
+use antbee_rs::antbee::ChannelMode;
+use antbee_rs::antbee::Dataset;
+use antbee_rs::antbee::DatasetSource;
+use antbee_rs::antbee::Model;
+use antbee_rs::antbee::Preprocess;
+use antbee_rs::antbee::ResizeMode;
+use antbee_rs::antbee::Sgd;
+use antbee_rs::antbee::TrainConfig;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::hint::black_box;
+use std::path::Path;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Runs `f` repeatedly for about a second and prints the mean time per call.
+fn bench(name: &str, mut f: impl FnMut()) {
+    f(); // Warm up
+    let start = Instant::now();
+    let mut iterations = 0u32;
+    while start.elapsed() < Duration::from_secs(1) {
+        f();
+        iterations += 1;
+    }
+    let per_call = start.elapsed() / iterations;
+    println!("{:<32} {:>10.3} ms", name, per_call.as_secs_f64() * 1000.0);
+}
+
+fn main() {
+    // `cargo bench` passes `--bench` to the harness; skip flags.
+    let size = std::env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with('-'))
+        .map_or(28, |arg| arg.parse().expect("image size must be a number"));
+    let preprocess = Preprocess {
+        width: size,
+        height: size,
+        channels: ChannelMode::Rgb,
+        resize: ResizeMode::Stretch,
+    };
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("dataset/val");
+    let cache = std::env::temp_dir().join(format!("antbee-bench-{}.bin", size));
+
+    bench("load folder", || {
+        black_box(
+            Dataset::from_dataset_path_with(&dir, preprocess).expect("failed to load dataset"),
+        );
+    });
+    let dataset =
+        Dataset::from_dataset_path_with(&dir, preprocess).expect("failed to load dataset");
+    dataset.to_cache(&cache).expect("failed to write cache");
+    bench("load cache", || {
+        black_box(Dataset::from_cache(&cache).expect("failed to read cache"));
+    });
+    println!("{} images of {}x{}", dataset.len(), size, size);
+
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut model = Model::from_rng(preprocess, dataset.num_classes(), &mut rng);
+    model.set_class_map(dataset.class_map().clone());
+    let mut config = TrainConfig::default();
+    let mut optimizer = Sgd::new(config.momentum);
+    let mut index = 0;
+    bench("train_step", || {
+        let data = dataset.get(index % dataset.len());
+        black_box(model.train_step(&data, &mut optimizer, config.learning_rate, &config));
+        index += 1;
+    });
+    for batch_size in [1, 32] {
+        config.batch_size = batch_size;
+        bench(&format!("epoch batch {}", batch_size), || {
+            let loss = model.partial_fit(&dataset, &config, &mut optimizer, &mut rng);
+            black_box(loss.expect("training diverged"));
+        });
+    }
+    bench("evaluate", || {
+        black_box(model.evaluate(&dataset));
+    });
+    std::fs::remove_file(&cache).ok();
+}
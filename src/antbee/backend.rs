@@ -0,0 +1,57 @@
+//! Selection of the device the matrix products of the linear layer run on.
+
+use super::error::Error;
+use super::error::Result;
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::Ordering;
+
+/// The backend in use, as its discriminant.
+static CURRENT: AtomicU8 = AtomicU8::new(Backend::Cpu as u8);
+
+/// Where the batched matrix products of the linear layer are computed: the
+/// forward pass, and the weight and input gradients of the backward pass.
+///
+/// The backend is chosen once for the whole process with
+/// [`Backend::select`]; models, trainers and datasets work unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Backend {
+    /// ndarray's matrix products, or the `simd` kernels. The default.
+    #[default]
+    Cpu,
+    /// OpenCL on the first GPU found. Requires the `gpu` feature (Unix
+    /// only) and an OpenCL driver; pays off for large batches and images,
+    /// where the products outweigh copying the operands to the device.
+    Gpu,
+}
+
+impl Backend {
+    /// The backend the products currently run on.
+    pub fn current() -> Self {
+        return match CURRENT.load(Ordering::Relaxed) {
+            0 => Backend::Cpu,
+            _ => Backend::Gpu,
+        };
+    }
+
+    /// Runs all following products on `self`.
+    ///
+    /// Selecting [`Backend::Gpu`] loads the system's OpenCL library and
+    /// compiles the kernel for the first GPU device, once per process.
+    ///
+    /// # Errors
+    /// [`Error::Unsupported`] if the GPU cannot be used: the crate was built
+    /// without the `gpu` feature, or no OpenCL GPU device was found. The
+    /// current backend is kept then.
+    pub fn select(self) -> Result<()> {
+        if self == Backend::Gpu {
+            #[cfg(all(feature = "gpu", unix))]
+            super::opencl::init().map_err(Error::Unsupported)?;
+            #[cfg(not(all(feature = "gpu", unix)))]
+            return Err(Error::Unsupported(
+                "built without the gpu feature (Unix only)".to_string(),
+            ));
+        }
+        CURRENT.store(self as u8, Ordering::Relaxed);
+        return Ok(());
+    }
+}
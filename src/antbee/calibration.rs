@@ -0,0 +1,146 @@
+//! Temperature scaling of the predicted probabilities.
+
+use super::kind::Kind;
+use super::model::Model;
+use super::source::DatasetSource;
+use ndarray::Array2;
+use ndarray::Axis;
+
+/// Temperatures searched by [`Model::calibrate`], as natural logarithms.
+const LOG_TEMPERATURE_RANGE: (f32, f32) = (-3.0, 3.0);
+
+/// Golden-section iterations; each shrinks the interval by a factor of 0.618.
+const ITERATIONS: usize = 40;
+
+impl Model {
+    /// Fits the [temperature](Model::temperature) that minimizes the
+    /// cross-entropy of the predicted probabilities on `dataset` (Guo et al.,
+    /// 2017, "On Calibration of Modern Neural Networks"), and sets it.
+    ///
+    /// Use a held-out split such as the validation set: the training set is
+    /// usually fit too well to reveal overconfidence. Only the confidence
+    /// changes, not which class is most probable, so accuracy is unaffected.
+    ///
+    /// The loss is convex in 1 / T, so a golden-section search over log T
+    /// in [e^-3, e^3] finds the optimum.
+    ///
+    /// # Returns
+    /// The fitted temperature.
+    pub fn calibrate(&mut self, dataset: &impl DatasetSource) -> f32 {
+        let mut logits = Vec::new();
+        for start in (0..dataset.len()).step_by(Self::EVAL_BATCH_SIZE) {
+            let range = start..(start + Self::EVAL_BATCH_SIZE).min(dataset.len());
+            let x = dataset.batch(range.clone());
+            logits.push((self.logits_batch(x.view()), &dataset.labels()[range]));
+        }
+        let temperature = fit_temperature(&logits);
+        self.set_temperature(temperature);
+        return temperature;
+    }
+}
+
+/// Returns the temperature that minimizes the summed [`scaled_loss`] of
+/// `logits`, batches of logits with the labels of their rows.
+fn fit_temperature(logits: &[(Array2<f32>, &[Kind])]) -> f32 {
+    let loss = |log_temperature: f32| -> f32 {
+        let temperature = log_temperature.exp();
+        let mut total = 0.0;
+        for (z, labels) in logits {
+            total += scaled_loss(z, labels, temperature);
+        }
+        return total;
+    };
+
+    let ratio = (5.0f32.sqrt() - 1.0) / 2.0;
+    let (mut low, mut high) = LOG_TEMPERATURE_RANGE;
+    let mut left = high - ratio * (high - low);
+    let mut right = low + ratio * (high - low);
+    let (mut left_loss, mut right_loss) = (loss(left), loss(right));
+    for _ in 0..ITERATIONS {
+        if left_loss < right_loss {
+            high = right;
+            (right, right_loss) = (left, left_loss);
+            left = high - ratio * (high - low);
+            left_loss = loss(left);
+        } else {
+            low = left;
+            (left, left_loss) = (right, right_loss);
+            right = low + ratio * (high - low);
+            right_loss = loss(right);
+        }
+    }
+
+    return ((low + high) / 2.0).exp();
+}
+
+/// Summed cross-entropy of the softmax of `z / temperature`.
+fn scaled_loss(z: &Array2<f32>, labels: &[Kind], temperature: f32) -> f32 {
+    let mut total = 0.0;
+    for (row, &kind) in z.axis_iter(Axis(0)).zip(labels) {
+        let mut probs = &row / temperature;
+        Model::softmax(probs.view_mut());
+        total += Model::cross_entropy_loss(probs.view(), kind);
+    }
+    return total;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::s;
+
+    /// `rows` copies of the two logits `[margin, 0]`, of which the first
+    /// `correct` are labeled class 0 and the rest class 1.
+    fn logits(margin: f32, rows: usize, correct: usize) -> (Array2<f32>, Vec<Kind>) {
+        let z = Array2::from_shape_fn((rows, 2), |(_, k)| if k == 0 { margin } else { 0.0 });
+        let labels = (0..rows)
+            .map(|i| if i < correct { Kind(0) } else { Kind(1) })
+            .collect();
+        return (z, labels);
+    }
+
+    /// Checks that the fitted temperature makes the predicted probability of
+    /// class 0 match its observed rate, sigmoid(margin / T) = correct / rows,
+    /// and lowers the loss below that of the raw logits.
+    fn check(margin: f32, rows: usize, correct: usize) {
+        let (z, labels) = logits(margin, rows, correct);
+        let temperature = fit_temperature(&[(z.clone(), &labels)]);
+        let rate = correct as f32 / rows as f32;
+        let expected = margin / (rate / (1.0 - rate)).ln();
+        assert!(
+            (temperature - expected).abs() < 1e-3 * expected,
+            "{} != {}",
+            temperature,
+            expected
+        );
+        assert!(scaled_loss(&z, &labels, temperature) < scaled_loss(&z, &labels, 1.0));
+    }
+
+    #[test]
+    fn overconfident_logits_are_softened() {
+        // 3 of 4 correct at a margin of 4, so T = 4 / ln 3.
+        check(4.0, 4, 3);
+    }
+
+    #[test]
+    fn underconfident_logits_are_sharpened() {
+        // 9 of 10 correct at a margin of 1, so T = 1 / ln 9.
+        check(1.0, 10, 9);
+    }
+
+    #[test]
+    fn batches_are_fitted_together() {
+        let (z, labels) = logits(4.0, 4, 3);
+        let split = fit_temperature(&[
+            (z.slice(s![..2, ..]).to_owned(), &labels[..2]),
+            (z.slice(s![2.., ..]).to_owned(), &labels[2..]),
+        ]);
+        let whole = fit_temperature(&[(z, &labels)]);
+        assert!(
+            (split - whole).abs() < 1e-3 * whole,
+            "{} != {}",
+            split,
+            whole
+        );
+    }
+}
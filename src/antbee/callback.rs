@@ -0,0 +1,278 @@
+//! Hooks into the training loop of [`Trainer`](super::Trainer).
+
+use super::error::Result;
+use super::metrics::EpochMetrics;
+use super::model::Model;
+use std::fs::File;
+use std::fs::create_dir_all;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// Whether training should go on after a [`Callback`] hook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Control {
+    #[default]
+    Continue,
+    /// End training. The model with the best validation loss so far is
+    /// still restored, as after early stopping.
+    Stop,
+}
+
+/// Progress of a training run after one batch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatchMetrics {
+    /// Zero-based epoch number.
+    pub epoch: usize,
+    /// Zero-based batch number within the epoch.
+    pub batch: usize,
+    /// Number of samples in the batch.
+    pub samples: usize,
+    /// Mean training loss over the batch, including the L2 penalty.
+    pub loss: f32,
+}
+
+/// User code run by the [`Trainer`](super::Trainer) at fixed points of every
+/// epoch, for custom logging, learning-rate adjustment, snapshots or early
+/// abort without forking the training loop.
+///
+/// Every hook has a default that does nothing, so an implementation only
+/// overrides the ones it needs. Callbacks run in the order they were added
+/// with [`Trainer::with_callback`](super::Trainer::with_callback), and an
+/// error returned by any hook aborts training with that error.
+pub trait Callback {
+    /// Called once before the first epoch of [`Trainer::fit`] or
+    /// [`Trainer::resume`](super::Trainer::resume), with the number of
+    /// epochs already completed. Reset any per-run state here: the same
+    /// trainer may be used for several runs.
+    ///
+    /// [`Trainer::fit`]: super::Trainer::fit
+    fn on_train_start(&mut self, model: &Model, epoch: usize) -> Result<()> {
+        let _ = (model, epoch);
+        return Ok(());
+    }
+
+    /// Called before epoch `epoch` with the learning rate the scheduler
+    /// picked for it; changing `learning_rate` changes the rate the whole
+    /// epoch is trained with. [`Control::Stop`] ends training without
+    /// running the epoch.
+    fn on_epoch_start(
+        &mut self,
+        model: &Model,
+        epoch: usize,
+        learning_rate: &mut f32,
+    ) -> Result<Control> {
+        let _ = (model, epoch, learning_rate);
+        return Ok(Control::Continue);
+    }
+
+    /// Called after every batch's gradients were computed and, at the end
+    /// of an accumulation, applied. [`Control::Stop`] cuts the epoch short:
+    /// it is still evaluated and reported to [`Callback::on_epoch_end`]
+    /// before training ends.
+    fn on_batch_end(&mut self, model: &Model, metrics: &BatchMetrics) -> Result<Control> {
+        let _ = (model, metrics);
+        return Ok(Control::Continue);
+    }
+
+    /// Called after every epoch was evaluated on the training and
+    /// validation sets, before checkpointing and early stopping.
+    fn on_epoch_end(&mut self, model: &Model, metrics: &EpochMetrics) -> Result<Control> {
+        let _ = (model, metrics);
+        return Ok(Control::Continue);
+    }
+}
+
+/// Stops training once the validation loss has not improved by at least
+/// `min_delta` for `patience` consecutive epochs.
+///
+/// Equivalent to [`TrainConfig::patience`](super::TrainConfig::patience),
+/// for combining with other callbacks or giving several runs of one trainer
+/// different rules. Only epochs seen by the callback count, so a resumed
+/// run starts waiting afresh.
+#[derive(Debug, Clone)]
+pub struct EarlyStopping {
+    patience: usize,
+    min_delta: f32,
+    best_val_loss: f32,
+    best_epoch: usize,
+}
+
+impl EarlyStopping {
+    pub fn new(patience: usize, min_delta: f32) -> Self {
+        return Self {
+            patience,
+            min_delta,
+            best_val_loss: f32::INFINITY,
+            best_epoch: 0,
+        };
+    }
+}
+
+impl Callback for EarlyStopping {
+    fn on_train_start(&mut self, _model: &Model, epoch: usize) -> Result<()> {
+        self.best_val_loss = f32::INFINITY;
+        self.best_epoch = epoch;
+        return Ok(());
+    }
+
+    fn on_epoch_end(&mut self, _model: &Model, metrics: &EpochMetrics) -> Result<Control> {
+        if metrics.val_loss < self.best_val_loss - self.min_delta {
+            self.best_val_loss = metrics.val_loss;
+            self.best_epoch = metrics.epoch;
+        } else if metrics.epoch - self.best_epoch >= self.patience {
+            println!(
+                "Early stopping at epoch {}: no improvement since epoch {}",
+                metrics.epoch, self.best_epoch
+            );
+            return Ok(Control::Stop);
+        }
+        return Ok(Control::Continue);
+    }
+}
+
+/// When a [`ModelCheckpoint`] saves the model.
+#[derive(Debug, Clone)]
+enum SaveRule {
+    /// To `dir/model_epoch_NNNN.model` after every `n`th completed epoch.
+    Every { dir: PathBuf, n: usize },
+    /// To `path` whenever the validation loss improves.
+    Best { path: PathBuf, val_loss: f32 },
+}
+
+/// Saves the model during training with [`Model::save`].
+///
+/// Unlike the [`Checkpoint`](super::Checkpoint)s of
+/// [`TrainConfig::checkpoint_dir`](super::TrainConfig::checkpoint_dir), the
+/// files hold only the model, ready for inference, but training cannot be
+/// resumed from them.
+#[derive(Debug, Clone)]
+pub struct ModelCheckpoint {
+    rule: SaveRule,
+}
+
+impl ModelCheckpoint {
+    /// Saves to `dir` after every `n` completed epochs, one file per save.
+    pub fn every(dir: &Path, n: usize) -> Self {
+        return Self {
+            rule: SaveRule::Every {
+                dir: dir.to_path_buf(),
+                n: n.max(1),
+            },
+        };
+    }
+
+    /// Overwrites `path` with the model of every epoch that lowers the
+    /// validation loss, so it always holds the best model so far.
+    pub fn best(path: &Path) -> Self {
+        return Self {
+            rule: SaveRule::Best {
+                path: path.to_path_buf(),
+                val_loss: f32::INFINITY,
+            },
+        };
+    }
+
+    /// Path of the model saved by [`ModelCheckpoint::every`] after `epoch`
+    /// completed epochs in `dir`.
+    pub fn path_in(dir: &Path, epoch: usize) -> PathBuf {
+        return dir.join(format!("model_epoch_{:04}.model", epoch));
+    }
+}
+
+impl Callback for ModelCheckpoint {
+    fn on_train_start(&mut self, _model: &Model, _epoch: usize) -> Result<()> {
+        if let SaveRule::Best { val_loss, .. } = &mut self.rule {
+            *val_loss = f32::INFINITY;
+        }
+        return Ok(());
+    }
+
+    fn on_epoch_end(&mut self, model: &Model, metrics: &EpochMetrics) -> Result<Control> {
+        match &mut self.rule {
+            SaveRule::Every { dir, n } => {
+                let completed = metrics.epoch + 1;
+                if completed.is_multiple_of(*n) {
+                    create_dir_all(&*dir)?;
+                    model.save(&Self::path_in(dir, completed))?;
+                }
+            }
+            SaveRule::Best { path, val_loss } => {
+                if metrics.val_loss < *val_loss {
+                    *val_loss = metrics.val_loss;
+                    if let Some(parent) = path.parent() {
+                        create_dir_all(parent)?;
+                    }
+                    model.save(path)?;
+                }
+            }
+        }
+        return Ok(Control::Continue);
+    }
+}
+
+/// Writes one CSV row per epoch with the learning rate and wall-clock time
+/// next to the [`EpochMetrics`].
+///
+/// The columns are `epoch,learning_rate,seconds,train_loss,train_acc,val_loss,val_acc`,
+/// `seconds` being the time since training started. Every row is flushed
+/// immediately, so the file can be watched while training is running.
+/// Add it after any callback that changes the learning rate, to log the
+/// rate actually used.
+pub struct CsvLogger {
+    writer: BufWriter<File>,
+    started: Instant,
+    learning_rate: f32,
+}
+
+impl CsvLogger {
+    const HEADER: &'static str =
+        "epoch,learning_rate,seconds,train_loss,train_acc,val_loss,val_acc";
+
+    /// Creates (or truncates) the log file at `path` and writes the header.
+    pub fn create(path: &Path) -> Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", Self::HEADER)?;
+        writer.flush()?;
+        return Ok(Self {
+            writer,
+            started: Instant::now(),
+            learning_rate: f32::NAN,
+        });
+    }
+}
+
+impl Callback for CsvLogger {
+    fn on_train_start(&mut self, _model: &Model, _epoch: usize) -> Result<()> {
+        self.started = Instant::now();
+        return Ok(());
+    }
+
+    fn on_epoch_start(
+        &mut self,
+        _model: &Model,
+        _epoch: usize,
+        learning_rate: &mut f32,
+    ) -> Result<Control> {
+        self.learning_rate = *learning_rate;
+        return Ok(Control::Continue);
+    }
+
+    fn on_epoch_end(&mut self, _model: &Model, metrics: &EpochMetrics) -> Result<Control> {
+        writeln!(
+            self.writer,
+            "{},{},{:.3},{},{},{},{}",
+            metrics.epoch,
+            self.learning_rate,
+            self.started.elapsed().as_secs_f64(),
+            metrics.train_loss,
+            metrics.train_acc,
+            metrics.val_loss,
+            metrics.val_acc
+        )?;
+        self.writer.flush()?;
+        return Ok(Control::Continue);
+    }
+}
@@ -0,0 +1,170 @@
+use super::codec;
+use super::config::TrainConfig;
+use super::error::Error;
+use super::error::Result;
+use super::metrics::EpochMetrics;
+use super::metrics::History;
+use super::model::Model;
+use super::optimizer::Sgd;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::fs::File;
+use std::fs::create_dir_all;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Complete state of an interrupted training run.
+///
+/// Holds everything [`Trainer::resume`](super::Trainer::resume) needs to
+/// continue exactly where the run left off: the current weights, the
+/// optimizer state, the number of completed epochs, the RNG state and the
+/// early-stopping bookkeeping, and the metrics of the completed epochs.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    /// Number of completed epochs; training resumes at this epoch.
+    pub(crate) epoch: usize,
+    pub(crate) model: Model,
+    pub(crate) optimizer: Sgd,
+    /// Drives the per-epoch shuffling order.
+    pub(crate) rng: ChaCha8Rng,
+    /// Weights from the epoch with the lowest validation loss so far.
+    pub(crate) best_model: Model,
+    pub(crate) best_val_loss: f32,
+    pub(crate) best_epoch: usize,
+    pub(crate) history: History,
+}
+
+impl Checkpoint {
+    /// Magic bytes at the start of a checkpoint file.
+    const MAGIC: &'static [u8; 8] = b"ANTBEECK";
+
+    /// Current version of the checkpoint format.
+    const FORMAT_VERSION: u32 = 3;
+
+    /// Creates the state for a fresh run starting from `model`.
+    pub(crate) fn start(model: Model, config: &TrainConfig) -> Self {
+        return Self {
+            epoch: 0,
+            best_model: model.clone(),
+            model,
+            optimizer: Sgd::new(config.momentum),
+            rng: ChaCha8Rng::seed_from_u64(config.seed),
+            best_val_loss: f32::INFINITY,
+            best_epoch: 0,
+            history: History::default(),
+        };
+    }
+
+    /// Number of epochs completed when the checkpoint was taken.
+    pub fn epoch(&self) -> usize {
+        return self.epoch;
+    }
+
+    /// Weights at the time the checkpoint was taken.
+    pub fn model(&self) -> &Model {
+        return &self.model;
+    }
+
+    /// Path of the checkpoint written after `epoch` completed epochs in `dir`.
+    pub fn path_in(dir: &Path, epoch: usize) -> PathBuf {
+        return dir.join(format!("checkpoint_epoch_{:04}.bin", epoch));
+    }
+
+    /// Writes the checkpoint to `path`, creating parent directories as needed.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        return Ok(());
+    }
+
+    /// Loads a checkpoint written by [`Checkpoint::save`].
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        return Self::read_from(&mut reader);
+    }
+
+    fn write_to(&self, writer: &mut impl Write) -> Result<()> {
+        codec::write_header(writer, Self::MAGIC, Self::FORMAT_VERSION)?;
+        codec::write_u64(writer, self.epoch as u64)?;
+        self.model.write_to(writer)?;
+        self.optimizer.write_to(writer)?;
+
+        writer.write_all(&self.rng.get_seed())?;
+        codec::write_u64(writer, self.rng.get_stream())?;
+        codec::write_u128(writer, self.rng.get_word_pos())?;
+
+        codec::write_u64(writer, self.best_epoch as u64)?;
+        codec::write_f32(writer, self.best_val_loss)?;
+        self.best_model.write_to(writer)?;
+
+        codec::write_u64(writer, self.history.len() as u64)?;
+        for metrics in self.history.epochs() {
+            codec::write_u64(writer, metrics.epoch as u64)?;
+            for value in [
+                metrics.train_loss,
+                metrics.train_acc,
+                metrics.val_loss,
+                metrics.val_acc,
+            ] {
+                codec::write_f32(writer, value)?;
+            }
+        }
+        return Ok(());
+    }
+
+    fn read_from(reader: &mut impl Read) -> Result<Self> {
+        let version = codec::read_header(reader, Self::MAGIC, Self::FORMAT_VERSION)?;
+        if version < 2 {
+            return Err(Error::InvalidFormat(format!(
+                "checkpoint version {} was written by an older release and cannot be resumed",
+                version
+            )));
+        }
+        let epoch = codec::read_u64(reader)? as usize;
+        let model = Model::read_from(reader)?;
+        let optimizer = Sgd::read_from(reader, &model)?;
+
+        let mut seed = [0u8; 32];
+        reader.read_exact(&mut seed)?;
+        let mut rng = ChaCha8Rng::from_seed(seed);
+        rng.set_stream(codec::read_u64(reader)?);
+        rng.set_word_pos(codec::read_u128(reader)?);
+
+        let best_epoch = codec::read_u64(reader)? as usize;
+        let best_val_loss = codec::read_f32(reader)?;
+        let best_model = Model::read_from(reader)?;
+
+        // Version 2 checkpoints resume with an empty history.
+        let mut history = History::default();
+        if version >= 3 {
+            for _ in 0..codec::read_u64(reader)? {
+                history.push(EpochMetrics {
+                    epoch: codec::read_u64(reader)? as usize,
+                    train_loss: codec::read_f32(reader)?,
+                    train_acc: codec::read_f32(reader)?,
+                    val_loss: codec::read_f32(reader)?,
+                    val_acc: codec::read_f32(reader)?,
+                });
+            }
+        }
+
+        return Ok(Self {
+            epoch,
+            model,
+            optimizer,
+            rng,
+            best_model,
+            best_val_loss,
+            best_epoch,
+            history,
+        });
+    }
+}
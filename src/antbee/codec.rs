@@ -0,0 +1,221 @@
+//! Little-endian helpers for the crate's binary file formats.
+//!
+//! Every file starts with an 8-byte magic followed by a `u32` format
+//! version, so readers can reject foreign files and outdated layouts.
+
+use super::error::Error;
+use super::error::Result;
+#[cfg(feature = "fs")]
+use half::f16;
+use std::io::Read;
+use std::io::Write;
+
+pub(crate) fn write_header(w: &mut impl Write, magic: &[u8; 8], version: u32) -> Result<()> {
+    w.write_all(magic)?;
+    write_u32(w, version)?;
+    return Ok(());
+}
+
+/// Reads and checks a header written by [`write_header`].
+///
+/// Returns the stored version if it is between 1 and `max_version`.
+pub(crate) fn read_header(r: &mut impl Read, magic: &[u8; 8], max_version: u32) -> Result<u32> {
+    let mut found = [0u8; 8];
+    r.read_exact(&mut found)?;
+    if &found != magic {
+        return Err(Error::InvalidFormat(format!(
+            "expected magic {:?}, found {:?}",
+            String::from_utf8_lossy(magic),
+            String::from_utf8_lossy(&found)
+        )));
+    }
+    let version = read_u32(r)?;
+    if version == 0 || version > max_version {
+        return Err(Error::InvalidFormat(format!(
+            "unsupported version {} (latest supported is {})",
+            version, max_version
+        )));
+    }
+    return Ok(version);
+}
+
+pub(crate) fn write_u32(w: &mut impl Write, value: u32) -> Result<()> {
+    w.write_all(&value.to_le_bytes())?;
+    return Ok(());
+}
+
+pub(crate) fn write_u64(w: &mut impl Write, value: u64) -> Result<()> {
+    w.write_all(&value.to_le_bytes())?;
+    return Ok(());
+}
+
+#[cfg(feature = "fs")]
+pub(crate) fn write_u128(w: &mut impl Write, value: u128) -> Result<()> {
+    w.write_all(&value.to_le_bytes())?;
+    return Ok(());
+}
+
+pub(crate) fn write_f32(w: &mut impl Write, value: f32) -> Result<()> {
+    w.write_all(&value.to_le_bytes())?;
+    return Ok(());
+}
+
+/// Writes a length-prefixed sequence of `f32` values.
+pub(crate) fn write_f32s<'a>(
+    w: &mut impl Write,
+    values: impl ExactSizeIterator<Item = &'a f32>,
+) -> Result<()> {
+    write_u64(w, values.len() as u64)?;
+    let mut bytes = Vec::with_capacity(values.len() * 4);
+    for value in values {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    w.write_all(&bytes)?;
+    return Ok(());
+}
+
+/// Writes a length-prefixed sequence of `f16` values.
+#[cfg(feature = "fs")]
+pub(crate) fn write_f16s<'a>(
+    w: &mut impl Write,
+    values: impl ExactSizeIterator<Item = &'a f16>,
+) -> Result<()> {
+    write_u64(w, values.len() as u64)?;
+    let mut bytes = Vec::with_capacity(values.len() * 2);
+    for value in values {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    w.write_all(&bytes)?;
+    return Ok(());
+}
+
+/// Writes a length-prefixed sequence of `i8` values.
+pub(crate) fn write_i8s<'a>(
+    w: &mut impl Write,
+    values: impl ExactSizeIterator<Item = &'a i8>,
+) -> Result<()> {
+    write_u64(w, values.len() as u64)?;
+    let bytes: Vec<u8> = values.map(|&value| value as u8).collect();
+    w.write_all(&bytes)?;
+    return Ok(());
+}
+
+/// Writes a length-prefixed sequence of bytes.
+#[cfg(feature = "fs")]
+pub(crate) fn write_u8s(w: &mut impl Write, values: &[u8]) -> Result<()> {
+    write_u64(w, values.len() as u64)?;
+    w.write_all(values)?;
+    return Ok(());
+}
+
+/// Writes a length-prefixed UTF-8 string.
+pub(crate) fn write_str(w: &mut impl Write, value: &str) -> Result<()> {
+    write_u64(w, value.len() as u64)?;
+    w.write_all(value.as_bytes())?;
+    return Ok(());
+}
+
+pub(crate) fn read_u32(r: &mut impl Read) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    return Ok(u32::from_le_bytes(buf));
+}
+
+pub(crate) fn read_u64(r: &mut impl Read) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    return Ok(u64::from_le_bytes(buf));
+}
+
+#[cfg(feature = "fs")]
+pub(crate) fn read_u128(r: &mut impl Read) -> Result<u128> {
+    let mut buf = [0u8; 16];
+    r.read_exact(&mut buf)?;
+    return Ok(u128::from_le_bytes(buf));
+}
+
+pub(crate) fn read_f32(r: &mut impl Read) -> Result<f32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    return Ok(f32::from_le_bytes(buf));
+}
+
+/// Reads a sequence written by [`write_f32s`], checking it has `expected_len` values.
+pub(crate) fn read_f32s(r: &mut impl Read, expected_len: usize) -> Result<Vec<f32>> {
+    let bytes = read_values(r, expected_len, 4)?;
+    let values = bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+        .collect();
+    return Ok(values);
+}
+
+/// Reads a sequence written by [`write_f16s`], checking it has `expected_len` values.
+#[cfg(feature = "fs")]
+pub(crate) fn read_f16s(r: &mut impl Read, expected_len: usize) -> Result<Vec<f16>> {
+    let bytes = read_values(r, expected_len, 2)?;
+    let values = bytes
+        .chunks_exact(2)
+        .map(|chunk| f16::from_le_bytes(chunk.try_into().unwrap()))
+        .collect();
+    return Ok(values);
+}
+
+/// Reads a sequence written by [`write_i8s`], checking it has `expected_len` values.
+pub(crate) fn read_i8s(r: &mut impl Read, expected_len: usize) -> Result<Vec<i8>> {
+    let bytes = read_values(r, expected_len, 1)?;
+    return Ok(bytes.into_iter().map(|byte| byte as i8).collect());
+}
+
+/// Reads a sequence written by [`write_u8s`], checking it has `expected_len` values.
+#[cfg(feature = "fs")]
+pub(crate) fn read_u8s(r: &mut impl Read, expected_len: usize) -> Result<Vec<u8>> {
+    return read_values(r, expected_len, 1);
+}
+
+/// Reads a string written by [`write_str`].
+pub(crate) fn read_str(r: &mut impl Read) -> Result<String> {
+    let len = read_u64(r)?;
+    let bytes = read_bytes(r, len)?;
+    return String::from_utf8(bytes)
+        .map_err(|err| Error::InvalidFormat(format!("invalid UTF-8 string: {}", err)));
+}
+
+/// Reads the bytes of a length-prefixed sequence of `expected_len` values
+/// of `width` bytes each.
+fn read_values(r: &mut impl Read, expected_len: usize, width: u64) -> Result<Vec<u8>> {
+    let len = read_u64(r)?;
+    if len != expected_len as u64 {
+        return Err(Error::InvalidFormat(format!(
+            "expected {} values, found {}",
+            expected_len, len
+        )));
+    }
+    let bytes = len.checked_mul(width).ok_or_else(|| {
+        return Error::InvalidFormat(format!("{} values overflow", len));
+    })?;
+    return read_bytes(r, bytes);
+}
+
+/// The number of values of an array whose dimensions `dims` were read from
+/// a file, or an error if it overflows.
+pub(crate) fn size(dims: &[usize]) -> Result<usize> {
+    return dims
+        .iter()
+        .try_fold(1usize, |size, &dim| size.checked_mul(dim))
+        .ok_or_else(|| {
+            let dims: Vec<String> = dims.iter().map(usize::to_string).collect();
+            return Error::InvalidFormat(format!("array of {} values overflows", dims.join("x")));
+        });
+}
+
+/// Reads exactly `len` bytes, growing the buffer as they arrive so that a
+/// corrupted length fails at the end of the input instead of reserving it.
+fn read_bytes(r: &mut impl Read, len: u64) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    r.take(len).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < len {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    return Ok(bytes);
+}
@@ -0,0 +1,195 @@
+use super::kind::ClassMap;
+use super::loss::CrossEntropy;
+use super::loss::Loss;
+use super::preprocess::Preprocess;
+use super::sampler::RandomSampler;
+use super::sampler::Sampler;
+use super::scheduler::Constant;
+use super::scheduler::LrScheduler;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Hyperparameters controlling a training run.
+#[derive(Debug, Clone)]
+pub struct TrainConfig {
+    /// Number of passes over the training dataset.
+    pub epochs: usize,
+    /// Base step size used for gradient descent updates.
+    pub learning_rate: f32,
+    /// Schedule applied to `learning_rate` at the start of every epoch.
+    pub scheduler: Arc<dyn LrScheduler>,
+    /// SGD momentum coefficient. `0.0` gives plain gradient descent.
+    pub momentum: f32,
+    /// Objective minimized per sample, see [`Loss`]. Validation loss and
+    /// early stopping always use cross-entropy, so runs with different
+    /// losses stay comparable.
+    pub loss: Arc<dyn Loss>,
+    /// Label smoothing e in [0, 1): the hard target of every sample is
+    /// replaced by (1 - e) on its class plus e / num_classes on every class,
+    /// e.g. 0.95 / 0.05 for e = 0.1 and two classes. This keeps the model
+    /// from driving its probabilities towards 0 and 1 on a small training
+    /// set, which usually improves calibration. Applies to the training
+    /// objective only; `0.0`, the default, disables it.
+    pub label_smoothing: f32,
+    /// L2 penalty (weight decay) coefficient applied to the weights.
+    ///
+    /// The penalty `0.5 * l2 * ||w||^2` is added to the loss, which adds
+    /// `l2 * w` to the weight gradient. The bias is not regularized.
+    /// A value of `0.0` disables regularization.
+    pub l2: f32,
+    /// Probability of zeroing each input feature of a training sample
+    /// (dropout on the inputs, after normalization). The kept features are
+    /// scaled by 1 / (1 - p), so predictions need no correction. `0.0`
+    /// disables dropout.
+    pub input_dropout: f32,
+    /// Standard deviation of Gaussian noise added to each input feature of a
+    /// training sample, after normalization. `0.0` disables the noise.
+    pub input_noise: f32,
+    /// Per-class loss weights, indexed by `Kind::index`.
+    ///
+    /// The loss (and gradient) of a sample of class `k` is multiplied by
+    /// `class_weights[k]`, so under-represented classes can be given more
+    /// influence. See [`DatasetSource::balanced_class_weights`](super::DatasetSource::balanced_class_weights)
+    /// for weights computed from the class counts. `None` weights all classes equally.
+    pub class_weights: Option<Vec<f32>>,
+    /// Number of epochs without validation-loss improvement after which
+    /// training stops early. `None` always runs all `epochs`.
+    pub patience: Option<usize>,
+    /// Minimum decrease in validation loss that counts as an improvement.
+    pub min_delta: f32,
+    /// Number of samples whose mean gradient is computed at once.
+    /// `1` gives per-sample stochastic gradient descent.
+    pub batch_size: usize,
+    /// Number of batches whose gradients are averaged before each
+    /// parameter update, for an effective batch size of
+    /// `batch_size * accumulation_steps`. A partial accumulation left at the
+    /// end of an epoch is applied as well.
+    pub accumulation_steps: usize,
+    /// Number of threads every batch is split across. Each thread computes
+    /// the gradients of its share of the batch and their mean is applied as
+    /// one synchronous update, so the result does not depend on scheduling.
+    /// Only batches of at least two samples are split. `0` uses one thread
+    /// per core.
+    pub num_threads: usize,
+    /// Largest global L2 norm of the gradients of one update; larger ones
+    /// are scaled down to it (see [`Gradients::clip_norm`](super::Gradients::clip_norm)).
+    /// Clipping happens after accumulation, on the gradients actually
+    /// applied. `None` disables clipping.
+    pub max_grad_norm: Option<f32>,
+    /// What to do when a batch produces a NaN or infinite loss.
+    pub on_divergence: DivergencePolicy,
+    /// Visit the training samples in a new random order every epoch.
+    pub shuffle: bool,
+    /// Groups the training samples into batches, see [`Sampler`].
+    pub sampler: Arc<dyn Sampler>,
+    /// Seed for the training random number generator.
+    pub seed: u64,
+    /// Directory that periodic checkpoints are written to.
+    /// `None` disables checkpointing.
+    pub checkpoint_dir: Option<PathBuf>,
+    /// Write a checkpoint after every `checkpoint_every` completed epochs.
+    pub checkpoint_every: usize,
+    /// File that per-epoch metrics are written to, as CSV or JSON Lines
+    /// depending on the extension (see [`MetricsFormat`](super::MetricsFormat)).
+    pub metrics_path: Option<PathBuf>,
+    /// Directory that a TensorBoard event file with the per-epoch metrics is
+    /// written to (see [`TensorBoardWriter`](super::TensorBoardWriter)).
+    pub tensorboard_dir: Option<PathBuf>,
+    /// Show a progress bar over the epochs on stderr.
+    pub show_progress: bool,
+    /// Stop gracefully on SIGINT (Ctrl+C) or SIGTERM: the current batch is
+    /// finished, the rest of the epoch dropped and a checkpoint of its start
+    /// written, see [`Trainer::fit`](super::Trainer::fit). A second signal
+    /// aborts immediately. Keeps a copy of the training state per epoch.
+    pub handle_signals: bool,
+}
+
+impl TrainConfig {
+    /// Returns the learning rate to use during `epoch` according to the scheduler.
+    pub fn learning_rate_at(&self, epoch: usize) -> f32 {
+        return self
+            .scheduler
+            .learning_rate(self.learning_rate, epoch, self.epochs);
+    }
+}
+
+impl Default for TrainConfig {
+    fn default() -> Self {
+        return Self {
+            epochs: 150,
+            learning_rate: 0.001,
+            scheduler: Arc::new(Constant),
+            momentum: 0.0,
+            loss: Arc::new(CrossEntropy),
+            label_smoothing: 0.0,
+            l2: 0.0,
+            input_dropout: 0.0,
+            input_noise: 0.0,
+            class_weights: None,
+            patience: None,
+            min_delta: 0.0,
+            batch_size: 1,
+            accumulation_steps: 1,
+            num_threads: 1,
+            max_grad_norm: None,
+            on_divergence: DivergencePolicy::Halt,
+            shuffle: true,
+            sampler: Arc::new(RandomSampler),
+            seed: 0,
+            checkpoint_dir: None,
+            checkpoint_every: 10,
+            metrics_path: None,
+            tensorboard_dir: None,
+            show_progress: true,
+            handle_signals: false,
+        };
+    }
+}
+
+/// Reaction of the training loop to a batch with a NaN or infinite loss.
+///
+/// The diverged batch is never applied, so the weights stay finite either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DivergencePolicy {
+    /// Stop training with [`Error::Diverged`](super::Error::Diverged).
+    #[default]
+    Halt,
+    /// Roll back to the state at the start of the epoch, halve the
+    /// learning rate for the rest of the run and retry the epoch, at most
+    /// `max_retries` times before halting. The learning-rate reduction is
+    /// not stored in checkpoints.
+    Rollback { max_retries: usize },
+}
+
+/// How an ImageFolder-style directory is loaded into a
+/// [`Dataset`](super::Dataset).
+///
+/// The default sorts the files and shuffles them with a fixed seed, so a
+/// folder gives the same samples in the same order on every machine and
+/// seeded experiments are reproducible end to end.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatasetConfig {
+    /// Preprocessing applied to every image.
+    pub preprocess: Preprocess,
+    /// Labels of the class directories. `None` makes every directory a
+    /// class, in sorted name order.
+    pub classes: Option<ClassMap>,
+    /// List the images of every class in file name order. `read_dir` order
+    /// differs between platforms and file systems, so without sorting even a
+    /// seeded shuffle is not reproducible across machines.
+    pub sorted: bool,
+    /// Seed of the shuffle applied to the loaded samples. `None` draws a new
+    /// random order on every load.
+    pub shuffle_seed: Option<u64>,
+}
+
+impl Default for DatasetConfig {
+    fn default() -> Self {
+        return Self {
+            preprocess: Preprocess::default(),
+            classes: None,
+            sorted: true,
+            shuffle_seed: Some(0),
+        };
+    }
+}
@@ -0,0 +1,1106 @@
+//! Reading and writing [`TrainConfig`], and the data and model settings of
+//! a training run, as a TOML config file.
+//!
+//! Only the subset of TOML that config files need is supported: `key =
+//! value` lines, `[table]` headers one level deep, `#` comments, basic
+//! and literal strings, integers, floats, booleans, and single-line arrays
+//! and inline tables.
+
+use super::config::DivergencePolicy;
+use super::config::TrainConfig;
+use super::error::Error;
+use super::error::Result;
+use super::loss::BinaryCrossEntropy;
+use super::loss::CrossEntropy;
+use super::loss::Focal;
+use super::loss::Hinge;
+use super::loss::Loss;
+use super::sampler::BalancedSampler;
+use super::sampler::RandomSampler;
+use super::sampler::Sampler;
+use super::sampler::StratifiedSampler;
+use super::scheduler::Constant;
+use super::scheduler::CosineAnnealing;
+use super::scheduler::LrScheduler;
+use super::scheduler::StepDecay;
+use super::scheduler::Warmup;
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::path::PathBuf;
+use std::str::Chars;
+use std::sync::Arc;
+
+#[cfg(feature = "fs")]
+use super::dataset::Precision;
+#[cfg(feature = "fs")]
+use super::preprocess::ChannelMode;
+#[cfg(feature = "fs")]
+use super::preprocess::Preprocess;
+#[cfg(feature = "fs")]
+use super::preprocess::ResizeMode;
+#[cfg(feature = "fs")]
+use std::fs::create_dir_all;
+#[cfg(feature = "fs")]
+use std::fs::read_to_string;
+#[cfg(feature = "fs")]
+use std::fs::write;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+/// A parsed TOML value.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Value>),
+    Table(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        return match self {
+            Value::Bool(_) => "a boolean",
+            Value::Integer(_) => "an integer",
+            Value::Float(_) => "a float",
+            Value::String(_) => "a string",
+            Value::Array(_) => "an array",
+            Value::Table(_) => "a table",
+        };
+    }
+}
+
+/// A parsed config file.
+struct Document {
+    root: Vec<(String, Value)>,
+    /// Line of every key, by dotted name, for error messages.
+    lines: Vec<(String, usize)>,
+}
+
+impl Document {
+    fn settings(&self) -> Settings<'_> {
+        return Settings {
+            table: "",
+            entries: &self.root,
+            lines: &self.lines,
+        };
+    }
+}
+
+/// Parses a config file into its top-level table.
+fn parse_document(text: &str) -> Result<Document> {
+    let mut root = Vec::<(String, Value)>::new();
+    let mut lines = Vec::new();
+    // Index into `root` of the table of the last `[header]`, if any.
+    let mut table: Option<usize> = None;
+    for (index, line) in text.lines().enumerate() {
+        let invalid = |reason: String| {
+            return Error::InvalidFormat(format!("config line {}: {}", index + 1, reason));
+        };
+        let mut chars = line.chars().peekable();
+        skip_space(&mut chars);
+        match chars.peek() {
+            None | Some('#') => continue,
+            Some('[') => {
+                chars.next();
+                let name = parse_key(&mut chars).map_err(invalid)?;
+                expect(&mut chars, ']').map_err(invalid)?;
+                end_of_line(&mut chars).map_err(invalid)?;
+                if root.iter().any(|(key, _)| *key == name) {
+                    return Err(invalid(format!("duplicate key `{}`", name)));
+                }
+                lines.push((name.clone(), index + 1));
+                root.push((name, Value::Table(Vec::new())));
+                table = Some(root.len() - 1);
+            }
+            Some(_) => {
+                let (key, value) = parse_entry(&mut chars).map_err(invalid)?;
+                end_of_line(&mut chars).map_err(invalid)?;
+                let (entries, name) = match table {
+                    Some(table) => match &mut root[table] {
+                        (name, Value::Table(entries)) => (entries, format!("{}.{}", name, key)),
+                        _ => unreachable!("headers always start tables"),
+                    },
+                    None => (&mut root, key.clone()),
+                };
+                if entries.iter().any(|(existing, _)| *existing == key) {
+                    return Err(invalid(format!("duplicate key `{}`", key)));
+                }
+                entries.push((key, value));
+                lines.push((name, index + 1));
+            }
+        }
+    }
+    return Ok(Document { root, lines });
+}
+
+type Input<'a> = Peekable<Chars<'a>>;
+
+fn skip_space(chars: &mut Input) {
+    while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+}
+
+fn expect(chars: &mut Input, expected: char) -> std::result::Result<(), String> {
+    skip_space(chars);
+    return match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        Some(c) => Err(format!("expected `{}`, found `{}`", expected, c)),
+        None => Err(format!("expected `{}` at end of line", expected)),
+    };
+}
+
+/// Accepts only spaces and a comment after a complete entry.
+fn end_of_line(chars: &mut Input) -> std::result::Result<(), String> {
+    skip_space(chars);
+    return match chars.next() {
+        None | Some('#') => Ok(()),
+        Some(c) => Err(format!("unexpected `{}` after value", c)),
+    };
+}
+
+/// Parses a bare key of ASCII letters, digits, `_` and `-`.
+fn parse_key(chars: &mut Input) -> std::result::Result<String, String> {
+    skip_space(chars);
+    let mut key = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-') {
+        key.push(c);
+    }
+    if key.is_empty() {
+        return Err("expected a key".to_string());
+    }
+    return Ok(key);
+}
+
+fn parse_entry(chars: &mut Input) -> std::result::Result<(String, Value), String> {
+    let key = parse_key(chars)?;
+    expect(chars, '=')?;
+    let value = parse_value(chars)?;
+    return Ok((key, value));
+}
+
+fn parse_value(chars: &mut Input) -> std::result::Result<Value, String> {
+    skip_space(chars);
+    match chars.peek() {
+        Some('"') | Some('\'') => return Ok(Value::String(parse_string(chars)?)),
+        Some('[') => {
+            chars.next();
+            let mut items = Vec::new();
+            loop {
+                skip_space(chars);
+                if chars.next_if_eq(&']').is_some() {
+                    return Ok(Value::Array(items));
+                }
+                items.push(parse_value(chars)?);
+                skip_space(chars);
+                if chars.next_if_eq(&',').is_none() {
+                    expect(chars, ']')?;
+                    return Ok(Value::Array(items));
+                }
+            }
+        }
+        Some('{') => {
+            chars.next();
+            let mut entries = Vec::<(String, Value)>::new();
+            skip_space(chars);
+            if chars.next_if_eq(&'}').is_some() {
+                return Ok(Value::Table(entries));
+            }
+            loop {
+                let (key, value) = parse_entry(chars)?;
+                if entries.iter().any(|(existing, _)| *existing == key) {
+                    return Err(format!("duplicate key `{}`", key));
+                }
+                entries.push((key, value));
+                skip_space(chars);
+                if chars.next_if_eq(&',').is_none() {
+                    expect(chars, '}')?;
+                    return Ok(Value::Table(entries));
+                }
+            }
+        }
+        _ => {
+            let mut word = String::new();
+            while let Some(c) =
+                chars.next_if(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.' | '_'))
+            {
+                word.push(c);
+            }
+            return match word.as_str() {
+                "" => Err("expected a value".to_string()),
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                _ => {
+                    let digits = word.replace('_', "");
+                    if let Ok(integer) = digits.parse() {
+                        Ok(Value::Integer(integer))
+                    } else if let Ok(float) = digits.parse() {
+                        Ok(Value::Float(float))
+                    } else {
+                        Err(format!("invalid value `{}`", word))
+                    }
+                }
+            };
+        }
+    }
+}
+
+/// Parses a `"basic"` string with escapes or a `'literal'` one.
+fn parse_string(chars: &mut Input) -> std::result::Result<String, String> {
+    let quote = chars.next().unwrap();
+    let mut string = String::new();
+    loop {
+        match chars.next() {
+            None => return Err("unterminated string".to_string()),
+            Some(c) if c == quote => return Ok(string),
+            Some('\\') if quote == '"' => match chars.next() {
+                Some('"') => string.push('"'),
+                Some('\\') => string.push('\\'),
+                Some('n') => string.push('\n'),
+                Some('t') => string.push('\t'),
+                Some(c) => return Err(format!("unsupported escape `\\{}`", c)),
+                None => return Err("unterminated string".to_string()),
+            },
+            Some(c) => string.push(c),
+        }
+    }
+}
+
+/// Quotes `value` as a basic TOML string.
+fn quote(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    return format!("\"{}\"", escaped);
+}
+
+/// Typed access to the entries of a settings table, reporting errors by key
+/// and line.
+struct Settings<'a> {
+    /// Dotted name of the table, for error messages.
+    table: &'a str,
+    entries: &'a [(String, Value)],
+    /// Lines of the keys of the whole document, see [`Document::lines`].
+    lines: &'a [(String, usize)],
+}
+
+impl<'a> Settings<'a> {
+    fn error(&self, key: &str, reason: &str) -> Error {
+        let name = match self.table {
+            "" => key.to_string(),
+            table => format!("{}.{}", table, key),
+        };
+        // Keys of inline tables, and missing keys, are reported at the
+        // line of the closest enclosing key.
+        let mut enclosing = name.as_str();
+        loop {
+            if let Some((_, line)) = self.lines.iter().find(|(key, _)| key == enclosing) {
+                return Error::InvalidFormat(format!(
+                    "config line {}: key `{}`: {}",
+                    line, name, reason
+                ));
+            }
+            match enclosing.rsplit_once('.') {
+                Some((parent, _)) => enclosing = parent,
+                None => break,
+            }
+        }
+        return Error::InvalidFormat(format!("config key `{}`: {}", name, reason));
+    }
+
+    fn get(&self, key: &str) -> Option<&'a Value> {
+        return self
+            .entries
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value);
+    }
+
+    /// Fails on the first entry not in `known`, to catch misspelled keys.
+    fn check_keys(&self, known: &[&str]) -> Result<()> {
+        for (key, _) in self.entries {
+            if !known.contains(&key.as_str()) {
+                return Err(self.error(key, "unknown setting"));
+            }
+        }
+        return Ok(());
+    }
+
+    fn float(&self, key: &str, value: &Value) -> Result<f32> {
+        return match value {
+            Value::Float(float) => Ok(*float as f32),
+            Value::Integer(integer) => Ok(*integer as f32),
+            other => Err(self.error(
+                key,
+                &format!("expected a number, found {}", other.type_name()),
+            )),
+        };
+    }
+
+    /// Returns the number `value` at `key` if `valid` holds for it, failing
+    /// with `expected` otherwise.
+    fn float_in(
+        &self,
+        key: &str,
+        value: &Value,
+        valid: impl Fn(f32) -> bool,
+        expected: &str,
+    ) -> Result<f32> {
+        let float = self.float(key, value)?;
+        if !valid(float) {
+            return Err(self.error(key, &format!("expected {}, found {}", expected, float)));
+        }
+        return Ok(float);
+    }
+
+    /// Returns the number at `key` if it is finite and not negative.
+    fn non_negative(&self, key: &str, value: &Value) -> Result<f32> {
+        return self.float_in(
+            key,
+            value,
+            |x| x.is_finite() && x >= 0.0,
+            "a finite number >= 0",
+        );
+    }
+
+    /// Returns the integer at `key` if it is at least 1.
+    fn positive_count(&self, key: &str, value: &Value) -> Result<usize> {
+        return match self.count(key, value)? {
+            0 => Err(self.error(key, "expected a positive integer, found 0")),
+            count => Ok(count),
+        };
+    }
+
+    fn count(&self, key: &str, value: &Value) -> Result<usize> {
+        return match value {
+            Value::Integer(integer) => usize::try_from(*integer)
+                .map_err(|_| self.error(key, "expected a non-negative integer")),
+            other => Err(self.error(
+                key,
+                &format!("expected an integer, found {}", other.type_name()),
+            )),
+        };
+    }
+
+    fn string(&self, key: &str, value: &'a Value) -> Result<&'a str> {
+        return match value {
+            Value::String(string) => Ok(string),
+            other => Err(self.error(
+                key,
+                &format!("expected a string, found {}", other.type_name()),
+            )),
+        };
+    }
+
+    fn bool(&self, key: &str, value: &Value) -> Result<bool> {
+        return match value {
+            Value::Bool(flag) => Ok(*flag),
+            other => Err(self.error(
+                key,
+                &format!("expected a boolean, found {}", other.type_name()),
+            )),
+        };
+    }
+
+    /// Returns the float at `key`, or `default` if it is missing.
+    fn float_or(&self, key: &str, default: f32) -> Result<f32> {
+        return self
+            .get(key)
+            .map_or(Ok(default), |value| self.float(key, value));
+    }
+
+    /// Returns the integer at `key`, or `default` if it is missing.
+    fn count_or(&self, key: &str, default: usize) -> Result<usize> {
+        return self
+            .get(key)
+            .map_or(Ok(default), |value| self.count(key, value));
+    }
+}
+
+/// Splits a named component value into its name and parameters: `"name"`
+/// or `{ name = "name", ... }`.
+fn component<'a>(
+    parent: &Settings<'a>,
+    key: &'a str,
+    value: &'a Value,
+) -> Result<(&'a str, Settings<'a>)> {
+    return match value {
+        Value::String(name) => Ok((
+            name,
+            Settings {
+                table: key,
+                entries: &[],
+                lines: parent.lines,
+            },
+        )),
+        Value::Table(entries) => {
+            let settings = Settings {
+                table: key,
+                entries,
+                lines: parent.lines,
+            };
+            let name = settings
+                .get("name")
+                .ok_or_else(|| settings.error("name", "missing"))?;
+            Ok((settings.string("name", name)?, settings))
+        }
+        other => Err(parent.error(
+            key,
+            &format!("expected a string or a table, found {}", other.type_name()),
+        )),
+    };
+}
+
+fn parse_loss(parent: &Settings, value: &Value) -> Result<Arc<dyn Loss>> {
+    let (name, settings) = component(parent, "loss", value)?;
+    return match name {
+        "cross-entropy" => {
+            settings.check_keys(&["name"])?;
+            Ok(Arc::new(CrossEntropy))
+        }
+        "bce" => {
+            settings.check_keys(&["name"])?;
+            Ok(Arc::new(BinaryCrossEntropy))
+        }
+        "hinge" => {
+            settings.check_keys(&["name", "margin"])?;
+            let margin = settings.float_or("margin", Hinge::default().margin)?;
+            Ok(Arc::new(Hinge { margin }))
+        }
+        "focal" => {
+            settings.check_keys(&["name", "gamma"])?;
+            let gamma = settings.float_or("gamma", Focal::default().gamma)?;
+            Ok(Arc::new(Focal { gamma }))
+        }
+        other => Err(settings.error(
+            "name",
+            &format!(
+                "unknown loss `{}` (expected cross-entropy, bce, hinge or focal)",
+                other
+            ),
+        )),
+    };
+}
+
+fn parse_scheduler(parent: &Settings, key: &str, value: &Value) -> Result<Arc<dyn LrScheduler>> {
+    let (name, settings) = component(parent, key, value)?;
+    return match name {
+        "constant" => {
+            settings.check_keys(&["name"])?;
+            Ok(Arc::new(Constant))
+        }
+        "step" => {
+            settings.check_keys(&["name", "step_size", "gamma"])?;
+            Ok(Arc::new(StepDecay {
+                step_size: settings.count_or("step_size", 10)?,
+                gamma: settings.float_or("gamma", 0.5)?,
+            }))
+        }
+        "cosine" => {
+            settings.check_keys(&["name", "min_lr"])?;
+            Ok(Arc::new(CosineAnnealing {
+                min_lr: settings.float_or("min_lr", 0.0)?,
+            }))
+        }
+        "warmup" => {
+            settings.check_keys(&["name", "warmup_epochs", "after"])?;
+            let warmup_epochs = settings.count_or("warmup_epochs", 5)?;
+            let after = match settings.get("after") {
+                Some(after) => parse_scheduler(&settings, "scheduler.after", after)?,
+                None => Arc::new(Constant),
+            };
+            Ok(Arc::new(Warmup {
+                warmup_epochs,
+                after,
+            }))
+        }
+        other => Err(settings.error(
+            "name",
+            &format!(
+                "unknown scheduler `{}` (expected constant, step, cosine or warmup)",
+                other
+            ),
+        )),
+    };
+}
+
+fn parse_sampler(parent: &Settings, value: &Value) -> Result<Arc<dyn Sampler>> {
+    let (name, settings) = component(parent, "sampler", value)?;
+    settings.check_keys(&["name"])?;
+    return match name {
+        "random" => Ok(Arc::new(RandomSampler)),
+        "stratified" => Ok(Arc::new(StratifiedSampler)),
+        "balanced" => Ok(Arc::new(BalancedSampler)),
+        other => Err(settings.error(
+            "name",
+            &format!(
+                "unknown sampler `{}` (expected random, stratified or balanced)",
+                other
+            ),
+        )),
+    };
+}
+
+fn parse_divergence_policy(parent: &Settings, value: &Value) -> Result<DivergencePolicy> {
+    let (name, settings) = component(parent, "on_divergence", value)?;
+    return match name {
+        "halt" => {
+            settings.check_keys(&["name"])?;
+            Ok(DivergencePolicy::Halt)
+        }
+        "rollback" => {
+            settings.check_keys(&["name", "max_retries"])?;
+            Ok(DivergencePolicy::Rollback {
+                max_retries: settings.count_or("max_retries", 3)?,
+            })
+        }
+        other => Err(settings.error(
+            "name",
+            &format!("unknown policy `{}` (expected halt or rollback)", other),
+        )),
+    };
+}
+
+/// Settings of a config file other than the named components, in the order
+/// [`TrainConfig::to_toml`] writes them.
+const KEYS: &[&str] = &[
+    "epochs",
+    "learning_rate",
+    "scheduler",
+    "momentum",
+    "loss",
+    "label_smoothing",
+    "l2",
+    "input_dropout",
+    "input_noise",
+    "class_weights",
+    "patience",
+    "min_delta",
+    "batch_size",
+    "accumulation_steps",
+    "num_threads",
+    "max_grad_norm",
+    "on_divergence",
+    "shuffle",
+    "sampler",
+    "seed",
+    "checkpoint_dir",
+    "checkpoint_every",
+    "metrics_path",
+    "tensorboard_dir",
+    "show_progress",
+    "handle_signals",
+];
+
+/// Reads the [`TrainConfig`] settings of a table whose keys were checked.
+fn train_config(settings: &Settings) -> Result<TrainConfig> {
+    let mut config = TrainConfig::default();
+    for (key, value) in settings.entries {
+        let key = key.as_str();
+        match key {
+            "epochs" => config.epochs = settings.count(key, value)?,
+            "learning_rate" => config.learning_rate = settings.non_negative(key, value)?,
+            "scheduler" => config.scheduler = parse_scheduler(settings, key, value)?,
+            "momentum" => {
+                config.momentum =
+                    settings.float_in(key, value, |m| (0.0..1.0).contains(&m), "[0, 1)")?;
+            }
+            "loss" => config.loss = parse_loss(settings, value)?,
+            "label_smoothing" => {
+                config.label_smoothing =
+                    settings.float_in(key, value, |e| (0.0..1.0).contains(&e), "[0, 1)")?;
+            }
+            "l2" => config.l2 = settings.non_negative(key, value)?,
+            "input_dropout" => {
+                config.input_dropout =
+                    settings.float_in(key, value, |p| (0.0..1.0).contains(&p), "[0, 1)")?;
+            }
+            "input_noise" => {
+                config.input_noise = settings.non_negative(key, value)?;
+            }
+            "class_weights" => {
+                let Value::Array(items) = value else {
+                    return Err(settings.error(key, "expected an array of numbers"));
+                };
+                // The length is checked against the data by `Trainer::fit`;
+                // every classifier has at least two classes.
+                if items.len() < 2 {
+                    return Err(settings.error(
+                        key,
+                        &format!("expected at least two weights, found {}", items.len()),
+                    ));
+                }
+                let weights = items
+                    .iter()
+                    .map(|item| settings.non_negative(key, item))
+                    .collect::<Result<Vec<f32>>>()?;
+                config.class_weights = Some(weights);
+            }
+            "patience" => config.patience = Some(settings.count(key, value)?),
+            "min_delta" => config.min_delta = settings.non_negative(key, value)?,
+            "batch_size" => config.batch_size = settings.positive_count(key, value)?,
+            "accumulation_steps" => {
+                config.accumulation_steps = settings.positive_count(key, value)?;
+            }
+            "num_threads" => config.num_threads = settings.count(key, value)?,
+            "max_grad_norm" => {
+                config.max_grad_norm =
+                    Some(settings.float_in(key, value, |max| max > 0.0, "> 0")?);
+            }
+            "on_divergence" => config.on_divergence = parse_divergence_policy(settings, value)?,
+            "shuffle" => config.shuffle = settings.bool(key, value)?,
+            "sampler" => config.sampler = parse_sampler(settings, value)?,
+            "seed" => {
+                config.seed = match value {
+                    // Seeds above i64::MAX are written as their bit pattern.
+                    Value::Integer(seed) => *seed as u64,
+                    _ => return Err(settings.error(key, "expected an integer")),
+                }
+            }
+            "checkpoint_dir" => {
+                config.checkpoint_dir = Some(PathBuf::from(settings.string(key, value)?));
+            }
+            "checkpoint_every" => config.checkpoint_every = settings.count(key, value)?,
+            "metrics_path" => {
+                config.metrics_path = Some(PathBuf::from(settings.string(key, value)?));
+            }
+            "tensorboard_dir" => {
+                config.tensorboard_dir = Some(PathBuf::from(settings.string(key, value)?));
+            }
+            "show_progress" => config.show_progress = settings.bool(key, value)?,
+            "handle_signals" => config.handle_signals = settings.bool(key, value)?,
+            // Tables read by `RunConfig::from_toml`.
+            "data" | "model" => {}
+            _ => unreachable!("keys were checked"),
+        }
+    }
+    return Ok(config);
+}
+
+impl TrainConfig {
+    /// Parses a TOML config file, starting from [`TrainConfig::default`].
+    ///
+    /// Every key is the name of a `TrainConfig` field and sets it; missing
+    /// keys keep their default, and unknown keys are an error. Optional
+    /// fields are set by giving a value and left at `None` by leaving the
+    /// key out. The trait-object fields take the name used on the command
+    /// line, or an inline table with the parameters:
+    ///
+    /// ```toml
+    /// epochs = 100
+    /// learning_rate = 0.01
+    /// momentum = 0.9
+    /// batch_size = 16
+    /// input_noise = 0.05
+    /// seed = 7
+    /// checkpoint_dir = "runs/a"
+    /// loss = { name = "focal", gamma = 2.0 }
+    /// scheduler = { name = "warmup", warmup_epochs = 5, after = "cosine" }
+    /// sampler = "balanced"
+    /// on_divergence = { name = "rollback", max_retries = 3 }
+    /// ```
+    ///
+    /// `loss` is one of `cross-entropy`, `bce`, `hinge` (with `margin`) and
+    /// `focal` (`gamma`); `scheduler` one of `constant`, `step`
+    /// (`step_size`, `gamma`), `cosine` (`min_lr`) and `warmup`
+    /// (`warmup_epochs`, `after`); `sampler` one of `random`, `stratified`
+    /// and `balanced`; `on_divergence` `halt` or `rollback`
+    /// (`max_retries`). A component can also be written as a `[table]`.
+    ///
+    /// # Errors
+    /// [`Error::InvalidFormat`] for a syntax error, an unknown key, a value
+    /// of the wrong type, or one that training would reject: a negative or
+    /// non-finite `learning_rate`, `l2`, `min_delta` or `input_noise`,
+    /// `momentum`, `input_dropout` or `label_smoothing` outside [0, 1), a
+    /// `max_grad_norm` of at most 0, a zero `batch_size` or
+    /// `accumulation_steps`, or `class_weights` with fewer than two
+    /// weights or a negative one. The message gives the line of the key.
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let document = parse_document(text)?;
+        let settings = document.settings();
+        settings.check_keys(KEYS)?;
+        return train_config(&settings);
+    }
+
+    /// Reads a config file written by hand or by [`TrainConfig::to_toml`],
+    /// see [`TrainConfig::from_toml`].
+    #[cfg(feature = "fs")]
+    pub fn load_toml(path: &Path) -> Result<Self> {
+        return Self::from_toml(&read_to_string(path)?).map_err(|err| match err {
+            Error::InvalidFormat(reason) => {
+                Error::InvalidFormat(format!("{}: {}", path.display(), reason))
+            }
+            err => err,
+        });
+    }
+
+    /// Writes [`TrainConfig::to_toml`] to `path`, creating its directory if
+    /// needed.
+    #[cfg(feature = "fs")]
+    pub fn save_toml(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        write(path, self.to_toml())?;
+        return Ok(());
+    }
+
+    /// Writes every setting as a config file that [`TrainConfig::from_toml`]
+    /// reads back into the same configuration.
+    ///
+    /// Settings that are `None` are left out. A loss, scheduler or sampler
+    /// that config files cannot express is written as a comment with its
+    /// debug representation, so the file still documents the run but loads
+    /// with the default in its place.
+    pub fn to_toml(&self) -> String {
+        let mut text = String::new();
+        let mut line = |key: &str, value: Option<String>, debug: &dyn std::fmt::Debug| {
+            let _ = match value {
+                Some(value) => writeln!(text, "{} = {}", key, value),
+                None => writeln!(text, "# {}: not expressible, {:?}", key, debug),
+            };
+        };
+        let path = |path: &Option<PathBuf>| {
+            return path.as_ref().map(|path| quote(&path.to_string_lossy()));
+        };
+        let divergence = match self.on_divergence {
+            DivergencePolicy::Halt => quote("halt"),
+            DivergencePolicy::Rollback { max_retries } => {
+                format!("{{ name = \"rollback\", max_retries = {} }}", max_retries)
+            }
+        };
+        let class_weights = self.class_weights.as_ref().map(|weights| {
+            let weights: Vec<String> = weights.iter().map(|w| format!("{:?}", w)).collect();
+            return format!("[{}]", weights.join(", "));
+        });
+        let optional = [
+            ("class_weights", class_weights),
+            ("patience", self.patience.map(|p| p.to_string())),
+            (
+                "max_grad_norm",
+                self.max_grad_norm.map(|n| format!("{:?}", n)),
+            ),
+            ("checkpoint_dir", path(&self.checkpoint_dir)),
+            ("metrics_path", path(&self.metrics_path)),
+            ("tensorboard_dir", path(&self.tensorboard_dir)),
+        ];
+        for &key in KEYS {
+            let value = match key {
+                "epochs" => Some(self.epochs.to_string()),
+                "learning_rate" => Some(format!("{:?}", self.learning_rate)),
+                "scheduler" => {
+                    line(key, self.scheduler.to_toml(), &self.scheduler);
+                    continue;
+                }
+                "momentum" => Some(format!("{:?}", self.momentum)),
+                "loss" => {
+                    line(key, self.loss.to_toml(), &self.loss);
+                    continue;
+                }
+                "label_smoothing" => Some(format!("{:?}", self.label_smoothing)),
+                "l2" => Some(format!("{:?}", self.l2)),
+                "input_dropout" => Some(format!("{:?}", self.input_dropout)),
+                "input_noise" => Some(format!("{:?}", self.input_noise)),
+                "min_delta" => Some(format!("{:?}", self.min_delta)),
+                "batch_size" => Some(self.batch_size.to_string()),
+                "accumulation_steps" => Some(self.accumulation_steps.to_string()),
+                "num_threads" => Some(self.num_threads.to_string()),
+                "on_divergence" => Some(divergence.clone()),
+                "shuffle" => Some(self.shuffle.to_string()),
+                "sampler" => {
+                    line(key, self.sampler.to_toml(), &self.sampler);
+                    continue;
+                }
+                "seed" => Some((self.seed as i64).to_string()),
+                "checkpoint_every" => Some(self.checkpoint_every.to_string()),
+                "show_progress" => Some(self.show_progress.to_string()),
+                "handle_signals" => Some(self.handle_signals.to_string()),
+                _ => {
+                    let (_, value) = optional.iter().find(|(name, _)| *name == key).unwrap();
+                    match value {
+                        Some(value) => Some(value.clone()),
+                        None => continue,
+                    }
+                }
+            };
+            line(key, value, &());
+        }
+        return text;
+    }
+}
+
+/// Where the images of a training run come from and how they are
+/// preprocessed: the `[data]` table of a config file.
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DataConfig {
+    /// Folder with `train` and `val` subfolders; `None` for the bundled
+    /// dataset.
+    pub dataset_dir: Option<PathBuf>,
+    /// Directory holding preprocessed dataset caches.
+    pub cache_dir: Option<PathBuf>,
+    /// Resolution, channels and resizing of the preprocessed images.
+    pub preprocess: Preprocess,
+    /// Element type the loaded features are stored in.
+    pub precision: Precision,
+    /// Memory-map dataset caches instead of reading them into memory.
+    pub mmap: bool,
+}
+
+/// Architecture of the model a training run starts from: the `[model]`
+/// table of a config file.
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ModelConfig {
+    /// Output channels of the conv blocks in front of the linear layer;
+    /// `None` for a linear model.
+    pub conv: Option<Vec<usize>>,
+    /// Standardize each channel with statistics of the training split.
+    pub normalize: bool,
+}
+
+/// A whole config file: the training settings at the top level, and the
+/// `[data]` and `[model]` tables.
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Default)]
+pub struct RunConfig {
+    pub train: TrainConfig,
+    pub data: DataConfig,
+    pub model: ModelConfig,
+}
+
+/// Settings of the `[data]` table, in the order [`RunConfig::to_toml`]
+/// writes them.
+#[cfg(feature = "fs")]
+const DATA_KEYS: &[&str] = &[
+    "dataset_dir",
+    "cache_dir",
+    "width",
+    "height",
+    "channels",
+    "resize",
+    "precision",
+    "mmap",
+];
+
+/// Settings of the `[model]` table.
+#[cfg(feature = "fs")]
+const MODEL_KEYS: &[&str] = &["conv", "normalize"];
+
+/// Returns the entries of the table `value` at `key`.
+#[cfg(feature = "fs")]
+fn table<'a>(parent: &Settings<'a>, key: &'a str, value: &'a Value) -> Result<Settings<'a>> {
+    let Value::Table(entries) = value else {
+        return Err(parent.error(
+            key,
+            &format!("expected a table, found {}", value.type_name()),
+        ));
+    };
+    return Ok(Settings {
+        table: key,
+        entries,
+        lines: parent.lines,
+    });
+}
+
+#[cfg(feature = "fs")]
+fn parse_data(settings: &Settings) -> Result<DataConfig> {
+    settings.check_keys(DATA_KEYS)?;
+    let mut data = DataConfig::default();
+    for (key, value) in settings.entries {
+        let key = key.as_str();
+        match key {
+            "dataset_dir" => data.dataset_dir = Some(PathBuf::from(settings.string(key, value)?)),
+            "cache_dir" => data.cache_dir = Some(PathBuf::from(settings.string(key, value)?)),
+            "width" | "height" => {
+                let size = u32::try_from(settings.positive_count(key, value)?)
+                    .map_err(|_| settings.error(key, "too large"))?;
+                match key {
+                    "width" => data.preprocess.width = size,
+                    _ => data.preprocess.height = size,
+                }
+            }
+            "channels" => {
+                data.preprocess.channels = match settings.string(key, value)? {
+                    "rgb" => ChannelMode::Rgb,
+                    "grayscale" => ChannelMode::Grayscale,
+                    other => {
+                        return Err(settings.error(
+                            key,
+                            &format!("unknown channels `{}` (expected rgb or grayscale)", other),
+                        ));
+                    }
+                };
+            }
+            "resize" => {
+                data.preprocess.resize = match settings.string(key, value)? {
+                    "stretch" => ResizeMode::Stretch,
+                    "crop" => ResizeMode::CenterCrop,
+                    "letterbox" => ResizeMode::Letterbox,
+                    other => {
+                        return Err(settings.error(
+                            key,
+                            &format!(
+                                "unknown resize mode `{}` (expected stretch, crop or letterbox)",
+                                other
+                            ),
+                        ));
+                    }
+                };
+            }
+            "precision" => {
+                data.precision = match settings.string(key, value)? {
+                    "f32" => Precision::F32,
+                    "f16" => Precision::F16,
+                    other => {
+                        return Err(settings.error(
+                            key,
+                            &format!("unknown precision `{}` (expected f32 or f16)", other),
+                        ));
+                    }
+                };
+            }
+            "mmap" => data.mmap = settings.bool(key, value)?,
+            _ => unreachable!("keys were checked"),
+        }
+    }
+    return Ok(data);
+}
+
+#[cfg(feature = "fs")]
+fn parse_model(settings: &Settings) -> Result<ModelConfig> {
+    settings.check_keys(MODEL_KEYS)?;
+    let mut model = ModelConfig::default();
+    for (key, value) in settings.entries {
+        let key = key.as_str();
+        match key {
+            "conv" => {
+                let Value::Array(items) = value else {
+                    return Err(settings.error(key, "expected an array of channel counts"));
+                };
+                if items.is_empty() {
+                    return Err(settings.error(key, "expected at least one conv block"));
+                }
+                let channels = items
+                    .iter()
+                    .map(|item| settings.positive_count(key, item))
+                    .collect::<Result<Vec<usize>>>()?;
+                model.conv = Some(channels);
+            }
+            "normalize" => model.normalize = settings.bool(key, value)?,
+            _ => unreachable!("keys were checked"),
+        }
+    }
+    return Ok(model);
+}
+
+#[cfg(feature = "fs")]
+impl RunConfig {
+    /// Parses a config file with [`TrainConfig::from_toml`], except that
+    /// it may also hold a `[data]` and a `[model]` table:
+    ///
+    /// ```toml
+    /// epochs = 100
+    ///
+    /// [data]
+    /// dataset_dir = "data/insects"
+    /// cache_dir = "cache"
+    /// width = 64
+    /// height = 48
+    /// channels = "grayscale"
+    /// resize = "letterbox"
+    /// precision = "f16"
+    /// mmap = true
+    ///
+    /// [model]
+    /// conv = [8, 16]
+    /// normalize = true
+    /// ```
+    ///
+    /// Every key is the name of a [`DataConfig`], [`Preprocess`] or
+    /// [`ModelConfig`] field; missing keys keep their default. `channels`
+    /// is `rgb` or `grayscale`, `resize` one of `stretch`, `crop` and
+    /// `letterbox`, and `precision` `f32` or `f16`, as on the command line.
+    ///
+    /// # Errors
+    /// [`Error::InvalidFormat`] as for [`TrainConfig::from_toml`], and for
+    /// a zero `width`, `height` or `conv` channel count or an empty `conv`.
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let document = parse_document(text)?;
+        let settings = document.settings();
+        let mut known = KEYS.to_vec();
+        known.extend(["data", "model"]);
+        settings.check_keys(&known)?;
+        let mut config = Self {
+            train: train_config(&settings)?,
+            ..Self::default()
+        };
+        if let Some(value) = settings.get("data") {
+            config.data = parse_data(&table(&settings, "data", value)?)?;
+        }
+        if let Some(value) = settings.get("model") {
+            config.model = parse_model(&table(&settings, "model", value)?)?;
+        }
+        return Ok(config);
+    }
+
+    /// Reads a config file, see [`RunConfig::from_toml`].
+    pub fn load_toml(path: &Path) -> Result<Self> {
+        return Self::from_toml(&read_to_string(path)?).map_err(|err| match err {
+            Error::InvalidFormat(reason) => {
+                Error::InvalidFormat(format!("{}: {}", path.display(), reason))
+            }
+            err => err,
+        });
+    }
+
+    /// Writes [`TrainConfig::to_toml`] followed by the `[data]` and
+    /// `[model]` tables, which [`RunConfig::from_toml`] reads back into the
+    /// same configuration.
+    pub fn to_toml(&self) -> String {
+        let mut text = self.train.to_toml();
+        let path = |path: &Option<PathBuf>| {
+            return path.as_ref().map(|path| quote(&path.to_string_lossy()));
+        };
+        let preprocess = &self.data.preprocess;
+        let channels = match preprocess.channels {
+            ChannelMode::Rgb => "rgb",
+            ChannelMode::Grayscale => "grayscale",
+        };
+        let resize = match preprocess.resize {
+            ResizeMode::Stretch => "stretch",
+            ResizeMode::CenterCrop => "crop",
+            ResizeMode::Letterbox => "letterbox",
+        };
+        let precision = match self.data.precision {
+            Precision::F32 => "f32",
+            Precision::F16 => "f16",
+        };
+        let _ = writeln!(text, "\n[data]");
+        for &key in DATA_KEYS {
+            let value = match key {
+                "dataset_dir" => path(&self.data.dataset_dir),
+                "cache_dir" => path(&self.data.cache_dir),
+                "width" => Some(preprocess.width.to_string()),
+                "height" => Some(preprocess.height.to_string()),
+                "channels" => Some(quote(channels)),
+                "resize" => Some(quote(resize)),
+                "precision" => Some(quote(precision)),
+                "mmap" => Some(self.data.mmap.to_string()),
+                _ => unreachable!("every data key is written"),
+            };
+            if let Some(value) = value {
+                let _ = writeln!(text, "{} = {}", key, value);
+            }
+        }
+        let _ = writeln!(text, "\n[model]");
+        if let Some(conv) = &self.model.conv {
+            let channels: Vec<String> = conv.iter().map(|c| c.to_string()).collect();
+            let _ = writeln!(text, "conv = [{}]", channels.join(", "));
+        }
+        let _ = writeln!(text, "normalize = {}", self.model.normalize);
+        return text;
+    }
+}
@@ -0,0 +1,585 @@
+use super::codec;
+use super::error::Error;
+use super::error::Result;
+use super::preprocess::Preprocess;
+use ndarray::Array1;
+use ndarray::Array2;
+use ndarray::Array3;
+use ndarray::Array4;
+use ndarray::ArrayView1;
+use ndarray::ArrayView2;
+use ndarray::ArrayView3;
+use ndarray::ArrayView4;
+use ndarray::Axis;
+use rand::Rng;
+use std::io::Read;
+use std::io::Write;
+
+/// One convolution block: a stride-1 convolution with zero "same" padding,
+/// followed by ReLU and 2x2 max-pooling (stride 2, odd rows/columns dropped).
+///
+/// Also used to hold the gradients and optimizer state of a block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvLayer {
+    /// Kernels of shape (out_channels, in_channels, kernel_size, kernel_size).
+    pub(crate) kernel: Array4<f32>,
+    /// Bias of shape (out_channels,).
+    pub(crate) bias: Array1<f32>,
+}
+
+/// What a block's forward pass keeps for its backward pass, for one sample.
+pub(crate) struct LayerCache {
+    /// im2col matrix of the input, shape (in_channels * k * k, height * width).
+    cols: Array2<f32>,
+    /// Convolution output before ReLU, shape (out_channels, height * width).
+    pre: Array2<f32>,
+    /// Position in `pre` of the maximum of every pooling window.
+    argmax: Vec<usize>,
+    /// Input shape (channels, height, width).
+    input: (usize, usize, usize),
+}
+
+impl ConvLayer {
+    /// Creates a block with uniform weights in [-scale, scale], where
+    /// scale = sqrt(2 / fan_in), and zero bias.
+    fn from_rng(
+        in_channels: usize,
+        out_channels: usize,
+        kernel_size: usize,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let fan_in = in_channels * kernel_size * kernel_size;
+        let scale = (2.0 / fan_in as f32).sqrt();
+        return Self {
+            kernel: Array4::from_shape_fn(
+                (out_channels, in_channels, kernel_size, kernel_size),
+                |_| (rng.random::<f32>() - 0.5) * 2.0 * scale,
+            ),
+            bias: Array1::zeros(out_channels),
+        };
+    }
+
+    fn zeros_like(&self) -> Self {
+        return Self {
+            kernel: Array4::zeros(self.kernel.raw_dim()),
+            bias: Array1::zeros(self.bias.raw_dim()),
+        };
+    }
+
+    pub fn in_channels(&self) -> usize {
+        return self.kernel.dim().1;
+    }
+
+    pub fn out_channels(&self) -> usize {
+        return self.kernel.dim().0;
+    }
+
+    pub fn kernel_size(&self) -> usize {
+        return self.kernel.dim().2;
+    }
+
+    /// Kernels of shape (out_channels, in_channels, kernel_size, kernel_size).
+    pub fn kernel(&self) -> ArrayView4<'_, f32> {
+        return self.kernel.view();
+    }
+
+    pub fn bias(&self) -> ArrayView1<'_, f32> {
+        return self.bias.view();
+    }
+
+    /// The kernels flattened to (out_channels, in_channels * k * k), matching
+    /// the row order of [`im2col`].
+    fn kernel_matrix(&self) -> ArrayView2<'_, f32> {
+        let (out_channels, in_channels, k, _) = self.kernel.dim();
+        return self
+            .kernel
+            .view()
+            .into_shape_with_order((out_channels, in_channels * k * k))
+            .unwrap();
+    }
+
+    /// Output shape (channels, height, width) for an input of `height` x `width`.
+    fn output_shape(&self, height: usize, width: usize) -> (usize, usize, usize) {
+        return (self.out_channels(), height / 2, width / 2);
+    }
+
+    /// Runs the block on one sample of shape (in_channels, height, width).
+    fn forward(&self, x: ArrayView3<f32>) -> (Array3<f32>, LayerCache) {
+        let (channels, height, width) = x.dim();
+        let (out_channels, out_height, out_width) = self.output_shape(height, width);
+
+        let cols = im2col(x, self.kernel_size());
+        let pre = self.kernel_matrix().dot(&cols) + self.bias.view().insert_axis(Axis(1));
+
+        // ReLU and max-pooling commute, so pool the raw outputs and clamp after.
+        let mut out = Array3::<f32>::zeros((out_channels, out_height, out_width));
+        let mut argmax = Vec::with_capacity(out.len());
+        for o in 0..out_channels {
+            for py in 0..out_height {
+                for px in 0..out_width {
+                    let mut best = (2 * py) * width + 2 * px;
+                    for (dy, dx) in [(0, 1), (1, 0), (1, 1)] {
+                        let index = (2 * py + dy) * width + 2 * px + dx;
+                        if pre[[o, index]] > pre[[o, best]] {
+                            best = index;
+                        }
+                    }
+                    out[[o, py, px]] = pre[[o, best]].max(0.0);
+                    argmax.push(best);
+                }
+            }
+        }
+
+        let cache = LayerCache {
+            cols,
+            pre,
+            argmax,
+            input: (channels, height, width),
+        };
+        return (out, cache);
+    }
+
+    /// Backpropagates `d_out` (shape (out_channels, out_height * out_width))
+    /// through the block, adding the parameter gradients to `grads`.
+    ///
+    /// Returns the gradient with respect to the input if `input_grad` is set.
+    fn backward(
+        &self,
+        cache: &LayerCache,
+        d_out: ArrayView2<f32>,
+        grads: &mut ConvLayer,
+        input_grad: bool,
+    ) -> Option<Array3<f32>> {
+        let mut d_pre = Array2::<f32>::zeros(cache.pre.raw_dim());
+        let pooled = d_out.ncols();
+        for ((o, p), &d) in d_out.indexed_iter() {
+            let index = cache.argmax[o * pooled + p];
+            if cache.pre[[o, index]] > 0.0 {
+                d_pre[[o, index]] += d; // only the window maximum passed ReLU
+            }
+        }
+
+        let (out_channels, in_channels, k, _) = self.kernel.dim();
+        let mut d_kernel = grads
+            .kernel
+            .view_mut()
+            .into_shape_with_order((out_channels, in_channels * k * k))
+            .unwrap();
+        d_kernel += &d_pre.dot(&cache.cols.t()); // dL/dK = dL/dpre · cols^T
+        grads.bias += &d_pre.sum_axis(Axis(1)); // dL/db = sum over positions
+
+        if !input_grad {
+            return None;
+        }
+        let d_cols = self.kernel_matrix().t().dot(&d_pre);
+        return Some(col2im(d_cols.view(), cache.input, k));
+    }
+}
+
+/// Unfolds the k x k neighborhood of every pixel of `x` (shape (channels,
+/// height, width)) into one column, zero-padding the borders.
+///
+/// Row `(c * k + i) * k + j` holds input channel `c` shifted by kernel
+/// offset `(i, j)`.
+fn im2col(x: ArrayView3<f32>, k: usize) -> Array2<f32> {
+    let (channels, height, width) = x.dim();
+    let pad = (k / 2) as isize;
+    let mut cols = Array2::<f32>::zeros((channels * k * k, height * width));
+    for c in 0..channels {
+        for i in 0..k {
+            for j in 0..k {
+                let row = (c * k + i) * k + j;
+                for y in 0..height {
+                    let sy = y as isize + i as isize - pad;
+                    if sy < 0 || sy >= height as isize {
+                        continue;
+                    }
+                    for x_pos in 0..width {
+                        let sx = x_pos as isize + j as isize - pad;
+                        if sx < 0 || sx >= width as isize {
+                            continue;
+                        }
+                        cols[[row, y * width + x_pos]] = x[[c, sy as usize, sx as usize]];
+                    }
+                }
+            }
+        }
+    }
+    return cols;
+}
+
+/// Inverse of [`im2col`] for gradients: sums every column entry back into
+/// the input pixel it was copied from.
+fn col2im(cols: ArrayView2<f32>, shape: (usize, usize, usize), k: usize) -> Array3<f32> {
+    let (channels, height, width) = shape;
+    let pad = (k / 2) as isize;
+    let mut x = Array3::<f32>::zeros(shape);
+    for c in 0..channels {
+        for i in 0..k {
+            for j in 0..k {
+                let row = (c * k + i) * k + j;
+                for y in 0..height {
+                    let sy = y as isize + i as isize - pad;
+                    if sy < 0 || sy >= height as isize {
+                        continue;
+                    }
+                    for x_pos in 0..width {
+                        let sx = x_pos as isize + j as isize - pad;
+                        if sx < 0 || sx >= width as isize {
+                            continue;
+                        }
+                        x[[c, sy as usize, sx as usize]] += cols[[row, y * width + x_pos]];
+                    }
+                }
+            }
+        }
+    }
+    return x;
+}
+
+/// A stack of [`ConvLayer`]s turning images into feature vectors for the
+/// linear head of a [`Model`](super::Model).
+///
+/// Unlike the flat input vector, convolutions see the spatial layout of the
+/// image: each block detects local patterns, and pooling halves the
+/// resolution so that later blocks see larger parts of the image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvNet {
+    /// Input shape (channels, height, width), from the preprocessing.
+    input: (usize, usize, usize),
+    layers: Vec<ConvLayer>,
+}
+
+impl ConvNet {
+    /// Kernel size of the blocks created by [`ConvNet::from_rng`].
+    pub const KERNEL_SIZE: usize = 3;
+
+    /// Creates one 3x3 block per entry of `channels`, the entry giving the
+    /// block's number of output channels.
+    ///
+    /// # Panics
+    /// Panics if `channels` is empty or the images become smaller than 1x1
+    /// after pooling.
+    pub fn from_rng(preprocess: &Preprocess, channels: &[usize], rng: &mut impl Rng) -> Self {
+        assert!(!channels.is_empty(), "need at least one conv block");
+        let mut in_channels = preprocess.channels.channels();
+        let mut layers = Vec::with_capacity(channels.len());
+        for &out_channels in channels {
+            assert!(out_channels >= 1, "conv blocks need at least one channel");
+            layers.push(ConvLayer::from_rng(
+                in_channels,
+                out_channels,
+                Self::KERNEL_SIZE,
+                rng,
+            ));
+            in_channels = out_channels;
+        }
+        let net = Self {
+            input: Self::shape_of(preprocess),
+            layers,
+        };
+        let (_, height, width) = net.output_shape();
+        assert!(
+            height >= 1 && width >= 1,
+            "images of {} are too small for {} conv blocks",
+            preprocess,
+            channels.len()
+        );
+        return net;
+    }
+
+    fn shape_of(preprocess: &Preprocess) -> (usize, usize, usize) {
+        return (
+            preprocess.channels.channels(),
+            preprocess.height as usize,
+            preprocess.width as usize,
+        );
+    }
+
+    pub fn layers(&self) -> &[ConvLayer] {
+        return &self.layers;
+    }
+
+    /// Input shape (channels, height, width).
+    pub fn input_shape(&self) -> (usize, usize, usize) {
+        return self.input;
+    }
+
+    /// Shape (channels, height, width) of the extracted features.
+    pub fn output_shape(&self) -> (usize, usize, usize) {
+        let (mut channels, mut height, mut width) = self.input;
+        for layer in &self.layers {
+            (channels, height, width) = layer.output_shape(height, width);
+        }
+        return (channels, height, width);
+    }
+
+    /// Length of the flattened feature vector fed to the linear head.
+    pub fn output_dim(&self) -> usize {
+        let (channels, height, width) = self.output_shape();
+        return channels * height * width;
+    }
+
+    /// Zero gradients with the parameter shapes of every block.
+    pub(crate) fn zeros_like(&self) -> Vec<ConvLayer> {
+        return self.layers.iter().map(ConvLayer::zeros_like).collect();
+    }
+
+    /// Squared L2 norm of all kernels (biases excluded).
+    pub(crate) fn kernel_norm_sq(&self) -> f32 {
+        return self
+            .layers
+            .iter()
+            .flat_map(|layer| layer.kernel.iter())
+            .map(|v| v * v)
+            .sum();
+    }
+
+    pub(crate) fn layers_mut(&mut self) -> &mut [ConvLayer] {
+        return &mut self.layers;
+    }
+
+    /// Extracts the features of every row of `x` (shape (batch, INPUT_DIM),
+    /// CHW-flattened images), giving shape (batch, output_dim).
+    pub fn forward_batch(&self, x: ArrayView2<f32>) -> Array2<f32> {
+        return self.forward_train(x).0;
+    }
+
+    /// Like [`ConvNet::forward_batch`], also returning what the backward
+    /// pass needs: one cache per block for every sample.
+    pub(crate) fn forward_train(&self, x: ArrayView2<f32>) -> (Array2<f32>, Vec<Vec<LayerCache>>) {
+        let mut features = Array2::<f32>::zeros((x.nrows(), self.output_dim()));
+        let mut caches = Vec::with_capacity(x.nrows());
+        for (row, mut out_row) in x.axis_iter(Axis(0)).zip(features.axis_iter_mut(Axis(0))) {
+            let mut activation = row.to_shape(self.input).unwrap().to_owned();
+            let mut sample_caches = Vec::with_capacity(self.layers.len());
+            for layer in &self.layers {
+                let (out, cache) = layer.forward(activation.view());
+                activation = out;
+                sample_caches.push(cache);
+            }
+            out_row.assign(&Array1::from_iter(activation.iter().copied()));
+            caches.push(sample_caches);
+        }
+        return (features, caches);
+    }
+
+    /// Backpropagates `d_features` (shape (batch, output_dim)) through all
+    /// blocks.
+    ///
+    /// Returns the parameter gradients summed over the batch and, if
+    /// `input_grad` is set, the gradient with respect to each input row.
+    pub(crate) fn backward(
+        &self,
+        caches: &[Vec<LayerCache>],
+        d_features: ArrayView2<f32>,
+        input_grad: bool,
+    ) -> (Vec<ConvLayer>, Option<Array2<f32>>) {
+        let mut grads = self.zeros_like();
+        let input_dim = self.input.0 * self.input.1 * self.input.2;
+        let mut d_input = input_grad.then(|| Array2::<f32>::zeros((d_features.nrows(), input_dim)));
+
+        for (sample, (sample_caches, d_row)) in
+            caches.iter().zip(d_features.axis_iter(Axis(0))).enumerate()
+        {
+            let (channels, height, width) = self.output_shape();
+            let mut d_out = d_row
+                .to_shape((channels, height * width))
+                .unwrap()
+                .to_owned();
+            for (index, layer) in self.layers.iter().enumerate().rev() {
+                let cache = &sample_caches[index];
+                let need_input = index > 0 || d_input.is_some();
+                let Some(d_x) = layer.backward(cache, d_out.view(), &mut grads[index], need_input)
+                else {
+                    break;
+                };
+                if index == 0 {
+                    if let Some(d_input) = &mut d_input {
+                        d_input
+                            .row_mut(sample)
+                            .assign(&Array1::from_iter(d_x.iter().copied()));
+                    }
+                    break;
+                }
+                let (c, h, w) = d_x.dim();
+                d_out = d_x.into_shape_with_order((c, h * w)).unwrap();
+            }
+        }
+        return (grads, d_input);
+    }
+
+    pub(crate) fn write_to(&self, writer: &mut impl Write) -> Result<()> {
+        codec::write_u32(writer, self.layers.len() as u32)?;
+        for layer in &self.layers {
+            codec::write_u32(writer, layer.out_channels() as u32)?;
+            codec::write_u32(writer, layer.kernel_size() as u32)?;
+            codec::write_f32s(writer, layer.kernel.iter())?;
+            codec::write_f32s(writer, layer.bias.iter())?;
+        }
+        return Ok(());
+    }
+
+    /// Reads blocks written by [`ConvNet::write_to`]; `None` if there are none.
+    pub(crate) fn read_from(
+        reader: &mut impl Read,
+        preprocess: &Preprocess,
+    ) -> Result<Option<Self>> {
+        let num_layers = codec::read_u32(reader)? as usize;
+        if num_layers == 0 {
+            return Ok(None);
+        }
+        let input = Self::shape_of(preprocess);
+        let (mut in_channels, mut height, mut width) = input;
+        let mut layers = Vec::with_capacity(num_layers.min(16));
+        for _ in 0..num_layers {
+            let out_channels = codec::read_u32(reader)? as usize;
+            let k = codec::read_u32(reader)? as usize;
+            if out_channels == 0 || k.is_multiple_of(2) || height < 2 || width < 2 {
+                return Err(Error::InvalidFormat(format!(
+                    "unsupported conv block {}x{} with {} channels on {}x{} input",
+                    k, k, out_channels, width, height
+                )));
+            }
+            let kernel =
+                codec::read_f32s(reader, codec::size(&[out_channels, in_channels, k, k])?)?;
+            let bias = codec::read_f32s(reader, out_channels)?;
+            let layer = ConvLayer {
+                kernel: Array4::from_shape_vec((out_channels, in_channels, k, k), kernel).unwrap(),
+                bias: Array1::from_vec(bias),
+            };
+            (in_channels, height, width) = layer.output_shape(height, width);
+            layers.push(layer);
+        }
+        codec::size(&[in_channels, height, width])?;
+        return Ok(Some(Self { input, layers }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::antbee::preprocess::ChannelMode;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    /// Blocks and a batch of two inputs, with the loss sum(features *
+    /// weights), whose gradient with respect to the features is `weights`.
+    struct Case {
+        net: ConvNet,
+        x: Array2<f32>,
+        weights: Array2<f32>,
+    }
+
+    impl Case {
+        fn new(width: u32, height: u32, channels: &[usize]) -> Self {
+            let mut rng = ChaCha8Rng::seed_from_u64(u64::from(width * 100 + height));
+            let preprocess = Preprocess {
+                width,
+                height,
+                channels: ChannelMode::Rgb,
+                ..Preprocess::default()
+            };
+            let net = ConvNet::from_rng(&preprocess, channels, &mut rng);
+            let mut uniform = |shape| Array2::from_shape_fn(shape, |_| rng.random_range(-1.0..1.0));
+            let x = uniform((2, preprocess.input_dim()));
+            let weights = uniform((2, net.output_dim()));
+            return Self { net, x, weights };
+        }
+
+        fn loss(&self) -> f32 {
+            return (self.net.forward_batch(self.x.view()) * &self.weights).sum();
+        }
+
+        /// Forward and backward differences of the loss in the parameter
+        /// `param` selects.
+        ///
+        /// The loss is piecewise linear in every single parameter, so both
+        /// are exact unless a ReLU or pooling window switches within the
+        /// step, which happens on one side at most.
+        fn numeric(&mut self, param: impl Fn(&mut Case) -> &mut f32) -> [f32; 2] {
+            const EPS: f32 = 1e-3;
+            let value = *param(self);
+            let loss = self.loss();
+            *param(self) = value + EPS;
+            let plus = self.loss();
+            *param(self) = value - EPS;
+            let minus = self.loss();
+            *param(self) = value;
+            return [(plus - loss) / EPS, (loss - minus) / EPS];
+        }
+    }
+
+    fn assert_close(analytic: f32, numeric: [f32; 2], what: &str) {
+        assert!(
+            numeric
+                .iter()
+                .any(|n| (analytic - n).abs() <= 1e-2 * analytic.abs().max(1.0)),
+            "{}: backward gives {}, finite differences {:?}",
+            what,
+            analytic,
+            numeric
+        );
+    }
+
+    /// Compares the kernel, bias and input gradients computed by
+    /// `ConvNet::backward` with finite differences of the loss.
+    fn check_gradients(width: u32, height: u32, channels: &[usize]) {
+        let mut case = Case::new(width, height, channels);
+        let (_, caches) = case.net.forward_train(case.x.view());
+        let (grads, d_input) = case.net.backward(&caches, case.weights.view(), true);
+        let d_input = d_input.unwrap();
+
+        for (layer, grad) in grads.iter().enumerate() {
+            assert!(
+                grad.kernel.iter().any(|&d| d != 0.0),
+                "block {} learns nothing",
+                layer
+            );
+            for (index, &analytic) in grad.kernel.iter().enumerate() {
+                let numeric = case.numeric(|case| {
+                    return &mut case.net.layers_mut()[layer].kernel.as_slice_mut().unwrap()[index];
+                });
+                assert_close(
+                    analytic,
+                    numeric,
+                    &format!("block {} kernel {}", layer, index),
+                );
+            }
+            for (index, &analytic) in grad.bias.iter().enumerate() {
+                let numeric = case.numeric(|case| {
+                    return &mut case.net.layers_mut()[layer].bias[index];
+                });
+                assert_close(
+                    analytic,
+                    numeric,
+                    &format!("block {} bias {}", layer, index),
+                );
+            }
+        }
+        assert!(d_input.iter().any(|&d| d != 0.0), "input gradient is zero");
+        for ((sample, index), &analytic) in d_input.indexed_iter() {
+            let numeric = case.numeric(|case| return &mut case.x[[sample, index]]);
+            assert_close(
+                analytic,
+                numeric,
+                &format!("sample {} input {}", sample, index),
+            );
+        }
+    }
+
+    #[test]
+    fn gradients_match_finite_differences_on_even_sizes() {
+        check_gradients(8, 6, &[2, 3]);
+    }
+
+    #[test]
+    fn gradients_match_finite_differences_on_odd_sizes() {
+        check_gradients(7, 9, &[3, 2]);
+    }
+
+    #[test]
+    fn gradients_match_finite_differences_through_three_blocks() {
+        check_gradients(11, 10, &[2, 2, 3]);
+    }
+}
@@ -0,0 +1,146 @@
+use super::config::TrainConfig;
+use super::dataset::Dataset;
+use super::error::Result;
+use super::model::Model;
+use super::source::DatasetSource;
+use super::trainer::FitReport;
+use super::trainer::Trainer;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+/// Result of training and evaluating on one cross-validation fold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoldResult {
+    /// Zero-based index of the held-out fold.
+    pub fold: usize,
+    /// Accuracy on the held-out fold.
+    pub accuracy: f32,
+    /// Macro-averaged F1 score on the held-out fold.
+    pub macro_f1: f32,
+    /// Training summary of the fold's model.
+    pub fit: FitReport,
+}
+
+/// Outcome of [`cross_validate`].
+#[derive(Debug, Clone)]
+pub struct CrossValidation {
+    /// Per-fold results, in fold order.
+    pub folds: Vec<FoldResult>,
+    /// Index into `folds` of the fold with the highest accuracy.
+    pub best_fold: usize,
+    /// The model trained for `best_fold`. Drop it if only the scores matter.
+    pub best_model: Model,
+    /// Whether a signal stopped the cross-validation, see
+    /// [`TrainConfig::handle_signals`]. `folds` then ends with the
+    /// interrupted fold, and the scores cover only the folds run.
+    pub interrupted: bool,
+}
+
+impl CrossValidation {
+    pub fn mean_accuracy(&self) -> f32 {
+        return mean(self.folds.iter().map(|fold| fold.accuracy));
+    }
+
+    /// Population standard deviation of the fold accuracies.
+    pub fn std_accuracy(&self) -> f32 {
+        return std(self.folds.iter().map(|fold| fold.accuracy));
+    }
+
+    pub fn mean_f1(&self) -> f32 {
+        return mean(self.folds.iter().map(|fold| fold.macro_f1));
+    }
+
+    /// Population standard deviation of the fold macro-F1 scores.
+    pub fn std_f1(&self) -> f32 {
+        return std(self.folds.iter().map(|fold| fold.macro_f1));
+    }
+}
+
+fn mean(values: impl ExactSizeIterator<Item = f32>) -> f32 {
+    let n = values.len() as f32;
+    return values.sum::<f32>() / n;
+}
+
+fn std(values: impl ExactSizeIterator<Item = f32> + Clone) -> f32 {
+    let mean_value = mean(values.clone());
+    return mean(values.map(|v| (v - mean_value) * (v - mean_value))).sqrt();
+}
+
+/// Estimates how well `config` generalizes with stratified k-fold cross-validation.
+///
+/// The dataset is split into `k` folds with [`Dataset::stratified_folds`]
+/// (seeded with `config.seed`). For every fold a fresh model is trained on
+/// the other `k - 1` folds, using the held-out fold for early stopping, and
+/// then scored on the held-out fold.
+///
+/// Checkpointing and metrics logging are disabled for the per-fold runs,
+/// since the folds would overwrite each other's files. An interrupted fold
+/// ends the cross-validation, see [`CrossValidation::interrupted`].
+pub fn cross_validate(
+    dataset: &Dataset,
+    k: usize,
+    config: &TrainConfig,
+) -> Result<CrossValidation> {
+    let fold_config = TrainConfig {
+        checkpoint_dir: None,
+        metrics_path: None,
+        tensorboard_dir: None,
+        ..config.clone()
+    };
+    let mut trainer = Trainer::new(fold_config);
+    let folds = dataset.stratified_folds(k, config.seed);
+
+    let mut results = Vec::<FoldResult>::with_capacity(k);
+    let mut best: Option<(usize, Model)> = None;
+    let mut interrupted = false;
+    for (fold, held_out) in folds.iter().enumerate() {
+        let train_indices: Vec<usize> = folds
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != fold)
+            .flat_map(|(_, indices)| indices.iter().copied())
+            .collect();
+        let train = dataset.subset(&train_indices);
+        let val = dataset.subset(held_out);
+
+        let mut rng = ChaCha8Rng::seed_from_u64(config.seed.wrapping_add(fold as u64));
+        let mut model = Model::from_rng(*dataset.preprocess(), dataset.num_classes(), &mut rng);
+        let fit = trainer.fit(&mut model, &train, &val)?;
+
+        let matrix = model.confusion_matrix(&val);
+        let result = FoldResult {
+            fold,
+            accuracy: matrix.accuracy(),
+            macro_f1: matrix.macro_f1(),
+            fit,
+        };
+        println!(
+            "fold {}/{}: acc={:.2}%, f1={:.4}",
+            fold + 1,
+            k,
+            result.accuracy * 100.0,
+            result.macro_f1
+        );
+
+        let is_best = match &best {
+            Some((best_fold, _)) => result.accuracy > results[*best_fold].accuracy,
+            None => true,
+        };
+        interrupted = result.fit.interrupted;
+        results.push(result);
+        if is_best {
+            best = Some((fold, model));
+        }
+        if interrupted {
+            break;
+        }
+    }
+
+    let (best_fold, best_model) = best.unwrap();
+    return Ok(CrossValidation {
+        folds: results,
+        best_fold,
+        best_model,
+        interrupted,
+    });
+}
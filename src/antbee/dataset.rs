@@ -1,51 +1,304 @@
+use super::codec;
+use super::config::DatasetConfig;
+use super::error::Error;
+use super::error::Result;
 use super::kind;
-use image::ImageReader;
-use image::imageops::FilterType;
-use image::imageops::resize;
+use super::kind::ClassMap;
+use super::mmap::Mmap;
+use super::preprocess::Preprocess;
+use super::source::DatasetSource;
+use half::f16;
+use image::ImageFormat;
 use ndarray::Array1;
+use ndarray::Array2;
+use ndarray::ArrayView1;
+use ndarray::ArrayViewMut1;
+use ndarray::Axis;
+use ndarray::CowArray;
+use ndarray::Ix1;
+use ndarray::Ix2;
+use rand::Rng;
+use rand::SeedableRng;
 use rand::prelude::SliceRandom;
 use rand::rng;
+use rand_chacha::ChaCha8Rng;
+use std::fs::File;
 use std::fs::read_dir;
+use std::io;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Seek;
+use std::io::Write;
+use std::ops::Range;
 use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
 
-pub struct Data {
+/// A single sample of a [`Dataset`], borrowed unless it had to be
+/// converted from half precision.
+#[derive(Clone)]
+pub struct Data<'a> {
     kind: kind::Kind,
-    data: Array1<f32>, // CHW flattened: 3*28*28 = 2352
+    data: CowArray<'a, f32, Ix1>, // CHW flattened: channels*height*width
 }
 
-impl Data {
+impl<'a> Data<'a> {
+    pub fn new(kind: kind::Kind, data: ArrayView1<'a, f32>) -> Self {
+        return Self {
+            kind,
+            data: data.into(),
+        };
+    }
+
     pub fn get_kind(&self) -> kind::Kind {
         return self.kind;
     }
 
-    pub fn get_data(&self) -> &Array1<f32> {
+    pub fn get_data(&self) -> ArrayView1<'_, f32> {
         // data is already flattened
-        return &self.data;
+        return self.data.view();
     }
 }
 
-pub struct Dataset {
-    values: Vec<Data>,
+/// Element type a [`Dataset`] stores its feature matrix in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Precision {
+    /// 32-bit floats, exactly as preprocessed.
+    #[default]
+    F32,
+    /// 16-bit floats, half the memory. Features in [0, 1] keep about three
+    /// significant digits, finer than the 1/255 steps of 8-bit images;
+    /// rows are converted back to `f32` whenever the model reads them.
+    F16,
 }
 
-impl Dataset {
-    fn jpg_to_chw(path: &Path) -> Array1<f32> {
-        let rgb = ImageReader::open(path).unwrap().decode().unwrap().to_rgb8();
-        let resized = resize(&rgb, 28, 28, FilterType::Lanczos3);
+/// Feature matrix of a [`Dataset`] in its storage precision.
+#[derive(Debug, Clone)]
+enum Features {
+    F32(Array2<f32>),
+    F16(Array2<f16>),
+    /// Rows of a memory-mapped cache file, see [`Dataset::map_cache`].
+    Mapped(MappedFeatures),
+}
 
-        let mut data = Vec::<f32>::with_capacity(28 * 28 * 3);
+/// A feature matrix left in a memory-mapped cache file, whose rows are
+/// decoded to `f32` whenever they are read.
+#[derive(Debug, Clone)]
+struct MappedFeatures {
+    map: Arc<Mmap>,
+    /// Byte offset of the first row in the file.
+    offset: usize,
+    dim: usize,
+    precision: Precision,
+    /// Row of the file holding every sample, by index; subsets and
+    /// shuffles only rearrange these.
+    rows: Vec<usize>,
+}
 
-        for pixel in resized.pixels() {
-            data.push(pixel[0] as f32 / 255.0);
-        }
-        for pixel in resized.pixels() {
-            data.push(pixel[1] as f32 / 255.0);
+impl MappedFeatures {
+    /// Bytes of the file row holding sample `index`.
+    fn row_bytes(&self, index: usize) -> &[u8] {
+        let size = self.dim * Self::width(self.precision);
+        let start = self.offset + self.rows[index] * size;
+        return &self.map.bytes()[start..start + size];
+    }
+
+    /// Bytes per value stored in `precision`.
+    fn width(precision: Precision) -> usize {
+        return match precision {
+            Precision::F32 => 4,
+            Precision::F16 => 2,
+        };
+    }
+
+    fn decode_into(&self, index: usize, mut out: ArrayViewMut1<f32>) {
+        let bytes = self.row_bytes(index);
+        match self.precision {
+            Precision::F32 => {
+                for (x, chunk) in out.iter_mut().zip(bytes.chunks_exact(4)) {
+                    *x = f32::from_le_bytes(chunk.try_into().unwrap());
+                }
+            }
+            Precision::F16 => {
+                for (x, chunk) in out.iter_mut().zip(bytes.chunks_exact(2)) {
+                    *x = f16::from_le_bytes(chunk.try_into().unwrap()).to_f32();
+                }
+            }
         }
-        for pixel in resized.pixels() {
-            data.push(pixel[2] as f32 / 255.0);
+    }
+
+    /// The samples at `indices`, decoded to `f32`.
+    fn decode(&self, indices: impl ExactSizeIterator<Item = usize>) -> Array2<f32> {
+        let mut rows = Array2::<f32>::zeros((indices.len(), self.dim));
+        for (row, index) in rows.axis_iter_mut(Axis(0)).zip(indices) {
+            self.decode_into(index, row);
         }
+        return rows;
+    }
+
+    /// Reads all samples into memory, in the stored precision.
+    fn load(&self) -> Features {
+        return match self.precision {
+            Precision::F32 => Features::F32(self.decode(0..self.rows.len())),
+            Precision::F16 => {
+                let mut values = Vec::with_capacity(self.rows.len() * self.dim);
+                for index in 0..self.rows.len() {
+                    values.extend(
+                        self.row_bytes(index)
+                            .chunks_exact(2)
+                            .map(|chunk| f16::from_le_bytes(chunk.try_into().unwrap())),
+                    );
+                }
+                Features::F16(Array2::from_shape_vec((self.rows.len(), self.dim), values).unwrap())
+            }
+        };
+    }
+}
+
+impl Features {
+    fn nrows(&self) -> usize {
+        return match self {
+            Features::F32(features) => features.nrows(),
+            Features::F16(features) => features.nrows(),
+            Features::Mapped(features) => features.rows.len(),
+        };
+    }
+
+    fn ncols(&self) -> usize {
+        return match self {
+            Features::F32(features) => features.ncols(),
+            Features::F16(features) => features.ncols(),
+            Features::Mapped(features) => features.dim,
+        };
+    }
+
+    fn precision(&self) -> Precision {
+        return match self {
+            Features::F32(_) => Precision::F32,
+            Features::F16(_) => Precision::F16,
+            Features::Mapped(features) => features.precision,
+        };
+    }
+
+    /// Converts the matrix to `precision`, rounding to nearest for `F16`.
+    /// A mapped matrix is read into memory unless it already has `precision`.
+    fn to_precision(&self, precision: Precision) -> Self {
+        return match (self, precision) {
+            (Features::Mapped(features), precision) if precision != features.precision => {
+                features.load().to_precision(precision)
+            }
+            (Features::F32(features), Precision::F16) => {
+                Features::F16(features.mapv(f16::from_f32))
+            }
+            (Features::F16(features), Precision::F32) => Features::F32(features.mapv(f16::to_f32)),
+            (features, _) => features.clone(),
+        };
+    }
+
+    fn row(&self, index: usize) -> CowArray<'_, f32, Ix1> {
+        return match self {
+            Features::F32(features) => features.row(index).into(),
+            Features::F16(features) => features.row(index).mapv(f16::to_f32).into(),
+            Features::Mapped(features) => {
+                let mut row = Array1::<f32>::zeros(features.dim);
+                features.decode_into(index, row.view_mut());
+                row.into()
+            }
+        };
+    }
+
+    fn rows(&self, range: Range<usize>) -> CowArray<'_, f32, Ix2> {
+        return match self {
+            Features::F32(features) => features.slice(ndarray::s![range, ..]).into(),
+            Features::F16(features) => features
+                .slice(ndarray::s![range, ..])
+                .mapv(f16::to_f32)
+                .into(),
+            Features::Mapped(features) => features.decode(range).into(),
+        };
+    }
+
+    /// The rows at `indices`, in the same precision.
+    fn select(&self, indices: &[usize]) -> Self {
+        return match self {
+            Features::F32(features) => Features::F32(features.select(Axis(0), indices)),
+            Features::F16(features) => Features::F16(features.select(Axis(0), indices)),
+            Features::Mapped(features) => Features::Mapped(MappedFeatures {
+                map: features.map.clone(),
+                offset: features.offset,
+                dim: features.dim,
+                precision: features.precision,
+                rows: indices.iter().map(|&i| features.rows[i]).collect(),
+            }),
+        };
+    }
+
+    /// The rows at `indices`, converted to `f32`.
+    fn select_f32(&self, indices: &[usize]) -> Array2<f32> {
+        return match self {
+            Features::F32(features) => features.select(Axis(0), indices),
+            Features::F16(features) => {
+                let mut rows = Array2::<f32>::zeros((indices.len(), features.ncols()));
+                for (mut row, &index) in rows.axis_iter_mut(Axis(0)).zip(indices) {
+                    row.zip_mut_with(&features.row(index), |x, &h| *x = h.to_f32());
+                }
+                rows
+            }
+            Features::Mapped(features) => features.decode(indices.iter().copied()),
+        };
+    }
+}
 
-        return Array1::from_vec(data);
+pub struct Dataset {
+    /// One row per sample, shape (len, preprocess.input_dim()).
+    /// Keeping all samples in one matrix lets the model process whole
+    /// batches with a single matrix product.
+    features: Features,
+    labels: Vec<kind::Kind>,
+    /// Image file of every sample, if the dataset was loaded from images.
+    paths: Option<Vec<PathBuf>>,
+    classes: ClassMap,
+    preprocess: Preprocess,
+}
+
+impl Dataset {
+    /// Magic bytes at the start of a dataset cache file.
+    const CACHE_MAGIC: &'static [u8; 8] = b"ANTBEEDS";
+
+    /// Current version of the dataset cache format.
+    ///
+    /// Version 1 predates configurable preprocessing and implies
+    /// `Preprocess::default()`; version 2 predates [`ResizeMode`](super::ResizeMode)
+    /// and always stretched; versions before 4 always store `f32` features;
+    /// versions before 5 store no source paths.
+    const CACHE_VERSION: u32 = 5;
+
+    /// Loads the image at `path`, naming it in the error if it cannot be
+    /// read or decoded. I/O errors stay [`Error::Io`]; the others become
+    /// [`Error::InvalidFormat`].
+    fn image_to_chw(path: &Path, preprocess: &Preprocess) -> Result<Array1<f32>> {
+        return preprocess.load_image(path).map_err(|err| match err {
+            Error::Io(err) => Error::Io(io::Error::new(
+                err.kind(),
+                format!("{}: {}", path.display(), err),
+            )),
+            err => Error::InvalidFormat(format!("{}: {}", path.display(), err)),
+        });
+    }
+
+    /// Returns whether `path` is an image file the dataset should load.
+    ///
+    /// Hidden files (starting with `.`, e.g. `.DS_Store`), directories and
+    /// files whose extension is not a decodable image format are skipped.
+    pub(crate) fn is_image_file(path: &Path) -> bool {
+        let is_hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if is_hidden || !path.is_file() {
+            return false;
+        }
+        return ImageFormat::from_path(path).is_ok_and(|format| format.reading_enabled());
     }
 
     #[cfg(debug_assertions)]
@@ -54,44 +307,598 @@ impl Dataset {
         debug_assert!(path.is_dir(), "Dataset path is not a directory");
     }
 
-    pub fn from_dataset_path(paths: &Path) -> Self {
-        #[cfg(debug_assertions)]
-        Self::assert_is_valid_dir(paths);
+    /// Lists the class subdirectories of `path`, sorted by name.
+    ///
+    /// Hidden directories (starting with `.`) and plain files are ignored.
+    fn class_dirs(path: &Path) -> Vec<(String, PathBuf)> {
+        let mut dirs = Vec::new();
+        for entry in read_dir(path).unwrap() {
+            let entry_path = entry.unwrap().path();
+            if !entry_path.is_dir() {
+                continue;
+            }
+            let name = entry_path
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned();
+            if name.starts_with('.') {
+                continue;
+            }
+            dirs.push((name, entry_path));
+        }
+        dirs.sort();
+        return dirs;
+    }
 
-        let ants_dir = paths.join("ants");
-        let bees_dir = paths.join("bees");
+    /// Lists the images of an ImageFolder-style dataset at `path`.
+    ///
+    /// Without `classes`, every class directory becomes a class, in sorted
+    /// order. With it, every directory named in the map gets that class's
+    /// label and the other directories are skipped.
+    ///
+    /// Returns the class map and every image path with its label, class by
+    /// class. Within a class, images are in file name order if `sorted`,
+    /// and in directory listing order otherwise.
+    pub(crate) fn list_images(
+        path: &Path,
+        classes: Option<&ClassMap>,
+        sorted: bool,
+    ) -> (ClassMap, Vec<(kind::Kind, PathBuf)>) {
         #[cfg(debug_assertions)]
-        {
-            Self::assert_is_valid_dir(&ants_dir);
-            Self::assert_is_valid_dir(&bees_dir);
+        Self::assert_is_valid_dir(path);
+
+        let mut class_dirs = Self::class_dirs(path);
+        let classes = match classes {
+            Some(classes) => {
+                class_dirs.retain(|(name, _)| classes.kind(name).is_some());
+                classes.clone()
+            }
+            None => ClassMap::new(class_dirs.iter().map(|(name, _)| name.clone()).collect()),
+        };
+        debug_assert!(
+            classes.len() >= 2,
+            "Dataset path must contain at least two class directories"
+        );
+
+        let mut images = Vec::new();
+        for (name, dir) in class_dirs {
+            let kind = classes.kind(&name).unwrap();
+            let mut class_images = Vec::new();
+            for img_path in read_dir(dir).unwrap() {
+                let img_path = img_path.unwrap().path();
+                if Self::is_image_file(&img_path) {
+                    class_images.push(img_path);
+                }
+            }
+            if sorted {
+                class_images.sort();
+            }
+            images.extend(class_images.into_iter().map(|img_path| (kind, img_path)));
         }
+        return (classes, images);
+    }
 
-        let mut values = Vec::<Data>::new();
+    /// Loads an ImageFolder-style dataset: every subdirectory of `paths`
+    /// is a class, named after the directory, and contains that class's images.
+    ///
+    /// Classes are indexed in sorted directory-name order, so datasets with the
+    /// same set of class directories always agree on their labels.
+    /// Any image format the `image` crate can decode (JPEG, PNG, BMP, WebP,
+    /// ...) may be mixed within a class; other files are ignored.
+    ///
+    /// Samples are ordered as set by [`DatasetConfig::default`]: the same
+    /// folder gives the same order on every machine.
+    ///
+    /// # Errors
+    /// Fails on the first image that cannot be read or decoded, naming its
+    /// path; [`Dataset::stats`] lists every such file instead.
+    pub fn from_dataset_path(paths: &Path) -> Result<Self> {
+        return Self::from_config(paths, &DatasetConfig::default());
+    }
 
-        for ant_img_path in read_dir(ants_dir).unwrap() {
-            let origin_img = Self::jpg_to_chw(&ant_img_path.unwrap().path());
-            values.push(Data {
-                kind: kind::Kind::Ant,
-                data: origin_img,
-            });
+    /// Like [`Dataset::from_dataset_path`], preprocessing every image with `preprocess`.
+    pub fn from_dataset_path_with(paths: &Path, preprocess: Preprocess) -> Result<Self> {
+        let config = DatasetConfig {
+            preprocess,
+            ..DatasetConfig::default()
+        };
+        return Self::from_config(paths, &config);
+    }
+
+    /// Loads the ImageFolder-style dataset at `paths` as described by `config`.
+    pub fn from_config(paths: &Path, config: &DatasetConfig) -> Result<Self> {
+        let (classes, images) = Self::list_images(paths, config.classes.as_ref(), config.sorted);
+        return Self::from_listing(classes, images, config);
+    }
+
+    /// Shuffles the listed `images` as set by `config.shuffle_seed` and
+    /// decodes them into a dataset.
+    pub(crate) fn from_listing(
+        classes: ClassMap,
+        mut images: Vec<(kind::Kind, PathBuf)>,
+        config: &DatasetConfig,
+    ) -> Result<Self> {
+        match config.shuffle_seed {
+            Some(seed) => images.shuffle(&mut ChaCha8Rng::seed_from_u64(seed)),
+            None => images.shuffle(&mut rng()),
         }
-        for bee_img_path in read_dir(bees_dir).unwrap() {
-            let origin_img = Self::jpg_to_chw(&bee_img_path.unwrap().path());
-            values.push(Data {
-                kind: kind::Kind::Bee,
-                data: origin_img,
-            });
+        return Self::load_images(classes, images, config.preprocess);
+    }
+
+    /// Like [`Dataset::from_dataset_path_with`], labeling the directories
+    /// with the user-defined `classes` instead of their sorted order.
+    ///
+    /// Only the directories named in `classes` are loaded; classes without
+    /// a directory simply have no samples. Loading every split with the
+    /// same map, e.g. the [`Model::class_map`](super::Model::class_map) of
+    /// a trained model, guarantees they agree on the labels.
+    pub fn from_dataset_path_mapped(
+        paths: &Path,
+        preprocess: Preprocess,
+        classes: &ClassMap,
+    ) -> Result<Self> {
+        let config = DatasetConfig {
+            preprocess,
+            classes: Some(classes.clone()),
+            ..DatasetConfig::default()
+        };
+        return Self::from_config(paths, &config);
+    }
+
+    /// Decodes `images` into a dataset, keeping their order.
+    fn load_images(
+        classes: ClassMap,
+        images: Vec<(kind::Kind, PathBuf)>,
+        preprocess: Preprocess,
+    ) -> Result<Self> {
+        let values = images
+            .iter()
+            .map(|(_, path)| Self::image_to_chw(path, &preprocess))
+            .collect::<Result<Vec<Array1<f32>>>>()?;
+
+        let dim = preprocess.input_dim();
+        let mut features = Array2::<f32>::zeros((values.len(), dim));
+        for (mut row, data) in features.axis_iter_mut(Axis(0)).zip(values) {
+            row.assign(&data);
         }
+        let (labels, paths) = images.into_iter().unzip();
+        return Ok(Self {
+            features: Features::F32(features),
+            labels,
+            paths: Some(paths),
+            classes,
+            preprocess,
+        });
+    }
+
+    /// Assembles a dataset from already preprocessed rows and, if known,
+    /// the image file of every row.
+    pub(crate) fn from_parts(
+        features: Array2<f32>,
+        labels: Vec<kind::Kind>,
+        paths: Option<Vec<PathBuf>>,
+        classes: ClassMap,
+        preprocess: Preprocess,
+    ) -> Self {
+        debug_assert_eq!(features.nrows(), labels.len());
+        debug_assert_eq!(features.ncols(), preprocess.input_dim());
+        debug_assert!(
+            paths
+                .as_ref()
+                .is_none_or(|paths| paths.len() == labels.len())
+        );
+        return Self {
+            features: Features::F32(features),
+            labels,
+            paths,
+            classes,
+            preprocess,
+        };
+    }
 
-        values.shuffle(&mut rng());
-        return Self { values };
+    /// Precision the features are stored in.
+    pub fn precision(&self) -> Precision {
+        return self.features.precision();
     }
 
-    pub fn get_values(&self) -> &Vec<Data> {
-        return &self.values;
+    /// Returns the dataset with its features stored in `precision`.
+    ///
+    /// [`Precision::F16`] halves the memory of the feature matrix, which
+    /// dominates for larger images; samples are converted back to `f32`
+    /// batch by batch as the model reads them, so training and evaluation
+    /// work unchanged.
+    ///
+    /// Typically chained onto loading, e.g.
+    /// `Dataset::from_dataset_path(path)?.with_precision(Precision::F16)`.
+    pub fn with_precision(self, precision: Precision) -> Self {
+        if precision == self.precision() {
+            return self;
+        }
+        return Self {
+            features: self.features.to_precision(precision),
+            ..self
+        };
+    }
+
+    /// Image file the sample at `index` was loaded from.
+    ///
+    /// `None` for datasets assembled from tensors rather than loaded from
+    /// an image folder, including caches written before paths were stored.
+    pub fn path(&self, index: usize) -> Option<&Path> {
+        return self.paths.as_ref().map(|paths| paths[index].as_path());
+    }
+
+    /// Returns the sample at `index`.
+    pub fn get(&self, index: usize) -> Data<'_> {
+        return Data {
+            kind: self.labels[index],
+            data: self.features.row(index),
+        };
+    }
+
+    /// Iterates over all samples in storage order.
+    pub fn iter(&self) -> impl Iterator<Item = Data<'_>> {
+        return (0..self.len()).map(|index| self.get(index));
+    }
+
+    /// Randomly permutes the stored samples in place.
+    pub fn shuffle(&mut self, rng: &mut impl Rng) {
+        let indices = self.shuffled_indices(rng);
+        self.features = self.features.select(&indices);
+        self.labels = indices.iter().map(|&i| self.labels[i]).collect();
+        self.paths = self.select_paths(&indices);
+    }
+
+    /// Builds a new dataset from the samples at `indices`, in that order.
+    ///
+    /// Indices may repeat; the class list and preprocessing are kept.
+    pub fn subset(&self, indices: &[usize]) -> Self {
+        return Self {
+            features: self.features.select(indices),
+            labels: indices.iter().map(|&i| self.labels[i]).collect(),
+            paths: self.select_paths(indices),
+            classes: self.classes.clone(),
+            preprocess: self.preprocess,
+        };
+    }
+
+    /// The source paths of the samples at `indices`, if known.
+    fn select_paths(&self, indices: &[usize]) -> Option<Vec<PathBuf>> {
+        return self
+            .paths
+            .as_ref()
+            .map(|paths| indices.iter().map(|&i| paths[i].clone()).collect());
+    }
+
+    /// Splits the dataset into two parts while preserving class proportions.
+    ///
+    /// From every class, a fraction `ratio` of its samples (rounded to the
+    /// nearest integer) goes to the first dataset and the rest to the second.
+    /// Which samples land where is decided by `seed`, so the split is
+    /// reproducible; both parts are shuffled.
+    ///
+    /// Typically used as `let (train, val) = dataset.split(0.8, seed);`.
+    pub fn split(&self, ratio: f32, seed: u64) -> (Self, Self) {
+        let (first, second) = stratified_split(self, ratio, seed);
+        return (self.subset(&first), self.subset(&second));
+    }
+
+    /// Partitions the sample indices into `k` folds with matching class proportions.
+    ///
+    /// The samples of every class are shuffled with `seed` and dealt to the
+    /// folds in turn, so fold sizes differ by at most one per class.
+    pub fn stratified_folds(&self, k: usize, seed: u64) -> Vec<Vec<usize>> {
+        assert!(k >= 2, "need at least 2 folds");
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+        let mut by_class = vec![Vec::<usize>::new(); self.classes.len()];
+        for (index, kind) in self.labels.iter().enumerate() {
+            by_class[kind.index()].push(index);
+        }
+
+        let mut folds = vec![Vec::<usize>::new(); k];
+        let mut next = 0;
+        for mut indices in by_class {
+            indices.shuffle(&mut rng);
+            for index in indices {
+                folds[next].push(index);
+                next = (next + 1) % k;
+            }
+        }
+        return folds;
+    }
+
+    /// Feature matrix with one sample per row, converted to `f32` if the
+    /// dataset is stored in half precision.
+    pub fn features(&self) -> CowArray<'_, f32, Ix2> {
+        return self.features.rows(0..self.len());
+    }
+
+    /// Writes the preprocessed samples, labels, class names and source
+    /// paths to `path`.
+    ///
+    /// The cache stores the already resized and normalized tensors in their
+    /// [`Precision`], so [`Dataset::from_cache`] skips image decoding entirely.
+    pub fn to_cache(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        codec::write_header(&mut writer, Self::CACHE_MAGIC, Self::CACHE_VERSION)?;
+        self.preprocess.write_to(&mut writer)?;
+
+        self.classes.write_to(&mut writer)?;
+
+        codec::write_u64(&mut writer, self.features.nrows() as u64)?;
+        codec::write_u64(&mut writer, self.features.ncols() as u64)?;
+        for kind in &self.labels {
+            codec::write_u32(&mut writer, kind.index() as u32)?;
+        }
+        match &self.paths {
+            Some(paths) => {
+                codec::write_u32(&mut writer, 1)?;
+                for path in paths {
+                    codec::write_str(&mut writer, &path.to_string_lossy())?;
+                }
+            }
+            None => codec::write_u32(&mut writer, 0)?,
+        }
+        match &self.features {
+            Features::F32(features) => {
+                codec::write_u32(&mut writer, 32)?;
+                codec::write_f32s(&mut writer, features.iter())?;
+            }
+            Features::F16(features) => {
+                codec::write_u32(&mut writer, 16)?;
+                codec::write_f16s(&mut writer, features.iter())?;
+            }
+            Features::Mapped(features) => {
+                // Copied row by row in the stored encoding, never all in memory.
+                let bits = MappedFeatures::width(features.precision) as u32 * 8;
+                codec::write_u32(&mut writer, bits)?;
+                codec::write_u64(&mut writer, (self.len() * features.dim) as u64)?;
+                for index in 0..self.len() {
+                    writer.write_all(features.row_bytes(index))?;
+                }
+            }
+        }
+
+        writer.flush()?;
+        return Ok(());
+    }
+
+    /// Loads a dataset written by [`Dataset::to_cache`].
+    ///
+    /// Samples keep the order they had when the cache was written.
+    pub fn from_cache(path: &Path) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let header = CacheHeader::read_from(&mut reader)?;
+        let (len, dim) = (header.labels.len(), header.preprocess.input_dim());
+        let features = match header.precision {
+            Precision::F32 => {
+                let features = codec::read_f32s(&mut reader, len * dim)?;
+                Features::F32(Array2::from_shape_vec((len, dim), features).unwrap())
+            }
+            Precision::F16 => {
+                let features = codec::read_f16s(&mut reader, len * dim)?;
+                Features::F16(Array2::from_shape_vec((len, dim), features).unwrap())
+            }
+        };
+        return Ok(header.into_dataset(features));
+    }
+
+    /// Opens a dataset written by [`Dataset::to_cache`] without reading its
+    /// features into memory.
+    ///
+    /// The feature matrix stays in the file, which is memory-mapped: rows
+    /// are decoded as batches ask for them, and the operating system pages
+    /// the file in and out as needed, so datasets far larger than RAM can be
+    /// trained on. Only labels, paths and the class list are loaded. The
+    /// dataset behaves exactly like one loaded with [`Dataset::from_cache`];
+    /// splits, subsets and shuffles keep referring to the file, while
+    /// [`Dataset::features`] and a change of [`Dataset::with_precision`]
+    /// read all samples into memory.
+    ///
+    /// The cache file must not be modified or rewritten while the dataset
+    /// or any subset of it is alive. On platforms other than Unix the file
+    /// is read into memory instead.
+    pub fn map_cache(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        let mut reader = BufReader::new(&file);
+        let header = CacheHeader::read_from(&mut reader)?;
+        let (len, dim) = (header.labels.len(), header.preprocess.input_dim());
+        let values = codec::size(&[len, dim])?;
+        let count = codec::read_u64(&mut reader)?;
+        if count != values as u64 {
+            return Err(Error::InvalidFormat(format!(
+                "expected {} values, found {}",
+                values, count
+            )));
+        }
+        let offset = usize::try_from(reader.stream_position()?)
+            .map_err(|_| Error::InvalidFormat("cache header too large".to_string()))?;
+        let map = Mmap::map(&file)?;
+        // Rows are sliced from the map by these bounds, so they must not wrap.
+        let size = codec::size(&[values, MappedFeatures::width(header.precision)])?;
+        let end = offset.checked_add(size).ok_or_else(|| {
+            return Error::InvalidFormat(format!("{} feature bytes overflow", size));
+        })?;
+        if map.bytes().len() < end {
+            return Err(Error::InvalidFormat(format!(
+                "cache holds {} of {} feature bytes",
+                map.bytes().len().saturating_sub(offset),
+                size
+            )));
+        }
+        let features = Features::Mapped(MappedFeatures {
+            map: Arc::new(map),
+            offset,
+            dim,
+            precision: header.precision,
+            rows: (0..len).collect(),
+        });
+        return Ok(header.into_dataset(features));
+    }
+
+    /// Whether the features are read from a memory-mapped cache file, see
+    /// [`Dataset::map_cache`], rather than held in memory.
+    pub fn is_mapped(&self) -> bool {
+        return matches!(self.features, Features::Mapped(_));
+    }
+}
+
+/// Everything of a dataset cache file in front of the features.
+struct CacheHeader {
+    preprocess: Preprocess,
+    classes: ClassMap,
+    labels: Vec<kind::Kind>,
+    paths: Option<Vec<PathBuf>>,
+    precision: Precision,
+}
+
+impl CacheHeader {
+    /// Reads the header of a cache written by [`Dataset::to_cache`], leaving
+    /// `reader` at the feature sequence.
+    fn read_from(reader: &mut impl Read) -> Result<Self> {
+        let version = codec::read_header(reader, Dataset::CACHE_MAGIC, Dataset::CACHE_VERSION)?;
+        let preprocess = match version {
+            1 => Preprocess::default(),
+            2 => Preprocess::read_without_resize(reader)?,
+            _ => Preprocess::read_from(reader)?,
+        };
+
+        let classes = ClassMap::read_from(reader)?;
+        let num_classes = classes.len();
+
+        let len = codec::read_u64(reader)?;
+        let dim = codec::read_u64(reader)?;
+        if dim != preprocess.input_dim() as u64 {
+            return Err(Error::InvalidFormat(format!(
+                "sample size {} does not match preprocessing {}",
+                dim, preprocess
+            )));
+        }
+        let len = usize::try_from(len)
+            .map_err(|_| Error::InvalidFormat(format!("{} samples overflow", len)))?;
+        // Readers size the feature matrix as len x dim.
+        codec::size(&[len, preprocess.input_dim()])?;
+        // The count comes from the file, so the vectors grow with the labels
+        // and paths actually read instead of reserving it up front.
+        let mut labels = Vec::with_capacity(len.min(1024));
+        for _ in 0..len {
+            let index = codec::read_u32(reader)? as usize;
+            if index >= num_classes {
+                return Err(Error::InvalidFormat(format!(
+                    "label {} out of range for {} classes",
+                    index, num_classes
+                )));
+            }
+            labels.push(kind::Kind(index));
+        }
+        let has_paths = match version {
+            1..=4 => false,
+            _ => codec::read_u32(reader)? != 0,
+        };
+        let paths = if has_paths {
+            let mut paths = Vec::with_capacity(len.min(1024));
+            for _ in 0..len {
+                paths.push(PathBuf::from(codec::read_str(reader)?));
+            }
+            Some(paths)
+        } else {
+            None
+        };
+        let bits = match version {
+            1..=3 => 32,
+            _ => codec::read_u32(reader)?,
+        };
+        let precision = match bits {
+            32 => Precision::F32,
+            16 => Precision::F16,
+            other => {
+                return Err(Error::InvalidFormat(format!(
+                    "unsupported feature precision of {} bits",
+                    other
+                )));
+            }
+        };
+        return Ok(Self {
+            preprocess,
+            classes,
+            labels,
+            paths,
+            precision,
+        });
+    }
+
+    fn into_dataset(self, features: Features) -> Dataset {
+        return Dataset {
+            features,
+            labels: self.labels,
+            paths: self.paths,
+            classes: self.classes,
+            preprocess: self.preprocess,
+        };
+    }
+}
+
+impl DatasetSource for Dataset {
+    fn len(&self) -> usize {
+        return self.labels.len();
+    }
+
+    fn class_map(&self) -> &ClassMap {
+        return &self.classes;
+    }
+
+    fn preprocess(&self) -> &Preprocess {
+        return &self.preprocess;
+    }
+
+    /// Labels, aligned with the rows of [`Dataset::features`].
+    fn labels(&self) -> &[kind::Kind] {
+        return &self.labels;
+    }
+
+    fn sample(&self, index: usize) -> CowArray<'_, f32, Ix1> {
+        return self.features.row(index);
+    }
+
+    fn batch(&self, range: Range<usize>) -> CowArray<'_, f32, Ix2> {
+        return self.features.rows(range);
+    }
+
+    fn select(&self, indices: &[usize]) -> Array2<f32> {
+        return self.features.select_f32(indices);
+    }
+}
+
+/// Splits the sample indices of `source` into two stratified parts.
+///
+/// See [`Dataset::split`]; shared with [`LazyDataset::split`](super::LazyDataset::split).
+pub(crate) fn stratified_split(
+    source: &impl DatasetSource,
+    ratio: f32,
+    seed: u64,
+) -> (Vec<usize>, Vec<usize>) {
+    assert!(
+        (0.0..=1.0).contains(&ratio),
+        "split ratio must be in [0, 1]"
+    );
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+    let mut by_class = vec![Vec::<usize>::new(); source.num_classes()];
+    for (index, kind) in source.labels().iter().enumerate() {
+        by_class[kind.index()].push(index);
     }
 
-    pub fn len(&self) -> usize {
-        self.values.len()
+    let mut first = Vec::new();
+    let mut second = Vec::new();
+    for mut indices in by_class {
+        indices.shuffle(&mut rng);
+        let take = (indices.len() as f32 * ratio).round() as usize;
+        first.extend_from_slice(&indices[..take]);
+        second.extend_from_slice(&indices[take..]);
     }
+    first.shuffle(&mut rng);
+    second.shuffle(&mut rng);
+    return (first, second);
 }
@@ -0,0 +1,273 @@
+//! Finding exact and near-duplicate images, which otherwise leak between
+//! the training and evaluation splits.
+
+use super::dataset::Dataset;
+use super::error::Error;
+use super::error::Result;
+use super::preprocess::ChannelMode;
+use super::source::DatasetSource;
+use rayon::prelude::*;
+use std::fs::read;
+use std::hash::DefaultHasher;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Width and height of the grid a difference hash compares: each of the 8
+/// rows gives 8 bits, one per pair of neighboring cells.
+const GRID_WIDTH: usize = 9;
+const GRID_HEIGHT: usize = 8;
+
+/// Images that are copies of each other, see [`Dataset::duplicates`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    /// Positions of the images, ascending, at least two.
+    pub indices: Vec<usize>,
+    /// Whether every image is identical to the first one. Otherwise some
+    /// only look alike, e.g. after re-encoding or resizing.
+    pub exact: bool,
+}
+
+impl DuplicateGroup {
+    /// Largest number of differing perceptual hash bits (out of 64) at which
+    /// two images still count as near-duplicates by default. Recompressed
+    /// and slightly resized copies typically differ in a few bits, unrelated
+    /// images in about 32.
+    pub const DEFAULT_MAX_DISTANCE: u32 = 4;
+}
+
+/// Hashes of one image: of its exact contents, and a perceptual hash that
+/// changes little when the image is re-encoded or resized.
+#[derive(Debug, Clone, Copy)]
+struct Fingerprint {
+    exact: u64,
+    perceptual: u64,
+}
+
+/// Computes the difference hash (dHash) of a grayscale image given as
+/// `pixel(x, y)`: the image is averaged down to a 9x8 grid and every bit
+/// tells whether a cell is darker than its right neighbor.
+fn difference_hash(width: usize, height: usize, pixel: impl Fn(usize, usize) -> f32) -> u64 {
+    // Cell i along an axis of `size` pixels covers [i * size / n, (i + 1) * size / n),
+    // and at least one pixel when the image is smaller than the grid.
+    let span = |i: usize, size: usize, n: usize| {
+        let start = (i * size / n).min(size - 1);
+        return start..((i + 1) * size / n).max(start + 1);
+    };
+    let mut grid = [[0.0f32; GRID_WIDTH]; GRID_HEIGHT];
+    for (v, row) in grid.iter_mut().enumerate() {
+        let rows = span(v, height, GRID_HEIGHT);
+        for (u, cell) in row.iter_mut().enumerate() {
+            let columns = span(u, width, GRID_WIDTH);
+            let mut sum = 0.0;
+            for y in rows.clone() {
+                for x in columns.clone() {
+                    sum += pixel(x, y);
+                }
+            }
+            *cell = sum / (rows.len() * columns.len()) as f32;
+        }
+    }
+    let mut hash = 0u64;
+    for row in &grid {
+        for pair in row.windows(2) {
+            hash = hash << 1 | (pair[0] < pair[1]) as u64;
+        }
+    }
+    return hash;
+}
+
+/// Groups the images whose fingerprints match exactly or whose perceptual
+/// hashes differ in at most `max_distance` bits.
+///
+/// Matches are chained: if A is close to B and B to C, all three form one
+/// group even when A and C are further apart.
+fn group(fingerprints: &[Fingerprint], max_distance: u32) -> Vec<DuplicateGroup> {
+    // Union-find over the image indices, each root the smallest index of its set.
+    let mut parent: Vec<usize> = (0..fingerprints.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        return i;
+    }
+    for i in 0..fingerprints.len() {
+        for j in i + 1..fingerprints.len() {
+            let (a, b) = (fingerprints[i], fingerprints[j]);
+            if a.exact == b.exact || (a.perceptual ^ b.perceptual).count_ones() <= max_distance {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+    }
+    let mut groups = Vec::<DuplicateGroup>::new();
+    let mut group_of = vec![usize::MAX; fingerprints.len()];
+    for i in 0..fingerprints.len() {
+        let r = root(&mut parent, i);
+        if r == i {
+            continue;
+        }
+        if group_of[r] == usize::MAX {
+            group_of[r] = groups.len();
+            groups.push(DuplicateGroup {
+                indices: vec![r],
+                exact: true,
+            });
+        }
+        let group = &mut groups[group_of[r]];
+        group.indices.push(i);
+        group.exact &= fingerprints[i].exact == fingerprints[r].exact;
+    }
+    groups.sort_by_key(|group| group.indices[0]);
+    return groups;
+}
+
+impl Dataset {
+    /// Finds the samples that are exact or near-duplicates of each other.
+    ///
+    /// Samples are compared after preprocessing: exactly by their features,
+    /// and approximately by a difference hash of the grayscale sample, with
+    /// at most `max_distance` differing bits (see
+    /// [`DuplicateGroup::DEFAULT_MAX_DISTANCE`]). Labels are ignored, so a
+    /// group may mix classes, which usually means a mislabeled copy.
+    ///
+    /// # Returns
+    /// The groups of duplicate sample indices, ordered by their first index.
+    pub fn duplicates(&self, max_distance: u32) -> Vec<DuplicateGroup> {
+        let preprocess = self.preprocess();
+        let (width, height) = (preprocess.width as usize, preprocess.height as usize);
+        let plane = width * height;
+        let fingerprints: Vec<Fingerprint> = (0..self.len())
+            .into_par_iter()
+            .map(|index| {
+                let sample = self.sample(index);
+                let mut hasher = DefaultHasher::new();
+                for value in sample.iter() {
+                    value.to_bits().hash(&mut hasher);
+                }
+                let perceptual = difference_hash(width, height, |x, y| {
+                    return match preprocess.channels {
+                        ChannelMode::Grayscale => sample[y * width + x],
+                        ChannelMode::Rgb => {
+                            let at = |channel: usize| sample[channel * plane + y * width + x];
+                            0.299 * at(0) + 0.587 * at(1) + 0.114 * at(2)
+                        }
+                    };
+                });
+                return Fingerprint {
+                    exact: hasher.finish(),
+                    perceptual,
+                };
+            })
+            .collect();
+        return group(&fingerprints, max_distance);
+    }
+
+    /// Returns the dataset without duplicates: of every group found by
+    /// [`Dataset::duplicates`] with the default distance, only the first
+    /// sample is kept. The remaining samples keep their order.
+    ///
+    /// Deduplicate before splitting, so that no copy of a training image
+    /// ends up in the validation split.
+    pub fn dedup(&self) -> Self {
+        let mut keep = vec![true; self.len()];
+        for group in self.duplicates(DuplicateGroup::DEFAULT_MAX_DISTANCE) {
+            for &index in &group.indices[1..] {
+                keep[index] = false;
+            }
+        }
+        let indices: Vec<usize> = (0..self.len()).filter(|&index| keep[index]).collect();
+        return self.subset(&indices);
+    }
+}
+
+/// Finds the image files in `paths` that are exact or near-duplicates of
+/// each other, like [`Dataset::duplicates`] but on the original files.
+///
+/// Files are compared exactly by their bytes and approximately by a
+/// difference hash of the decoded image, so copies in different formats
+/// or sizes are found as well. Files are read and decoded on all cores.
+///
+/// # Returns
+/// The groups of duplicate positions in `paths`, ordered by their first
+/// position; an error if any file cannot be read or decoded.
+pub fn find_duplicate_images(paths: &[PathBuf], max_distance: u32) -> Result<Vec<DuplicateGroup>> {
+    let fingerprints = paths
+        .par_iter()
+        .map(|path| {
+            return fingerprint_file(path).map_err(|err| {
+                return Error::InvalidFormat(format!("{}: {}", path.display(), err));
+            });
+        })
+        .collect::<Result<Vec<Fingerprint>>>()?;
+    return Ok(group(&fingerprints, max_distance));
+}
+
+fn fingerprint_file(path: &Path) -> Result<Fingerprint> {
+    let bytes = read(path)?;
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    let image = image::load_from_memory(&bytes)?.to_luma8();
+    let perceptual = difference_hash(image.width() as usize, image.height() as usize, |x, y| {
+        return image.get_pixel(x as u32, y as u32).0[0] as f32;
+    });
+    return Ok(Fingerprint {
+        exact: hasher.finish(),
+        perceptual,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprint(exact: u64, perceptual: u64) -> Fingerprint {
+        return Fingerprint { exact, perceptual };
+    }
+
+    #[test]
+    fn difference_hash_compares_neighboring_cells() {
+        // Brighter to the right everywhere: every bit is set.
+        assert_eq!(difference_hash(18, 16, |x, _| x as f32), u64::MAX);
+        assert_eq!(difference_hash(18, 16, |x, _| -(x as f32)), 0);
+        // Only the top row of cells gets brighter to the right.
+        let top = difference_hash(18, 16, |x, y| if y < 2 { x as f32 } else { 0.0 });
+        assert_eq!(top, 0xFF << 56);
+        // Images smaller than the grid repeat their pixels.
+        assert_eq!(difference_hash(3, 1, |x, _| x as f32).count_ones(), 16);
+    }
+
+    #[test]
+    fn groups_chain_near_matches() {
+        let fingerprints = [
+            fingerprint(1, 0b0000),
+            fingerprint(2, 0xFFFF_0000),
+            fingerprint(3, 0b0011),
+            fingerprint(1, 0xF0F0),
+            fingerprint(4, 0b1111),
+            fingerprint(5, 0xFFFF_FFFF),
+        ];
+        // 0 and 3 are exact copies, 0 - 2 - 4 a chain of two-bit steps.
+        let groups = group(&fingerprints, 2);
+        assert_eq!(
+            groups,
+            [DuplicateGroup {
+                indices: vec![0, 2, 3, 4],
+                exact: false,
+            }]
+        );
+        let exact_only = group(&fingerprints, 0);
+        assert_eq!(
+            exact_only,
+            [DuplicateGroup {
+                indices: vec![0, 3],
+                exact: true,
+            }]
+        );
+        // 1 and 5 differ in 16 bits.
+        assert_eq!(group(&fingerprints, 16).len(), 1);
+        assert_eq!(group(&fingerprints, 16)[0].indices, [0, 1, 2, 3, 4, 5]);
+    }
+}
@@ -0,0 +1,312 @@
+use super::codec;
+#[cfg(feature = "fs")]
+use super::config::TrainConfig;
+use super::error::Error;
+use super::error::Result;
+use super::evaluation::ConfusionMatrix;
+use super::evaluation::ReliabilityDiagram;
+use super::evaluation::RocCurve;
+use super::kind::Kind;
+use super::kind::Prediction;
+use super::model::Model;
+use super::preprocess::Preprocess;
+use super::source::DatasetSource;
+#[cfg(feature = "fs")]
+use super::trainer::FitReport;
+#[cfg(feature = "fs")]
+use super::trainer::Trainer;
+use ndarray::Array1;
+use ndarray::Array2;
+use ndarray::ArrayView1;
+use ndarray::ArrayView2;
+use ndarray::Axis;
+#[cfg(feature = "fs")]
+use rand::SeedableRng;
+#[cfg(feature = "fs")]
+use rand_chacha::ChaCha8Rng;
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::io::BufReader;
+#[cfg(feature = "fs")]
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+/// Several models that predict by averaging their class probabilities.
+///
+/// Single runs of the linear model vary a lot with the initial weights and
+/// the shuffling order; averaging a few independently trained members
+/// smooths that out. The prediction and evaluation methods mirror those of
+/// [`Model`]. Decision thresholds of the members are ignored; the ensemble
+/// always predicts the class with the highest mean probability.
+#[derive(Debug, Clone)]
+pub struct Ensemble {
+    members: Vec<Model>,
+}
+
+impl Ensemble {
+    /// Magic bytes at the start of an ensemble file.
+    const MAGIC: &'static [u8; 8] = b"ANTBEEEN";
+
+    /// Current version of the ensemble format.
+    const FORMAT_VERSION: u32 = 1;
+
+    /// Combines already trained models.
+    ///
+    /// # Panics
+    /// Panics if `members` is empty or the models differ in preprocessing
+    /// or number of classes.
+    pub fn new(members: Vec<Model>) -> Self {
+        assert!(!members.is_empty(), "an ensemble needs at least one member");
+        let first = &members[0];
+        for member in &members[1..] {
+            assert_eq!(
+                member.preprocess(),
+                first.preprocess(),
+                "ensemble members use different preprocessing"
+            );
+            assert_eq!(
+                member.num_classes(),
+                first.num_classes(),
+                "ensemble members have different numbers of classes"
+            );
+        }
+        return Self { members };
+    }
+
+    /// Trains `size` members on `train`, monitoring `val` for early stopping
+    /// as [`Trainer::fit`] does.
+    ///
+    /// Member `i` uses the seed `config.seed + i`, both for the RNG that
+    /// `init` draws its initial weights from and for the shuffling order,
+    /// so the members differ while the whole ensemble stays reproducible.
+    ///
+    /// Checkpointing and metrics logging (including TensorBoard) are
+    /// disabled for the members,
+    /// since they would overwrite each other's files.
+    ///
+    /// A member interrupted by a signal (see
+    /// [`TrainConfig::handle_signals`]) is the last one trained, so the
+    /// ensemble may have fewer than `size` members; check the last report.
+    ///
+    /// # Returns
+    /// The ensemble and the training summary of every member.
+    #[cfg(feature = "fs")]
+    pub fn fit(
+        size: usize,
+        mut init: impl FnMut(&mut ChaCha8Rng) -> Model,
+        train: &impl DatasetSource,
+        val: &impl DatasetSource,
+        config: &TrainConfig,
+    ) -> Result<(Self, Vec<FitReport>)> {
+        assert!(size >= 1, "an ensemble needs at least one member");
+        let mut members = Vec::with_capacity(size);
+        let mut reports = Vec::with_capacity(size);
+        for member in 0..size {
+            let seed = config.seed.wrapping_add(member as u64);
+            let mut trainer = Trainer::new(TrainConfig {
+                seed,
+                checkpoint_dir: None,
+                metrics_path: None,
+                tensorboard_dir: None,
+                ..config.clone()
+            });
+            let mut model = init(&mut ChaCha8Rng::seed_from_u64(seed));
+            let fit = trainer.fit(&mut model, train, val)?;
+            println!(
+                "member {}/{}: best epoch {} (val_loss={:.4}, val_acc={:.2}%)",
+                member + 1,
+                size,
+                fit.best_epoch,
+                fit.best_val_loss,
+                model.evaluate(val) * 100.0
+            );
+            let interrupted = fit.interrupted;
+            members.push(model);
+            reports.push(fit);
+            if interrupted {
+                break;
+            }
+        }
+        return Ok((Self::new(members), reports));
+    }
+
+    pub fn members(&self) -> &[Model] {
+        return &self.members;
+    }
+
+    pub fn len(&self) -> usize {
+        return self.members.len();
+    }
+
+    /// Always `false`: an ensemble has at least one member.
+    pub fn is_empty(&self) -> bool {
+        return self.members.is_empty();
+    }
+
+    pub fn preprocess(&self) -> &Preprocess {
+        return self.members[0].preprocess();
+    }
+
+    pub fn num_classes(&self) -> usize {
+        return self.members[0].num_classes();
+    }
+
+    /// Mean of the members' class probabilities for the input.
+    pub fn predict_probs(&self, x: ArrayView1<f32>) -> Array1<f32> {
+        let mut probs = Array1::<f32>::zeros(self.num_classes());
+        for member in &self.members {
+            probs += &member.predict_probs(x);
+        }
+        return probs / self.len() as f32;
+    }
+
+    /// Batch version of [`Ensemble::predict_probs`], one sample per row.
+    pub fn predict_probs_batch(&self, x: ArrayView2<f32>) -> Array2<f32> {
+        let mut probs = Array2::<f32>::zeros((x.nrows(), self.num_classes()));
+        for member in &self.members {
+            probs += &member.predict_probs_batch(x);
+        }
+        return probs / self.len() as f32;
+    }
+
+    /// Classifies a single image file, preprocessed like the training data.
+    #[cfg(feature = "fs")]
+    pub fn predict_image(&self, path: &Path) -> Result<Prediction> {
+        let x = self.preprocess().load_image(path)?;
+        return Ok(self.predict_with_probability(x.view()));
+    }
+
+    /// Predicts the class with the highest mean probability, together with
+    /// that probability.
+    pub fn predict_with_probability(&self, x: ArrayView1<f32>) -> Prediction {
+        let probs = self.predict_probs(x);
+        let k = Model::argmax(probs.view());
+        return Prediction {
+            kind: Kind(k),
+            probability: probs[k],
+        };
+    }
+
+    /// Fraction of `dataset` classified correctly.
+    pub fn evaluate(&self, dataset: &impl DatasetSource) -> f32 {
+        return self.confusion_matrix(dataset).accuracy();
+    }
+
+    /// Tallies predicted against ground truth labels on `dataset`.
+    pub fn confusion_matrix(&self, dataset: &impl DatasetSource) -> ConfusionMatrix {
+        let mut matrix = ConfusionMatrix::new(self.num_classes());
+        for (probs, labels) in self.batched_probs(dataset) {
+            for (row, &kind) in probs.axis_iter(Axis(0)).zip(labels) {
+                matrix.add(kind, Kind(Model::argmax(row)));
+            }
+        }
+        return matrix;
+    }
+
+    /// Mean cross-entropy of the averaged probabilities on `dataset`.
+    pub fn loss(&self, dataset: &impl DatasetSource) -> f32 {
+        let mut total_loss = 0.0;
+        for (probs, labels) in self.batched_probs(dataset) {
+            for (row, &kind) in probs.axis_iter(Axis(0)).zip(labels) {
+                total_loss += Model::cross_entropy_loss(row, kind);
+            }
+        }
+        return total_loss / dataset.len() as f32;
+    }
+
+    /// Computes the ROC curve of class `positive` against all others,
+    /// scoring each sample by its mean P(positive | x).
+    pub fn roc_curve(&self, dataset: &impl DatasetSource, positive: Kind) -> RocCurve {
+        let mut scores = Vec::with_capacity(dataset.len());
+        for (probs, labels) in self.batched_probs(dataset) {
+            for (row, &kind) in probs.axis_iter(Axis(0)).zip(labels) {
+                scores.push((row[positive.index()], kind == positive));
+            }
+        }
+        return RocCurve::from_scores(scores);
+    }
+
+    /// Bins the predictions on `dataset` by the mean probability of the
+    /// predicted class, see [`Model::reliability_diagram`].
+    pub fn reliability_diagram(
+        &self,
+        dataset: &impl DatasetSource,
+        bins: usize,
+    ) -> ReliabilityDiagram {
+        let mut predictions = Vec::with_capacity(dataset.len());
+        for (probs, labels) in self.batched_probs(dataset) {
+            for (row, &kind) in probs.axis_iter(Axis(0)).zip(labels) {
+                let predicted = Model::argmax(row);
+                predictions.push((row[predicted], predicted == kind.index()));
+            }
+        }
+        return ReliabilityDiagram::from_predictions(predictions, bins);
+    }
+
+    /// Yields mean class probabilities for `dataset` in batches of
+    /// [`Model`]'s evaluation batch size, together with the matching labels.
+    fn batched_probs<'a>(
+        &'a self,
+        dataset: &'a impl DatasetSource,
+    ) -> impl Iterator<Item = (Array2<f32>, &'a [Kind])> {
+        return (0..dataset.len())
+            .step_by(Model::EVAL_BATCH_SIZE)
+            .map(move |start| {
+                let range = start..(start + Model::EVAL_BATCH_SIZE).min(dataset.len());
+                let x = dataset.batch(range.clone());
+                (self.predict_probs_batch(x.view()), &dataset.labels()[range])
+            });
+    }
+
+    /// Serializes all members, each in the [`Model::write_to`] format.
+    pub fn write_to(&self, writer: &mut impl Write) -> Result<()> {
+        codec::write_header(writer, Self::MAGIC, Self::FORMAT_VERSION)?;
+        codec::write_u32(writer, self.len() as u32)?;
+        for member in &self.members {
+            member.write_to(writer)?;
+        }
+        return Ok(());
+    }
+
+    /// Deserializes an ensemble previously written with [`Ensemble::write_to`].
+    pub fn read_from(reader: &mut impl Read) -> Result<Self> {
+        codec::read_header(reader, Self::MAGIC, Self::FORMAT_VERSION)?;
+        let size = codec::read_u32(reader)? as usize;
+        if size == 0 {
+            return Err(Error::InvalidFormat("ensemble without members".to_string()));
+        }
+        let mut members = Vec::with_capacity(size);
+        for _ in 0..size {
+            members.push(Model::read_from(reader)?);
+        }
+        let first = &members[0];
+        if members.iter().any(|member| {
+            member.preprocess() != first.preprocess() || member.num_classes() != first.num_classes()
+        }) {
+            return Err(Error::InvalidFormat(
+                "ensemble members do not fit the same inputs and classes".to_string(),
+            ));
+        }
+        return Ok(Self { members });
+    }
+
+    /// Saves all members to a file at `path`.
+    #[cfg(feature = "fs")]
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        return Ok(());
+    }
+
+    /// Loads an ensemble saved with [`Ensemble::save`].
+    #[cfg(feature = "fs")]
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        return Self::read_from(&mut reader);
+    }
+}
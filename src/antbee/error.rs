@@ -0,0 +1,62 @@
+use image::ImageError;
+use std::fmt;
+use std::io;
+
+/// Errors produced when reading or writing model, checkpoint, dataset and
+/// image files, and when training fails.
+#[derive(Debug)]
+pub enum Error {
+    /// The underlying filesystem operation failed.
+    Io(io::Error),
+    /// An image could not be decoded.
+    Image(ImageError),
+    /// The file was readable but its contents are not in the expected format.
+    InvalidFormat(String),
+    /// Training produced a NaN or infinite loss in batch `batch` of epoch
+    /// `epoch`, usually from too high a learning rate.
+    Diverged { epoch: usize, batch: usize },
+    /// The requested feature is not available in this build or on this
+    /// machine, e.g. the GPU backend without an OpenCL device.
+    Unsupported(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::Image(err) => write!(f, "image error: {}", err),
+            Error::InvalidFormat(msg) => write!(f, "invalid format: {}", msg),
+            Error::Diverged { epoch, batch } => write!(
+                f,
+                "training diverged: non-finite loss in epoch {}, batch {}; \
+                 lower the learning rate or clip the gradient norm",
+                epoch, batch
+            ),
+            Error::Unsupported(msg) => write!(f, "unsupported: {}", msg),
+        };
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return match self {
+            Error::Io(err) => Some(err),
+            Error::Image(err) => Some(err),
+            Error::InvalidFormat(_) | Error::Diverged { .. } | Error::Unsupported(_) => None,
+        };
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        return Error::Io(err);
+    }
+}
+
+impl From<ImageError> for Error {
+    fn from(err: ImageError) -> Self {
+        return Error::Image(err);
+    }
+}
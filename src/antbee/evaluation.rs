@@ -0,0 +1,306 @@
+use super::kind::Kind;
+use ndarray::Array2;
+
+/// Counts of (true class, predicted class) pairs over a dataset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfusionMatrix {
+    /// `counts[[t, p]]` is the number of samples of class `t` predicted as `p`.
+    counts: Array2<usize>,
+}
+
+impl ConfusionMatrix {
+    /// Creates an empty matrix for `num_classes` classes.
+    pub fn new(num_classes: usize) -> Self {
+        return Self {
+            counts: Array2::zeros((num_classes, num_classes)),
+        };
+    }
+
+    /// Records one prediction.
+    pub fn add(&mut self, actual: Kind, predicted: Kind) {
+        self.counts[[actual.index(), predicted.index()]] += 1;
+    }
+
+    pub fn num_classes(&self) -> usize {
+        return self.counts.nrows();
+    }
+
+    /// Number of samples of class `actual` that were predicted as `predicted`.
+    pub fn count(&self, actual: Kind, predicted: Kind) -> usize {
+        return self.counts[[actual.index(), predicted.index()]];
+    }
+
+    /// Total number of recorded predictions.
+    pub fn total(&self) -> usize {
+        return self.counts.sum();
+    }
+
+    /// Fraction of predictions that were correct.
+    pub fn accuracy(&self) -> f32 {
+        let correct: usize = self.counts.diag().sum();
+        return ratio(correct, self.total());
+    }
+
+    /// Fraction of the samples of class `kind` that were predicted as `kind`.
+    ///
+    /// Returns 0.0 if the class has no samples.
+    pub fn recall(&self, kind: Kind) -> f32 {
+        let k = kind.index();
+        return ratio(self.counts[[k, k]], self.counts.row(k).sum());
+    }
+
+    /// Fraction of the predictions of class `kind` that were correct.
+    ///
+    /// Returns 0.0 if `kind` was never predicted.
+    pub fn precision(&self, kind: Kind) -> f32 {
+        let k = kind.index();
+        return ratio(self.counts[[k, k]], self.counts.column(k).sum());
+    }
+
+    /// Harmonic mean of precision and recall for class `kind`.
+    pub fn f1(&self, kind: Kind) -> f32 {
+        let precision = self.precision(kind);
+        let recall = self.recall(kind);
+        if precision + recall == 0.0 {
+            return 0.0;
+        }
+        return 2.0 * precision * recall / (precision + recall);
+    }
+
+    /// Unweighted mean of the per-class F1 scores.
+    pub fn macro_f1(&self) -> f32 {
+        let n = self.num_classes();
+        let sum: f32 = (0..n).map(|k| self.f1(Kind(k))).sum();
+        return sum / n as f32;
+    }
+}
+
+/// `numerator / denominator`, or 0.0 when the denominator is zero.
+fn ratio(numerator: usize, denominator: usize) -> f32 {
+    if denominator == 0 {
+        return 0.0;
+    }
+    return numerator as f32 / denominator as f32;
+}
+
+/// One operating point of a [`RocCurve`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RocPoint {
+    /// Samples with a score >= `threshold` are predicted positive.
+    pub threshold: f32,
+    /// Number of positives predicted positive at this threshold.
+    pub true_positives: usize,
+    /// Number of negatives predicted positive at this threshold.
+    pub false_positives: usize,
+}
+
+/// Receiver operating characteristic of a score against binary labels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RocCurve {
+    /// Operating points from the strictest threshold (nothing predicted
+    /// positive) down to the most lenient one (everything positive).
+    pub points: Vec<RocPoint>,
+    /// Number of positive samples.
+    pub positives: usize,
+    /// Number of negative samples.
+    pub negatives: usize,
+}
+
+impl RocCurve {
+    /// Builds the curve from `(score, is_positive)` pairs.
+    ///
+    /// Every distinct score becomes a threshold.
+    pub fn from_scores(scores: impl IntoIterator<Item = (f32, bool)>) -> Self {
+        let mut scores: Vec<(f32, bool)> = scores.into_iter().collect();
+        scores.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let positives = scores.iter().filter(|(_, positive)| *positive).count();
+        let negatives = scores.len() - positives;
+
+        let mut points = vec![RocPoint {
+            threshold: f32::INFINITY,
+            true_positives: 0,
+            false_positives: 0,
+        }];
+        let mut true_positives = 0;
+        let mut false_positives = 0;
+        for (i, &(score, positive)) in scores.iter().enumerate() {
+            if positive {
+                true_positives += 1;
+            } else {
+                false_positives += 1;
+            }
+            // Only emit a point once all samples sharing this score are counted.
+            if i + 1 == scores.len() || scores[i + 1].0 != score {
+                points.push(RocPoint {
+                    threshold: score,
+                    true_positives,
+                    false_positives,
+                });
+            }
+        }
+
+        return Self {
+            points,
+            positives,
+            negatives,
+        };
+    }
+
+    /// True positive rate (recall) at `point`.
+    pub fn tpr(&self, point: &RocPoint) -> f32 {
+        return ratio(point.true_positives, self.positives);
+    }
+
+    /// False positive rate at `point`.
+    pub fn fpr(&self, point: &RocPoint) -> f32 {
+        return ratio(point.false_positives, self.negatives);
+    }
+
+    /// Area under the curve, integrated with the trapezoidal rule.
+    ///
+    /// 1.0 means the score separates the classes perfectly, 0.5 is chance.
+    pub fn auc(&self) -> f32 {
+        let mut area = 0.0;
+        for pair in self.points.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            area += (self.fpr(b) - self.fpr(a)) * (self.tpr(a) + self.tpr(b)) / 2.0;
+        }
+        return area;
+    }
+
+    /// Scores `point` with `metric`.
+    pub fn score(&self, point: &RocPoint, metric: ThresholdMetric) -> f32 {
+        let tp = point.true_positives;
+        let fp = point.false_positives;
+        let false_negatives = self.positives - tp;
+        let true_negatives = self.negatives - fp;
+        return match metric {
+            ThresholdMetric::Accuracy => {
+                ratio(tp + true_negatives, self.positives + self.negatives)
+            }
+            ThresholdMetric::F1 => ratio(2 * tp, 2 * tp + fp + false_negatives),
+            ThresholdMetric::Youden => self.tpr(point) - self.fpr(point),
+        };
+    }
+
+    /// Returns the finite threshold that maximizes `metric`.
+    pub fn best_threshold(&self, metric: ThresholdMetric) -> f32 {
+        let mut best = 0.5;
+        let mut best_score = f32::NEG_INFINITY;
+        for point in self.points.iter().filter(|p| p.threshold.is_finite()) {
+            let score = self.score(point, metric);
+            if score > best_score {
+                best_score = score;
+                best = point.threshold;
+            }
+        }
+        return best;
+    }
+}
+
+/// Criterion for choosing a decision threshold on a validation set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThresholdMetric {
+    /// Fraction of correct predictions.
+    Accuracy,
+    /// F1 score of the positive class; balances precision and recall.
+    F1,
+    /// Youden's J statistic, TPR - FPR.
+    Youden,
+}
+
+/// Predictions whose confidence falls into one bin of a
+/// [`ReliabilityDiagram`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CalibrationBin {
+    /// Number of predictions in the bin.
+    pub count: usize,
+    /// Mean confidence of those predictions.
+    pub mean_confidence: f32,
+    /// Fraction of those predictions that were correct.
+    pub accuracy: f32,
+}
+
+/// Confidence against accuracy of a classifier's predictions.
+///
+/// A well calibrated classifier is right about 90% of the time when it
+/// predicts with 0.9 confidence; [`ReliabilityDiagram::expected_calibration_error`]
+/// measures how far it is from that.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReliabilityDiagram {
+    /// Equal-width confidence bins covering [0, 1], in increasing order.
+    pub bins: Vec<CalibrationBin>,
+}
+
+impl ReliabilityDiagram {
+    /// Number of bins used by the evaluation reports.
+    pub const DEFAULT_BINS: usize = 10;
+
+    /// Bins `(confidence, is_correct)` pairs into `bins` equal-width bins.
+    ///
+    /// # Panics
+    /// Panics if `bins` is zero.
+    pub fn from_predictions(
+        predictions: impl IntoIterator<Item = (f32, bool)>,
+        bins: usize,
+    ) -> Self {
+        assert!(bins >= 1, "need at least one bin");
+        let mut sums = vec![(0usize, 0.0f64, 0usize); bins];
+        for (confidence, correct) in predictions {
+            let bin = ((confidence * bins as f32) as usize).min(bins - 1);
+            let (count, confidences, hits) = &mut sums[bin];
+            *count += 1;
+            *confidences += confidence as f64;
+            *hits += correct as usize;
+        }
+        let bins = sums
+            .into_iter()
+            .map(|(count, confidences, hits)| CalibrationBin {
+                count,
+                mean_confidence: if count == 0 {
+                    0.0
+                } else {
+                    (confidences / count as f64) as f32
+                },
+                accuracy: ratio(hits, count),
+            })
+            .collect();
+        return Self { bins };
+    }
+
+    /// Total number of predictions.
+    pub fn total(&self) -> usize {
+        return self.bins.iter().map(|bin| bin.count).sum();
+    }
+
+    /// Mean confidence over all predictions. Above the accuracy means the
+    /// classifier is overconfident, below it underconfident.
+    pub fn mean_confidence(&self) -> f32 {
+        let total = self.total();
+        if total == 0 {
+            return 0.0;
+        }
+        return self
+            .bins
+            .iter()
+            .map(|bin| bin.count as f32 * bin.mean_confidence)
+            .sum::<f32>()
+            / total as f32;
+    }
+
+    /// Expected calibration error: the mean gap between confidence and
+    /// accuracy over the bins, weighted by their number of predictions.
+    pub fn expected_calibration_error(&self) -> f32 {
+        let total = self.total();
+        if total == 0 {
+            return 0.0;
+        }
+        return self
+            .bins
+            .iter()
+            .map(|bin| bin.count as f32 * (bin.accuracy - bin.mean_confidence).abs())
+            .sum::<f32>()
+            / total as f32;
+    }
+}
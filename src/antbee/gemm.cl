@@ -0,0 +1,18 @@
+// C = A·B for A of shape (m, k) and B of shape (k, n), both given by their
+// row and column strides so that transposed operands need no copy. C is
+// written in row-major order, one work item per element.
+__kernel void gemm(const int m, const int n, const int k,
+                   __global const float *a, const int a_row, const int a_col,
+                   __global const float *b, const int b_row, const int b_col,
+                   __global float *c) {
+    const int i = get_global_id(0);
+    const int j = get_global_id(1);
+    if (i >= m || j >= n) {
+        return;
+    }
+    float sum = 0.0f;
+    for (int p = 0; p < k; ++p) {
+        sum += a[i * a_row + p * a_col] * b[p * b_row + j * b_col];
+    }
+    c[i * n + j] = sum;
+}
@@ -0,0 +1,82 @@
+use super::dataset::Dataset;
+use super::error::Result;
+use super::kind::Prediction;
+use super::model::Model;
+use super::tta::TtaConfig;
+use ndarray::Array1;
+use rayon::prelude::*;
+use std::fs::read_dir;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Lists every image below `dir`, recursing into subdirectories.
+///
+/// Hidden files and directories (starting with `.`) and files that are not
+/// a decodable image format are skipped, as when loading a [`Dataset`].
+/// The paths are sorted, so repeated runs list the same images in the same
+/// order.
+pub fn find_images(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut images = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                let is_hidden = path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+                if !is_hidden {
+                    pending.push(path);
+                }
+            } else if Dataset::is_image_file(&path) {
+                images.push(path);
+            }
+        }
+    }
+    images.sort();
+    return Ok(images);
+}
+
+impl Model {
+    /// Classifies every image in `paths` with [`Model::predict_image`],
+    /// decoding and predicting on all available cores.
+    ///
+    /// # Returns
+    /// One result per path, in the order of `paths`; images that cannot be
+    /// read or decoded yield an error without affecting the others.
+    pub fn predict_images(&self, paths: &[PathBuf]) -> Vec<Result<Prediction>> {
+        return paths
+            .par_iter()
+            .map(|path| self.predict_image(path))
+            .collect();
+    }
+
+    /// Classifies every image in `paths` like [`Model::predict_images`],
+    /// with test-time augmentation (see [`Model::predict_tta`]).
+    pub fn predict_images_tta(
+        &self,
+        paths: &[PathBuf],
+        config: TtaConfig,
+    ) -> Vec<Result<Prediction>> {
+        return paths
+            .par_iter()
+            .map(|path| self.predict_image_tta(path, config))
+            .collect();
+    }
+
+    /// Computes the [embedding](Model::embed) of every image in `paths`,
+    /// decoding on all available cores.
+    ///
+    /// # Returns
+    /// One result per path, in the order of `paths`, as for
+    /// [`Model::predict_images`].
+    pub fn embed_images(&self, paths: &[PathBuf]) -> Vec<Result<Array1<f32>>> {
+        return paths
+            .par_iter()
+            .map(|path| {
+                let x = self.preprocess().load_image(path)?;
+                return Ok(self.embed(x.view()));
+            })
+            .collect();
+    }
+}
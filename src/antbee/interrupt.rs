@@ -0,0 +1,56 @@
+//! SIGINT/SIGTERM handling, for stopping training gracefully.
+//!
+//! The first signal only sets a flag that the training loop polls after
+//! every batch; a second one exits the process immediately, as the default
+//! handler would have.
+
+use std::sync::Once;
+use std::sync::atomic::AtomicI32;
+use std::sync::atomic::Ordering;
+
+/// Number of the first signal received, 0 before any.
+static SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+/// Installs the handler for SIGINT and SIGTERM, once per process.
+///
+/// Signals are only handled on Unix; elsewhere this does nothing.
+pub(crate) fn install() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        #[cfg(unix)]
+        for signal in [libc::SIGINT, libc::SIGTERM] {
+            // SAFETY: `handle` only touches an atomic and calls the
+            // async-signal-safe `write` and `_exit`.
+            unsafe {
+                libc::signal(
+                    signal,
+                    handle as extern "C" fn(libc::c_int) as libc::sighandler_t,
+                );
+            }
+        }
+    });
+}
+
+/// The first SIGINT or SIGTERM received since [`install`], if any.
+pub(crate) fn received() -> Option<i32> {
+    return match SIGNAL.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal),
+    };
+}
+
+#[cfg(unix)]
+extern "C" fn handle(signal: libc::c_int) {
+    let message: &[u8] =
+        match SIGNAL.compare_exchange(0, signal, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => b"\ninterrupted: stopping after the current batch, interrupt again to abort\n",
+            Err(_) => {
+                // SAFETY: exits without running any non-reentrant cleanup.
+                unsafe { libc::_exit(128 + signal) };
+            }
+        };
+    // SAFETY: writes a static buffer to stderr; a failure is ignored.
+    unsafe {
+        libc::write(libc::STDERR_FILENO, message.as_ptr().cast(), message.len());
+    }
+}
@@ -1,5 +1,118 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Kind {
-    Ant = 0,
-    Bee,
+use super::codec;
+use super::error::Error;
+use super::error::Result;
+use std::io::Read;
+use std::io::Write;
+
+/// A class label, stored as the index of the class in the dataset's class list.
+///
+/// Class indices follow the sorted order of the class directory names, so
+/// for the bundled dataset `ants` is `Kind(0)` and `bees` is `Kind(1)`.
+/// A [`ClassMap`] translates between indices and class names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Kind(pub usize);
+
+impl Kind {
+    /// Returns the class index.
+    pub fn index(self) -> usize {
+        return self.0;
+    }
+}
+
+/// The outcome of classifying a single input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Prediction {
+    /// The predicted class.
+    pub kind: Kind,
+    /// The model's probability for `kind`, in [1 / num_classes, 1.0].
+    pub probability: f32,
+}
+
+/// Names of the classes, indexed by [`Kind::index`].
+///
+/// Datasets build one from their class directories; a user-defined map
+/// passed to [`Dataset::from_dataset_path_mapped`](super::Dataset::from_dataset_path_mapped)
+/// fixes the label of every directory instead. Models keep the map of the
+/// data they were trained on and save it, so predictions can be reported
+/// by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassMap {
+    names: Vec<String>,
+}
+
+impl ClassMap {
+    /// Creates a map giving `names[k]` the label `Kind(k)`.
+    ///
+    /// # Panics
+    /// Panics if a name occurs more than once.
+    pub fn new(names: Vec<String>) -> Self {
+        for (index, name) in names.iter().enumerate() {
+            assert!(
+                !names[..index].contains(name),
+                "duplicate class name {:?}",
+                name
+            );
+        }
+        return Self { names };
+    }
+
+    /// Names the classes after their indices, "0", "1", ...
+    ///
+    /// Used for models that were saved without class names.
+    pub fn indices(num_classes: usize) -> Self {
+        return Self {
+            names: (0..num_classes).map(|k| k.to_string()).collect(),
+        };
+    }
+
+    /// Number of classes.
+    pub fn len(&self) -> usize {
+        return self.names.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.names.is_empty();
+    }
+
+    /// Class names in label order.
+    pub fn names(&self) -> &[String] {
+        return &self.names;
+    }
+
+    /// Returns the name of class `kind`.
+    ///
+    /// # Panics
+    /// Panics if `kind` is out of range.
+    pub fn name(&self, kind: Kind) -> &str {
+        return &self.names[kind.index()];
+    }
+
+    /// Returns the label of the class called `name`, if there is one.
+    pub fn kind(&self, name: &str) -> Option<Kind> {
+        return self.names.iter().position(|n| n == name).map(Kind);
+    }
+
+    pub(crate) fn write_to(&self, writer: &mut impl Write) -> Result<()> {
+        codec::write_u64(writer, self.names.len() as u64)?;
+        for name in &self.names {
+            codec::write_str(writer, name)?;
+        }
+        return Ok(());
+    }
+
+    pub(crate) fn read_from(reader: &mut impl Read) -> Result<Self> {
+        let len = codec::read_u64(reader)? as usize;
+        let mut names = Vec::<String>::with_capacity(len.min(1024));
+        for _ in 0..len {
+            let name = codec::read_str(reader)?;
+            if names.contains(&name) {
+                return Err(Error::InvalidFormat(format!(
+                    "duplicate class name {:?}",
+                    name
+                )));
+            }
+            names.push(name);
+        }
+        return Ok(Self { names });
+    }
 }
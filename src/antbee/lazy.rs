@@ -0,0 +1,191 @@
+use super::dataset::Dataset;
+use super::dataset::stratified_split;
+use super::kind::ClassMap;
+use super::kind::Kind;
+use super::preprocess::Preprocess;
+use super::source::DatasetSource;
+use ndarray::Array1;
+use ndarray::Array2;
+use ndarray::Axis;
+use ndarray::CowArray;
+use ndarray::Ix1;
+use ndarray::Ix2;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Least-recently-used cache of decoded samples, keyed by sample index.
+#[derive(Debug)]
+struct LruCache {
+    capacity: usize,
+    /// Sample index -> (last use, features).
+    entries: HashMap<usize, (u64, Array1<f32>)>,
+    /// Last use -> sample index, oldest first.
+    order: BTreeMap<u64, usize>,
+    clock: u64,
+}
+
+impl LruCache {
+    fn new(capacity: usize) -> Self {
+        return Self {
+            capacity,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            clock: 0,
+        };
+    }
+
+    fn get(&mut self, index: usize) -> Option<Array1<f32>> {
+        self.clock += 1;
+        let (used, features) = self.entries.get_mut(&index)?;
+        self.order.remove(used);
+        self.order.insert(self.clock, index);
+        *used = self.clock;
+        return Some(features.clone());
+    }
+
+    fn insert(&mut self, index: usize, features: Array1<f32>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity
+            && let Some((_, oldest)) = self.order.pop_first()
+        {
+            self.entries.remove(&oldest);
+        }
+        self.clock += 1;
+        self.order.insert(self.clock, index);
+        self.entries.insert(index, (self.clock, features));
+    }
+}
+
+/// An ImageFolder-style dataset that keeps only image paths and labels in
+/// memory and decodes images when they are accessed.
+///
+/// Use it instead of [`Dataset`] when the preprocessed images do not fit in
+/// RAM. Every access decodes and resizes the image again unless it is held
+/// by the LRU cache, so epochs are slower than with an in-memory dataset.
+#[derive(Debug)]
+pub struct LazyDataset {
+    paths: Vec<PathBuf>,
+    labels: Vec<Kind>,
+    classes: ClassMap,
+    preprocess: Preprocess,
+    cache: Mutex<LruCache>,
+}
+
+impl LazyDataset {
+    /// Indexes the dataset at `path` like [`Dataset::from_dataset_path_with`],
+    /// without decoding any image.
+    ///
+    /// # Arguments
+    /// * `path` - Directory with one subdirectory of images per class.
+    /// * `preprocess` - Preprocessing applied when an image is decoded.
+    /// * `cache_capacity` - Number of decoded samples kept in memory;
+    ///   0 disables caching.
+    pub fn from_dataset_path(path: &Path, preprocess: Preprocess, cache_capacity: usize) -> Self {
+        let (classes, images) = Dataset::list_images(path, None, true);
+        let (labels, paths) = images.into_iter().unzip();
+        return Self {
+            paths,
+            labels,
+            classes,
+            preprocess,
+            cache: Mutex::new(LruCache::new(cache_capacity)),
+        };
+    }
+
+    /// Path of the image behind the sample at `index`.
+    pub fn path(&self, index: usize) -> &Path {
+        return &self.paths[index];
+    }
+
+    /// Maximum number of decoded samples held in memory.
+    pub fn cache_capacity(&self) -> usize {
+        return self.cache.lock().unwrap().capacity;
+    }
+
+    /// Builds a new dataset from the samples at `indices`, in that order.
+    ///
+    /// The subset gets its own, empty cache of the same capacity.
+    pub fn subset(&self, indices: &[usize]) -> Self {
+        return Self {
+            paths: indices.iter().map(|&i| self.paths[i].clone()).collect(),
+            labels: indices.iter().map(|&i| self.labels[i]).collect(),
+            classes: self.classes.clone(),
+            preprocess: self.preprocess,
+            cache: Mutex::new(LruCache::new(self.cache_capacity())),
+        };
+    }
+
+    /// Splits the dataset into two parts while preserving class proportions.
+    ///
+    /// Uses exactly the same assignment as [`Dataset::split`].
+    pub fn split(&self, ratio: f32, seed: u64) -> (Self, Self) {
+        let (first, second) = stratified_split(self, ratio, seed);
+        return (self.subset(&first), self.subset(&second));
+    }
+
+    /// Decodes every image into an in-memory [`Dataset`], keeping the order.
+    pub fn to_dataset(&self) -> Dataset {
+        let features = self.batch(0..self.len()).into_owned();
+        return Dataset::from_parts(
+            features,
+            self.labels.clone(),
+            Some(self.paths.clone()),
+            self.classes.clone(),
+            self.preprocess,
+        );
+    }
+
+    /// Returns the features of the sample at `index`, decoding the image
+    /// on a cache miss.
+    ///
+    /// # Panics
+    /// Panics if the image cannot be read or decoded.
+    fn load(&self, index: usize) -> Array1<f32> {
+        if let Some(features) = self.cache.lock().unwrap().get(index) {
+            return features;
+        }
+        let path = &self.paths[index];
+        let features = self
+            .preprocess
+            .load_image(path)
+            .unwrap_or_else(|error| panic!("failed to load {}: {}", path.display(), error));
+        self.cache.lock().unwrap().insert(index, features.clone());
+        return features;
+    }
+}
+
+impl DatasetSource for LazyDataset {
+    fn len(&self) -> usize {
+        return self.labels.len();
+    }
+
+    fn class_map(&self) -> &ClassMap {
+        return &self.classes;
+    }
+
+    fn preprocess(&self) -> &Preprocess {
+        return &self.preprocess;
+    }
+
+    fn labels(&self) -> &[Kind] {
+        return &self.labels;
+    }
+
+    fn sample(&self, index: usize) -> CowArray<'_, f32, Ix1> {
+        return self.load(index).into();
+    }
+
+    fn batch(&self, range: Range<usize>) -> CowArray<'_, f32, Ix2> {
+        let mut features = Array2::<f32>::zeros((range.len(), self.preprocess.input_dim()));
+        for (mut row, index) in features.axis_iter_mut(Axis(0)).zip(range) {
+            row.assign(&self.load(index));
+        }
+        return features.into();
+    }
+}
@@ -0,0 +1,174 @@
+use super::kind::Kind;
+use super::model::Model;
+use ndarray::Array1;
+use ndarray::ArrayView1;
+use std::fmt::Debug;
+
+/// Training objective of a single sample, computed from the model's logits.
+///
+/// The model's probabilities are always the softmax of the logits; the loss
+/// only decides what the gradient pushes them towards. Class weights and the
+/// L2 penalty are applied by the model on top of it.
+pub trait Loss: Debug + Send + Sync {
+    /// Returns the loss of logits `z` for a sample of class `target`.
+    fn forward(&self, z: ArrayView1<f32>, target: Kind) -> f32;
+
+    /// Returns dL/dz, the gradient of [`Loss::forward`] w.r.t. the logits.
+    fn gradient(&self, z: ArrayView1<f32>, target: Kind) -> Array1<f32>;
+
+    /// Describes the loss as the TOML value of `loss` in a config file (see
+    /// [`TrainConfig::from_toml`](super::TrainConfig::from_toml)), or `None`
+    /// if config files cannot express it, as for losses defined outside
+    /// this crate.
+    fn to_toml(&self) -> Option<String> {
+        return None;
+    }
+}
+
+/// Softmax of `z` as a new vector.
+fn softmax(z: ArrayView1<f32>) -> Array1<f32> {
+    let mut probs = z.to_owned();
+    Model::softmax(probs.view_mut());
+    return probs;
+}
+
+/// Softmax cross-entropy, -ln p_target. The default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CrossEntropy;
+
+impl Loss for CrossEntropy {
+    fn forward(&self, z: ArrayView1<f32>, target: Kind) -> f32 {
+        return Model::cross_entropy_loss(softmax(z).view(), target);
+    }
+
+    fn gradient(&self, z: ArrayView1<f32>, target: Kind) -> Array1<f32> {
+        let mut dz = softmax(z);
+        dz[target.index()] -= 1.0; // dz = probs - onehot(y)
+        return dz;
+    }
+
+    fn to_toml(&self) -> Option<String> {
+        return Some("\"cross-entropy\"".to_string());
+    }
+}
+
+/// Binary cross-entropy of every class against the rest, with a sigmoid per
+/// logit instead of a softmax over all of them.
+///
+/// L = sum_k -y_k ln(sigmoid(z_k)) - (1 - y_k) ln(1 - sigmoid(z_k))
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BinaryCrossEntropy;
+
+impl Loss for BinaryCrossEntropy {
+    fn forward(&self, z: ArrayView1<f32>, target: Kind) -> f32 {
+        // -ln(sigmoid(z)) = softplus(-z) and -ln(1 - sigmoid(z)) = softplus(z)
+        let softplus = |v: f32| v.max(0.0) + (-v.abs()).exp().ln_1p();
+        return z
+            .iter()
+            .enumerate()
+            .map(|(k, &z)| softplus(if k == target.index() { -z } else { z }))
+            .sum();
+    }
+
+    fn gradient(&self, z: ArrayView1<f32>, target: Kind) -> Array1<f32> {
+        let mut dz = z.mapv(|v| 1.0 / (1.0 + (-v).exp()));
+        dz[target.index()] -= 1.0; // dz = sigmoid(z) - onehot(y)
+        return dz;
+    }
+
+    fn to_toml(&self) -> Option<String> {
+        return Some("\"bce\"".to_string());
+    }
+}
+
+/// Multiclass hinge loss: every other class's logit should stay at least
+/// `margin` below the target's.
+///
+/// L = sum_{j != y} max(0, margin + z_j - z_y)
+#[derive(Debug, Clone, Copy)]
+pub struct Hinge {
+    pub margin: f32,
+}
+
+impl Default for Hinge {
+    fn default() -> Self {
+        return Self { margin: 1.0 };
+    }
+}
+
+impl Loss for Hinge {
+    fn forward(&self, z: ArrayView1<f32>, target: Kind) -> f32 {
+        let y = target.index();
+        return z
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| j != y)
+            .map(|(_, &z_j)| (self.margin + z_j - z[y]).max(0.0))
+            .sum();
+    }
+
+    fn gradient(&self, z: ArrayView1<f32>, target: Kind) -> Array1<f32> {
+        let y = target.index();
+        let mut dz = Array1::zeros(z.len());
+        for (j, &z_j) in z.iter().enumerate() {
+            if j != y && self.margin + z_j - z[y] > 0.0 {
+                dz[j] += 1.0;
+                dz[y] -= 1.0;
+            }
+        }
+        return dz;
+    }
+
+    fn to_toml(&self) -> Option<String> {
+        return Some(format!(
+            "{{ name = \"hinge\", margin = {:?} }}",
+            self.margin
+        ));
+    }
+}
+
+/// Focal loss (Lin et al., 2017), cross-entropy scaled down for samples
+/// that are already classified confidently, so training concentrates on
+/// the hard and minority-class ones.
+///
+/// L = -(1 - p_y)^gamma ln(p_y), which is [`CrossEntropy`] for `gamma = 0`.
+#[derive(Debug, Clone, Copy)]
+pub struct Focal {
+    pub gamma: f32,
+}
+
+impl Default for Focal {
+    fn default() -> Self {
+        return Self { gamma: 2.0 };
+    }
+}
+
+impl Loss for Focal {
+    fn forward(&self, z: ArrayView1<f32>, target: Kind) -> f32 {
+        let probs = softmax(z);
+        let p = probs[target.index()];
+        return (1.0 - p).powf(self.gamma) * Model::cross_entropy_loss(probs.view(), target);
+    }
+
+    fn gradient(&self, z: ArrayView1<f32>, target: Kind) -> Array1<f32> {
+        let probs = softmax(z);
+        let y = target.index();
+        let p = probs[y].max(f32::MIN_POSITIVE);
+        // dL/dz_k = [gamma (1 - p)^(gamma - 1) p ln(p) - (1 - p)^gamma] (onehot_k - p_k)
+        let q = 1.0 - p;
+        let focus = if q > 0.0 {
+            self.gamma * q.powf(self.gamma - 1.0) * p * p.ln()
+        } else {
+            0.0
+        };
+        let factor = focus - q.powf(self.gamma);
+        let mut dz = -probs;
+        dz[y] += 1.0;
+        dz *= factor;
+        return dz;
+    }
+
+    fn to_toml(&self) -> Option<String> {
+        return Some(format!("{{ name = \"focal\", gamma = {:?} }}", self.gamma));
+    }
+}
@@ -0,0 +1,120 @@
+//! Datasets listed in a CSV manifest instead of class directories.
+
+use super::config::DatasetConfig;
+use super::dataset::Dataset;
+use super::error::Error;
+use super::error::Result;
+use super::kind::ClassMap;
+use std::fs::read_to_string;
+use std::mem::take;
+use std::path::Path;
+use std::path::PathBuf;
+
+impl Dataset {
+    /// Loads the images listed in the CSV manifest at `path`, like
+    /// [`Dataset::from_dataset_path`] does for a class-per-directory layout.
+    ///
+    /// See [`Dataset::from_manifest_with`] for the file format.
+    pub fn from_manifest(path: &Path) -> Result<Self> {
+        return Self::from_manifest_with(path, &DatasetConfig::default());
+    }
+
+    /// Loads the images listed in the CSV manifest at `path` as described
+    /// by `config`.
+    ///
+    /// Every row is `path,label`: an image file, relative to the directory
+    /// of the manifest unless absolute, and the name of its class. Fields
+    /// containing commas or quotes are quoted as in RFC 4180. A first row
+    /// of exactly `path,label` is taken as a header, and blank lines are
+    /// skipped.
+    ///
+    /// Without `config.classes`, every distinct label becomes a class, in
+    /// sorted order, just as directory names do; with it, rows whose label
+    /// is not in the map are skipped. Rows keep the manifest order, which
+    /// is already reproducible, before the shuffle of `config.shuffle_seed`;
+    /// `config.sorted` does not apply.
+    ///
+    /// # Errors
+    /// Fails if the manifest cannot be read, a row does not have two
+    /// fields, a listed file does not exist or cannot be decoded, or there
+    /// are fewer than two labels.
+    ///
+    /// # Panics
+    /// Panics if a listed image cannot be decoded, like the folder loader.
+    pub fn from_manifest_with(path: &Path, config: &DatasetConfig) -> Result<Self> {
+        let base = path.parent().unwrap_or(Path::new(""));
+        let mut rows = Vec::<(PathBuf, String)>::new();
+        for (index, line) in read_to_string(path)?.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid = |reason: &str| {
+                return Error::InvalidFormat(format!(
+                    "{} line {}: {}",
+                    path.display(),
+                    index + 1,
+                    reason
+                ));
+            };
+            let fields = parse_csv_line(line).ok_or_else(|| invalid("unterminated quote"))?;
+            let [image, label] = <[String; 2]>::try_from(fields)
+                .map_err(|_| invalid("expected two fields, path and label"))?;
+            if index == 0 && image == "path" && label == "label" {
+                continue;
+            }
+            let image = base.join(image);
+            if !image.is_file() {
+                return Err(invalid(&format!("{} is not a file", image.display())));
+            }
+            rows.push((image, label));
+        }
+
+        let classes = match &config.classes {
+            Some(classes) => classes.clone(),
+            None => {
+                let mut names: Vec<String> = rows.iter().map(|(_, label)| label.clone()).collect();
+                names.sort();
+                names.dedup();
+                if names.len() < 2 {
+                    return Err(Error::InvalidFormat(format!(
+                        "{} lists fewer than two classes",
+                        path.display()
+                    )));
+                }
+                ClassMap::new(names)
+            }
+        };
+        let images = rows
+            .into_iter()
+            .filter_map(|(image, label)| Some((classes.kind(&label)?, image)))
+            .collect();
+        return Self::from_listing(classes, images, config);
+    }
+}
+
+/// Splits one CSV line into its fields, unquoting quoted ones.
+///
+/// Returns `None` if a quoted field is not closed.
+fn parse_csv_line(line: &str) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => fields.push(take(&mut field)),
+            (_, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return None;
+    }
+    fields.push(field);
+    return Some(fields);
+}
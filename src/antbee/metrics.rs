@@ -0,0 +1,202 @@
+use super::error::Result;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+
+/// Metrics recorded at the end of a training epoch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EpochMetrics {
+    /// Zero-based epoch number.
+    pub epoch: usize,
+    /// Mean training loss over the samples trained on in the epoch, all of
+    /// them unless it was cut short, including the L2 penalty.
+    pub train_loss: f32,
+    /// Accuracy on the training set after the epoch.
+    pub train_acc: f32,
+    /// Mean loss on the validation set after the epoch.
+    pub val_loss: f32,
+    /// Accuracy on the validation set after the epoch.
+    pub val_acc: f32,
+}
+
+/// Metrics of every epoch of a training run, returned in
+/// [`FitReport::history`](super::FitReport::history) for inspecting or
+/// plotting a run without parsing a metrics log.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct History {
+    epochs: Vec<EpochMetrics>,
+}
+
+impl History {
+    /// Metrics of every completed epoch, in order.
+    pub fn epochs(&self) -> &[EpochMetrics] {
+        return &self.epochs;
+    }
+
+    pub fn len(&self) -> usize {
+        return self.epochs.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.epochs.is_empty();
+    }
+
+    /// Metrics of the last completed epoch.
+    pub fn last(&self) -> Option<&EpochMetrics> {
+        return self.epochs.last();
+    }
+
+    /// Mean training loss of every epoch.
+    pub fn train_losses(&self) -> Vec<f32> {
+        return self
+            .epochs
+            .iter()
+            .map(|metrics| metrics.train_loss)
+            .collect();
+    }
+
+    /// Training accuracy after every epoch.
+    pub fn train_accuracies(&self) -> Vec<f32> {
+        return self
+            .epochs
+            .iter()
+            .map(|metrics| metrics.train_acc)
+            .collect();
+    }
+
+    /// Validation loss after every epoch.
+    pub fn val_losses(&self) -> Vec<f32> {
+        return self.epochs.iter().map(|metrics| metrics.val_loss).collect();
+    }
+
+    /// Validation accuracy after every epoch.
+    pub fn val_accuracies(&self) -> Vec<f32> {
+        return self.epochs.iter().map(|metrics| metrics.val_acc).collect();
+    }
+
+    pub(crate) fn push(&mut self, metrics: EpochMetrics) {
+        self.epochs.push(metrics);
+    }
+}
+
+/// Output format of a [`MetricsLogger`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricsFormat {
+    /// Comma-separated values with a header row.
+    Csv,
+    /// One JSON object per line.
+    JsonLines,
+}
+
+impl MetricsFormat {
+    /// Picks the format from the file extension: `.jsonl`/`.json` select
+    /// JSON Lines, anything else CSV.
+    pub fn from_path(path: &Path) -> Self {
+        return match path.extension().and_then(|ext| ext.to_str()) {
+            Some("jsonl") | Some("json") => MetricsFormat::JsonLines,
+            _ => MetricsFormat::Csv,
+        };
+    }
+}
+
+/// Appends one record per epoch to a CSV or JSON Lines file for later plotting.
+///
+/// Every record is flushed immediately, so the file can be watched while
+/// training is still running.
+pub struct MetricsLogger {
+    writer: BufWriter<File>,
+    format: MetricsFormat,
+}
+
+impl MetricsLogger {
+    const CSV_HEADER: &'static str = "epoch,train_loss,train_acc,val_loss,val_acc";
+
+    /// Creates (or truncates) the log file at `path`.
+    pub fn create(path: &Path) -> Result<Self> {
+        let format = MetricsFormat::from_path(path);
+        let mut logger = Self {
+            writer: BufWriter::new(File::create(path)?),
+            format,
+        };
+        if format == MetricsFormat::Csv {
+            writeln!(logger.writer, "{}", Self::CSV_HEADER)?;
+            logger.writer.flush()?;
+        }
+        return Ok(logger);
+    }
+
+    /// Opens the log file at `path` for appending, e.g. when resuming a run.
+    ///
+    /// A CSV header is only written if the file is new or empty.
+    pub fn append(path: &Path) -> Result<Self> {
+        let format = MetricsFormat::from_path(path);
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let is_empty = file.metadata()?.len() == 0;
+        let mut logger = Self {
+            writer: BufWriter::new(file),
+            format,
+        };
+        if is_empty && format == MetricsFormat::Csv {
+            writeln!(logger.writer, "{}", Self::CSV_HEADER)?;
+            logger.writer.flush()?;
+        }
+        return Ok(logger);
+    }
+
+    /// Writes one record.
+    pub fn log(&mut self, metrics: &EpochMetrics) -> Result<()> {
+        match self.format {
+            MetricsFormat::Csv => writeln!(
+                self.writer,
+                "{},{},{},{},{}",
+                metrics.epoch,
+                metrics.train_loss,
+                metrics.train_acc,
+                metrics.val_loss,
+                metrics.val_acc
+            )?,
+            MetricsFormat::JsonLines => writeln!(
+                self.writer,
+                "{{\"epoch\":{},\"train_loss\":{},\"train_acc\":{},\"val_loss\":{},\"val_acc\":{}}}",
+                metrics.epoch,
+                json_number(metrics.train_loss),
+                json_number(metrics.train_acc),
+                json_number(metrics.val_loss),
+                json_number(metrics.val_acc)
+            )?,
+        }
+        self.writer.flush()?;
+        return Ok(());
+    }
+}
+
+/// Formats `value` as a JSON number; JSON has no NaN/infinity, so those become `null`.
+pub(crate) fn json_number(value: f32) -> String {
+    if value.is_finite() {
+        return value.to_string();
+    }
+    return "null".to_string();
+}
+
+/// Encodes `value` as a JSON string literal.
+pub(crate) fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                out.push_str(&format!("\\u{:04x}", c as u32));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    return out;
+}
@@ -0,0 +1,101 @@
+//! Read-only memory maps of files, for datasets larger than RAM.
+
+use super::error::Result;
+use std::fmt;
+use std::fs::File;
+
+/// The contents of a file mapped read-only into memory.
+///
+/// Pages are read from disk when first touched and may be evicted again
+/// under memory pressure, so mapping costs no memory up front. On platforms
+/// other than Unix the file is read into memory instead.
+///
+/// The file must not be truncated or modified while it is mapped.
+pub(crate) struct Mmap {
+    #[cfg(unix)]
+    ptr: *const u8,
+    #[cfg(unix)]
+    len: usize,
+    #[cfg(not(unix))]
+    bytes: Vec<u8>,
+}
+
+// SAFETY: the mapping is read-only and owned by `Mmap` alone, so sharing it
+// between threads is like sharing a `&[u8]`.
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    /// Maps all of `file`.
+    #[cfg(unix)]
+    pub(crate) fn map(file: &File) -> Result<Self> {
+        use std::os::fd::AsRawFd;
+
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            // mmap rejects empty mappings.
+            return Ok(Self {
+                ptr: std::ptr::NonNull::dangling().as_ptr(),
+                len,
+            });
+        }
+        // SAFETY: a fresh private read-only mapping of a valid descriptor;
+        // the result is checked before use.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        return Ok(Self {
+            ptr: ptr as *const u8,
+            len,
+        });
+    }
+
+    /// Reads all of `file`, lacking memory maps.
+    #[cfg(not(unix))]
+    pub(crate) fn map(file: &File) -> Result<Self> {
+        use std::io::Read;
+
+        let mut bytes = Vec::new();
+        (&*file).read_to_end(&mut bytes)?;
+        return Ok(Self { bytes });
+    }
+
+    pub(crate) fn bytes(&self) -> &[u8] {
+        #[cfg(unix)]
+        // SAFETY: `ptr` points to `len` readable bytes until `drop`.
+        return unsafe { std::slice::from_raw_parts(self.ptr, self.len) };
+        #[cfg(not(unix))]
+        return &self.bytes;
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if self.len > 0 {
+            // SAFETY: unmaps exactly the mapping created by `map`.
+            unsafe {
+                libc::munmap(self.ptr as *mut libc::c_void, self.len);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Mmap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return f
+            .debug_struct("Mmap")
+            .field("len", &self.bytes().len())
+            .finish();
+    }
+}
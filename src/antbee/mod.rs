@@ -1,6 +1,127 @@
+mod backend;
+mod calibration;
+#[cfg(feature = "fs")]
+mod callback;
+#[cfg(feature = "fs")]
+mod checkpoint;
+mod codec;
+mod config;
+mod config_file;
+mod conv;
+#[cfg(feature = "fs")]
+mod crossval;
+#[cfg(feature = "fs")]
 mod dataset;
+#[cfg(feature = "fs")]
+mod dedup;
+mod ensemble;
+mod error;
+mod evaluation;
+#[cfg(feature = "fs")]
+mod inference;
+#[cfg(feature = "fs")]
+mod interrupt;
 mod kind;
+#[cfg(feature = "fs")]
+mod lazy;
+mod loss;
+#[cfg(feature = "fs")]
+mod manifest;
+#[cfg(feature = "fs")]
+mod metrics;
+#[cfg(feature = "fs")]
+mod mmap;
 mod model;
+mod noise;
+mod normalize;
+#[cfg(feature = "fs")]
+mod npz;
+#[cfg(feature = "fs")]
+mod onnx;
+#[cfg(all(feature = "gpu", unix))]
+mod opencl;
+mod optimizer;
+#[cfg(feature = "fs")]
+mod pack;
+mod preprocess;
+#[cfg(feature = "fs")]
+mod protobuf;
+mod quantize;
+#[cfg(feature = "fs")]
+mod report;
+#[cfg(feature = "fs")]
+mod review;
+mod saliency;
+mod sampler;
+mod scheduler;
+#[cfg(feature = "serve")]
+mod serve;
+mod simd;
+mod source;
+#[cfg(feature = "fs")]
+mod stats;
+#[cfg(feature = "fs")]
+mod tensorboard;
+#[cfg(feature = "fs")]
+mod torch;
+#[cfg(feature = "fs")]
+mod trainer;
+mod tta;
+#[cfg(feature = "fs")]
+mod tune;
+#[cfg(feature = "wasm")]
+mod wasm;
 
+pub use backend::*;
+#[cfg(feature = "fs")]
+pub use callback::*;
+#[cfg(feature = "fs")]
+pub use checkpoint::*;
+pub use config::*;
+#[cfg(feature = "fs")]
+pub use config_file::*;
+pub use conv::*;
+#[cfg(feature = "fs")]
+pub use crossval::*;
+#[cfg(feature = "fs")]
 pub use dataset::*;
+#[cfg(feature = "fs")]
+pub use dedup::*;
+pub use ensemble::*;
+pub use error::*;
+pub use evaluation::*;
+#[cfg(feature = "fs")]
+pub use inference::*;
+pub use kind::*;
+#[cfg(feature = "fs")]
+pub use lazy::*;
+pub use loss::*;
+#[cfg(feature = "fs")]
+pub use metrics::*;
 pub use model::*;
+pub use normalize::*;
+#[cfg(feature = "fs")]
+pub use npz::*;
+pub use optimizer::*;
+pub use preprocess::*;
+pub use quantize::*;
+#[cfg(feature = "fs")]
+pub use report::*;
+#[cfg(feature = "fs")]
+pub use review::*;
+pub use sampler::*;
+pub use scheduler::*;
+#[cfg(feature = "serve")]
+pub use serve::*;
+pub use source::*;
+#[cfg(feature = "fs")]
+pub use stats::*;
+#[cfg(feature = "fs")]
+pub use tensorboard::*;
+#[cfg(feature = "fs")]
+pub use trainer::*;
+pub use tta::*;
+#[cfg(feature = "fs")]
+pub use tune::*;
+#[cfg(feature = "wasm")]
+pub use wasm::*;
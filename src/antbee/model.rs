@@ -1,31 +1,168 @@
+use super::codec;
+use super::config::TrainConfig;
+use super::conv::ConvLayer;
+use super::conv::ConvNet;
+#[cfg(feature = "fs")]
 use super::dataset::Data;
-use super::dataset::Dataset;
+use super::error::Error;
+use super::error::Result;
+use super::evaluation::ConfusionMatrix;
+use super::evaluation::ReliabilityDiagram;
+use super::evaluation::RocCurve;
+use super::evaluation::ThresholdMetric;
+use super::kind::ClassMap;
 use super::kind::Kind;
+use super::kind::Prediction;
+use super::noise;
+use super::normalize::Normalizer;
+use super::optimizer::Sgd;
+use super::preprocess::Preprocess;
+use super::simd;
+use super::source::DatasetSource;
 use ndarray::Array1;
-use rand::random;
+use ndarray::Array2;
+use ndarray::ArrayView1;
+use ndarray::ArrayView2;
+use ndarray::ArrayViewMut1;
+use ndarray::Axis;
+use ndarray::CowArray;
+use ndarray::Ix2;
+use rand::Rng;
+#[cfg(feature = "fs")]
+use rand::rng;
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::io::BufReader;
+#[cfg(feature = "fs")]
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+#[cfg(feature = "fs")]
+use std::path::Path;
 
-/// A binary classification model using logistic regression with sigmoid activation.
+/// A multi-class classification model using softmax (multinomial logistic) regression.
 ///
-/// This model performs binary classification (Ant vs Bee) on 28x28 RGB images
-/// (3 channels * 28 * 28 = 2352 input features) using a single-layer neural network
-/// with sigmoid activation and cross-entropy loss.
+/// This model classifies images preprocessed according to its [`Preprocess`]
+/// (by default 28x28 RGB, i.e. 3 channels * 28 * 28 = 2352 input features)
+/// into one of `num_classes` classes using a single-layer neural network
+/// with softmax activation and categorical cross-entropy loss.
+///
+/// Optionally a convolutional front-end ([`ConvNet`]) first turns the image
+/// into FEATURE_DIM features for the softmax layer; without one,
+/// FEATURE_DIM = INPUT_DIM and the pixels are classified directly.
+#[derive(Debug, Clone)]
 pub struct Model {
-    /// Weight vector of shape (INPUT_DIM,).
-    /// Stores the learned parameters for each input feature.
-    w: Array1<f32>,
-    /// Bias term (intercept).
-    /// Allows the decision boundary to shift from the origin.
-    b: f32,
+    /// How raw images are turned into input vectors.
+    /// Determines the input dimensionality INPUT_DIM = preprocess.input_dim().
+    preprocess: Preprocess,
+    /// Convolutional feature extractor applied before the linear layer.
+    conv: Option<ConvNet>,
+    /// Weight matrix of shape (num_classes, FEATURE_DIM).
+    /// Row `k` holds the learned parameters of class `k` for each input feature.
+    w: Array2<f32>,
+    /// Bias vector of shape (num_classes,).
+    /// Allows each class's decision boundary to shift from the origin.
+    b: Array1<f32>,
+    /// Decision threshold on P(class 1 | x) for two-class models.
+    /// `None` predicts the most probable class.
+    threshold: Option<f32>,
+    /// Standardization applied to every input before the linear layer.
+    normalizer: Option<Normalizer>,
+    /// The logits are divided by this before the softmax; fit by
+    /// [`Model::calibrate`]. `1.0` leaves the probabilities uncalibrated.
+    temperature: f32,
+    /// Names of the output classes, taken from the training data.
+    classes: ClassMap,
+}
+
+/// Gradients of the loss with respect to the parameters of a [`Model`].
+///
+/// Also used by optimizers to hold per-parameter state such as momentum.
+#[derive(Debug, Clone)]
+pub struct Gradients {
+    /// Gradient w.r.t. the weight matrix, shape (num_classes, FEATURE_DIM).
+    pub(crate) w: Array2<f32>,
+    /// Gradient w.r.t. the bias vector, shape (num_classes,).
+    pub(crate) b: Array1<f32>,
+    /// Gradients w.r.t. the conv blocks, empty without a front-end.
+    pub(crate) conv: Vec<ConvLayer>,
+}
+
+impl Gradients {
+    /// Creates zero gradients matching the parameter shapes of `model`.
+    pub fn zeros_like(model: &Model) -> Self {
+        return Self {
+            w: Array2::zeros(model.w.raw_dim()),
+            b: Array1::zeros(model.b.raw_dim()),
+            conv: model.conv.as_ref().map_or(Vec::new(), ConvNet::zeros_like),
+        };
+    }
+
+    /// Adds `other` to these gradients element-wise.
+    pub fn add_assign(&mut self, other: &Gradients) {
+        self.w += &other.w;
+        self.b += &other.b;
+        for (layer, other) in self.conv.iter_mut().zip(&other.conv) {
+            layer.kernel += &other.kernel;
+            layer.bias += &other.bias;
+        }
+    }
+
+    /// Multiplies all gradients by `factor`.
+    pub fn scale(&mut self, factor: f32) {
+        self.w *= factor;
+        self.b *= factor;
+        for layer in &mut self.conv {
+            layer.kernel *= factor;
+            layer.bias *= factor;
+        }
+    }
+
+    /// Global L2 norm of all gradients, as if they were one vector.
+    pub fn norm(&self) -> f32 {
+        let squares = |values: &mut dyn Iterator<Item = &f32>| values.map(|g| g * g).sum::<f32>();
+        let mut total = squares(&mut self.w.iter()) + squares(&mut self.b.iter());
+        for layer in &self.conv {
+            total += squares(&mut layer.kernel.iter()) + squares(&mut layer.bias.iter());
+        }
+        return total.sqrt();
+    }
+
+    /// Scales the gradients down so that their [norm](Gradients::norm) is
+    /// at most `max_norm`, keeping their direction.
+    ///
+    /// # Returns
+    /// The norm before clipping.
+    pub fn clip_norm(&mut self, max_norm: f32) -> f32 {
+        let norm = self.norm();
+        if norm > max_norm {
+            self.scale(max_norm / norm);
+        }
+        return norm;
+    }
 }
 
 impl Model {
-    /// Learning rate for gradient descent optimization.
-    /// Controls the step size during weight updates.
-    const LEARNING_RATE: f32 = 0.001;
+    /// Magic bytes at the start of a saved model file.
+    const MAGIC: &'static [u8; 8] = b"ANTBEEMD";
+
+    /// Current version of the saved model format.
+    ///
+    /// Version 1 stored a single sigmoid weight vector; it is still readable
+    /// and is converted to an equivalent two-class softmax model.
+    /// Versions 1 and 2 predate configurable preprocessing and imply
+    /// `Preprocess::default()`.
+    /// Versions before 4 carry no decision threshold, versions before 5
+    /// no normalizer, versions before 6 no conv front-end, versions before
+    /// 7 no temperature, versions before 8 no class names; their classes
+    /// are named after the indices. Versions before 9 always used
+    /// [`ResizeMode::Stretch`](super::ResizeMode::Stretch).
+    const FORMAT_VERSION: u32 = 9;
 
-    /// Input dimensionality.
-    /// 3 channels (RGB) * 28 pixels * 28 pixels = 2352 features.
-    const INPUT_DIM: usize = 2352;
+    /// Number of samples processed per matrix product during evaluation.
+    /// Bounds the size of the intermediate logit matrix on large datasets.
+    pub(crate) const EVAL_BATCH_SIZE: usize = 1024;
 
     /// Creates a new `Model` with Xavier/He-inspired weight initialization.
     ///
@@ -33,148 +170,942 @@ impl Model {
     /// scale = sqrt(2.0 / INPUT_DIM). This helps prevent vanishing/exploding
     /// gradients in early training stages.
     ///
+    /// # Arguments
+    /// * `preprocess` - Preprocessing of the images the model will see;
+    ///   must match the training dataset's.
+    /// * `num_classes` - Number of output classes (at least 2).
+    ///
     /// # Returns
     /// A new `Model` instance with initialized weights and zero bias.
-    pub fn new() -> Self {
-        let scale = (2.0 / Self::INPUT_DIM as f32).sqrt();
+    #[cfg(feature = "fs")]
+    pub fn new(preprocess: Preprocess, num_classes: usize) -> Self {
+        return Self::from_rng(preprocess, num_classes, &mut rng());
+    }
+
+    /// Creates a new `Model` like [`Model::new`], drawing the initial
+    /// weights from `rng` so that runs can be reproduced from a seed.
+    ///
+    /// # Arguments
+    /// * `preprocess` - Preprocessing of the images the model will see.
+    /// * `num_classes` - Number of output classes (at least 2).
+    /// * `rng` - Random number generator used for weight initialization.
+    pub fn from_rng(preprocess: Preprocess, num_classes: usize, rng: &mut impl Rng) -> Self {
+        return Self::with_front_end(preprocess, None, num_classes, rng);
+    }
+
+    /// Creates a model with a convolutional front-end of one 3x3
+    /// conv + ReLU + 2x2 max-pool block per entry of `channels`, the entry
+    /// giving the block's number of output channels.
+    ///
+    /// E.g. `&[8, 16]` turns 28x28 images into 16 feature maps of 7x7.
+    ///
+    /// # Arguments
+    /// * `preprocess` - Preprocessing of the images the model will see.
+    /// * `channels` - Output channels of every conv block, in order.
+    /// * `num_classes` - Number of output classes (at least 2).
+    /// * `rng` - Random number generator used for weight initialization.
+    pub fn with_conv(
+        preprocess: Preprocess,
+        channels: &[usize],
+        num_classes: usize,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let conv = ConvNet::from_rng(&preprocess, channels, rng);
+        return Self::with_front_end(preprocess, Some(conv), num_classes, rng);
+    }
+
+    fn with_front_end(
+        preprocess: Preprocess,
+        conv: Option<ConvNet>,
+        num_classes: usize,
+        rng: &mut impl Rng,
+    ) -> Self {
+        assert!(num_classes >= 2, "a classifier needs at least 2 classes");
+        let feature_dim = match &conv {
+            Some(conv) => conv.output_dim(),
+            None => preprocess.input_dim(),
+        };
+        let scale = (2.0 / feature_dim as f32).sqrt();
         return Self {
-            w: Array1::from_shape_fn(Self::INPUT_DIM, |_| (random::<f32>() - 0.5) * 2.0 * scale),
-            b: 0.0,
+            preprocess,
+            conv,
+            w: Array2::from_shape_fn((num_classes, feature_dim), |_| {
+                (rng.random::<f32>() - 0.5) * 2.0 * scale
+            }),
+            b: Array1::zeros(num_classes),
+            threshold: None,
+            normalizer: None,
+            temperature: 1.0,
+            classes: ClassMap::indices(num_classes),
+        };
+    }
+
+    /// Preprocessing the model was built for.
+    pub fn preprocess(&self) -> &Preprocess {
+        return &self.preprocess;
+    }
+
+    /// Convolutional front-end, if the model has one.
+    pub fn conv(&self) -> Option<&ConvNet> {
+        return self.conv.as_ref();
+    }
+
+    /// Weight matrix of the softmax layer, shape (num_classes, FEATURE_DIM).
+    pub fn weights(&self) -> ArrayView2<'_, f32> {
+        return self.w.view();
+    }
+
+    /// Bias vector of shape (num_classes,).
+    pub fn bias(&self) -> ArrayView1<'_, f32> {
+        return self.b.view();
+    }
+
+    /// Returns the number of input features the model expects.
+    pub fn input_dim(&self) -> usize {
+        return self.preprocess.input_dim();
+    }
+
+    /// Returns the number of features the softmax layer sees: the output
+    /// size of the conv front-end, or INPUT_DIM without one.
+    pub fn feature_dim(&self) -> usize {
+        return self.w.ncols();
+    }
+
+    /// Returns the number of classes the model distinguishes.
+    pub fn num_classes(&self) -> usize {
+        return self.w.nrows();
+    }
+
+    /// Decision threshold set with [`Model::set_threshold`], if any.
+    pub fn threshold(&self) -> Option<f32> {
+        return self.threshold;
+    }
+
+    /// Sets the probability P(class 1 | x) above which a two-class model
+    /// predicts class 1, trading precision against recall.
+    ///
+    /// By default the most probable class is predicted, which is the same
+    /// as a threshold of 0.5. `None` restores the default.
+    ///
+    /// # Panics
+    /// Panics if the model has more than two classes or `threshold` is not
+    /// within [0, 1].
+    pub fn set_threshold(&mut self, threshold: Option<f32>) {
+        if let Some(t) = threshold {
+            assert_eq!(
+                self.num_classes(),
+                2,
+                "a decision threshold needs a two-class model"
+            );
+            assert!((0.0..=1.0).contains(&t), "threshold must be in [0, 1]");
+        }
+        self.threshold = threshold;
+    }
+
+    /// Input standardization set with [`Model::set_normalizer`], if any.
+    pub fn normalizer(&self) -> Option<&Normalizer> {
+        return self.normalizer.as_ref();
+    }
+
+    /// Makes the model standardize every input with `normalizer` before
+    /// the linear layer, during training as well as prediction.
+    ///
+    /// Set it before training, usually to `Normalizer::fit(&train)`; the
+    /// statistics are saved with the model so new images are normalized
+    /// the same way.
+    ///
+    /// # Panics
+    /// Panics if `normalizer` has a different channel count than the
+    /// model's preprocessing.
+    pub fn set_normalizer(&mut self, normalizer: Option<Normalizer>) {
+        if let Some(normalizer) = &normalizer {
+            assert!(
+                normalizer.matches(&self.preprocess),
+                "normalizer and model use different channel counts"
+            );
+        }
+        self.normalizer = normalizer;
+    }
+
+    /// Returns the softmax temperature, `1.0` if the model is not calibrated.
+    pub fn temperature(&self) -> f32 {
+        return self.temperature;
+    }
+
+    /// Sets the temperature the logits are divided by before the softmax.
+    ///
+    /// Temperatures above 1 soften overconfident probabilities, below 1
+    /// sharpen them. The most probable class never changes.
+    ///
+    /// # Panics
+    /// Panics unless `temperature` is positive and finite.
+    pub fn set_temperature(&mut self, temperature: f32) {
+        assert!(
+            temperature > 0.0 && temperature.is_finite(),
+            "temperature must be positive and finite"
+        );
+        self.temperature = temperature;
+    }
+
+    /// Names of the classes the model predicts.
+    ///
+    /// Training sets them to the classes of the training data; models
+    /// created otherwise, or saved before class names were stored, name
+    /// their classes "0", "1", ...
+    pub fn class_map(&self) -> &ClassMap {
+        return &self.classes;
+    }
+
+    /// Sets the names of the classes the model predicts.
+    ///
+    /// # Panics
+    /// Panics if `classes` does not have one name per class.
+    pub fn set_class_map(&mut self, classes: ClassMap) {
+        assert_eq!(
+            classes.len(),
+            self.num_classes(),
+            "class map and model have different numbers of classes"
+        );
+        self.classes = classes;
+    }
+
+    /// Returns `x` standardized by the normalizer, or `x` itself without one.
+    fn normalized_batch<'a>(&self, x: ArrayView2<'a, f32>) -> CowArray<'a, f32, Ix2> {
+        let Some(normalizer) = &self.normalizer else {
+            return x.into();
         };
+        let mut x = x.to_owned();
+        normalizer.apply_batch(x.view_mut());
+        return x.into();
+    }
+
+    /// Softmax activation function.
+    ///
+    /// Maps a vector of real-valued logits to a probability distribution.
+    /// The maximum logit is subtracted first so that `exp` cannot overflow.
+    ///
+    /// The logits are overwritten in place with the probabilities
+    /// exp(z_k) / sum_j exp(z_j) for every class k.
+    ///
+    /// # Arguments
+    /// * `z` - The input logits.
+    pub(crate) fn softmax(mut z: ArrayViewMut1<f32>) {
+        let max = z.fold(f32::NEG_INFINITY, |acc, &v| acc.max(v));
+        z.mapv_inplace(|v| (v - max).exp());
+        let sum = z.sum();
+        z /= sum;
     }
 
-    /// Sigmoid activation function.
+    /// Computes the probability of each class for the input.
     ///
-    /// Maps any real-valued number to the range (0, 1), which can be
-    /// interpreted as a probability.
+    /// Performs forward propagation: z = W·f(x) + b, where f is the conv
+    /// front-end (the identity without one), then applies softmax to z / T,
+    /// T being the [temperature](Model::temperature).
     ///
     /// # Arguments
-    /// * `z` - The input value (logit).
+    /// * `x` - Input feature vector of shape (INPUT_DIM,).
     ///
     /// # Returns
-    /// The sigmoid of `z`: 1 / (1 + exp(-z))
-    fn sigmoid(z: f32) -> f32 {
-        return 1.0 / (1.0 + (-z).exp());
+    /// A vector of shape (num_classes,) whose entry k is P(class = k | x).
+    pub fn predict_probs(&self, x: ArrayView1<f32>) -> Array1<f32> {
+        return self
+            .predict_probs_batch(x.insert_axis(Axis(0)))
+            .index_axis_move(Axis(0), 0);
     }
 
-    /// Computes the probability that the input belongs to class `Bee`.
+    /// Computes class probabilities for a batch of inputs at once.
     ///
-    /// Performs forward propagation: z = w·x + b, then applies sigmoid.
+    /// All logits are computed with a single matrix product
+    /// Z = X·W^T + b, which is much faster than one dot product per sample.
     ///
     /// # Arguments
-    /// * `x` - Input feature vector of shape (INPUT_DIM,).
+    /// * `x` - Input matrix of shape (batch_size, INPUT_DIM), one sample per row.
     ///
     /// # Returns
-    /// A value in (0, 1) representing P(class = Bee | x).
-    fn predict_prob(&self, x: &Array1<f32>) -> f32 {
-        let z = self.w.dot(x) + self.b;
-        return Self::sigmoid(z);
+    /// A matrix of shape (batch_size, num_classes) whose row i holds
+    /// the class probabilities of sample i.
+    pub fn predict_probs_batch(&self, x: ArrayView2<f32>) -> Array2<f32> {
+        let mut z = self.logits_batch(x);
+        if self.temperature != 1.0 {
+            z /= self.temperature;
+        }
+        for row in z.axis_iter_mut(Axis(0)) {
+            Self::softmax(row);
+        }
+        return z;
     }
 
-    /// Predicts the class label for the given input.
+    /// Computes the logits Z = W·f(X) + b of a batch, before temperature
+    /// scaling and softmax.
     ///
-    /// Uses a threshold of 0.5 on the predicted probability.
+    /// # Arguments
+    /// * `x` - Input matrix of shape (batch_size, INPUT_DIM), one sample per row.
+    ///
+    /// # Returns
+    /// A matrix of shape (batch_size, num_classes).
+    pub fn logits_batch(&self, x: ArrayView2<f32>) -> Array2<f32> {
+        let x = self.normalized_batch(x);
+        return match &self.conv {
+            Some(conv) => self.linear(conv.forward_batch(x.view()).view()),
+            None => self.linear(x.view()),
+        };
+    }
+
+    /// Computes the embedding of the input: the penultimate-layer
+    /// activations f(x) the softmax layer classifies, of shape
+    /// (FEATURE_DIM,).
+    ///
+    /// With a conv front-end these are its flattened output features;
+    /// without one, the (normalized) input itself. Similar images have
+    /// nearby embeddings, which makes them useful for clustering,
+    /// nearest-neighbor search or as inputs to another model.
+    pub fn embed(&self, x: ArrayView1<f32>) -> Array1<f32> {
+        return self
+            .embed_batch(x.insert_axis(Axis(0)))
+            .index_axis_move(Axis(0), 0);
+    }
+
+    /// Computes the embeddings of a batch of inputs, one row per sample,
+    /// like [`Model::embed`].
+    ///
+    /// # Returns
+    /// A matrix of shape (batch_size, FEATURE_DIM).
+    pub fn embed_batch(&self, x: ArrayView2<f32>) -> Array2<f32> {
+        let x = self.normalized_batch(x);
+        return match &self.conv {
+            Some(conv) => conv.forward_batch(x.view()),
+            None => x.into_owned(),
+        };
+    }
+
+    /// Linear layer Z = X·W^T + b on a batch of (normalized, extracted) features.
+    fn linear(&self, x: ArrayView2<f32>) -> Array2<f32> {
+        return simd::matmul_transposed(x, self.w.view()) + &self.b;
+    }
+
+    /// Returns the index of the largest entry of `probs`.
+    pub(crate) fn argmax(probs: ArrayView1<f32>) -> usize {
+        let mut best = 0;
+        for (k, &p) in probs.iter().enumerate() {
+            if p > probs[best] {
+                best = k;
+            }
+        }
+        return best;
+    }
+
+    /// Returns the class predicted from `probs`, honoring the threshold.
+    pub(crate) fn decide(&self, probs: ArrayView1<f32>) -> usize {
+        return match self.threshold {
+            Some(t) => (probs[1] >= t) as usize,
+            None => Self::argmax(probs),
+        };
+    }
+
+    /// Classifies a single image file.
+    ///
+    /// The image is decoded and preprocessed exactly like the training data
+    /// (see [`Preprocess::load_image`]), so a trained model can be used directly
+    /// without building a `Dataset`.
+    ///
+    /// # Arguments
+    /// * `path` - Path to an image file.
+    ///
+    /// # Returns
+    /// The predicted class and the model's probability for that class,
+    /// or an error if the file cannot be read or decoded.
+    #[cfg(feature = "fs")]
+    pub fn predict_image(&self, path: &Path) -> Result<Prediction> {
+        let x = self.preprocess.load_image(path)?;
+        return Ok(self.predict_with_probability(x.view()));
+    }
+
+    /// Classifies an encoded image held in memory, in any format
+    /// [`Model::predict_image`] accepts.
+    pub fn predict_image_bytes(&self, bytes: &[u8]) -> Result<Prediction> {
+        let x = self.preprocess.load_image_bytes(bytes)?;
+        return Ok(self.predict_with_probability(x.view()));
+    }
+
+    /// Classifies a raw RGB image of `width` x `height` pixels, three bytes
+    /// per pixel in row-major order (see [`Preprocess::apply_rgb`]).
+    ///
+    /// Unlike [`Model::predict_image`] this needs no filesystem or image
+    /// decoder, which makes it the entry point for WebAssembly builds.
+    pub fn predict_from_rgb(&self, rgb: &[u8], width: u32, height: u32) -> Result<Prediction> {
+        let x = self.preprocess.apply_rgb(rgb, width, height)?;
+        return Ok(self.predict_with_probability(x.view()));
+    }
+
+    /// Predicts the class label for the given input together with its probability.
     ///
     /// # Arguments
     /// * `x` - Input feature vector.
     ///
     /// # Returns
-    /// * `Kind::Bee` if P(Bee) > 0.5
-    /// * `Kind::Ant` otherwise
-    fn predict(&self, x: &Array1<f32>) -> Kind {
-        if self.predict_prob(x) > 0.5 {
-            return Kind::Bee;
-        } else {
-            return Kind::Ant;
-        }
+    /// The predicted class and P(class | x) for it.
+    pub fn predict_with_probability(&self, x: ArrayView1<f32>) -> Prediction {
+        let probs = self.predict_probs(x);
+        let k = self.decide(probs.view());
+        return Prediction {
+            kind: Kind(k),
+            probability: probs[k],
+        };
     }
 
-    /// Computes the binary cross-entropy loss.
+    /// Computes the categorical cross-entropy loss.
     ///
-    /// For numerical stability, predictions are clamped to [EPS, 1-EPS]
-    /// to avoid log(0) which would result in infinity.
+    /// For numerical stability, the probability of the true class is clamped
+    /// to [EPS, 1-EPS] to avoid log(0) which would result in infinity.
     ///
     /// # Arguments
-    /// * `y_pred` - Predicted probability (output of sigmoid).
+    /// * `probs` - Predicted class probabilities (output of softmax).
     /// * `y_true` - Ground truth label.
     ///
     /// # Returns
-    /// The cross-entropy loss value.
-    fn cross_entropy_loss(y_pred: f32, y_true: Kind) -> f32 {
+    /// The cross-entropy loss value: -ln(probs[y_true]).
+    pub(crate) fn cross_entropy_loss(probs: ArrayView1<f32>, y_true: Kind) -> f32 {
         const EPS: f32 = 1e-7;
-        return match y_true {
-            Kind::Ant => -(1.0 - y_pred.clamp(EPS, 1.0 - EPS)).ln(),
-            Kind::Bee => -y_pred.clamp(EPS, 1.0 - EPS).ln(),
+        return -probs[y_true.index()].clamp(EPS, 1.0 - EPS).ln();
+    }
+
+    /// Computes the L2 regularization term.
+    ///
+    /// Only the weights (including conv kernels) are penalized; the biases
+    /// are left unregularized.
+    ///
+    /// # Arguments
+    /// * `l2` - The L2 penalty coefficient.
+    ///
+    /// # Returns
+    /// The penalty 0.5 * l2 * ||W||^2 (squared Frobenius norm).
+    fn l2_penalty(&self, l2: f32) -> f32 {
+        if l2 == 0.0 {
+            return 0.0;
+        }
+        let conv_norm = self.conv.as_ref().map_or(0.0, ConvNet::kernel_norm_sq);
+        return 0.5 * l2 * (self.w.iter().map(|v| v * v).sum::<f32>() + conv_norm);
+    }
+
+    /// Returns the loss weight of class `kind` under `config`.
+    ///
+    /// # Arguments
+    /// * `config` - Training hyperparameters (class weights).
+    /// * `kind` - The class of the training example.
+    ///
+    /// # Returns
+    /// `config.class_weights[kind]`, or 1.0 when no weights are configured.
+    fn class_weight(config: &TrainConfig, kind: Kind) -> f32 {
+        return match &config.class_weights {
+            Some(weights) => weights[kind.index()],
+            None => 1.0,
         };
     }
 
-    /// Performs backward propagation and updates model parameters.
+    /// Returns the loss `config.loss` of logits `z` and its gradient, with
+    /// the target smoothed by `config.label_smoothing` = e:
+    ///
+    /// l_e(z, y) = (1 - e) * l(z, y) + (e / K) * sum_k l(z, k)
+    ///
+    /// For cross-entropy this is exactly the cross-entropy against the
+    /// soft target (1 - e) * onehot(y) + e / K, with gradient probs - target.
+    fn smoothed_loss(config: &TrainConfig, z: ArrayView1<f32>, kind: Kind) -> (f32, Array1<f32>) {
+        let (loss, epsilon) = (&config.loss, config.label_smoothing);
+        if epsilon == 0.0 {
+            return (loss.forward(z, kind), loss.gradient(z, kind));
+        }
+        let share = epsilon / z.len() as f32;
+        let mut value = (1.0 - epsilon) * loss.forward(z, kind);
+        let mut grad = loss.gradient(z, kind) * (1.0 - epsilon);
+        for class in (0..z.len()).map(Kind) {
+            value += share * loss.forward(z, class);
+            grad.scaled_add(share, &loss.gradient(z, class));
+        }
+        return (value, grad);
+    }
+
+    /// Computes the loss and its gradients on a batch of samples.
     ///
-    /// Computes gradients of the loss with respect to weights and bias,
-    /// then performs gradient descent update.
+    /// Performs forward and backward propagation without modifying the
+    /// parameters, so several batches can be accumulated (see
+    /// [`GradientAccumulator`](super::GradientAccumulator)) before the
+    /// optimizer applies an update.
     ///
     /// # Mathematical Derivations
-    /// - dL/dz = prob - y (where y is 0 for Ant, 1 for Bee)
-    /// - dL/dw = x * dL/dz (chain rule)
-    /// - dL/db = dL/dz
+    /// For every sample i of the batch of size n:
+    /// - dL/dz_i = c_i * dl(z_i, y_i)/dz_i, where l is `config.loss` and c_i
+    ///   the weight of the sample's class; for the default cross-entropy
+    ///   this is c_i * (probs_i - onehot(y_i)), or c_i * (probs_i - target_i)
+    ///   with the smoothed target of `config.label_smoothing`
+    ///
+    /// and over the batch:
+    /// - dL/dW = (1/n) * sum_i dL/dz_i ⊗ x_i + l2 * W (mean outer product
+    ///   plus weight decay)
+    /// - dL/db = (1/n) * sum_i dL/dz_i
+    ///
+    /// With a conv front-end, x_i are the extracted features, and
+    /// dL/dx = (1/n) * dL/dz · W is backpropagated through the conv blocks
+    /// (see [`ConvNet`]).
     ///
     /// # Arguments
-    /// * `prob` - Predicted probability from forward pass.
-    /// * `data` - Training data containing input features and label.
-    fn backward(&mut self, prob: f32, data: &Data) {
-        // Compute gradient of loss w.r.t. z (pre-activation)
-        let dz = match data.get_kind() {
-            Kind::Ant => prob,       // y = 0, so dz = prob - 0 = prob
-            Kind::Bee => prob - 1.0, // y = 1, so dz = prob - 1
+    /// * `x` - Input matrix of shape (batch_size, INPUT_DIM), one sample per row.
+    /// * `labels` - Ground truth label of every row of `x`.
+    /// * `config` - Training hyperparameters (loss, L2 penalty, class weights).
+    ///
+    /// # Returns
+    /// The summed loss of the batch, each sample weighted by its class
+    /// weight and including the L2 regularization term, and the mean
+    /// gradients over the batch.
+    ///
+    /// `config.input_dropout` and `config.input_noise` are ignored here,
+    /// since they need randomness; see [`Model::gradients_with_noise`].
+    pub fn gradients(
+        &self,
+        x: ArrayView2<f32>,
+        labels: &[Kind],
+        config: &TrainConfig,
+    ) -> (f32, Gradients) {
+        return self.backprop(self.normalized_batch(x), labels, config);
+    }
+
+    /// Like [`Model::gradients`], but first corrupts the normalized inputs
+    /// with `config.input_noise` and `config.input_dropout`, drawing the
+    /// noise and the dropout masks from `rng`.
+    ///
+    /// # Panics
+    /// Panics unless `config.input_dropout` is in [0, 1).
+    pub fn gradients_with_noise(
+        &self,
+        x: ArrayView2<f32>,
+        labels: &[Kind],
+        config: &TrainConfig,
+        rng: &mut impl Rng,
+    ) -> (f32, Gradients) {
+        let mut x = self.normalized_batch(x).into_owned();
+        noise::corrupt(x.view_mut(), config.input_dropout, config.input_noise, rng);
+        return self.backprop(x.into(), labels, config);
+    }
+
+    /// Forward and backward pass of [`Model::gradients`] on normalized inputs.
+    fn backprop(
+        &self,
+        x: CowArray<f32, Ix2>,
+        labels: &[Kind],
+        config: &TrainConfig,
+    ) -> (f32, Gradients) {
+        assert_eq!(x.nrows(), labels.len(), "need one label per sample");
+        let n = labels.len() as f32;
+
+        let (x, caches) = match &self.conv {
+            Some(conv) => {
+                let (features, caches) = conv.forward_train(x.view());
+                (CowArray::from(features), caches)
+            }
+            None => (x, Vec::new()),
         };
+        let mut dz = self.linear(x.view()); // Forward pass
+        let mut loss = n * self.l2_penalty(config.l2);
+        for (mut row, &kind) in dz.axis_iter_mut(Axis(0)).zip(labels) {
+            let weight = Self::class_weight(config, kind);
+            let (sample_loss, grad) = Self::smoothed_loss(config, row.view(), kind);
+            loss += weight * sample_loss;
+            row.assign(&(grad * weight)); // dz = c * dl/dz
+        }
 
         // Compute gradients w.r.t. parameters
-        let dw = data.get_data() * dz; // dL/dw = x * dz
-        let db = dz; // dL/db = dz
+        let mut dw = simd::transposed_matmul(dz.view(), x.view()) / n; // dL/dW = mean of dz ⊗ x
+        if config.l2 != 0.0 {
+            dw.scaled_add(config.l2, &self.w); // dL/dW += l2 * W
+        }
+        let db = dz.sum_axis(Axis(0)) / n; // dL/db = mean of dz
+
+        let mut conv_grads = Vec::new();
+        if let Some(conv) = &self.conv {
+            let d_features = simd::matmul(dz.view(), self.w.view()) / n; // dL/dx for the front-end
+            (conv_grads, _) = conv.backward(&caches, d_features.view(), false);
+            for (grads, layer) in conv_grads.iter_mut().zip(conv.layers()) {
+                if config.l2 != 0.0 {
+                    grads.kernel.scaled_add(config.l2, &layer.kernel);
+                }
+            }
+        }
 
-        // Gradient descent parameter update
-        // w = w - learning_rate * dw
-        // b = b - learning_rate * db
-        self.w.scaled_add(-Self::LEARNING_RATE, &dw);
-        self.b -= Self::LEARNING_RATE * db;
+        return (
+            loss,
+            Gradients {
+                w: dw,
+                b: db,
+                conv: conv_grads,
+            },
+        );
+    }
+
+    /// Adds `scale * delta` to the parameters.
+    ///
+    /// Gradient descent calls this with `scale = -learning_rate`.
+    ///
+    /// # Arguments
+    /// * `scale` - Factor applied to `delta` before adding it.
+    /// * `delta` - Per-parameter update direction.
+    pub(crate) fn apply_update(&mut self, scale: f32, delta: &Gradients) {
+        self.w.scaled_add(scale, &delta.w);
+        self.b.scaled_add(scale, &delta.b);
+        if let Some(conv) = &mut self.conv {
+            for (layer, delta) in conv.layers_mut().iter_mut().zip(&delta.conv) {
+                layer.kernel.scaled_add(scale, &delta.kernel);
+                layer.bias.scaled_add(scale, &delta.bias);
+            }
+        }
+    }
+
+    /// Overwrites the parameters with `params`, which has the shapes of
+    /// [`Gradients::zeros_like`] for this model.
+    #[cfg(feature = "fs")]
+    pub(crate) fn set_parameters(&mut self, params: Gradients) {
+        debug_assert_eq!(self.w.raw_dim(), params.w.raw_dim());
+        debug_assert_eq!(self.b.raw_dim(), params.b.raw_dim());
+        self.w = params.w;
+        self.b = params.b;
+        if let Some(conv) = &mut self.conv {
+            for (layer, params) in conv.layers_mut().iter_mut().zip(params.conv) {
+                *layer = params;
+            }
+        }
     }
 
     /// Performs one training step on a single data point.
     ///
-    /// Executes forward propagation, computes loss, and performs
-    /// backward propagation with parameter update.
+    /// Executes forward propagation, computes loss, performs
+    /// backward propagation and lets `optimizer` update the parameters.
     ///
     /// # Arguments
     /// * `data` - A single training example.
+    /// * `optimizer` - Optimizer applying the parameter update.
+    /// * `learning_rate` - Step size for this update, usually
+    ///   `config.learning_rate_at(epoch)`.
+    /// * `config` - Training hyperparameters.
     ///
     /// # Returns
-    /// The computed loss value for this training step.
-    pub fn train_step(&mut self, data: &Data) -> f32 {
-        let prob = self.predict_prob(data.get_data()); // Forward pass
-        let loss = Self::cross_entropy_loss(prob, data.get_kind());
-        self.backward(prob, data); // Backward pass and update
+    /// The computed loss value for this training step, weighted by the
+    /// sample's class weight and including the L2 regularization term.
+    #[cfg(feature = "fs")]
+    pub fn train_step(
+        &mut self,
+        data: &Data,
+        optimizer: &mut Sgd,
+        learning_rate: f32,
+        config: &TrainConfig,
+    ) -> f32 {
+        return self.train_batch(
+            data.get_data().insert_axis(Axis(0)),
+            &[data.get_kind()],
+            optimizer,
+            learning_rate,
+            config,
+        );
+    }
 
+    /// Performs one training step on a mini-batch.
+    ///
+    /// Like [`Model::train_step`], but the update follows the mean gradient
+    /// of all rows of `x`.
+    ///
+    /// # Returns
+    /// The summed loss of the batch, see [`Model::gradients`].
+    pub fn train_batch(
+        &mut self,
+        x: ArrayView2<f32>,
+        labels: &[Kind],
+        optimizer: &mut Sgd,
+        learning_rate: f32,
+        config: &TrainConfig,
+    ) -> f32 {
+        let (loss, grads) = self.gradients(x, labels, config);
+        optimizer.step(self, &grads, learning_rate); // Parameter update
         return loss;
     }
 
     /// Evaluates the model accuracy on a given dataset.
     ///
-    /// Compares predicted labels against ground truth labels.
+    /// Compares predicted labels (the most probable class) against ground
+    /// truth labels.
     ///
     /// # Arguments
     /// * `dataset` - The dataset to evaluate on.
     ///
     /// # Returns
     /// Accuracy as a float in range [0.0, 1.0].
-    pub fn evaluate(&self, dataset: &Dataset) -> f32 {
-        let mut correct = 0;
-        for data in dataset.get_values() {
-            let pred = self.predict(data.get_data());
-            // Compare discriminant to check if variants match
-            if pred == data.get_kind() {
-                correct += 1;
+    pub fn evaluate(&self, dataset: &impl DatasetSource) -> f32 {
+        return self.confusion_matrix(dataset).accuracy();
+    }
+
+    /// Tallies predicted against ground truth labels on a given dataset.
+    ///
+    /// Samples are processed in batches of `EVAL_BATCH_SIZE` rows with
+    /// [`Model::predict_probs_batch`]. The matrix gives access to accuracy
+    /// as well as per-class recall, precision and F1.
+    ///
+    /// # Arguments
+    /// * `dataset` - The dataset to evaluate on.
+    ///
+    /// # Returns
+    /// The confusion matrix of the model's predictions.
+    pub fn confusion_matrix(&self, dataset: &impl DatasetSource) -> ConfusionMatrix {
+        let mut matrix = ConfusionMatrix::new(self.num_classes());
+        for (probs, labels) in self.batched_probs(dataset) {
+            for (row, &kind) in probs.axis_iter(Axis(0)).zip(labels) {
+                matrix.add(kind, Kind(self.decide(row)));
             }
         }
-        return correct as f32 / dataset.len() as f32;
+        return matrix;
+    }
+
+    /// Computes the mean cross-entropy loss on a given dataset.
+    ///
+    /// No parameters are updated, and neither the L2 penalty nor class
+    /// weights are applied, which makes this suitable for monitoring
+    /// validation loss.
+    ///
+    /// # Arguments
+    /// * `dataset` - The dataset to evaluate on.
+    ///
+    /// # Returns
+    /// The average loss over all samples.
+    pub fn loss(&self, dataset: &impl DatasetSource) -> f32 {
+        let mut total_loss = 0.0;
+        for (probs, labels) in self.batched_probs(dataset) {
+            for (row, &kind) in probs.axis_iter(Axis(0)).zip(labels) {
+                total_loss += Self::cross_entropy_loss(row, kind);
+            }
+        }
+        return total_loss / dataset.len() as f32;
+    }
+
+    /// Computes the ROC curve of class `positive` against all others.
+    ///
+    /// Each sample is scored by P(positive | x); the decision threshold of
+    /// the model is ignored.
+    ///
+    /// # Arguments
+    /// * `dataset` - The dataset to evaluate on.
+    /// * `positive` - The class treated as positive.
+    pub fn roc_curve(&self, dataset: &impl DatasetSource, positive: Kind) -> RocCurve {
+        let mut scores = Vec::with_capacity(dataset.len());
+        for (probs, labels) in self.batched_probs(dataset) {
+            for (row, &kind) in probs.axis_iter(Axis(0)).zip(labels) {
+                scores.push((row[positive.index()], kind == positive));
+            }
+        }
+        return RocCurve::from_scores(scores);
+    }
+
+    /// Bins the predictions on `dataset` by their confidence, the
+    /// probability of the predicted class, to compare it with the accuracy.
+    ///
+    /// # Arguments
+    /// * `dataset` - The dataset to evaluate on.
+    /// * `bins` - Number of equal-width confidence bins, usually
+    ///   [`ReliabilityDiagram::DEFAULT_BINS`].
+    pub fn reliability_diagram(
+        &self,
+        dataset: &impl DatasetSource,
+        bins: usize,
+    ) -> ReliabilityDiagram {
+        let mut predictions = Vec::with_capacity(dataset.len());
+        for (probs, labels) in self.batched_probs(dataset) {
+            for (row, &kind) in probs.axis_iter(Axis(0)).zip(labels) {
+                let predicted = self.decide(row);
+                predictions.push((row[predicted], predicted == kind.index()));
+            }
+        }
+        return ReliabilityDiagram::from_predictions(predictions, bins);
+    }
+
+    /// Finds the decision threshold that maximizes `metric` on `dataset`.
+    ///
+    /// Intended for a validation split; pass the result to
+    /// [`Model::set_threshold`].
+    ///
+    /// # Panics
+    /// Panics if the model has more than two classes.
+    pub fn find_best_threshold(
+        &self,
+        dataset: &impl DatasetSource,
+        metric: ThresholdMetric,
+    ) -> f32 {
+        assert_eq!(
+            self.num_classes(),
+            2,
+            "a decision threshold needs a two-class model"
+        );
+        return self.roc_curve(dataset, Kind(1)).best_threshold(metric);
+    }
+
+    /// Yields class probabilities for `dataset` in batches of
+    /// `EVAL_BATCH_SIZE` rows, together with the matching labels.
+    pub(crate) fn batched_probs<'a>(
+        &'a self,
+        dataset: &'a impl DatasetSource,
+    ) -> impl Iterator<Item = (Array2<f32>, &'a [Kind])> {
+        return (0..dataset.len())
+            .step_by(Self::EVAL_BATCH_SIZE)
+            .map(move |start| {
+                let range = start..(start + Self::EVAL_BATCH_SIZE).min(dataset.len());
+                let x = dataset.batch(range.clone());
+                (self.predict_probs_batch(x.view()), &dataset.labels()[range])
+            });
+    }
+
+    /// Serializes the model parameters to `writer`.
+    ///
+    /// The stream starts with a magic/version header, making it
+    /// self-describing for [`Model::read_from`].
+    pub fn write_to(&self, writer: &mut impl Write) -> Result<()> {
+        codec::write_header(writer, Self::MAGIC, Self::FORMAT_VERSION)?;
+        self.preprocess.write_to(writer)?;
+        match &self.conv {
+            Some(conv) => conv.write_to(writer)?,
+            None => codec::write_u32(writer, 0)?,
+        }
+        codec::write_u64(writer, self.num_classes() as u64)?;
+        codec::write_u64(writer, self.feature_dim() as u64)?;
+        codec::write_f32s(writer, self.w.iter())?;
+        codec::write_f32s(writer, self.b.iter())?;
+        // A negative threshold marks "none"; valid thresholds are in [0, 1].
+        codec::write_f32(writer, self.threshold.unwrap_or(-1.0))?;
+        match &self.normalizer {
+            Some(normalizer) => {
+                codec::write_u32(writer, 1)?;
+                normalizer.write_to(writer)?;
+            }
+            None => codec::write_u32(writer, 0)?,
+        }
+        codec::write_f32(writer, self.temperature)?;
+        self.classes.write_to(writer)?;
+        return Ok(());
+    }
+
+    /// Deserializes a model previously written with [`Model::write_to`].
+    pub fn read_from(reader: &mut impl Read) -> Result<Self> {
+        let version = codec::read_header(reader, Self::MAGIC, Self::FORMAT_VERSION)?;
+        if version == 1 {
+            return Self::read_v1(reader);
+        }
+
+        let preprocess = match version {
+            2 => Preprocess::default(),
+            3..=8 => Preprocess::read_without_resize(reader)?,
+            _ => Preprocess::read_from(reader)?,
+        };
+        let conv = match version {
+            2..=5 => None,
+            _ => ConvNet::read_from(reader, &preprocess)?,
+        };
+        let expected_dim = match &conv {
+            Some(conv) => conv.output_dim(),
+            None => preprocess.input_dim(),
+        };
+        let num_classes = codec::read_u64(reader)? as usize;
+        let feature_dim = codec::read_u64(reader)? as usize;
+        if num_classes < 2 || feature_dim != expected_dim {
+            return Err(Error::InvalidFormat(format!(
+                "unsupported model shape {}x{}",
+                num_classes, feature_dim
+            )));
+        }
+        let w = codec::read_f32s(reader, codec::size(&[num_classes, feature_dim])?)?;
+        let b = codec::read_f32s(reader, num_classes)?;
+        let threshold = match version {
+            2 | 3 => None,
+            _ => Some(codec::read_f32(reader)?).filter(|&t| t >= 0.0),
+        };
+        if threshold.is_some() && num_classes != 2 {
+            return Err(Error::InvalidFormat(
+                "decision threshold on a model with more than two classes".to_string(),
+            ));
+        }
+        let normalizer = match version {
+            2..=4 => None,
+            _ => match codec::read_u32(reader)? {
+                0 => None,
+                _ => Some(Normalizer::read_from(reader)?),
+            },
+        };
+        if normalizer.as_ref().is_some_and(|n| !n.matches(&preprocess)) {
+            return Err(Error::InvalidFormat(
+                "normalizer does not match the preprocessing channels".to_string(),
+            ));
+        }
+        let temperature = match version {
+            2..=6 => 1.0,
+            _ => codec::read_f32(reader)?,
+        };
+        if !(temperature > 0.0 && temperature.is_finite()) {
+            return Err(Error::InvalidFormat(format!(
+                "invalid temperature {}",
+                temperature
+            )));
+        }
+        let classes = match version {
+            2..=7 => ClassMap::indices(num_classes),
+            _ => ClassMap::read_from(reader)?,
+        };
+        if classes.len() != num_classes {
+            return Err(Error::InvalidFormat(format!(
+                "{} class names for {} classes",
+                classes.len(),
+                num_classes
+            )));
+        }
+        return Ok(Self {
+            preprocess,
+            conv,
+            w: Array2::from_shape_vec((num_classes, feature_dim), w).unwrap(),
+            b: Array1::from_vec(b),
+            threshold,
+            normalizer,
+            temperature,
+            classes,
+        });
+    }
+
+    /// Reads the body of a version 1 (binary sigmoid) model.
+    ///
+    /// sigmoid(w·x + b) equals the class-1 output of a softmax over the
+    /// logits (0, w·x + b), so the old weights become row 1 and row 0 is zero.
+    fn read_v1(reader: &mut impl Read) -> Result<Self> {
+        let preprocess = Preprocess::default();
+        let w = codec::read_f32s(reader, preprocess.input_dim())?;
+        let b = codec::read_f32(reader)?;
+
+        let mut model = Self {
+            preprocess,
+            conv: None,
+            w: Array2::zeros((2, preprocess.input_dim())),
+            b: Array1::zeros(2),
+            threshold: None,
+            normalizer: None,
+            temperature: 1.0,
+            classes: ClassMap::indices(2),
+        };
+        model.w.row_mut(1).assign(&Array1::from_vec(w));
+        model.b[1] = b;
+        return Ok(model);
+    }
+
+    /// Saves the model parameters to a file at `path`.
+    #[cfg(feature = "fs")]
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        return Ok(());
+    }
+
+    /// Loads a model saved with [`Model::save`].
+    #[cfg(feature = "fs")]
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        return Self::read_from(&mut reader);
     }
 }
@@ -0,0 +1,36 @@
+//! Input corruption used as regularization during training.
+
+use ndarray::ArrayViewMut2;
+use rand::Rng;
+use std::f32::consts::TAU;
+
+/// Adds Gaussian noise with standard deviation `std` to every entry of `x`,
+/// then zeroes every entry with probability `dropout` and scales the kept
+/// ones by 1 / (1 - dropout), so the expected value of every entry stays
+/// the same (inverted dropout).
+pub(crate) fn corrupt(mut x: ArrayViewMut2<f32>, dropout: f32, std: f32, rng: &mut impl Rng) {
+    assert!(
+        (0.0..1.0).contains(&dropout),
+        "dropout probability must be in [0, 1)"
+    );
+    let keep_scale = 1.0 / (1.0 - dropout);
+    for value in x.iter_mut() {
+        if std > 0.0 {
+            *value += std * standard_normal(rng);
+        }
+        if dropout > 0.0 {
+            *value = if rng.random::<f32>() < dropout {
+                0.0
+            } else {
+                *value * keep_scale
+            };
+        }
+    }
+}
+
+/// Draws from N(0, 1) with the Box-Muller transform.
+fn standard_normal(rng: &mut impl Rng) -> f32 {
+    // 1 - u is in (0, 1], so the logarithm is finite.
+    let radius = (-2.0 * (1.0 - rng.random::<f32>()).ln()).sqrt();
+    return radius * (TAU * rng.random::<f32>()).cos();
+}
@@ -0,0 +1,146 @@
+use super::codec;
+use super::error::Error;
+use super::error::Result;
+use super::preprocess::Preprocess;
+use super::source::DatasetSource;
+use ndarray::Array1;
+use ndarray::ArrayViewMut1;
+use ndarray::ArrayViewMut2;
+use ndarray::Axis;
+use std::io::Read;
+use std::io::Write;
+
+/// Per-channel standardization of preprocessed images.
+///
+/// Every feature of channel `c` is mapped to `(x - mean[c]) / std[c]`, so
+/// that each channel has zero mean and unit variance on the data the
+/// normalizer was fit on. Centered inputs of similar scale make gradient
+/// descent converge faster than raw [0, 1] pixel values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Normalizer {
+    mean: Vec<f32>,
+    std: Vec<f32>,
+}
+
+impl Normalizer {
+    /// Samples read per batch while computing the statistics.
+    const FIT_BATCH_SIZE: usize = 1024;
+
+    /// Standard deviations below this are treated as 1, so that constant
+    /// channels are only centered instead of blown up.
+    const MIN_STD: f32 = 1e-6;
+
+    /// Creates a normalizer from explicit per-channel statistics.
+    ///
+    /// # Panics
+    /// Panics if `mean` and `std` differ in length or are empty.
+    pub fn new(mean: Vec<f32>, std: Vec<f32>) -> Self {
+        assert_eq!(mean.len(), std.len(), "need one std per mean");
+        assert!(!mean.is_empty(), "need at least one channel");
+        let std = std
+            .into_iter()
+            .map(|s| if s < Self::MIN_STD { 1.0 } else { s })
+            .collect();
+        return Self { mean, std };
+    }
+
+    /// Computes the mean and standard deviation of every channel over all
+    /// pixels of all samples in `source`.
+    ///
+    /// Fit on the training split only, then use the result for every
+    /// dataset the model sees.
+    pub fn fit(source: &impl DatasetSource) -> Self {
+        let channels = source.preprocess().channels.channels();
+        let pixels = source.preprocess().input_dim() / channels;
+        let mut sum = vec![0.0f64; channels];
+        let mut sum_sq = vec![0.0f64; channels];
+
+        for start in (0..source.len()).step_by(Self::FIT_BATCH_SIZE) {
+            let end = (start + Self::FIT_BATCH_SIZE).min(source.len());
+            let batch = source.batch(start..end);
+            for row in batch.axis_iter(Axis(0)) {
+                for (c, plane) in row.exact_chunks(pixels).into_iter().enumerate() {
+                    for &v in plane {
+                        sum[c] += v as f64;
+                        sum_sq[c] += (v as f64) * (v as f64);
+                    }
+                }
+            }
+        }
+
+        let count = (source.len() * pixels).max(1) as f64;
+        let mean: Vec<f32> = sum.iter().map(|s| (s / count) as f32).collect();
+        let std = sum_sq
+            .iter()
+            .zip(&sum)
+            .map(|(sq, s)| {
+                let m = s / count;
+                ((sq / count - m * m).max(0.0).sqrt()) as f32
+            })
+            .collect();
+        return Self::new(mean, std);
+    }
+
+    /// Per-channel means.
+    pub fn mean(&self) -> &[f32] {
+        return &self.mean;
+    }
+
+    /// Per-channel standard deviations.
+    pub fn std(&self) -> &[f32] {
+        return &self.std;
+    }
+
+    pub fn channels(&self) -> usize {
+        return self.mean.len();
+    }
+
+    /// Whether the statistics fit inputs preprocessed by `preprocess`.
+    pub fn matches(&self, preprocess: &Preprocess) -> bool {
+        return self.channels() == preprocess.channels.channels();
+    }
+
+    /// Standardizes one CHW-flattened sample in place.
+    pub fn apply(&self, mut x: ArrayViewMut1<f32>) {
+        let pixels = x.len() / self.channels();
+        for (c, mut plane) in x.exact_chunks_mut(pixels).into_iter().enumerate() {
+            let (mean, std) = (self.mean[c], self.std[c]);
+            plane.mapv_inplace(|v| (v - mean) / std);
+        }
+    }
+
+    /// Standardizes every row of `x` in place.
+    pub fn apply_batch(&self, mut x: ArrayViewMut2<f32>) {
+        for row in x.axis_iter_mut(Axis(0)) {
+            self.apply(row);
+        }
+    }
+
+    /// Expands the statistics to one (mean, std) pair per feature of a
+    /// CHW-flattened input of length `input_dim`.
+    pub(crate) fn per_feature(&self, input_dim: usize) -> (Array1<f32>, Array1<f32>) {
+        let pixels = input_dim / self.channels();
+        let mean = Array1::from_shape_fn(input_dim, |i| self.mean[i / pixels]);
+        let std = Array1::from_shape_fn(input_dim, |i| self.std[i / pixels]);
+        return (mean, std);
+    }
+
+    pub(crate) fn write_to(&self, writer: &mut impl Write) -> Result<()> {
+        codec::write_u32(writer, self.channels() as u32)?;
+        codec::write_f32s(writer, self.mean.iter())?;
+        codec::write_f32s(writer, self.std.iter())?;
+        return Ok(());
+    }
+
+    pub(crate) fn read_from(reader: &mut impl Read) -> Result<Self> {
+        let channels = codec::read_u32(reader)? as usize;
+        if channels == 0 {
+            return Err(Error::InvalidFormat(
+                "normalizer without channels".to_string(),
+            ));
+        }
+        let mean = codec::read_f32s(reader, channels)?;
+        let std = codec::read_f32s(reader, channels)?;
+        return Ok(Self::new(mean, std));
+    }
+}
@@ -0,0 +1,396 @@
+//! NumPy `.npy` arrays and `.npz` archives, for exchanging weights with
+//! Python.
+//!
+//! Arrays are written as little-endian float32 (`<f4`) in C order; reading
+//! also accepts float64 and Fortran order. Archives are written
+//! uncompressed like `np.savez`; reading also accepts the deflated entries
+//! of `np.savez_compressed`.
+
+use super::error::Error;
+use super::error::Result;
+use super::model::Gradients;
+use super::model::Model;
+use flate2::Crc;
+use flate2::read::DeflateDecoder;
+use ndarray::ArrayD;
+use ndarray::ArrayViewD;
+use ndarray::IxDyn;
+use ndarray::ShapeBuilder;
+use std::fs::File;
+use std::fs::read;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+
+/// Magic bytes at the start of a `.npy` file.
+const NPY_MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+const ZIP_LOCAL_HEADER: u32 = 0x0403_4b50;
+const ZIP_CENTRAL_HEADER: u32 = 0x0201_4b50;
+const ZIP_END_OF_DIRECTORY: u32 = 0x0605_4b50;
+/// Id of the extra field holding 64-bit sizes and offsets.
+const ZIP64_EXTRA: u16 = 0x0001;
+/// MS-DOS date of 1980-01-01, the earliest representable; entries carry no
+/// meaningful timestamp.
+const ZIP_DATE: u16 = (1 << 5) | 1;
+
+/// Encodes `array` as a version 1.0 `.npy` file of float32 values.
+pub(crate) fn encode_npy(array: ArrayViewD<f32>) -> Vec<u8> {
+    let shape = match array.shape() {
+        [] => "()".to_string(),
+        [len] => format!("({},)", len),
+        dims => {
+            let dims: Vec<String> = dims.iter().map(usize::to_string).collect();
+            format!("({})", dims.join(", "))
+        }
+    };
+    let mut header = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': {}, }}",
+        shape
+    );
+    // Magic, version and length take 10 bytes; numpy pads the header with
+    // spaces and a newline so the data starts at a multiple of 64.
+    let padding = 63 - (10 + header.len()) % 64;
+    header.extend(std::iter::repeat_n(' ', padding));
+    header.push('\n');
+
+    let mut bytes = Vec::with_capacity(10 + header.len() + array.len() * 4);
+    bytes.extend_from_slice(NPY_MAGIC);
+    bytes.extend_from_slice(&[1, 0]);
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    for &value in array.iter() {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    return bytes;
+}
+
+/// Writes `array` to a `.npy` file at `path`, readable with `np.load(path)`.
+pub fn save_npy(path: &Path, array: ArrayViewD<f32>) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&encode_npy(array))?;
+    writer.flush()?;
+    return Ok(());
+}
+
+/// Decodes a `.npy` file of float32 or float64 values.
+pub(crate) fn decode_npy(bytes: &[u8]) -> Result<ArrayD<f32>> {
+    let invalid = |reason: &str| Error::InvalidFormat(format!("invalid .npy file: {}", reason));
+    if bytes.len() < 10 || &bytes[..6] != NPY_MAGIC {
+        return Err(invalid("bad magic"));
+    }
+    let (header_len, header_start) = match bytes[6] {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        2 | 3 if bytes.len() >= 12 => (
+            u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize,
+            12,
+        ),
+        _ => return Err(invalid("unsupported version")),
+    };
+    let data = bytes
+        .get(header_start + header_len..)
+        .ok_or_else(|| invalid("truncated header"))?;
+    let header = std::str::from_utf8(&bytes[header_start..header_start + header_len])
+        .map_err(|_| invalid("header is not text"))?;
+
+    let descr = header_value(header, "descr").ok_or_else(|| invalid("missing descr"))?;
+    let fortran_order = match header_value(header, "fortran_order") {
+        Some("False") => false,
+        Some("True") => true,
+        _ => return Err(invalid("missing fortran_order")),
+    };
+    let shape = header_value(header, "shape").ok_or_else(|| invalid("missing shape"))?;
+    let shape = shape
+        .trim_start_matches('(')
+        .trim_end_matches(')')
+        .split(',')
+        .map(str::trim)
+        .filter(|dim| !dim.is_empty())
+        .map(|dim| dim.parse::<usize>().map_err(|_| invalid("bad shape")))
+        .collect::<Result<Vec<usize>>>()?;
+
+    let len: usize = shape.iter().product();
+    let values: Vec<f32> = match descr.trim_matches('\'') {
+        "<f4" if data.len() == len * 4 => data
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect(),
+        "<f8" if data.len() == len * 8 => data
+            .chunks_exact(8)
+            .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()) as f32)
+            .collect(),
+        "<f4" | "<f8" => return Err(invalid("data does not match the shape")),
+        other => {
+            return Err(Error::InvalidFormat(format!(
+                "unsupported .npy dtype {}, expected float32 or float64",
+                other
+            )));
+        }
+    };
+    let array = if fortran_order {
+        ArrayD::from_shape_vec(IxDyn(&shape).f(), values)
+    } else {
+        ArrayD::from_shape_vec(IxDyn(&shape), values)
+    };
+    return Ok(array.unwrap());
+}
+
+/// Returns the raw value of `key` in a `.npy` header dictionary, e.g.
+/// `'<f4'` or `(2, 3)`.
+fn header_value<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let start = header.find(&format!("'{}'", key))? + key.len() + 2;
+    let rest = header[start..].trim_start().strip_prefix(':')?.trim_start();
+    let end = if rest.starts_with('(') {
+        rest.find(')')? + 1
+    } else {
+        rest.find([',', '}'])?
+    };
+    return Some(rest[..end].trim());
+}
+
+/// Writes `arrays` to an uncompressed `.npz` archive at `path`, each as
+/// `<name>.npy`.
+pub(crate) fn write_npz(path: &Path, arrays: &[(String, ArrayViewD<f32>)]) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mut directory = Vec::new();
+    let mut offset = 0u64;
+    for (name, array) in arrays {
+        let file_name = format!("{}.npy", name);
+        let data = encode_npy(array.view());
+        let mut crc = Crc::new();
+        crc.update(&data);
+        if offset > u32::MAX as u64 || data.len() > u32::MAX as usize {
+            return Err(Error::InvalidFormat(
+                "arrays too large for an .npz archive without zip64".to_string(),
+            ));
+        }
+
+        let mut entry = Vec::new();
+        entry.extend_from_slice(&20u16.to_le_bytes()); // version needed: 2.0
+        entry.extend_from_slice(&0u16.to_le_bytes()); // flags
+        entry.extend_from_slice(&0u16.to_le_bytes()); // method: stored
+        entry.extend_from_slice(&0u16.to_le_bytes()); // time
+        entry.extend_from_slice(&ZIP_DATE.to_le_bytes());
+        entry.extend_from_slice(&crc.sum().to_le_bytes());
+        entry.extend_from_slice(&(data.len() as u32).to_le_bytes()); // compressed
+        entry.extend_from_slice(&(data.len() as u32).to_le_bytes()); // uncompressed
+        entry.extend_from_slice(&(file_name.len() as u16).to_le_bytes());
+        entry.extend_from_slice(&0u16.to_le_bytes()); // extra length
+
+        writer.write_all(&ZIP_LOCAL_HEADER.to_le_bytes())?;
+        writer.write_all(&entry)?;
+        writer.write_all(file_name.as_bytes())?;
+        writer.write_all(&data)?;
+
+        directory.extend_from_slice(&ZIP_CENTRAL_HEADER.to_le_bytes());
+        directory.extend_from_slice(&20u16.to_le_bytes()); // version made by
+        directory.extend_from_slice(&entry);
+        directory.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        directory.extend_from_slice(&0u16.to_le_bytes()); // disk
+        directory.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+        directory.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+        directory.extend_from_slice(&(offset as u32).to_le_bytes());
+        directory.extend_from_slice(file_name.as_bytes());
+        offset += (30 + file_name.len() + data.len()) as u64;
+    }
+    if offset > u32::MAX as u64 {
+        return Err(Error::InvalidFormat(
+            "arrays too large for an .npz archive without zip64".to_string(),
+        ));
+    }
+
+    writer.write_all(&directory)?;
+    writer.write_all(&ZIP_END_OF_DIRECTORY.to_le_bytes())?;
+    writer.write_all(&0u16.to_le_bytes())?; // this disk
+    writer.write_all(&0u16.to_le_bytes())?; // disk with the directory
+    writer.write_all(&(arrays.len() as u16).to_le_bytes())?;
+    writer.write_all(&(arrays.len() as u16).to_le_bytes())?;
+    writer.write_all(&(directory.len() as u32).to_le_bytes())?;
+    writer.write_all(&(offset as u32).to_le_bytes())?;
+    writer.write_all(&0u16.to_le_bytes())?; // comment length
+    writer.flush()?;
+    return Ok(());
+}
+
+/// Reads every `.npy` array of the `.npz` archive at `path`, in archive
+/// order, named without the `.npy` suffix.
+pub(crate) fn read_npz(path: &Path) -> Result<Vec<(String, ArrayD<f32>)>> {
+    let bytes = read(path)?;
+    let invalid = |reason: &str| Error::InvalidFormat(format!("invalid .npz archive: {}", reason));
+    // Offsets and sizes come from the archive, so every range is checked.
+    let range = |at: usize, len: u64| -> Result<&[u8]> {
+        return usize::try_from(len)
+            .ok()
+            .and_then(|len| at.checked_add(len))
+            .and_then(|end| bytes.get(at..end))
+            .ok_or_else(|| invalid("truncated"));
+    };
+    let u16_at = |at: usize| -> Result<u16> {
+        return Ok(u16::from_le_bytes(range(at, 2)?.try_into().unwrap()));
+    };
+    let u32_at = |at: usize| -> Result<u32> {
+        return Ok(u32::from_le_bytes(range(at, 4)?.try_into().unwrap()));
+    };
+    let u64_at = |at: usize| -> Result<u64> {
+        return Ok(u64::from_le_bytes(range(at, 8)?.try_into().unwrap()));
+    };
+
+    // The end record is the last 22 bytes, followed by a comment of at
+    // most 64 KiB.
+    let end = (0..bytes.len().saturating_sub(21))
+        .rev()
+        .take(22 + u16::MAX as usize)
+        .find(|&at| u32_at(at).ok() == Some(ZIP_END_OF_DIRECTORY))
+        .ok_or_else(|| invalid("no end of central directory"))?;
+    let entries = u16_at(end + 10)? as usize;
+    let mut at = u32_at(end + 16)? as usize;
+
+    let mut arrays = Vec::with_capacity(entries);
+    for _ in 0..entries {
+        if u32_at(at)? != ZIP_CENTRAL_HEADER {
+            return Err(invalid("bad central directory"));
+        }
+        let method = u16_at(at + 10)?;
+        let crc = u32_at(at + 16)?;
+        let mut compressed = u32_at(at + 20)? as u64;
+        let mut uncompressed = u32_at(at + 24)? as u64;
+        let name_len = u16_at(at + 28)? as usize;
+        let extra_len = u16_at(at + 30)? as usize;
+        let comment_len = u16_at(at + 32)? as usize;
+        let mut offset = u32_at(at + 42)? as u64;
+        let name = String::from_utf8_lossy(range(at + 46, name_len as u64)?).into_owned();
+
+        // Fields saturated at u32::MAX are stored in the zip64 extra field,
+        // in this order.
+        let mut extra = at + 46 + name_len;
+        let extra_end = extra + extra_len;
+        while extra + 4 <= extra_end {
+            let (id, len) = (u16_at(extra)?, u16_at(extra + 2)? as usize);
+            if id == ZIP64_EXTRA {
+                let mut field = extra + 4;
+                for value in [&mut uncompressed, &mut compressed, &mut offset] {
+                    if *value == u32::MAX as u64 && field + 8 <= extra + 4 + len {
+                        *value = u64_at(field)?;
+                        field += 8;
+                    }
+                }
+            }
+            extra += 4 + len;
+        }
+        at = extra_end + comment_len;
+
+        let offset = usize::try_from(offset).map_err(|_| invalid("truncated"))?;
+        if u32_at(offset)? != ZIP_LOCAL_HEADER {
+            return Err(invalid("bad local header"));
+        }
+        // The local header is 30 bytes, followed by the name and extra field.
+        let data_start = offset
+            .checked_add(30 + u16_at(offset + 26)? as usize + u16_at(offset + 28)? as usize)
+            .ok_or_else(|| invalid("truncated"))?;
+        let stored = range(data_start, compressed)?;
+        let data = match method {
+            0 => stored.to_vec(),
+            8 => {
+                // Deflate expands data at most 1032-fold, which bounds what
+                // a corrupted size field can make us allocate or inflate.
+                let limit = uncompressed.min(stored.len() as u64 * 1032);
+                let mut data = Vec::with_capacity(limit as usize);
+                DeflateDecoder::new(stored)
+                    .take(limit + 1)
+                    .read_to_end(&mut data)?;
+                data
+            }
+            other => {
+                return Err(Error::InvalidFormat(format!(
+                    "unsupported .npz compression method {}",
+                    other
+                )));
+            }
+        };
+        let mut actual = Crc::new();
+        actual.update(&data);
+        if actual.sum() != crc || data.len() as u64 != uncompressed {
+            return Err(invalid(&format!("{} is corrupted", name)));
+        }
+
+        let name = name.strip_suffix(".npy").unwrap_or(&name).to_string();
+        arrays.push((name, decode_npy(&data)?));
+    }
+    return Ok(arrays);
+}
+
+impl Model {
+    /// Saves the weights to a NumPy `.npz` archive at `path`, readable with
+    /// `np.load(path)`.
+    ///
+    /// The archive holds `w` of shape (num_classes, FEATURE_DIM) and `b` of
+    /// shape (num_classes,), plus `conv{i}_kernel` of shape (out_channels,
+    /// in_channels, k, k) and `conv{i}_bias` for every conv block `i`. The
+    /// preprocessing, normalizer and other settings are not included; use
+    /// [`Model::save`] to store the complete model.
+    pub fn save_npz(&self, path: &Path) -> Result<()> {
+        let mut arrays = vec![
+            ("w".to_string(), self.weights().into_dyn()),
+            ("b".to_string(), self.bias().into_dyn()),
+        ];
+        for (index, layer) in self
+            .conv()
+            .map_or(&[][..], |conv| conv.layers())
+            .iter()
+            .enumerate()
+        {
+            arrays.push((format!("conv{}_kernel", index), layer.kernel().into_dyn()));
+            arrays.push((format!("conv{}_bias", index), layer.bias().into_dyn()));
+        }
+        return write_npz(path, &arrays);
+    }
+
+    /// Replaces the weights with the arrays of the `.npz` archive at
+    /// `path`, laid out like [`Model::save_npz`] writes them, e.g. after
+    /// editing them in NumPy or exporting them from PyTorch with
+    /// `np.savez(path, w=linear.weight.numpy(), b=linear.bias.numpy())`.
+    ///
+    /// The archive only carries weights, so the model must already have the
+    /// right architecture. Returns an error if an array is missing, has a
+    /// different shape than the model's, or is not a parameter of the model;
+    /// the model is left unchanged then.
+    pub fn load_npz(&mut self, path: &Path) -> Result<()> {
+        let mut arrays = read_npz(path)?;
+        let mut take = |name: &str, shape: &[usize]| -> Result<ArrayD<f32>> {
+            let Some(position) = arrays.iter().position(|(n, _)| n == name) else {
+                return Err(Error::InvalidFormat(format!("missing array {}", name)));
+            };
+            let (_, array) = arrays.swap_remove(position);
+            if array.shape() != shape {
+                return Err(Error::InvalidFormat(format!(
+                    "array {} has shape {:?}, expected {:?}",
+                    name,
+                    array.shape(),
+                    shape
+                )));
+            }
+            return Ok(array);
+        };
+
+        let mut params = Gradients::zeros_like(self);
+        let w = take("w", params.w.shape())?;
+        params.w.assign(&w);
+        let b = take("b", params.b.shape())?;
+        params.b.assign(&b);
+        for (index, layer) in params.conv.iter_mut().enumerate() {
+            let kernel = take(&format!("conv{}_kernel", index), layer.kernel.shape())?;
+            layer.kernel.assign(&kernel);
+            let bias = take(&format!("conv{}_bias", index), layer.bias.shape())?;
+            layer.bias.assign(&bias);
+        }
+        if let Some((name, _)) = arrays.first() {
+            return Err(Error::InvalidFormat(format!(
+                "unexpected array {} in .npz archive",
+                name
+            )));
+        }
+        self.set_parameters(params);
+        return Ok(());
+    }
+}
@@ -0,0 +1,290 @@
+//! Minimal ONNX export.
+//!
+//! ONNX files are Protocol Buffers messages. The handful of message types
+//! needed for a (convolutional) linear classifier are encoded by hand with
+//! [`Message`], which avoids pulling in a protobuf code generator for a few
+//! dozen fields.
+//! Field numbers follow `onnx.proto` from the ONNX repository.
+
+use super::error::Result;
+use super::model::Model;
+use super::protobuf::Message;
+use ndarray::Array1;
+use ndarray::Array2;
+use std::fs::write;
+use std::path::Path;
+
+/// ONNX IR version the exported files declare (ONNX 1.10+).
+const IR_VERSION: u64 = 8;
+
+/// Version of the default operator set the graph uses.
+const OPSET_VERSION: u64 = 13;
+
+/// `TensorProto.DataType.FLOAT`.
+const DATA_TYPE_FLOAT: u64 = 1;
+
+/// `TensorProto.DataType.INT64`.
+const DATA_TYPE_INT64: u64 = 7;
+
+/// `AttributeProto.AttributeType.INT`.
+const ATTRIBUTE_TYPE_INT: u64 = 2;
+
+/// `AttributeProto.AttributeType.INTS`.
+const ATTRIBUTE_TYPE_INTS: u64 = 7;
+
+/// A tensor dimension: either fixed or symbolic (e.g. the batch size).
+enum Dim<'a> {
+    Value(usize),
+    Param(&'a str),
+}
+
+/// `ValueInfoProto` for a float tensor named `name` with the given shape.
+fn value_info(name: &str, dims: &[Dim]) -> Message {
+    let mut shape = Message::default();
+    for dim in dims {
+        let mut dimension = Message::default();
+        match dim {
+            Dim::Value(value) => dimension.varint(1, *value as u64),
+            Dim::Param(param) => dimension.string(2, param),
+        };
+        shape.message(1, &dimension);
+    }
+
+    let mut tensor_type = Message::default();
+    tensor_type.varint(1, DATA_TYPE_FLOAT).message(2, &shape);
+    let mut type_proto = Message::default();
+    type_proto.message(1, &tensor_type);
+
+    let mut info = Message::default();
+    info.string(1, name).message(2, &type_proto);
+    return info;
+}
+
+/// `TensorProto` holding float `values` (row-major) with shape `dims`.
+fn tensor<'a>(name: &str, dims: &[usize], values: impl Iterator<Item = &'a f32>) -> Message {
+    let mut tensor = Message::default();
+    for &dim in dims {
+        tensor.varint(1, dim as u64);
+    }
+    tensor.varint(2, DATA_TYPE_FLOAT).string(8, name);
+
+    let raw: Vec<u8> = values.flat_map(|value| value.to_le_bytes()).collect();
+    tensor.bytes(9, &raw);
+    return tensor;
+}
+
+/// `TensorProto` holding int64 `values` as a 1-D tensor.
+fn int64_tensor(name: &str, values: &[i64]) -> Message {
+    let mut tensor = Message::default();
+    tensor
+        .varint(1, values.len() as u64)
+        .varint(2, DATA_TYPE_INT64)
+        .string(8, name);
+
+    let raw: Vec<u8> = values
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect();
+    tensor.bytes(9, &raw);
+    return tensor;
+}
+
+/// `NodeProto` applying `op_type` to `inputs`, producing `output`.
+fn node(op_type: &str, inputs: &[&str], output: &str) -> Message {
+    let mut node = Message::default();
+    for input in inputs {
+        node.string(1, input);
+    }
+    node.string(2, output).string(3, output).string(4, op_type);
+    return node;
+}
+
+/// `AttributeProto` carrying a single integer.
+fn int_attribute(name: &str, value: i64) -> Message {
+    let mut attribute = Message::default();
+    attribute
+        .string(1, name)
+        .varint(3, value as u64)
+        .varint(20, ATTRIBUTE_TYPE_INT);
+    return attribute;
+}
+
+/// `AttributeProto` carrying a list of integers.
+fn ints_attribute(name: &str, values: &[i64]) -> Message {
+    let mut attribute = Message::default();
+    attribute.string(1, name);
+    for &value in values {
+        attribute.varint(8, value as u64);
+    }
+    attribute.varint(20, ATTRIBUTE_TYPE_INTS);
+    return attribute;
+}
+
+impl Model {
+    /// Writes the model as an ONNX graph to `path`.
+    ///
+    /// The graph takes a float tensor `input` of shape (batch, INPUT_DIM),
+    /// preprocessed exactly as for [`Model::predict_probs_batch`], and computes
+    /// `probabilities = Softmax(MatMul(input, W^T) + b)` of shape
+    /// (batch, num_classes). It only uses opset 13 operators, so it can be
+    /// run by onnxruntime, onnxruntime-web, or mobile runtimes.
+    ///
+    /// A [`Normalizer`](super::Normalizer) is folded into `W` and `b`, so the
+    /// graph still takes unnormalized inputs. A calibration temperature other
+    /// than 1 becomes a `Div` of the logits. The decision threshold is not
+    /// exported; apply it to the probabilities if needed.
+    ///
+    /// With a [`ConvNet`](super::ConvNet) front-end, the input is
+    /// normalized with `Sub`/`Div` nodes instead, reshaped to
+    /// (batch, C, H, W), and passed through one `Conv`, `Relu` and `MaxPool`
+    /// per block before being flattened for the `MatMul`.
+    pub fn export_onnx(&self, path: &Path) -> Result<()> {
+        write(path, self.to_onnx_bytes())?;
+        return Ok(());
+    }
+
+    /// Returns the weights and bias of an equivalent model without a
+    /// normalizer.
+    ///
+    /// W((x - mean) / std) + b = (W / std)x + (b - (W / std)·mean).
+    fn folded_parameters(&self) -> (Array2<f32>, Array1<f32>) {
+        let Some(normalizer) = self.normalizer() else {
+            return (self.weights().to_owned(), self.bias().to_owned());
+        };
+        let (mean, std) = normalizer.per_feature(self.input_dim());
+        let weights = &self.weights() / &std;
+        let bias = &self.bias() - &weights.dot(&mean);
+        return (weights, bias);
+    }
+
+    fn to_onnx_bytes(&self) -> Vec<u8> {
+        let num_classes = self.num_classes();
+        let input_dim = self.input_dim();
+        let mut graph = Message::default();
+        let (weights, bias) = match self.conv() {
+            Some(_) => {
+                self.write_conv_nodes(&mut graph);
+                graph.message(1, &node("MatMul", &["features", "weight"], "matmul"));
+                (self.weights().to_owned(), self.bias().to_owned())
+            }
+            None => {
+                graph.message(1, &node("MatMul", &["input", "weight"], "matmul"));
+                self.folded_parameters()
+            }
+        };
+        graph.message(1, &node("Add", &["matmul", "bias"], "logits"));
+        let mut logits = "logits";
+        if self.temperature() != 1.0 {
+            graph.message(1, &node("Div", &["logits", "temperature"], "scaled_logits"));
+            logits = "scaled_logits";
+        }
+        let mut softmax = node("Softmax", &[logits], "probabilities");
+        softmax.message(5, &int_attribute("axis", 1));
+        graph.message(1, &softmax);
+
+        graph.string(2, "antbee");
+        graph.message(
+            5,
+            &tensor(
+                "weight",
+                &[self.feature_dim(), num_classes],
+                weights.t().iter(),
+            ),
+        );
+        graph.message(5, &tensor("bias", &[num_classes], bias.iter()));
+        if self.temperature() != 1.0 {
+            graph.message(5, &tensor("temperature", &[], [self.temperature()].iter()));
+        }
+        graph.message(
+            11,
+            &value_info("input", &[Dim::Param("batch"), Dim::Value(input_dim)]),
+        );
+        graph.message(
+            12,
+            &value_info(
+                "probabilities",
+                &[Dim::Param("batch"), Dim::Value(num_classes)],
+            ),
+        );
+
+        let mut opset = Message::default();
+        opset.string(1, "").varint(2, OPSET_VERSION);
+
+        let mut model = Message::default();
+        model
+            .varint(1, IR_VERSION)
+            .string(2, env!("CARGO_PKG_NAME"))
+            .string(3, env!("CARGO_PKG_VERSION"))
+            .message(7, &graph)
+            .message(8, &opset);
+        return model.bytes;
+    }
+
+    /// Adds the nodes and initializers turning `input` into the flattened
+    /// conv features `features`.
+    fn write_conv_nodes(&self, graph: &mut Message) {
+        let conv = self.conv().unwrap();
+        let (channels, height, width) = conv.input_shape();
+
+        let mut current = "input".to_string();
+        if let Some(normalizer) = self.normalizer() {
+            let (mean, std) = normalizer.per_feature(self.input_dim());
+            graph.message(1, &node("Sub", &["input", "mean"], "centered"));
+            graph.message(1, &node("Div", &["centered", "std"], "normalized"));
+            graph.message(5, &tensor("mean", &[mean.len()], mean.iter()));
+            graph.message(5, &tensor("std", &[std.len()], std.iter()));
+            current = "normalized".to_string();
+        }
+
+        graph.message(1, &node("Reshape", &[&current, "image_shape"], "image"));
+        graph.message(
+            5,
+            &int64_tensor(
+                "image_shape",
+                &[-1, channels as i64, height as i64, width as i64],
+            ),
+        );
+        current = "image".to_string();
+
+        for (index, layer) in conv.layers().iter().enumerate() {
+            let k = layer.kernel_size() as i64;
+            let pad = k / 2;
+            let (weight, bias) = (format!("conv{index}_weight"), format!("conv{index}_bias"));
+            let (conv_out, relu_out, pool_out) = (
+                format!("conv{index}"),
+                format!("relu{index}"),
+                format!("pool{index}"),
+            );
+
+            let mut conv_node = node("Conv", &[&current, &weight, &bias], &conv_out);
+            conv_node
+                .message(5, &ints_attribute("kernel_shape", &[k, k]))
+                .message(5, &ints_attribute("pads", &[pad, pad, pad, pad]))
+                .message(5, &ints_attribute("strides", &[1, 1]));
+            graph.message(1, &conv_node);
+            graph.message(1, &node("Relu", &[&conv_out], &relu_out));
+            let mut pool_node = node("MaxPool", &[&relu_out], &pool_out);
+            pool_node
+                .message(5, &ints_attribute("kernel_shape", &[2, 2]))
+                .message(5, &ints_attribute("strides", &[2, 2]));
+            graph.message(1, &pool_node);
+
+            let (out_channels, in_channels, _, _) = layer.kernel().dim();
+            let k = k as usize;
+            graph.message(
+                5,
+                &tensor(
+                    &weight,
+                    &[out_channels, in_channels, k, k],
+                    layer.kernel().iter(),
+                ),
+            );
+            graph.message(5, &tensor(&bias, &[out_channels], layer.bias().iter()));
+            current = pool_out;
+        }
+
+        let mut flatten = node("Flatten", &[&current], "features");
+        flatten.message(5, &int_attribute("axis", 1));
+        graph.message(1, &flatten);
+    }
+}
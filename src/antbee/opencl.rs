@@ -0,0 +1,464 @@
+//! Matrix products on an OpenCL GPU, for [`Backend::Gpu`](super::Backend).
+//!
+//! The OpenCL library is loaded at run time with `dlopen`, so the binary
+//! neither links against nor requires it unless the GPU backend is
+//! selected. Each product copies its operands to the device, runs the
+//! kernel in `gemm.cl` and reads the result back.
+
+use ndarray::Array2;
+use ndarray::ArrayView2;
+use std::ffi::CStr;
+use std::ffi::c_void;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+
+type ClInt = i32;
+type ClUint = u32;
+type Handle = *mut c_void;
+
+const CL_SUCCESS: ClInt = 0;
+const CL_TRUE: ClUint = 1;
+const CL_DEVICE_TYPE_GPU: u64 = 1 << 2;
+const CL_MEM_WRITE_ONLY: u64 = 1 << 1;
+const CL_MEM_READ_ONLY: u64 = 1 << 2;
+const CL_MEM_COPY_HOST_PTR: u64 = 1 << 5;
+const CL_PROGRAM_BUILD_LOG: ClUint = 0x1183;
+
+const LIBRARIES: [&CStr; 3] = [
+    c"libOpenCL.so.1",
+    c"libOpenCL.so",
+    c"/System/Library/Frameworks/OpenCL.framework/OpenCL",
+];
+
+const KERNEL_SOURCE: &str = include_str!("gemm.cl");
+
+/// The OpenCL entry points used, resolved from the library.
+struct Api {
+    get_platform_ids: unsafe extern "C" fn(ClUint, *mut Handle, *mut ClUint) -> ClInt,
+    get_device_ids: unsafe extern "C" fn(Handle, u64, ClUint, *mut Handle, *mut ClUint) -> ClInt,
+    create_context: unsafe extern "C" fn(
+        *const isize,
+        ClUint,
+        *const Handle,
+        *const c_void,
+        *mut c_void,
+        *mut ClInt,
+    ) -> Handle,
+    create_command_queue: unsafe extern "C" fn(Handle, Handle, u64, *mut ClInt) -> Handle,
+    create_program_with_source:
+        unsafe extern "C" fn(Handle, ClUint, *const *const u8, *const usize, *mut ClInt) -> Handle,
+    build_program: unsafe extern "C" fn(
+        Handle,
+        ClUint,
+        *const Handle,
+        *const u8,
+        *const c_void,
+        *mut c_void,
+    ) -> ClInt,
+    get_program_build_info:
+        unsafe extern "C" fn(Handle, Handle, ClUint, usize, *mut c_void, *mut usize) -> ClInt,
+    create_kernel: unsafe extern "C" fn(Handle, *const u8, *mut ClInt) -> Handle,
+    create_buffer: unsafe extern "C" fn(Handle, u64, usize, *mut c_void, *mut ClInt) -> Handle,
+    set_kernel_arg: unsafe extern "C" fn(Handle, ClUint, usize, *const c_void) -> ClInt,
+    enqueue_nd_range_kernel: unsafe extern "C" fn(
+        Handle,
+        Handle,
+        ClUint,
+        *const usize,
+        *const usize,
+        *const usize,
+        ClUint,
+        *const Handle,
+        *mut Handle,
+    ) -> ClInt,
+    enqueue_read_buffer: unsafe extern "C" fn(
+        Handle,
+        Handle,
+        ClUint,
+        usize,
+        usize,
+        *mut c_void,
+        ClUint,
+        *const Handle,
+        *mut Handle,
+    ) -> ClInt,
+    release_mem_object: unsafe extern "C" fn(Handle) -> ClInt,
+}
+
+/// A context on the first GPU device with the compiled kernel.
+struct Gpu {
+    api: Api,
+    context: Handle,
+    /// The command queue and the kernel, whose arguments are shared state.
+    queue: Mutex<(Handle, Handle)>,
+}
+
+// SAFETY: OpenCL contexts, queues and buffers may be used from any thread;
+// the kernel, whose arguments are set per call, is guarded by the mutex.
+unsafe impl Send for Gpu {}
+unsafe impl Sync for Gpu {}
+
+fn gpu() -> &'static std::result::Result<Gpu, String> {
+    static GPU: OnceLock<std::result::Result<Gpu, String>> = OnceLock::new();
+    // SAFETY: `Gpu::open` only calls OpenCL entry points with their
+    // documented argument types.
+    return GPU.get_or_init(|| unsafe { Gpu::open() });
+}
+
+/// Loads OpenCL and compiles the kernel for the first GPU device, once.
+///
+/// Fails with a description if there is no OpenCL library or GPU device,
+/// or the kernel does not build.
+pub(crate) fn init() -> std::result::Result<(), String> {
+    return gpu().as_ref().map(|_| ()).map_err(Clone::clone);
+}
+
+/// Computes A·B for A of shape (m, k) and B of shape (k, n) on the GPU.
+///
+/// Operands of any layout are accepted, so transposed views cost no copy.
+/// Empty products and ones too large for the kernel's `int` indices are
+/// computed on the CPU.
+///
+/// # Panics
+/// If [`init`] did not succeed, or an OpenCL call fails.
+pub(crate) fn matmul(a: ArrayView2<f32>, b: ArrayView2<f32>) -> Array2<f32> {
+    let (m, k, n) = (a.nrows(), a.ncols(), b.ncols());
+    let fits = |len: usize| return i32::try_from(len).is_ok();
+    if m == 0 || n == 0 || k == 0 || !fits(m * k) || !fits(k * n) || !fits(m * n) {
+        return a.dot(&b);
+    }
+    let gpu = gpu()
+        .as_ref()
+        .expect("GPU backend used before it was selected");
+    // SAFETY: the buffers are sized from the operands' shapes and the
+    // kernel only indexes within them.
+    return unsafe { gpu.matmul(a, b) }.unwrap_or_else(|err| panic!("OpenCL: {}", err));
+}
+
+/// Releases a buffer when dropped.
+struct Buffer<'a>(&'a Api, Handle);
+
+impl Drop for Buffer<'_> {
+    fn drop(&mut self) {
+        // SAFETY: the buffer was created by `create_buffer` and is released
+        // once.
+        unsafe { (self.0.release_mem_object)(self.1) };
+    }
+}
+
+fn check(status: ClInt, call: &str) -> std::result::Result<(), String> {
+    if status != CL_SUCCESS {
+        return Err(format!("{} failed with error {}", call, status));
+    }
+    return Ok(());
+}
+
+/// Resolves the function `name` from `library` as a pointer of type `F`.
+///
+/// # Safety
+/// `F` must be a function pointer type matching the symbol's signature.
+unsafe fn symbol<F: Copy>(library: *mut c_void, name: &CStr) -> std::result::Result<F, String> {
+    assert_eq!(size_of::<F>(), size_of::<*mut c_void>());
+    // SAFETY: `library` is a handle returned by `dlopen`.
+    let address = unsafe { libc::dlsym(library, name.as_ptr()) };
+    if address.is_null() {
+        return Err(format!("OpenCL library lacks {}", name.to_string_lossy()));
+    }
+    // SAFETY: the caller guarantees that `F` is the symbol's type.
+    return Ok(unsafe { std::mem::transmute_copy(&address) });
+}
+
+/// A view as a contiguous buffer with its row and column strides, copying
+/// only if the view does not cover its elements without gaps.
+fn strided(view: ArrayView2<f32>) -> (Vec<f32>, i32, i32) {
+    let strides = view.strides();
+    if let Some(slice) = view.as_slice_memory_order()
+        && strides.iter().all(|&stride| stride >= 0)
+    {
+        return (slice.to_vec(), strides[0] as i32, strides[1] as i32);
+    }
+    let standard = view.as_standard_layout();
+    return (
+        standard.as_slice().unwrap().to_vec(),
+        view.ncols() as i32,
+        1,
+    );
+}
+
+impl Gpu {
+    unsafe fn open() -> std::result::Result<Self, String> {
+        let library = LIBRARIES
+            .iter()
+            .map(|name| unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_NOW) })
+            .find(|handle| !handle.is_null())
+            .ok_or("no OpenCL library found (libOpenCL.so)")?;
+        // SAFETY: each symbol is an OpenCL 1.2 entry point with the signature
+        // of the field it is stored in.
+        let api = unsafe {
+            Api {
+                get_platform_ids: symbol(library, c"clGetPlatformIDs")?,
+                get_device_ids: symbol(library, c"clGetDeviceIDs")?,
+                create_context: symbol(library, c"clCreateContext")?,
+                create_command_queue: symbol(library, c"clCreateCommandQueue")?,
+                create_program_with_source: symbol(library, c"clCreateProgramWithSource")?,
+                build_program: symbol(library, c"clBuildProgram")?,
+                get_program_build_info: symbol(library, c"clGetProgramBuildInfo")?,
+                create_kernel: symbol(library, c"clCreateKernel")?,
+                create_buffer: symbol(library, c"clCreateBuffer")?,
+                set_kernel_arg: symbol(library, c"clSetKernelArg")?,
+                enqueue_nd_range_kernel: symbol(library, c"clEnqueueNDRangeKernel")?,
+                enqueue_read_buffer: symbol(library, c"clEnqueueReadBuffer")?,
+                release_mem_object: symbol(library, c"clReleaseMemObject")?,
+            }
+        };
+
+        let mut count: ClUint = 0;
+        unsafe { (api.get_platform_ids)(0, std::ptr::null_mut(), &mut count) };
+        let mut platforms = vec![std::ptr::null_mut(); count as usize];
+        if count > 0 {
+            check(
+                unsafe { (api.get_platform_ids)(count, platforms.as_mut_ptr(), &mut count) },
+                "clGetPlatformIDs",
+            )?;
+        }
+        let device = platforms
+            .iter()
+            .find_map(|&platform| {
+                let mut device = std::ptr::null_mut();
+                let status = unsafe {
+                    (api.get_device_ids)(
+                        platform,
+                        CL_DEVICE_TYPE_GPU,
+                        1,
+                        &mut device,
+                        std::ptr::null_mut(),
+                    )
+                };
+                return (status == CL_SUCCESS).then_some(device);
+            })
+            .ok_or("no OpenCL GPU device found")?;
+
+        let mut status = CL_SUCCESS;
+        let context = unsafe {
+            (api.create_context)(
+                std::ptr::null(),
+                1,
+                &device,
+                std::ptr::null(),
+                std::ptr::null_mut(),
+                &mut status,
+            )
+        };
+        check(status, "clCreateContext")?;
+        let queue = unsafe { (api.create_command_queue)(context, device, 0, &mut status) };
+        check(status, "clCreateCommandQueue")?;
+        let (source, length) = (KERNEL_SOURCE.as_ptr(), KERNEL_SOURCE.len());
+        let program =
+            unsafe { (api.create_program_with_source)(context, 1, &source, &length, &mut status) };
+        check(status, "clCreateProgramWithSource")?;
+        let status = unsafe {
+            (api.build_program)(
+                program,
+                1,
+                &device,
+                c"".as_ptr().cast(),
+                std::ptr::null(),
+                std::ptr::null_mut(),
+            )
+        };
+        if status != CL_SUCCESS {
+            let mut log = vec![0u8; 16 * 1024];
+            let mut len = 0;
+            unsafe {
+                (api.get_program_build_info)(
+                    program,
+                    device,
+                    CL_PROGRAM_BUILD_LOG,
+                    log.len(),
+                    log.as_mut_ptr().cast(),
+                    &mut len,
+                )
+            };
+            log.truncate(len.min(log.len()));
+            return Err(format!(
+                "building the kernel failed with error {}: {}",
+                status,
+                String::from_utf8_lossy(&log).trim_end_matches('\0')
+            ));
+        }
+        let mut status = CL_SUCCESS;
+        let kernel = unsafe { (api.create_kernel)(program, c"gemm".as_ptr().cast(), &mut status) };
+        check(status, "clCreateKernel")?;
+        return Ok(Self {
+            api,
+            context,
+            queue: Mutex::new((queue, kernel)),
+        });
+    }
+
+    unsafe fn matmul(
+        &self,
+        a: ArrayView2<f32>,
+        b: ArrayView2<f32>,
+    ) -> std::result::Result<Array2<f32>, String> {
+        let api = &self.api;
+        let (m, k, n) = (a.nrows(), a.ncols(), b.ncols());
+        let (a, a_row, a_col) = strided(a);
+        let (b, b_row, b_col) = strided(b);
+        let input = |data: &[f32]| -> std::result::Result<Buffer<'_>, String> {
+            let mut status = CL_SUCCESS;
+            let buffer = unsafe {
+                (api.create_buffer)(
+                    self.context,
+                    CL_MEM_READ_ONLY | CL_MEM_COPY_HOST_PTR,
+                    std::mem::size_of_val(data),
+                    data.as_ptr() as *mut c_void,
+                    &mut status,
+                )
+            };
+            check(status, "clCreateBuffer")?;
+            return Ok(Buffer(api, buffer));
+        };
+        let a_buffer = input(&a)?;
+        let b_buffer = input(&b)?;
+        let mut out = vec![0f32; m * n];
+        let mut status = CL_SUCCESS;
+        let c_buffer = Buffer(api, unsafe {
+            (api.create_buffer)(
+                self.context,
+                CL_MEM_WRITE_ONLY,
+                std::mem::size_of_val(out.as_slice()),
+                std::ptr::null_mut(),
+                &mut status,
+            )
+        });
+        check(status, "clCreateBuffer")?;
+
+        let dims = [m as i32, n as i32, k as i32];
+        let queue = self.queue.lock().unwrap();
+        let (queue, kernel) = *queue;
+        let int = |index: ClUint, value: &i32| {
+            return unsafe { (api.set_kernel_arg)(kernel, index, 4, (value as *const i32).cast()) };
+        };
+        let mem = |index: ClUint, buffer: &Buffer| {
+            return unsafe {
+                (api.set_kernel_arg)(
+                    kernel,
+                    index,
+                    size_of::<Handle>(),
+                    (&buffer.1 as *const Handle).cast(),
+                )
+            };
+        };
+        let statuses = [
+            int(0, &dims[0]),
+            int(1, &dims[1]),
+            int(2, &dims[2]),
+            mem(3, &a_buffer),
+            int(4, &a_row),
+            int(5, &a_col),
+            mem(6, &b_buffer),
+            int(7, &b_row),
+            int(8, &b_col),
+            mem(9, &c_buffer),
+        ];
+        for status in statuses {
+            check(status, "clSetKernelArg")?;
+        }
+        let global = [m, n];
+        check(
+            unsafe {
+                (api.enqueue_nd_range_kernel)(
+                    queue,
+                    kernel,
+                    2,
+                    std::ptr::null(),
+                    global.as_ptr(),
+                    std::ptr::null(),
+                    0,
+                    std::ptr::null(),
+                    std::ptr::null_mut(),
+                )
+            },
+            "clEnqueueNDRangeKernel",
+        )?;
+        check(
+            unsafe {
+                (api.enqueue_read_buffer)(
+                    queue,
+                    c_buffer.1,
+                    CL_TRUE,
+                    0,
+                    std::mem::size_of_val(out.as_slice()),
+                    out.as_mut_ptr().cast(),
+                    0,
+                    std::ptr::null(),
+                    std::ptr::null_mut(),
+                )
+            },
+            "clEnqueueReadBuffer",
+        )?;
+        return Ok(Array2::from_shape_vec((m, n), out).unwrap());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::s;
+    use rand::Rng;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn uniform(rng: &mut ChaCha8Rng, shape: (usize, usize)) -> Array2<f32> {
+        return Array2::from_shape_fn(shape, |_| rng.random_range(-1.0..1.0));
+    }
+
+    fn assert_close(gpu: &Array2<f32>, cpu: &Array2<f32>, case: &str) {
+        assert_eq!(gpu.shape(), cpu.shape(), "{}", case);
+        for (&g, &c) in gpu.iter().zip(cpu) {
+            assert!(
+                (g - c).abs() <= 1e-4 * (1.0 + c.abs()),
+                "{}: {} vs {}",
+                case,
+                g,
+                c
+            );
+        }
+    }
+
+    /// Runs whenever an OpenCL GPU is present; passes without checking
+    /// anything otherwise.
+    #[test]
+    fn matmul_matches_the_cpu_for_every_layout() {
+        if let Err(err) = init() {
+            eprintln!("skipping the GPU comparison: {}", err);
+            return;
+        }
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        for (m, k, n) in [(1, 1, 1), (3, 5, 2), (17, 33, 9), (64, 300, 2)] {
+            let a = uniform(&mut rng, (m, k));
+            let b = uniform(&mut rng, (k, n));
+            let expected = a.dot(&b);
+            assert_close(&matmul(a.view(), b.view()), &expected, "standard");
+
+            // Transposed operands are uploaded with swapped strides.
+            let (a_t, b_t) = (a.t().to_owned(), b.t().to_owned());
+            assert_close(&matmul(a_t.t(), b.view()), &expected, "transposed a");
+            assert_close(&matmul(a.view(), b_t.t()), &expected, "transposed b");
+
+            // Views with gaps or negative strides are copied first.
+            let wide = uniform(&mut rng, (m, 2 * k));
+            let gaps = wide.slice(s![.., ..;2]);
+            assert_close(&matmul(gaps, b.view()), &gaps.dot(&b), "gaps");
+            let reversed = b.slice(s![..;-1, ..]);
+            assert_close(&matmul(a.view(), reversed), &a.dot(&reversed), "reversed");
+        }
+        // Empty products stay on the CPU.
+        let empty = Array2::<f32>::zeros((0, 4));
+        assert_eq!(
+            matmul(empty.view(), uniform(&mut rng, (4, 3)).view()).shape(),
+            [0, 3]
+        );
+    }
+}
@@ -0,0 +1,195 @@
+#[cfg(feature = "fs")]
+use super::codec;
+#[cfg(feature = "fs")]
+use super::error::Result;
+use super::model::Gradients;
+use super::model::Model;
+#[cfg(feature = "fs")]
+use ndarray::Array1;
+#[cfg(feature = "fs")]
+use ndarray::Array2;
+#[cfg(feature = "fs")]
+use ndarray::Array4;
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::io::BufReader;
+#[cfg(feature = "fs")]
+use std::io::BufWriter;
+#[cfg(feature = "fs")]
+use std::io::Read;
+#[cfg(feature = "fs")]
+use std::io::Write;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+/// Stochastic gradient descent with optional (heavy-ball) momentum.
+///
+/// With `momentum = 0.0` this is plain SGD: `p = p - lr * g`.
+/// Otherwise a velocity buffer is kept per parameter:
+/// `v = momentum * v + g`, `p = p - lr * v`.
+#[derive(Debug, Clone)]
+pub struct Sgd {
+    momentum: f32,
+    /// Velocity buffers, allocated on the first step that needs them.
+    velocity: Option<Gradients>,
+}
+
+impl Sgd {
+    pub fn new(momentum: f32) -> Self {
+        return Self {
+            momentum,
+            velocity: None,
+        };
+    }
+
+    pub fn momentum(&self) -> f32 {
+        return self.momentum;
+    }
+
+    /// Applies one update to `model` using `grads`.
+    pub fn step(&mut self, model: &mut Model, grads: &Gradients, learning_rate: f32) {
+        if self.momentum == 0.0 {
+            model.apply_update(-learning_rate, grads);
+            return;
+        }
+
+        let velocity = self
+            .velocity
+            .get_or_insert_with(|| Gradients::zeros_like(model));
+        velocity.scale(self.momentum);
+        velocity.add_assign(grads);
+        model.apply_update(-learning_rate, velocity);
+    }
+
+    /// Magic bytes at the start of a saved optimizer state file.
+    #[cfg(feature = "fs")]
+    const MAGIC: &'static [u8; 8] = b"ANTBEEOP";
+
+    /// Current version of the optimizer state format.
+    #[cfg(feature = "fs")]
+    const FORMAT_VERSION: u32 = 1;
+
+    /// Saves the optimizer state to a file at `path`, so training with
+    /// [`Model::partial_fit`] can continue with the same momentum later.
+    #[cfg(feature = "fs")]
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        codec::write_header(&mut writer, Self::MAGIC, Self::FORMAT_VERSION)?;
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        return Ok(());
+    }
+
+    /// Loads optimizer state saved with [`Sgd::save`] for `model`.
+    ///
+    /// Returns an error if the state was saved for a model with other
+    /// parameter shapes.
+    #[cfg(feature = "fs")]
+    pub fn load(path: &Path, model: &Model) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        codec::read_header(&mut reader, Self::MAGIC, Self::FORMAT_VERSION)?;
+        return Self::read_from(&mut reader, model);
+    }
+
+    /// Serializes the optimizer state (momentum and velocity buffers).
+    #[cfg(feature = "fs")]
+    pub(crate) fn write_to(&self, writer: &mut impl Write) -> Result<()> {
+        codec::write_f32(writer, self.momentum)?;
+        match &self.velocity {
+            None => codec::write_u32(writer, 0)?,
+            Some(velocity) => {
+                codec::write_u32(writer, 1)?;
+                codec::write_f32s(writer, velocity.w.iter())?;
+                codec::write_f32s(writer, velocity.b.iter())?;
+                for layer in &velocity.conv {
+                    codec::write_f32s(writer, layer.kernel.iter())?;
+                    codec::write_f32s(writer, layer.bias.iter())?;
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    /// Deserializes optimizer state for `model`'s parameter shapes.
+    #[cfg(feature = "fs")]
+    pub(crate) fn read_from(reader: &mut impl Read, model: &Model) -> Result<Self> {
+        let momentum = codec::read_f32(reader)?;
+        let velocity = match codec::read_u32(reader)? {
+            0 => None,
+            _ => {
+                let mut velocity = Gradients::zeros_like(model);
+                let w = codec::read_f32s(reader, velocity.w.len())?;
+                let b = codec::read_f32s(reader, velocity.b.len())?;
+                velocity.w = Array2::from_shape_vec(velocity.w.raw_dim(), w).unwrap();
+                velocity.b = Array1::from_vec(b);
+                for layer in &mut velocity.conv {
+                    let kernel = codec::read_f32s(reader, layer.kernel.len())?;
+                    let bias = codec::read_f32s(reader, layer.bias.len())?;
+                    layer.kernel = Array4::from_shape_vec(layer.kernel.raw_dim(), kernel).unwrap();
+                    layer.bias = Array1::from_vec(bias);
+                }
+                Some(velocity)
+            }
+        };
+        return Ok(Self { momentum, velocity });
+    }
+}
+
+/// Collects gradients over several batches so that the optimizer can step
+/// on their mean.
+///
+/// Accumulating `steps` batches of size `n` behaves like one batch of size
+/// `steps * n` while only holding `n` samples in memory at a time.
+#[derive(Debug, Clone)]
+pub struct GradientAccumulator {
+    steps: usize,
+    sum: Option<Gradients>,
+    count: usize,
+}
+
+impl GradientAccumulator {
+    /// Creates an accumulator that is ready after `steps` batches.
+    ///
+    /// `steps = 1` applies every batch's gradients directly.
+    pub fn new(steps: usize) -> Self {
+        assert!(steps >= 1, "need at least one accumulation step");
+        return Self {
+            steps,
+            sum: None,
+            count: 0,
+        };
+    }
+
+    pub fn steps(&self) -> usize {
+        return self.steps;
+    }
+
+    /// Number of batches accumulated since the last [`GradientAccumulator::take`].
+    pub fn pending(&self) -> usize {
+        return self.count;
+    }
+
+    /// Adds the gradients of one batch.
+    ///
+    /// # Returns
+    /// Whether `steps` batches have been collected and the optimizer
+    /// should step on [`GradientAccumulator::take`].
+    pub fn add(&mut self, grads: Gradients) -> bool {
+        match &mut self.sum {
+            Some(sum) => sum.add_assign(&grads),
+            None => self.sum = Some(grads),
+        }
+        self.count += 1;
+        return self.count >= self.steps;
+    }
+
+    /// Returns the mean of the accumulated gradients and resets the
+    /// accumulator, or `None` if nothing was accumulated.
+    pub fn take(&mut self) -> Option<Gradients> {
+        let mut mean = self.sum.take()?;
+        mean.scale(1.0 / self.count as f32);
+        self.count = 0;
+        return Some(mean);
+    }
+}
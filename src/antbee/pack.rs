@@ -0,0 +1,167 @@
+//! Single-file dataset archives, for moving a preprocessed dataset between
+//! machines instead of thousands of small image files.
+
+use super::codec;
+use super::dataset::Dataset;
+use super::error::Error;
+use super::error::Result;
+use super::kind::ClassMap;
+use super::kind::Kind;
+use super::preprocess::ChannelMode;
+use super::preprocess::Preprocess;
+use super::source::DatasetSource;
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use image::GrayImage;
+use image::RgbImage;
+use ndarray::Array2;
+use std::fs::File;
+use std::fs::create_dir_all;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+impl Dataset {
+    /// Magic bytes at the start of every pack file.
+    const PACK_MAGIC: &'static [u8; 8] = b"ANTBEEPK";
+    /// Layout version of pack files.
+    const PACK_VERSION: u32 = 1;
+
+    /// Writes the dataset to a single compressed pack file at `path`.
+    ///
+    /// A pack holds the same preprocessing, class map, labels, source paths
+    /// and samples as a [cache](Dataset::to_cache), but stores every pixel
+    /// as the byte it was decoded from and deflates the whole file, which
+    /// makes it several times smaller. Pixel values are rounded to the
+    /// nearest multiple of 1/255, so this is lossless for datasets loaded
+    /// from images in any [`Precision`](super::Precision).
+    pub fn to_pack(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        codec::write_header(&mut writer, Self::PACK_MAGIC, Self::PACK_VERSION)?;
+        let mut encoder = DeflateEncoder::new(writer, Compression::best());
+        self.preprocess().write_to(&mut encoder)?;
+        self.class_map().write_to(&mut encoder)?;
+
+        codec::write_u64(&mut encoder, self.len() as u64)?;
+        for kind in self.labels() {
+            codec::write_u32(&mut encoder, kind.index() as u32)?;
+        }
+        let has_paths = self.len() > 0 && self.path(0).is_some();
+        codec::write_u32(&mut encoder, has_paths as u32)?;
+        if has_paths {
+            for index in 0..self.len() {
+                let path = self.path(index).unwrap_or(Path::new(""));
+                codec::write_str(&mut encoder, &path.to_string_lossy())?;
+            }
+        }
+        let pixels: Vec<u8> = self
+            .features()
+            .iter()
+            .map(|&value| (value * 255.0).round().clamp(0.0, 255.0) as u8)
+            .collect();
+        codec::write_u8s(&mut encoder, &pixels)?;
+
+        encoder.finish()?.flush()?;
+        return Ok(());
+    }
+
+    /// Loads a dataset written by [`Dataset::to_pack`], in `f32` precision
+    /// (see [`Dataset::with_precision`]).
+    ///
+    /// Samples keep the order they had when the pack was written.
+    pub fn from_pack(path: &Path) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        codec::read_header(&mut reader, Self::PACK_MAGIC, Self::PACK_VERSION)?;
+        let mut decoder = DeflateDecoder::new(reader);
+        let preprocess = Preprocess::read_from(&mut decoder)?;
+        let classes = ClassMap::read_from(&mut decoder)?;
+
+        // The sizes come from the file: check their products and let the
+        // vectors grow with the data actually read rather than reserving
+        // what a corrupted count claims.
+        let dim = preprocess.input_dim();
+        let len = codec::read_u64(&mut decoder)?;
+        let len = usize::try_from(len)
+            .map_err(|_| Error::InvalidFormat(format!("{} samples overflow", len)))?;
+        let values = codec::size(&[len, dim])?;
+        let mut labels = Vec::with_capacity(len.min(1024));
+        for _ in 0..len {
+            let index = codec::read_u32(&mut decoder)? as usize;
+            if index >= classes.len() {
+                return Err(Error::InvalidFormat(format!(
+                    "label {} out of range for {} classes",
+                    index,
+                    classes.len()
+                )));
+            }
+            labels.push(Kind(index));
+        }
+        let paths = if codec::read_u32(&mut decoder)? != 0 {
+            let mut paths = Vec::with_capacity(len.min(1024));
+            for _ in 0..len {
+                paths.push(PathBuf::from(codec::read_str(&mut decoder)?));
+            }
+            Some(paths)
+        } else {
+            None
+        };
+        let pixels = codec::read_u8s(&mut decoder, values)?;
+        let features = Array2::from_shape_vec(
+            (len, dim),
+            pixels.into_iter().map(|byte| byte as f32 / 255.0).collect(),
+        )
+        .unwrap();
+
+        return Ok(Self::from_parts(
+            features, labels, paths, classes, preprocess,
+        ));
+    }
+
+    /// Writes every sample as a PNG at the preprocessed resolution into an
+    /// ImageFolder-style layout below `dir`: one directory per class,
+    /// files numbered in dataset order (keeping the original file name
+    /// when the dataset knows it, e.g. `00042_img.png`).
+    ///
+    /// Loading `dir` with [`Dataset::from_dataset_path_with`] and the same
+    /// preprocessing gives back the same samples and labels, though not in
+    /// the same order.
+    ///
+    /// # Returns
+    /// The number of images written.
+    pub fn save_images(&self, dir: &Path) -> Result<usize> {
+        let preprocess = self.preprocess();
+        let (width, height) = (preprocess.width, preprocess.height);
+        let plane = (width * height) as usize;
+        for name in self.classes() {
+            create_dir_all(dir.join(name))?;
+        }
+        for index in 0..self.len() {
+            let sample = self.sample(index);
+            let pixel = |channel: usize, x: u32, y: u32| {
+                let value = sample[channel * plane + (y * width + x) as usize];
+                return (value * 255.0).round().clamp(0.0, 255.0) as u8;
+            };
+            let file_name = match self.path(index).and_then(Path::file_stem) {
+                Some(stem) => format!("{:05}_{}.png", index, stem.to_string_lossy()),
+                None => format!("{:05}.png", index),
+            };
+            let path = dir
+                .join(self.class_map().name(self.labels()[index]))
+                .join(file_name);
+            match preprocess.channels {
+                ChannelMode::Rgb => RgbImage::from_fn(width, height, |x, y| {
+                    image::Rgb([pixel(0, x, y), pixel(1, x, y), pixel(2, x, y)])
+                })
+                .save(&path)?,
+                ChannelMode::Grayscale => {
+                    GrayImage::from_fn(width, height, |x, y| image::Luma([pixel(0, x, y)]))
+                        .save(&path)?
+                }
+            }
+        }
+        return Ok(self.len());
+    }
+}
@@ -0,0 +1,368 @@
+use super::codec;
+use super::error::Error;
+use super::error::Result;
+use image::DynamicImage;
+use image::ImageBuffer;
+use image::ImageReader;
+use image::Pixel;
+use image::RgbImage;
+use image::imageops::FilterType;
+use image::imageops::crop_imm;
+use image::imageops::replace;
+use image::imageops::resize;
+use ndarray::Array1;
+use std::fmt;
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Write;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+/// Color channels fed to the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChannelMode {
+    /// Three channels: red, green, blue.
+    Rgb,
+    /// A single luminance channel.
+    Grayscale,
+}
+
+impl ChannelMode {
+    /// Number of channels per pixel.
+    pub fn channels(self) -> usize {
+        return match self {
+            ChannelMode::Rgb => 3,
+            ChannelMode::Grayscale => 1,
+        };
+    }
+}
+
+/// How images are brought to the input resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ResizeMode {
+    /// Scales both sides independently, distorting images whose aspect
+    /// ratio differs from the input's.
+    #[default]
+    Stretch,
+    /// Cuts the largest centered region with the input's aspect ratio out
+    /// of the image and scales it, dropping the borders of the longer side.
+    CenterCrop,
+    /// Scales the whole image to fit inside the input and fills the
+    /// remaining border with black.
+    Letterbox,
+}
+
+impl ResizeMode {
+    fn id(self) -> u32 {
+        return match self {
+            ResizeMode::Stretch => 0,
+            ResizeMode::CenterCrop => 1,
+            ResizeMode::Letterbox => 2,
+        };
+    }
+
+    fn from_id(id: u32) -> Result<Self> {
+        return match id {
+            0 => Ok(ResizeMode::Stretch),
+            1 => Ok(ResizeMode::CenterCrop),
+            2 => Ok(ResizeMode::Letterbox),
+            other => Err(Error::InvalidFormat(format!(
+                "unsupported resize mode {}",
+                other
+            ))),
+        };
+    }
+}
+
+/// How images are turned into model inputs.
+///
+/// The same `Preprocess` must be used for the dataset a model is trained on
+/// and for every image it classifies later, so it is stored with both
+/// [`Dataset`](super::Dataset) and [`Model`](super::Model).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Preprocess {
+    /// Width images are resized to, in pixels.
+    pub width: u32,
+    /// Height images are resized to, in pixels.
+    pub height: u32,
+    /// Channels kept after decoding.
+    pub channels: ChannelMode,
+    /// How images of another aspect ratio are fit to `width` x `height`.
+    pub resize: ResizeMode,
+}
+
+impl Preprocess {
+    /// Returns the length of the flattened input vector:
+    /// channels * height * width.
+    pub fn input_dim(&self) -> usize {
+        return self.channels.channels() * self.height as usize * self.width as usize;
+    }
+
+    /// Decodes the image at `path` and preprocesses it with [`Preprocess::apply`].
+    ///
+    /// The format (JPEG, PNG, BMP, WebP, ...) is detected from the file
+    /// contents, so files with a wrong or missing extension still decode.
+    #[cfg(feature = "fs")]
+    pub fn load_image(&self, path: &Path) -> Result<Array1<f32>> {
+        let image = ImageReader::open(path)?.with_guessed_format()?.decode()?;
+        check_not_empty(image.width(), image.height())?;
+        return Ok(self.apply(&image));
+    }
+
+    /// Decodes an encoded image held in memory (e.g. an upload) and
+    /// preprocesses it like [`Preprocess::load_image`].
+    pub fn load_image_bytes(&self, bytes: &[u8]) -> Result<Array1<f32>> {
+        let image = ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()?
+            .decode()?;
+        check_not_empty(image.width(), image.height())?;
+        return Ok(self.apply(&image));
+    }
+
+    /// Preprocesses a raw RGB image: `rgb` holds `width * height` pixels
+    /// row by row, three bytes (red, green, blue) each, as decoded by a
+    /// browser or camera API.
+    ///
+    /// Returns an error if the image is empty or `rgb` is not exactly
+    /// `width * height * 3` bytes.
+    pub fn apply_rgb(&self, rgb: &[u8], width: u32, height: u32) -> Result<Array1<f32>> {
+        check_not_empty(width, height)?;
+        let Some(image) = RgbImage::from_raw(width, height, rgb.to_vec()) else {
+            return Err(Error::InvalidFormat(format!(
+                "expected {} bytes for a {}x{} RGB image, got {}",
+                width as u128 * height as u128 * 3,
+                width,
+                height,
+                rgb.len()
+            )));
+        };
+        return Ok(self.apply(&DynamicImage::ImageRgb8(image)));
+    }
+
+    /// Resizes `image` to `width` x `height` as set by `resize`, keeps the
+    /// configured channels, scales pixel values to [0, 1] and flattens the
+    /// result in CHW order.
+    pub fn apply(&self, image: &DynamicImage) -> Array1<f32> {
+        let mut data = Vec::<f32>::with_capacity(self.input_dim());
+
+        match self.channels {
+            ChannelMode::Rgb => {
+                let resized = self.resized(&image.to_rgb8());
+                for channel in 0..3 {
+                    for pixel in resized.pixels() {
+                        data.push(pixel[channel] as f32 / 255.0);
+                    }
+                }
+            }
+            ChannelMode::Grayscale => {
+                let resized = self.resized(&image.to_luma8());
+                for pixel in resized.pixels() {
+                    data.push(pixel[0] as f32 / 255.0);
+                }
+            }
+        }
+
+        return Array1::from_vec(data);
+    }
+
+    /// Brings `image` to `width` x `height` according to `resize`.
+    fn resized<P>(&self, image: &ImageBuffer<P, Vec<u8>>) -> ImageBuffer<P, Vec<u8>>
+    where
+        P: Pixel<Subpixel = u8> + 'static,
+    {
+        let (width, height) = (self.width, self.height);
+        let (source_width, source_height) = image.dimensions();
+        match self.resize {
+            ResizeMode::Stretch => {
+                return resize(image, width, height, FilterType::Lanczos3);
+            }
+            ResizeMode::CenterCrop => {
+                // Keep the full short side: crop_width / crop_height = width / height.
+                let wide =
+                    source_width as u64 * height as u64 > source_height as u64 * width as u64;
+                let (crop_width, crop_height) = if wide {
+                    (
+                        scaled(source_height, width, height, source_width),
+                        source_height,
+                    )
+                } else {
+                    (
+                        source_width,
+                        scaled(source_width, height, width, source_height),
+                    )
+                };
+                let region = crop_imm(
+                    image,
+                    (source_width - crop_width) / 2,
+                    (source_height - crop_height) / 2,
+                    crop_width,
+                    crop_height,
+                );
+                return resize(&*region, width, height, FilterType::Lanczos3);
+            }
+            ResizeMode::Letterbox => {
+                // Scale by the smaller of width / source_width and height / source_height.
+                let wide =
+                    source_width as u64 * height as u64 > source_height as u64 * width as u64;
+                let (inner_width, inner_height) = if wide {
+                    (width, scaled(source_height, width, source_width, height))
+                } else {
+                    (scaled(source_width, height, source_height, width), height)
+                };
+                let inner = resize(image, inner_width, inner_height, FilterType::Lanczos3);
+                let mut canvas = ImageBuffer::new(width, height);
+                replace(
+                    &mut canvas,
+                    &inner,
+                    ((width - inner_width) / 2) as i64,
+                    ((height - inner_height) / 2) as i64,
+                );
+                return canvas;
+            }
+        }
+    }
+
+    pub(crate) fn write_to(&self, writer: &mut impl Write) -> Result<()> {
+        codec::write_u32(writer, self.width)?;
+        codec::write_u32(writer, self.height)?;
+        codec::write_u32(writer, self.channels.channels() as u32)?;
+        codec::write_u32(writer, self.resize.id())?;
+        return Ok(());
+    }
+
+    pub(crate) fn read_from(reader: &mut impl Read) -> Result<Self> {
+        let mut preprocess = Self::read_without_resize(reader)?;
+        preprocess.resize = ResizeMode::from_id(codec::read_u32(reader)?)?;
+        return Ok(preprocess);
+    }
+
+    /// Reads the preprocessing of files that predate [`ResizeMode`], which
+    /// always stretched.
+    pub(crate) fn read_without_resize(reader: &mut impl Read) -> Result<Self> {
+        let width = codec::read_u32(reader)?;
+        let height = codec::read_u32(reader)?;
+        let channels = match codec::read_u32(reader)? {
+            3 => ChannelMode::Rgb,
+            1 => ChannelMode::Grayscale,
+            other => {
+                return Err(Error::InvalidFormat(format!(
+                    "unsupported channel count {}",
+                    other
+                )));
+            }
+        };
+        codec::size(&[channels.channels(), height as usize, width as usize])?;
+        return Ok(Self {
+            width,
+            height,
+            channels,
+            resize: ResizeMode::Stretch,
+        });
+    }
+}
+
+impl Default for Preprocess {
+    /// 28x28 RGB, i.e. 3 * 28 * 28 = 2352 input features.
+    fn default() -> Self {
+        return Self {
+            width: 28,
+            height: 28,
+            channels: ChannelMode::Rgb,
+            resize: ResizeMode::Stretch,
+        };
+    }
+}
+
+impl fmt::Display for Preprocess {
+    /// Formats as e.g. `28x28-rgb` or `28x28-rgb-crop`, suitable for file names.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let channels = match self.channels {
+            ChannelMode::Rgb => "rgb",
+            ChannelMode::Grayscale => "gray",
+        };
+        let resize = match self.resize {
+            ResizeMode::Stretch => "",
+            ResizeMode::CenterCrop => "-crop",
+            ResizeMode::Letterbox => "-letterbox",
+        };
+        return write!(f, "{}x{}-{}{}", self.width, self.height, channels, resize);
+    }
+}
+
+/// Images without pixels have no aspect ratio to crop or letterbox to.
+fn check_not_empty(width: u32, height: u32) -> Result<()> {
+    if width == 0 || height == 0 {
+        return Err(Error::InvalidFormat(format!(
+            "empty {}x{} image",
+            width, height
+        )));
+    }
+    return Ok(());
+}
+
+/// Returns `length * numerator / denominator` rounded, in [1, max]; `max`
+/// if either is zero.
+fn scaled(length: u32, numerator: u32, denominator: u32, max: u32) -> u32 {
+    if denominator == 0 || max == 0 {
+        return max;
+    }
+    let value = (length as u64 * numerator as u64 + denominator as u64 / 2) / denominator as u64;
+    return value.clamp(1, max as u64) as u32;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::antbee::Model;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    const MODES: [ResizeMode; 3] = [
+        ResizeMode::Stretch,
+        ResizeMode::CenterCrop,
+        ResizeMode::Letterbox,
+    ];
+
+    fn preprocess(width: u32, height: u32, resize: ResizeMode) -> Preprocess {
+        return Preprocess {
+            width,
+            height,
+            channels: ChannelMode::Rgb,
+            resize,
+        };
+    }
+
+    #[test]
+    fn empty_images_are_rejected() {
+        for resize in MODES {
+            let preprocess = preprocess(4, 4, resize);
+            for (width, height) in [(0, 0), (0, 3), (3, 0)] {
+                assert!(matches!(
+                    preprocess.apply_rgb(&[], width, height),
+                    Err(Error::InvalidFormat(_))
+                ));
+            }
+            let model = Model::from_rng(preprocess, 2, &mut ChaCha8Rng::seed_from_u64(0));
+            assert!(model.predict_from_rgb(&[], 0, 0).is_err());
+            assert!(model.predict_from_rgb(&[0; 3], 1, 1).is_ok());
+        }
+    }
+
+    #[test]
+    fn zero_sizes_do_not_panic() {
+        assert_eq!(scaled(5, 3, 0, 4), 4);
+        assert_eq!(scaled(5, 3, 2, 0), 0);
+        assert_eq!(scaled(0, 3, 2, 4), 1);
+        assert_eq!(scaled(u32::MAX, u32::MAX, 1, 7), 7);
+        for resize in MODES {
+            for (target_width, target_height) in [(0, 0), (0, 5), (5, 0), (4, 4)] {
+                let preprocess = preprocess(target_width, target_height, resize);
+                for (width, height) in [(1, 1), (1, 7), (7, 1), (3, 3)] {
+                    let rgb = vec![128; (width * height * 3) as usize];
+                    let x = preprocess.apply_rgb(&rgb, width, height).unwrap();
+                    assert_eq!(x.len(), preprocess.input_dim());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! Hand-written Protocol Buffers encoding.
+//!
+//! Just enough of the wire format to write the few message types the ONNX
+//! export and TensorBoard logging need, without a protobuf code generator.
+
+/// A protobuf message under construction.
+#[derive(Default)]
+pub(crate) struct Message {
+    pub(crate) bytes: Vec<u8>,
+}
+
+impl Message {
+    fn raw_varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    /// Writes a varint field (wire type 0).
+    pub(crate) fn varint(&mut self, field: u32, value: u64) -> &mut Self {
+        self.raw_varint((field as u64) << 3);
+        self.raw_varint(value);
+        return self;
+    }
+
+    /// Writes a length-delimited field (wire type 2).
+    pub(crate) fn bytes(&mut self, field: u32, value: &[u8]) -> &mut Self {
+        self.raw_varint(((field as u64) << 3) | 2);
+        self.raw_varint(value.len() as u64);
+        self.bytes.extend_from_slice(value);
+        return self;
+    }
+
+    pub(crate) fn string(&mut self, field: u32, value: &str) -> &mut Self {
+        return self.bytes(field, value.as_bytes());
+    }
+
+    pub(crate) fn message(&mut self, field: u32, value: &Message) -> &mut Self {
+        return self.bytes(field, &value.bytes);
+    }
+
+    /// Writes a 64-bit field (wire type 1), e.g. a `double`.
+    pub(crate) fn fixed64(&mut self, field: u32, value: u64) -> &mut Self {
+        self.raw_varint(((field as u64) << 3) | 1);
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+        return self;
+    }
+
+    /// Writes a 32-bit field (wire type 5), e.g. a `float`.
+    pub(crate) fn fixed32(&mut self, field: u32, value: u32) -> &mut Self {
+        self.raw_varint(((field as u64) << 3) | 5);
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+        return self;
+    }
+}
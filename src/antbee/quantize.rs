@@ -0,0 +1,468 @@
+//! Post-training int8 quantization of the linear layer.
+
+use super::codec;
+use super::conv::ConvNet;
+use super::error::Error;
+use super::error::Result;
+use super::evaluation::ConfusionMatrix;
+use super::kind::ClassMap;
+use super::kind::Kind;
+use super::kind::Prediction;
+use super::model::Model;
+use super::normalize::Normalizer;
+use super::preprocess::Preprocess;
+use super::source::DatasetSource;
+use ndarray::Array1;
+use ndarray::Array2;
+use ndarray::ArrayView1;
+use ndarray::ArrayView2;
+use ndarray::Axis;
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::io::BufReader;
+#[cfg(feature = "fs")]
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+/// Affine mapping between `f32` values and `i8` codes:
+/// value = scale * (code - zero_point).
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Affine {
+    scale: f32,
+    zero_point: i8,
+}
+
+impl Affine {
+    /// Fits the mapping to the range of `values`, widened to include 0 so
+    /// that zero is represented exactly.
+    fn fit(values: ArrayView1<f32>) -> Self {
+        let low = values.fold(0.0f32, |acc, &v| acc.min(v));
+        let high = values.fold(0.0f32, |acc, &v| acc.max(v));
+        let scale = if high > low {
+            (high - low) / 255.0
+        } else {
+            1.0
+        };
+        let zero_point = (-128.0 - low / scale).round().clamp(-128.0, 127.0) as i8;
+        return Self { scale, zero_point };
+    }
+
+    fn quantize(&self, value: f32) -> i8 {
+        let code = (value / self.scale).round() + self.zero_point as f32;
+        return code.clamp(-128.0, 127.0) as i8;
+    }
+}
+
+/// A [`Model`] whose linear layer is stored and evaluated in 8-bit integers,
+/// for small and fast deployment.
+///
+/// Every row of the weight matrix (one per class) is quantized to `i8`
+/// with its own scale and zero point; at inference, the features of every
+/// sample are quantized the same way, the dot products are accumulated in
+/// integers and only the logits are dequantized to `f32`. This stores the
+/// weights in a quarter of the space. Preprocessing, the normalizer and
+/// the conv front-end stay in `f32`.
+///
+/// Create one with [`Model::quantize`] and check what it costs with
+/// [`QuantizedModel::compare`].
+#[derive(Debug, Clone)]
+pub struct QuantizedModel {
+    preprocess: Preprocess,
+    normalizer: Option<Normalizer>,
+    conv: Option<ConvNet>,
+    /// Weight codes of shape (num_classes, feature_dim).
+    weights: Array2<i8>,
+    /// Mapping of every row of `weights`.
+    rows: Vec<Affine>,
+    /// Sum of the codes of every row of `weights`, used to apply the
+    /// zero points without touching every product.
+    row_sums: Vec<i64>,
+    bias: Array1<f32>,
+    threshold: Option<f32>,
+    temperature: f32,
+    classes: ClassMap,
+}
+
+/// How a [`QuantizedModel`] compares to the [`Model`] it was quantized from
+/// on a dataset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuantizationReport {
+    /// Accuracy of the `f32` model.
+    pub float_accuracy: f32,
+    /// Accuracy of the quantized model.
+    pub quantized_accuracy: f32,
+    /// Fraction of samples both models assign the same class.
+    pub agreement: f32,
+    /// Largest absolute difference of any class probability.
+    pub max_probability_error: f32,
+    /// Mean absolute difference of the class probabilities.
+    pub mean_probability_error: f32,
+}
+
+impl QuantizationReport {
+    /// Accuracy gained by quantizing, usually slightly negative.
+    pub fn accuracy_delta(&self) -> f32 {
+        return self.quantized_accuracy - self.float_accuracy;
+    }
+}
+
+impl Model {
+    /// Quantizes the linear layer to `i8`, see [`QuantizedModel`].
+    ///
+    /// No data is needed: the weight ranges come from the weights
+    /// themselves, and the feature ranges are fitted per sample at
+    /// inference.
+    pub fn quantize(&self) -> QuantizedModel {
+        let w = self.weights();
+        let mut weights = Array2::<i8>::zeros(w.raw_dim());
+        let mut rows = Vec::with_capacity(w.nrows());
+        for (row, mut codes) in w.outer_iter().zip(weights.outer_iter_mut()) {
+            let affine = Affine::fit(row);
+            codes.zip_mut_with(&row, |code, &value| *code = affine.quantize(value));
+            rows.push(affine);
+        }
+        return QuantizedModel::from_parts(
+            *self.preprocess(),
+            self.normalizer().cloned(),
+            self.conv().cloned(),
+            weights,
+            rows,
+            self.bias().to_owned(),
+            self.threshold(),
+            self.temperature(),
+            self.class_map().clone(),
+        );
+    }
+}
+
+impl QuantizedModel {
+    /// Magic bytes at the start of a saved quantized model file.
+    const MAGIC: &'static [u8; 8] = b"ANTBEEQM";
+
+    /// Current version of the saved quantized model format.
+    const FORMAT_VERSION: u32 = 1;
+
+    #[allow(clippy::too_many_arguments)]
+    fn from_parts(
+        preprocess: Preprocess,
+        normalizer: Option<Normalizer>,
+        conv: Option<ConvNet>,
+        weights: Array2<i8>,
+        rows: Vec<Affine>,
+        bias: Array1<f32>,
+        threshold: Option<f32>,
+        temperature: f32,
+        classes: ClassMap,
+    ) -> Self {
+        let row_sums = weights
+            .outer_iter()
+            .map(|codes| codes.iter().map(|&code| code as i64).sum())
+            .collect();
+        return Self {
+            preprocess,
+            normalizer,
+            conv,
+            weights,
+            rows,
+            row_sums,
+            bias,
+            threshold,
+            temperature,
+            classes,
+        };
+    }
+
+    pub fn preprocess(&self) -> &Preprocess {
+        return &self.preprocess;
+    }
+
+    pub fn num_classes(&self) -> usize {
+        return self.weights.nrows();
+    }
+
+    /// Length of the feature vector the linear layer sees.
+    pub fn feature_dim(&self) -> usize {
+        return self.weights.ncols();
+    }
+
+    /// Names of the classes, as in [`Model::class_map`].
+    pub fn class_map(&self) -> &ClassMap {
+        return &self.classes;
+    }
+
+    /// The weights decoded back to `f32`, the values the quantized model
+    /// effectively computes with.
+    pub fn dequantized_weights(&self) -> Array2<f32> {
+        let mut w = Array2::<f32>::zeros(self.weights.raw_dim());
+        for ((mut row, codes), affine) in w
+            .outer_iter_mut()
+            .zip(self.weights.outer_iter())
+            .zip(&self.rows)
+        {
+            row.zip_mut_with(&codes, |value, &code| {
+                *value = affine.scale * (code as f32 - affine.zero_point as f32);
+            });
+        }
+        return w;
+    }
+
+    /// Computes the logits of a batch like [`Model::logits_batch`], with
+    /// the linear layer in integer arithmetic.
+    pub fn logits_batch(&self, x: ArrayView2<f32>) -> Array2<f32> {
+        let mut x = x.to_owned();
+        if let Some(normalizer) = &self.normalizer {
+            normalizer.apply_batch(x.view_mut());
+        }
+        if let Some(conv) = &self.conv {
+            x = conv.forward_batch(x.view());
+        }
+
+        let dim = self.feature_dim() as i64;
+        let mut z = Array2::<f32>::zeros((x.nrows(), self.num_classes()));
+        let mut codes = vec![0i8; self.feature_dim()];
+        for (features, mut logits) in x.outer_iter().zip(z.outer_iter_mut()) {
+            let input = Affine::fit(features);
+            for (code, &value) in codes.iter_mut().zip(features) {
+                *code = input.quantize(value);
+            }
+            let input_sum: i64 = codes.iter().map(|&code| code as i64).sum();
+            let input_zero = input.zero_point as i64;
+            for (k, logit) in logits.iter_mut().enumerate() {
+                let row = self.rows[k];
+                let row_zero = row.zero_point as i64;
+                let products: i64 = self
+                    .weights
+                    .row(k)
+                    .iter()
+                    .zip(&codes)
+                    .map(|(&w, &x)| w as i64 * x as i64)
+                    .sum();
+                // sum (w - zw)(x - zx) expanded, so the loop needs no subtractions
+                let dot = products - input_zero * self.row_sums[k] - row_zero * input_sum
+                    + dim * row_zero * input_zero;
+                *logit = row.scale * input.scale * dot as f32 + self.bias[k];
+            }
+        }
+        return z;
+    }
+
+    /// Computes class probabilities of a batch like
+    /// [`Model::predict_probs_batch`].
+    pub fn predict_probs_batch(&self, x: ArrayView2<f32>) -> Array2<f32> {
+        let mut z = self.logits_batch(x);
+        if self.temperature != 1.0 {
+            z /= self.temperature;
+        }
+        for row in z.axis_iter_mut(Axis(0)) {
+            Model::softmax(row);
+        }
+        return z;
+    }
+
+    /// Predicts the class of a single input together with its probability,
+    /// honoring the decision threshold like [`Model::predict_with_probability`].
+    pub fn predict_with_probability(&self, x: ArrayView1<f32>) -> Prediction {
+        let probs = self
+            .predict_probs_batch(x.insert_axis(Axis(0)))
+            .index_axis_move(Axis(0), 0);
+        let k = self.decide(probs.view());
+        return Prediction {
+            kind: Kind(k),
+            probability: probs[k],
+        };
+    }
+
+    /// Classifies a single image file like [`Model::predict_image`].
+    #[cfg(feature = "fs")]
+    pub fn predict_image(&self, path: &Path) -> Result<Prediction> {
+        let x = self.preprocess.load_image(path)?;
+        return Ok(self.predict_with_probability(x.view()));
+    }
+
+    /// Returns the class predicted from `probs`, honoring the threshold.
+    fn decide(&self, probs: ArrayView1<f32>) -> usize {
+        return match self.threshold {
+            Some(t) => (probs[1] >= t) as usize,
+            None => Model::argmax(probs),
+        };
+    }
+
+    /// Tallies predicted against ground truth labels like
+    /// [`Model::confusion_matrix`].
+    pub fn confusion_matrix(&self, dataset: &impl DatasetSource) -> ConfusionMatrix {
+        let mut matrix = ConfusionMatrix::new(self.num_classes());
+        for start in (0..dataset.len()).step_by(Model::EVAL_BATCH_SIZE) {
+            let range = start..(start + Model::EVAL_BATCH_SIZE).min(dataset.len());
+            let probs = self.predict_probs_batch(dataset.batch(range.clone()).view());
+            for (row, &kind) in probs.axis_iter(Axis(0)).zip(&dataset.labels()[range]) {
+                matrix.add(kind, Kind(self.decide(row)));
+            }
+        }
+        return matrix;
+    }
+
+    /// Accuracy on `dataset`.
+    pub fn evaluate(&self, dataset: &impl DatasetSource) -> f32 {
+        return self.confusion_matrix(dataset).accuracy();
+    }
+
+    /// Measures how much accuracy and probability the quantization cost,
+    /// against `model`, the model it was quantized from, on `dataset`
+    /// (typically the validation split).
+    pub fn compare(&self, model: &Model, dataset: &impl DatasetSource) -> QuantizationReport {
+        let mut float_matrix = ConfusionMatrix::new(self.num_classes());
+        let mut quantized_matrix = ConfusionMatrix::new(self.num_classes());
+        let (mut agreed, mut max_error, mut total_error) = (0, 0.0f32, 0.0f32);
+        for start in (0..dataset.len()).step_by(Model::EVAL_BATCH_SIZE) {
+            let range = start..(start + Model::EVAL_BATCH_SIZE).min(dataset.len());
+            let x = dataset.batch(range.clone());
+            let float_probs = model.predict_probs_batch(x.view());
+            let quantized_probs = self.predict_probs_batch(x.view());
+            let rows = float_probs.outer_iter().zip(quantized_probs.outer_iter());
+            for ((float, quantized), &kind) in rows.zip(&dataset.labels()[range]) {
+                let float_class = Kind(model.decide(float));
+                let quantized_class = Kind(self.decide(quantized));
+                float_matrix.add(kind, float_class);
+                quantized_matrix.add(kind, quantized_class);
+                agreed += (float_class == quantized_class) as usize;
+                for (&p, &q) in float.iter().zip(&quantized) {
+                    max_error = max_error.max((p - q).abs());
+                    total_error += (p - q).abs();
+                }
+            }
+        }
+        let samples = dataset.len().max(1) as f32;
+        return QuantizationReport {
+            float_accuracy: float_matrix.accuracy(),
+            quantized_accuracy: quantized_matrix.accuracy(),
+            agreement: agreed as f32 / samples,
+            max_probability_error: max_error,
+            mean_probability_error: total_error / (samples * self.num_classes() as f32),
+        };
+    }
+
+    /// Serializes the quantized model to `writer`, in a format of its own
+    /// that [`Model::read_from`] does not accept.
+    pub fn write_to(&self, writer: &mut impl Write) -> Result<()> {
+        codec::write_header(writer, Self::MAGIC, Self::FORMAT_VERSION)?;
+        self.preprocess.write_to(writer)?;
+        match &self.conv {
+            Some(conv) => conv.write_to(writer)?,
+            None => codec::write_u32(writer, 0)?,
+        }
+        codec::write_u64(writer, self.num_classes() as u64)?;
+        codec::write_u64(writer, self.feature_dim() as u64)?;
+        codec::write_i8s(writer, self.weights.iter())?;
+        let scales: Vec<f32> = self.rows.iter().map(|row| row.scale).collect();
+        let zero_points: Vec<i8> = self.rows.iter().map(|row| row.zero_point).collect();
+        codec::write_f32s(writer, scales.iter())?;
+        codec::write_i8s(writer, zero_points.iter())?;
+        codec::write_f32s(writer, self.bias.iter())?;
+        codec::write_f32(writer, self.threshold.unwrap_or(-1.0))?;
+        match &self.normalizer {
+            Some(normalizer) => {
+                codec::write_u32(writer, 1)?;
+                normalizer.write_to(writer)?;
+            }
+            None => codec::write_u32(writer, 0)?,
+        }
+        codec::write_f32(writer, self.temperature)?;
+        self.classes.write_to(writer)?;
+        return Ok(());
+    }
+
+    /// Deserializes a model previously written with [`QuantizedModel::write_to`].
+    pub fn read_from(reader: &mut impl Read) -> Result<Self> {
+        codec::read_header(reader, Self::MAGIC, Self::FORMAT_VERSION)?;
+        let preprocess = Preprocess::read_from(reader)?;
+        let conv = ConvNet::read_from(reader, &preprocess)?;
+        let expected_dim = match &conv {
+            Some(conv) => conv.output_dim(),
+            None => preprocess.input_dim(),
+        };
+        let num_classes = codec::read_u64(reader)? as usize;
+        let feature_dim = codec::read_u64(reader)? as usize;
+        if num_classes < 2 || feature_dim != expected_dim {
+            return Err(Error::InvalidFormat(format!(
+                "unsupported model shape {}x{}",
+                num_classes, feature_dim
+            )));
+        }
+        let weights = codec::read_i8s(reader, codec::size(&[num_classes, feature_dim])?)?;
+        let scales = codec::read_f32s(reader, num_classes)?;
+        let zero_points = codec::read_i8s(reader, num_classes)?;
+        if scales
+            .iter()
+            .any(|&scale| !(scale > 0.0 && scale.is_finite()))
+        {
+            return Err(Error::InvalidFormat("invalid weight scale".to_string()));
+        }
+        let rows = scales
+            .into_iter()
+            .zip(zero_points)
+            .map(|(scale, zero_point)| Affine { scale, zero_point })
+            .collect();
+        let bias = codec::read_f32s(reader, num_classes)?;
+        let threshold = Some(codec::read_f32(reader)?).filter(|&t| t >= 0.0);
+        if threshold.is_some() && num_classes != 2 {
+            return Err(Error::InvalidFormat(
+                "decision threshold on a model with more than two classes".to_string(),
+            ));
+        }
+        let normalizer = match codec::read_u32(reader)? {
+            0 => None,
+            _ => Some(Normalizer::read_from(reader)?),
+        };
+        if normalizer.as_ref().is_some_and(|n| !n.matches(&preprocess)) {
+            return Err(Error::InvalidFormat(
+                "normalizer does not match the preprocessing channels".to_string(),
+            ));
+        }
+        let temperature = codec::read_f32(reader)?;
+        if !(temperature > 0.0 && temperature.is_finite()) {
+            return Err(Error::InvalidFormat(format!(
+                "invalid temperature {}",
+                temperature
+            )));
+        }
+        let classes = ClassMap::read_from(reader)?;
+        if classes.len() != num_classes {
+            return Err(Error::InvalidFormat(format!(
+                "{} class names for {} classes",
+                classes.len(),
+                num_classes
+            )));
+        }
+        return Ok(Self::from_parts(
+            preprocess,
+            normalizer,
+            conv,
+            Array2::from_shape_vec((num_classes, feature_dim), weights).unwrap(),
+            rows,
+            Array1::from_vec(bias),
+            threshold,
+            temperature,
+            classes,
+        ));
+    }
+
+    /// Saves the quantized model to a file at `path`.
+    #[cfg(feature = "fs")]
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        return Ok(());
+    }
+
+    /// Loads a quantized model saved with [`QuantizedModel::save`].
+    #[cfg(feature = "fs")]
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        return Self::read_from(&mut reader);
+    }
+}
@@ -0,0 +1,415 @@
+//! Machine-readable JSON reports of predictions and evaluations, for
+//! downstream tooling.
+
+use super::dataset::Dataset;
+use super::error::Result;
+use super::evaluation::ConfusionMatrix;
+use super::evaluation::ReliabilityDiagram;
+use super::evaluation::RocCurve;
+use super::kind::ClassMap;
+use super::kind::Kind;
+use super::metrics::json_number;
+use super::metrics::json_string;
+use super::model::Model;
+use super::preprocess::ChannelMode;
+use super::preprocess::Preprocess;
+use super::preprocess::ResizeMode;
+use super::stats::FileIssue;
+use super::tta::TtaConfig;
+use rayon::prelude::*;
+use std::fmt::Write;
+use std::fs::write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+/// Version of the JSON layout written by [`Report::to_json`].
+///
+/// Fields are only ever added within a version, so consumers should ignore
+/// unknown ones; renaming or removing a field, or changing its meaning,
+/// increments the version.
+pub const REPORT_SCHEMA_VERSION: u32 = 1;
+
+/// The prediction for one image or dataset sample of a [`Report`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleResult {
+    /// Image file of the sample, if known.
+    pub path: Option<PathBuf>,
+    /// True class, if known.
+    pub label: Option<Kind>,
+    pub predicted: Kind,
+    /// Probability of every class, by index.
+    pub probabilities: Vec<f32>,
+}
+
+/// Predictions of a model on a set of images, with the model's settings
+/// and, where the true classes are known, the evaluation metrics.
+///
+/// The JSON layout is described at [`Report::to_json`].
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// When the predictions were made.
+    pub created: SystemTime,
+    /// File the model was loaded from or saved to, if any.
+    pub model_path: Option<PathBuf>,
+    pub preprocess: Preprocess,
+    pub classes: ClassMap,
+    /// Output channels of the conv blocks; empty for a linear model.
+    pub conv_channels: Vec<usize>,
+    /// Whether the model standardizes its inputs.
+    pub normalized: bool,
+    pub temperature: f32,
+    pub threshold: Option<f32>,
+    /// Number of augmented views every prediction averages over, 1 without
+    /// test-time augmentation.
+    pub views: usize,
+    pub samples: Vec<SampleResult>,
+    /// Images that could not be classified.
+    pub failures: Vec<FileIssue>,
+}
+
+impl Report {
+    /// Creates an empty report of `model`'s settings, timestamped now.
+    pub fn new(model: &Model) -> Self {
+        let conv_channels = model.conv().map_or(Vec::new(), |conv| {
+            return conv
+                .layers()
+                .iter()
+                .map(|layer| layer.out_channels())
+                .collect();
+        });
+        return Self {
+            created: SystemTime::now(),
+            model_path: None,
+            preprocess: *model.preprocess(),
+            classes: model.class_map().clone(),
+            conv_channels,
+            normalized: model.normalizer().is_some(),
+            temperature: model.temperature(),
+            threshold: model.threshold(),
+            views: 1,
+            samples: Vec::new(),
+            failures: Vec::new(),
+        };
+    }
+
+    /// Predicts every sample of `dataset`, whose labels make the report an
+    /// evaluation, as [`Model::confusion_matrix`] and friends compute it.
+    pub fn evaluate(model: &Model, dataset: &Dataset) -> Self {
+        let mut report = Self::new(model);
+        let mut index = 0;
+        for (probs, labels) in model.batched_probs(dataset) {
+            for (row, &label) in probs.rows().into_iter().zip(labels) {
+                report.samples.push(SampleResult {
+                    path: dataset.path(index).map(Path::to_path_buf),
+                    label: Some(label),
+                    predicted: Kind(model.decide(row)),
+                    probabilities: row.to_vec(),
+                });
+                index += 1;
+            }
+        }
+        return report;
+    }
+
+    /// Classifies the image files in `paths` like
+    /// [`Model::predict_images`], or [`Model::predict_images_tta`] with a
+    /// `tta` config. The true classes are unknown, so the report has no
+    /// metrics; files that fail to decode are listed as failures.
+    pub fn predict_images(model: &Model, paths: &[PathBuf], tta: Option<TtaConfig>) -> Self {
+        let mut report = Self::new(model);
+        report.views = tta.map_or(1, |config| config.views());
+        let results: Vec<Result<Vec<f32>>> = paths
+            .par_iter()
+            .map(|path| {
+                let x = model.preprocess().load_image(path)?;
+                let probs = match tta {
+                    Some(config) => model.predict_probs_tta(x.view(), config),
+                    None => model.predict_probs(x.view()),
+                };
+                return Ok(probs.to_vec());
+            })
+            .collect();
+        for (path, result) in paths.iter().zip(results) {
+            match result {
+                Ok(probabilities) => report.samples.push(SampleResult {
+                    path: Some(path.clone()),
+                    label: None,
+                    predicted: Kind(model.decide((&probabilities[..]).into())),
+                    probabilities,
+                }),
+                Err(err) => report.failures.push(FileIssue {
+                    path: path.clone(),
+                    reason: err.to_string(),
+                }),
+            }
+        }
+        return report;
+    }
+
+    /// Confusion matrix of the samples with a label, `None` if there are
+    /// none.
+    pub fn confusion_matrix(&self) -> Option<ConfusionMatrix> {
+        let mut matrix = ConfusionMatrix::new(self.classes.len());
+        for sample in &self.samples {
+            if let Some(label) = sample.label {
+                matrix.add(label, sample.predicted);
+            }
+        }
+        return (matrix.total() > 0).then_some(matrix);
+    }
+
+    /// Encodes the report as a JSON object of the following form (version
+    /// [`REPORT_SCHEMA_VERSION`]):
+    ///
+    /// ```json
+    /// {
+    ///   "schema": "antbee-rs/report",
+    ///   "schema_version": 1,
+    ///   "generator": "antbee-rs 0.1.0",
+    ///   "created": "2026-10-14T09:30:00Z",
+    ///   "model": {
+    ///     "path": "ants.model",
+    ///     "input": { "width": 28, "height": 28, "channels": "rgb", "resize": "stretch" },
+    ///     "classes": ["ants", "bees"],
+    ///     "conv_channels": [],
+    ///     "normalized": false,
+    ///     "temperature": 1,
+    ///     "threshold": null,
+    ///     "views": 1
+    ///   },
+    ///   "metrics": {
+    ///     "samples": 153,
+    ///     "accuracy": 0.65,
+    ///     "macro_f1": 0.64,
+    ///     "auc": 0.7,
+    ///     "ece": 0.08,
+    ///     "mean_confidence": 0.62,
+    ///     "classes": [{ "class": "ants", "precision": 0.6, "recall": 0.7, "f1": 0.65, "support": 70 }],
+    ///     "confusion_matrix": [[49, 21], [32, 51]]
+    ///   },
+    ///   "predictions": [
+    ///     { "path": "val/ants/1.jpg", "label": "ants", "predicted": "ants",
+    ///       "probability": 0.71, "probabilities": [0.71, 0.29] }
+    ///   ],
+    ///   "failures": [{ "path": "val/bees/broken.jpg", "error": "..." }]
+    /// }
+    /// ```
+    ///
+    /// `created` is UTC. `metrics` is `null` unless some samples have a
+    /// label, and then covers only those; `auc` is `null` unless there are
+    /// two classes, with the second as positive. Confusion matrix rows are
+    /// true classes, columns predicted ones. Missing paths and labels, and
+    /// values that are not finite, are `null`.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        let names = |classes: &ClassMap| -> String {
+            let names: Vec<String> = classes.names().iter().map(|n| json_string(n)).collect();
+            return format!("[{}]", names.join(", "));
+        };
+        let path = |path: Option<&Path>| {
+            return path.map_or("null".to_string(), |path| {
+                return json_string(&path.to_string_lossy());
+            });
+        };
+        let list = |values: &mut dyn Iterator<Item = String>| {
+            return format!("[{}]", values.collect::<Vec<_>>().join(", "));
+        };
+
+        json.push_str("{\n");
+        json.push_str("  \"schema\": \"antbee-rs/report\",\n");
+        let _ = writeln!(json, "  \"schema_version\": {},", REPORT_SCHEMA_VERSION);
+        let _ = writeln!(
+            json,
+            "  \"generator\": \"antbee-rs {}\",",
+            env!("CARGO_PKG_VERSION")
+        );
+        let _ = writeln!(json, "  \"created\": \"{}\",", rfc3339(self.created));
+
+        json.push_str("  \"model\": {\n");
+        let _ = writeln!(json, "    \"path\": {},", path(self.model_path.as_deref()));
+        let channels = match self.preprocess.channels {
+            ChannelMode::Rgb => "rgb",
+            ChannelMode::Grayscale => "gray",
+        };
+        let resize = match self.preprocess.resize {
+            ResizeMode::Stretch => "stretch",
+            ResizeMode::CenterCrop => "crop",
+            ResizeMode::Letterbox => "letterbox",
+        };
+        let _ = writeln!(
+            json,
+            "    \"input\": {{ \"width\": {}, \"height\": {}, \"channels\": \"{}\", \"resize\": \"{}\" }},",
+            self.preprocess.width, self.preprocess.height, channels, resize
+        );
+        let _ = writeln!(json, "    \"classes\": {},", names(&self.classes));
+        let _ = writeln!(
+            json,
+            "    \"conv_channels\": {},",
+            list(&mut self.conv_channels.iter().map(usize::to_string))
+        );
+        let _ = writeln!(json, "    \"normalized\": {},", self.normalized);
+        let _ = writeln!(
+            json,
+            "    \"temperature\": {},",
+            json_number(self.temperature)
+        );
+        let _ = writeln!(
+            json,
+            "    \"threshold\": {},",
+            self.threshold.map_or("null".to_string(), json_number)
+        );
+        let _ = writeln!(json, "    \"views\": {}", self.views);
+        json.push_str("  },\n");
+
+        match self.confusion_matrix() {
+            Some(matrix) => {
+                json.push_str("  \"metrics\": {\n");
+                let labeled = || self.samples.iter().filter(|s| s.label.is_some());
+                let auc = (self.classes.len() == 2).then(|| {
+                    let scores = labeled().map(|s| (s.probabilities[1], s.label == Some(Kind(1))));
+                    return RocCurve::from_scores(scores).auc();
+                });
+                let reliability = ReliabilityDiagram::from_predictions(
+                    labeled().map(|s| {
+                        return (
+                            s.probabilities[s.predicted.index()],
+                            s.label == Some(s.predicted),
+                        );
+                    }),
+                    ReliabilityDiagram::DEFAULT_BINS,
+                );
+                let _ = writeln!(json, "    \"samples\": {},", matrix.total());
+                let _ = writeln!(
+                    json,
+                    "    \"accuracy\": {},",
+                    json_number(matrix.accuracy())
+                );
+                let _ = writeln!(
+                    json,
+                    "    \"macro_f1\": {},",
+                    json_number(matrix.macro_f1())
+                );
+                let _ = writeln!(
+                    json,
+                    "    \"auc\": {},",
+                    auc.map_or("null".to_string(), json_number)
+                );
+                let _ = writeln!(
+                    json,
+                    "    \"ece\": {},",
+                    json_number(reliability.expected_calibration_error())
+                );
+                let _ = writeln!(
+                    json,
+                    "    \"mean_confidence\": {},",
+                    json_number(reliability.mean_confidence())
+                );
+                let classes = self.classes.names().iter().enumerate().map(|(k, name)| {
+                    let support: usize = (0..matrix.num_classes())
+                        .map(|p| matrix.count(Kind(k), Kind(p)))
+                        .sum();
+                    return format!(
+                        "{{ \"class\": {}, \"precision\": {}, \"recall\": {}, \"f1\": {}, \"support\": {} }}",
+                        json_string(name),
+                        json_number(matrix.precision(Kind(k))),
+                        json_number(matrix.recall(Kind(k))),
+                        json_number(matrix.f1(Kind(k))),
+                        support
+                    );
+                });
+                let _ = writeln!(json, "    \"classes\": {},", list(&mut classes.into_iter()));
+                let rows = (0..matrix.num_classes()).map(|actual| {
+                    return list(
+                        &mut (0..matrix.num_classes())
+                            .map(|p| matrix.count(Kind(actual), Kind(p)).to_string()),
+                    );
+                });
+                let _ = writeln!(
+                    json,
+                    "    \"confusion_matrix\": {}",
+                    list(&mut rows.into_iter())
+                );
+                json.push_str("  },\n");
+            }
+            None => json.push_str("  \"metrics\": null,\n"),
+        }
+
+        json.push_str("  \"predictions\": [");
+        for (index, sample) in self.samples.iter().enumerate() {
+            let _ = write!(
+                json,
+                "{}\n    {{ \"path\": {}, \"label\": {}, \"predicted\": {}, \"probability\": {}, \"probabilities\": {} }}",
+                if index == 0 { "" } else { "," },
+                path(sample.path.as_deref()),
+                sample.label.map_or("null".to_string(), |label| json_string(
+                    self.classes.name(label)
+                )),
+                json_string(self.classes.name(sample.predicted)),
+                json_number(sample.probabilities[sample.predicted.index()]),
+                list(&mut sample.probabilities.iter().map(|&p| json_number(p)))
+            );
+        }
+        json.push_str(if self.samples.is_empty() {
+            "],\n"
+        } else {
+            "\n  ],\n"
+        });
+
+        json.push_str("  \"failures\": [");
+        for (index, failure) in self.failures.iter().enumerate() {
+            let _ = write!(
+                json,
+                "{}\n    {{ \"path\": {}, \"error\": {} }}",
+                if index == 0 { "" } else { "," },
+                path(Some(&failure.path)),
+                json_string(&failure.reason)
+            );
+        }
+        json.push_str(if self.failures.is_empty() {
+            "]\n"
+        } else {
+            "\n  ]\n"
+        });
+        json.push_str("}\n");
+        return json;
+    }
+
+    /// Writes [`Report::to_json`] to `path`.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write(path, self.to_json())?;
+        return Ok(());
+    }
+}
+
+/// Formats `time` as an RFC 3339 UTC timestamp with second precision, e.g.
+/// `2026-10-14T09:30:00Z`.
+fn rfc3339(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rest) = (seconds / 86_400, seconds % 86_400);
+    // Civil date from the day count, after Howard Hinnant's `civil_from_days`.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    return format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rest / 3600,
+        rest % 3600 / 60,
+        rest % 60
+    );
+}
@@ -0,0 +1,100 @@
+//! Mining the samples a model gets wrong, for manual review.
+
+use super::dataset::Dataset;
+use super::error::Result;
+use super::kind::ClassMap;
+use super::kind::Kind;
+use super::model::Model;
+use super::source::DatasetSource;
+use ndarray::Axis;
+use std::fs::copy;
+use std::fs::create_dir_all;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// A sample of a [`Dataset`] whose predicted class is not its label.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MisclassifiedSample {
+    /// Position of the sample in the dataset.
+    pub index: usize,
+    /// Image file of the sample, if the dataset knows it (see [`Dataset::path`]).
+    pub path: Option<PathBuf>,
+    /// Ground truth label.
+    pub actual: Kind,
+    /// Class the model predicted.
+    pub predicted: Kind,
+    /// Probability the model gave the predicted class.
+    pub probability: f32,
+    /// Probability the model gave the ground truth class.
+    pub actual_probability: f32,
+}
+
+impl Model {
+    /// Lists the samples of `dataset` the model misclassifies, most
+    /// confident mistakes first.
+    ///
+    /// Predictions honor the temperature and decision threshold, exactly as
+    /// in [`Model::confusion_matrix`], so the list has one entry per
+    /// off-diagonal count. Confident mistakes are the likeliest to be
+    /// mislabeled images or genuinely confusable samples.
+    pub fn misclassified(&self, dataset: &Dataset) -> Vec<MisclassifiedSample> {
+        let mut samples = Vec::new();
+        for start in (0..dataset.len()).step_by(Self::EVAL_BATCH_SIZE) {
+            let range = start..(start + Self::EVAL_BATCH_SIZE).min(dataset.len());
+            let probs = self.predict_probs_batch(dataset.batch(range.clone()).view());
+            for (index, row) in range.zip(probs.axis_iter(Axis(0))) {
+                let actual = dataset.labels()[index];
+                let predicted = Kind(self.decide(row));
+                if predicted == actual {
+                    continue;
+                }
+                samples.push(MisclassifiedSample {
+                    index,
+                    path: dataset.path(index).map(Path::to_path_buf),
+                    actual,
+                    predicted,
+                    probability: row[predicted.index()],
+                    actual_probability: row[actual.index()],
+                });
+            }
+        }
+        samples.sort_by(|a, b| b.probability.total_cmp(&a.probability));
+        return samples;
+    }
+}
+
+/// Copies the image files of `samples` into `dir` for review.
+///
+/// Every sample goes to `dir/<actual>_as_<predicted>/`, named after
+/// `classes`, with its rank and confidence prefixed to the file name
+/// (e.g. `003_0.912_img42.jpg`), so each folder lists the most confident
+/// mistakes first. Samples without a path are skipped.
+///
+/// # Returns
+/// The number of files copied.
+pub fn copy_misclassified(
+    samples: &[MisclassifiedSample],
+    classes: &ClassMap,
+    dir: &Path,
+) -> Result<usize> {
+    let mut copied = 0;
+    for (rank, sample) in samples.iter().enumerate() {
+        let Some(path) = &sample.path else {
+            continue;
+        };
+        let folder = dir.join(format!(
+            "{}_as_{}",
+            classes.name(sample.actual),
+            classes.name(sample.predicted)
+        ));
+        create_dir_all(&folder)?;
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let target = folder.join(format!(
+            "{:03}_{:.3}_{}",
+            rank, sample.probability, file_name
+        ));
+        copy(path, target)?;
+        copied += 1;
+    }
+    return Ok(copied);
+}
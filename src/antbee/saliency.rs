@@ -0,0 +1,86 @@
+//! Saliency maps: which pixels a prediction is sensitive to.
+
+use super::kind::Kind;
+use super::model::Model;
+use image::Rgb;
+use image::RgbImage;
+use ndarray::Array1;
+use ndarray::ArrayView1;
+use ndarray::Axis;
+
+impl Model {
+    /// Computes the gradient of the logit of `class` with respect to every
+    /// input feature of `x`, i.e. how much z_class = W_class·f(x) + b_class
+    /// changes per unit change of each (unnormalized) input value.
+    ///
+    /// Without a conv front-end the logit is linear in `x`, so the gradient
+    /// is the class's weight row (divided by the normalizer's std) for every
+    /// input; with one, it is backpropagated through the conv blocks and
+    /// depends on `x`.
+    ///
+    /// # Arguments
+    /// * `x` - Input feature vector of shape (INPUT_DIM,).
+    /// * `class` - The class whose logit is differentiated.
+    ///
+    /// # Returns
+    /// The gradient, CHW-flattened like `x`.
+    pub fn logit_gradient(&self, x: ArrayView1<f32>, class: Kind) -> Array1<f32> {
+        let weights = self.weights().index_axis_move(Axis(0), class.index());
+        let mut gradient = match self.conv() {
+            Some(conv) => {
+                let mut input = x.to_owned();
+                if let Some(normalizer) = self.normalizer() {
+                    normalizer.apply(input.view_mut());
+                }
+                let (_, caches) = conv.forward_train(input.view().insert_axis(Axis(0)));
+                let (_, d_input) = conv.backward(&caches, weights.insert_axis(Axis(0)), true);
+                d_input.unwrap().index_axis_move(Axis(0), 0)
+            }
+            None => weights.to_owned(),
+        };
+        // d((x - mean) / std)/dx = 1 / std
+        if let Some(normalizer) = self.normalizer() {
+            let (_, std) = normalizer.per_feature(self.input_dim());
+            gradient /= &std;
+        }
+        return gradient;
+    }
+
+    /// Renders the saliency of `x` for `class` as a heatmap of the size
+    /// the images are preprocessed to.
+    ///
+    /// A pixel's saliency is the largest absolute [`Model::logit_gradient`]
+    /// over its channels, scaled so that the most salient pixel is 1. It is
+    /// drawn with a black-red-yellow-white color map, so bright pixels are
+    /// the ones the prediction depends on most. Save the image with
+    /// [`RgbImage::save`] to get a PNG.
+    ///
+    /// # Arguments
+    /// * `x` - Input feature vector of shape (INPUT_DIM,).
+    /// * `class` - The class to explain, e.g. the predicted one.
+    pub fn saliency_map(&self, x: ArrayView1<f32>, class: Kind) -> RgbImage {
+        let gradient = self.logit_gradient(x, class);
+        let (width, height) = (self.preprocess().width, self.preprocess().height);
+        let pixels = (width * height) as usize;
+
+        let mut saliency = vec![0.0f32; pixels];
+        for plane in gradient.exact_chunks(pixels) {
+            for (s, g) in saliency.iter_mut().zip(plane) {
+                *s = s.max(g.abs());
+            }
+        }
+        let max = saliency.iter().copied().fold(0.0, f32::max);
+        let scale = if max > 0.0 { 1.0 / max } else { 0.0 };
+
+        return RgbImage::from_fn(width, height, |px, py| {
+            let value = saliency[(py * width + px) as usize] * scale;
+            heat(value)
+        });
+    }
+}
+
+/// Maps `value` in [0, 1] to the "hot" color map: black, red, yellow, white.
+fn heat(value: f32) -> Rgb<u8> {
+    let channel = |offset: f32| ((3.0 * value - offset).clamp(0.0, 1.0) * 255.0).round() as u8;
+    return Rgb([channel(0.0), channel(1.0), channel(2.0)]);
+}
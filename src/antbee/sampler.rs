@@ -0,0 +1,166 @@
+use super::kind::Kind;
+use rand::Rng;
+use rand::RngCore;
+use rand::prelude::SliceRandom;
+use std::fmt::Debug;
+
+/// Decides which samples make up each mini-batch of a training epoch.
+///
+/// The training loop asks the sampler for a fresh set of batches at the
+/// start of every epoch and trains on them in order.
+pub trait Sampler: Debug + Send + Sync {
+    /// Returns the batches of one epoch as lists of sample indices.
+    ///
+    /// # Arguments
+    /// * `labels` - Label of every training sample, by index.
+    /// * `num_classes` - Number of classes the labels are drawn from.
+    /// * `batch_size` - Requested number of samples per batch, at least 1.
+    /// * `shuffle` - Whether to randomize the order (`TrainConfig::shuffle`).
+    /// * `rng` - Source of all randomness, for reproducible epochs.
+    fn batches(
+        &self,
+        labels: &[Kind],
+        num_classes: usize,
+        batch_size: usize,
+        shuffle: bool,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Vec<usize>>;
+
+    /// Describes the sampler as the TOML value of `sampler` in a config
+    /// file (see [`TrainConfig::from_toml`](super::TrainConfig::from_toml)),
+    /// or `None` if config files cannot express it.
+    fn to_toml(&self) -> Option<String> {
+        return None;
+    }
+}
+
+/// Cuts a random permutation of the samples (or the samples in order,
+/// without shuffling) into consecutive batches. The default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomSampler;
+
+impl Sampler for RandomSampler {
+    fn batches(
+        &self,
+        labels: &[Kind],
+        _num_classes: usize,
+        batch_size: usize,
+        shuffle: bool,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Vec<usize>> {
+        let mut order: Vec<usize> = (0..labels.len()).collect();
+        if shuffle {
+            order.shuffle(rng);
+        }
+        return order.chunks(batch_size).map(<[usize]>::to_vec).collect();
+    }
+
+    fn to_toml(&self) -> Option<String> {
+        return Some("\"random\"".to_string());
+    }
+}
+
+/// Gives every batch the class proportions of the whole training set.
+///
+/// Each class's samples are spread evenly over the epoch, so within every
+/// batch a class's count differs from its share of `batch_size` by less
+/// than one with two classes, and by less than two with more. Every sample
+/// is still seen exactly once per epoch; this only keeps small batches of
+/// imbalanced data from missing a class by chance.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StratifiedSampler;
+
+impl Sampler for StratifiedSampler {
+    fn batches(
+        &self,
+        labels: &[Kind],
+        num_classes: usize,
+        batch_size: usize,
+        shuffle: bool,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Vec<usize>> {
+        // Place the j-th of a class's m samples at (j + offset) / m in the
+        // epoch; sorting by position interleaves the classes evenly.
+        let mut positions = Vec::<(f64, usize)>::with_capacity(labels.len());
+        for mut indices in by_class(labels, num_classes) {
+            let offset = if shuffle {
+                indices.shuffle(rng);
+                rng.random::<f64>()
+            } else {
+                0.5
+            };
+            let m = indices.len() as f64;
+            for (j, index) in indices.into_iter().enumerate() {
+                positions.push(((j as f64 + offset) / m, index));
+            }
+        }
+        positions.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let order: Vec<usize> = positions.into_iter().map(|(_, index)| index).collect();
+        return order.chunks(batch_size).map(<[usize]>::to_vec).collect();
+    }
+
+    fn to_toml(&self) -> Option<String> {
+        return Some("\"stratified\"".to_string());
+    }
+}
+
+/// Gives every batch an equal number of samples of each class.
+///
+/// Classes are taken in turn, each from its own shuffled samples, and a
+/// class that runs out starts over, so minority samples are repeated and
+/// majority ones may be skipped within an epoch. An epoch has as many
+/// samples as the training set. A class without samples is left out.
+///
+/// This rebalances the data; [`DatasetSource::balanced_class_weights`](super::DatasetSource::balanced_class_weights)
+/// is the alternative that reweights the loss instead, and the two should
+/// not be combined.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BalancedSampler;
+
+impl Sampler for BalancedSampler {
+    fn batches(
+        &self,
+        labels: &[Kind],
+        num_classes: usize,
+        batch_size: usize,
+        shuffle: bool,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Vec<usize>> {
+        let mut classes: Vec<Vec<usize>> = by_class(labels, num_classes)
+            .into_iter()
+            .filter(|indices| !indices.is_empty())
+            .collect();
+        if shuffle {
+            for indices in &mut classes {
+                indices.shuffle(rng);
+            }
+        }
+        let mut next = vec![0; classes.len()];
+        let mut order = Vec::with_capacity(labels.len());
+        for turn in 0..labels.len() {
+            let class = turn % classes.len();
+            if next[class] == classes[class].len() {
+                if shuffle {
+                    classes[class].shuffle(rng);
+                }
+                next[class] = 0;
+            }
+            order.push(classes[class][next[class]]);
+            next[class] += 1;
+        }
+        return order.chunks(batch_size).map(<[usize]>::to_vec).collect();
+    }
+
+    fn to_toml(&self) -> Option<String> {
+        return Some("\"balanced\"".to_string());
+    }
+}
+
+/// Groups the sample indices by class, in index order.
+fn by_class(labels: &[Kind], num_classes: usize) -> Vec<Vec<usize>> {
+    let mut by_class = vec![Vec::<usize>::new(); num_classes];
+    for (index, kind) in labels.iter().enumerate() {
+        by_class[kind.index()].push(index);
+    }
+    return by_class;
+}
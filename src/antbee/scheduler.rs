@@ -0,0 +1,131 @@
+use std::f32::consts::PI;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// Decides the learning rate used for each training epoch.
+///
+/// The training loop queries the scheduler once at the start of every epoch
+/// and uses the returned value for all updates in that epoch.
+pub trait LrScheduler: Debug + Send + Sync {
+    /// Returns the learning rate for `epoch` (zero-based).
+    ///
+    /// # Arguments
+    /// * `base_lr` - The configured learning rate (`TrainConfig::learning_rate`).
+    /// * `epoch` - The current epoch, starting at 0.
+    /// * `total_epochs` - The total number of epochs in the run.
+    fn learning_rate(&self, base_lr: f32, epoch: usize, total_epochs: usize) -> f32;
+
+    /// Describes the schedule as the TOML value of `scheduler` in a config
+    /// file (see [`TrainConfig::from_toml`](super::TrainConfig::from_toml)),
+    /// or `None` if config files cannot express it.
+    fn to_toml(&self) -> Option<String> {
+        return None;
+    }
+}
+
+/// Keeps the learning rate fixed at its base value.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Constant;
+
+impl LrScheduler for Constant {
+    fn learning_rate(&self, base_lr: f32, _epoch: usize, _total_epochs: usize) -> f32 {
+        return base_lr;
+    }
+
+    fn to_toml(&self) -> Option<String> {
+        return Some("\"constant\"".to_string());
+    }
+}
+
+/// Multiplies the learning rate by `gamma` every `step_size` epochs.
+///
+/// lr = base_lr * gamma ^ (epoch / step_size)
+#[derive(Debug, Clone, Copy)]
+pub struct StepDecay {
+    pub step_size: usize,
+    pub gamma: f32,
+}
+
+impl LrScheduler for StepDecay {
+    fn learning_rate(&self, base_lr: f32, epoch: usize, _total_epochs: usize) -> f32 {
+        let steps = epoch / self.step_size.max(1);
+        return base_lr * self.gamma.powi(steps as i32);
+    }
+
+    fn to_toml(&self) -> Option<String> {
+        return Some(format!(
+            "{{ name = \"step\", step_size = {}, gamma = {:?} }}",
+            self.step_size, self.gamma
+        ));
+    }
+}
+
+/// Anneals the learning rate from its base value down to `min_lr`
+/// following half a cosine period over the whole run.
+///
+/// lr = min_lr + 0.5 * (base_lr - min_lr) * (1 + cos(pi * epoch / total_epochs))
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CosineAnnealing {
+    pub min_lr: f32,
+}
+
+impl LrScheduler for CosineAnnealing {
+    fn learning_rate(&self, base_lr: f32, epoch: usize, total_epochs: usize) -> f32 {
+        if total_epochs <= 1 {
+            return base_lr;
+        }
+        let progress = epoch as f32 / (total_epochs - 1) as f32;
+        return self.min_lr + 0.5 * (base_lr - self.min_lr) * (1.0 + (PI * progress).cos());
+    }
+
+    fn to_toml(&self) -> Option<String> {
+        return Some(format!(
+            "{{ name = \"cosine\", min_lr = {:?} }}",
+            self.min_lr
+        ));
+    }
+}
+
+/// Linearly ramps the learning rate up over the first `warmup_epochs`
+/// epochs, then hands over to the wrapped scheduler.
+///
+/// The wrapped scheduler sees epochs counted from the end of the warmup,
+/// so e.g. cosine annealing still covers the full remaining run.
+#[derive(Debug, Clone, Copy)]
+pub struct Warmup<S: LrScheduler> {
+    pub warmup_epochs: usize,
+    pub after: S,
+}
+
+impl<S: LrScheduler> LrScheduler for Warmup<S> {
+    fn learning_rate(&self, base_lr: f32, epoch: usize, total_epochs: usize) -> f32 {
+        if epoch < self.warmup_epochs {
+            return base_lr * (epoch + 1) as f32 / self.warmup_epochs as f32;
+        }
+        return self.after.learning_rate(
+            base_lr,
+            epoch - self.warmup_epochs,
+            total_epochs.saturating_sub(self.warmup_epochs),
+        );
+    }
+
+    fn to_toml(&self) -> Option<String> {
+        return Some(format!(
+            "{{ name = \"warmup\", warmup_epochs = {}, after = {} }}",
+            self.warmup_epochs,
+            self.after.to_toml()?
+        ));
+    }
+}
+
+/// Lets a shared scheduler, as stored in `TrainConfig::scheduler`, be
+/// wrapped by another, e.g. as the `after` of a [`Warmup`].
+impl LrScheduler for Arc<dyn LrScheduler> {
+    fn learning_rate(&self, base_lr: f32, epoch: usize, total_epochs: usize) -> f32 {
+        return (**self).learning_rate(base_lr, epoch, total_epochs);
+    }
+
+    fn to_toml(&self) -> Option<String> {
+        return (**self).to_toml();
+    }
+}
@@ -0,0 +1,573 @@
+//! Minimal HTTP inference server.
+//!
+//! Only what a classification endpoint needs is implemented on top of
+//! `std::net`: HTTP/1.1 requests with a `Content-Length` body, one request
+//! per connection, and JSON responses. Available with the `serve` feature.
+
+use super::error::Error;
+use super::error::Result;
+use super::metrics::json_string;
+use super::model::Model;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::net::Shutdown;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+/// Largest accepted request body.
+const MAX_BODY_BYTES: usize = 32 * 1024 * 1024;
+
+/// Most connections handled at once; further ones are answered with 503.
+/// Together with [`MAX_BODY_BYTES`] this bounds the memory held by request
+/// bodies.
+const MAX_CONNECTIONS: usize = 16;
+
+/// Largest accepted request line plus headers.
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+
+/// How long a rejected connection may hold up accepting.
+const REJECT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How long a connection may stall before it is dropped.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Serves `model` over HTTP on `addr` until the process is stopped.
+///
+/// Endpoints:
+/// - `POST /predict` with an image as the raw request body, or as the first
+///   file of a `multipart/form-data` upload, answers
+///   `{"class": "...", "probability": ...}`.
+/// - `GET /` answers `{"classes": [...]}`.
+///
+/// Errors are answered with a 4xx status and `{"error": "..."}`. Every
+/// connection is handled on its own thread, at most 16 at a time; beyond
+/// that, connections are answered with 503 Service Unavailable at once.
+///
+/// # Arguments
+/// * `model` - The model to classify with.
+/// * `classes` - Class names by index, reported in the responses.
+/// * `addr` - Address to listen on, e.g. `("0.0.0.0", 8080)`.
+///
+/// # Errors
+/// [`Error::InvalidFormat`] if `classes` does not hold one name per class
+/// of `model`, and [`Error::Io`] if `addr` cannot be listened on.
+pub fn serve(model: &Model, classes: &[String], addr: impl ToSocketAddrs) -> Result<()> {
+    if classes.len() != model.num_classes() {
+        return Err(Error::InvalidFormat(format!(
+            "{} class names for a model of {} classes",
+            classes.len(),
+            model.num_classes()
+        )));
+    }
+    let listener = TcpListener::bind(addr)?;
+    println!("listening on http://{}", listener.local_addr()?);
+    let in_flight = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    eprintln!("failed to accept connection: {}", err);
+                    continue;
+                }
+            };
+            let Some(slot) = Slot::take(&in_flight) else {
+                reject(stream);
+                continue;
+            };
+            scope.spawn(move || {
+                let _slot = slot;
+                if let Err(err) = handle(model, classes, stream) {
+                    eprintln!("failed to handle request: {}", err);
+                }
+            });
+        }
+    });
+    return Ok(());
+}
+
+/// Answers a connection beyond [`MAX_CONNECTIONS`] with 503 without
+/// reading its request, on the accepting thread.
+fn reject(mut stream: TcpStream) {
+    // The short response fits in the socket buffer. Discarding what the
+    // client sends until it closes keeps the close from resetting the
+    // connection before the response is read; the short timeout bounds how
+    // long this holds up accepting.
+    let _ = stream.set_write_timeout(Some(REJECT_TIMEOUT));
+    let _ = stream.set_read_timeout(Some(REJECT_TIMEOUT));
+    if Response::error(503, "too many connections")
+        .write_to(&mut stream)
+        .is_ok()
+        && stream.shutdown(Shutdown::Write).is_ok()
+    {
+        let _ = std::io::copy(
+            &mut stream.take(MAX_HEADER_BYTES as u64),
+            &mut std::io::sink(),
+        );
+    }
+}
+
+/// One of the [`MAX_CONNECTIONS`] connections in flight, released on drop.
+struct Slot<'a>(&'a AtomicUsize);
+
+impl<'a> Slot<'a> {
+    fn take(in_flight: &'a AtomicUsize) -> Option<Self> {
+        return in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                return (count < MAX_CONNECTIONS).then_some(count + 1);
+            })
+            .ok()
+            .map(|_| Self(in_flight));
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// A parsed request: method, path (without query), content type and body.
+struct Request {
+    method: String,
+    path: String,
+    content_type: Option<String>,
+    body: Vec<u8>,
+}
+
+/// An HTTP response with a JSON body.
+struct Response {
+    status: u16,
+    body: String,
+}
+
+impl Response {
+    fn ok(body: String) -> Self {
+        return Self { status: 200, body };
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        return Self {
+            status,
+            body: format!("{{\"error\":{}}}", json_string(message)),
+        };
+    }
+
+    fn write_to(&self, stream: &mut impl Write) -> std::io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            411 => "Length Required",
+            413 => "Payload Too Large",
+            503 => "Service Unavailable",
+            _ => "Error",
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            reason,
+            self.body.len(),
+            self.body
+        )?;
+        return stream.flush();
+    }
+}
+
+fn handle(model: &Model, classes: &[String], mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let response = match read_request(&mut stream)? {
+        Ok(request) => route(model, classes, &request),
+        Err(response) => response,
+    };
+    return response.write_to(&mut stream);
+}
+
+fn route(model: &Model, classes: &[String], request: &Request) -> Response {
+    return match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/predict") => predict(model, classes, request),
+        ("GET", "/") => {
+            let names: Vec<String> = classes.iter().map(|c| json_string(c)).collect();
+            Response::ok(format!("{{\"classes\":[{}]}}", names.join(",")))
+        }
+        (_, "/predict") | (_, "/") => Response::error(405, "method not allowed"),
+        _ => Response::error(404, "not found"),
+    };
+}
+
+fn predict(model: &Model, classes: &[String], request: &Request) -> Response {
+    let image = match request.content_type.as_deref().and_then(multipart_boundary) {
+        Some(boundary) => match first_multipart_file(&request.body, &boundary) {
+            Some(image) => image,
+            None => return Response::error(400, "multipart body without a file"),
+        },
+        None => &request.body[..],
+    };
+    if image.is_empty() {
+        return Response::error(400, "empty image");
+    }
+    return match model.predict_image_bytes(image) {
+        Ok(prediction) => Response::ok(format!(
+            "{{\"class\":{},\"probability\":{}}}",
+            json_string(&classes[prediction.kind.index()]),
+            prediction.probability
+        )),
+        Err(err) => Response::error(400, &err.to_string()),
+    };
+}
+
+/// Reads one request from `stream`.
+///
+/// The outer error is a broken connection; the inner one a malformed
+/// request that should be answered with the given response.
+fn read_request(
+    stream: &mut (impl Read + Write),
+) -> std::io::Result<std::result::Result<Request, Response>> {
+    let mut reader = BufReader::new(stream);
+    let mut head = Vec::new();
+    loop {
+        let read = reader
+            .by_ref()
+            .take((MAX_HEADER_BYTES - head.len()) as u64 + 1)
+            .read_until(b'\n', &mut head)?;
+        if read == 0 || head.len() > MAX_HEADER_BYTES {
+            return Ok(Err(Response::error(400, "incomplete or oversized header")));
+        }
+        if head.ends_with(b"\r\n\r\n") || head.ends_with(b"\n\n") {
+            break;
+        }
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or("").split_whitespace();
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+        return Ok(Err(Response::error(400, "malformed request line")));
+    };
+
+    let mut content_length = None;
+    let mut content_type = None;
+    let mut expect_continue = false;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            match value.parse::<usize>() {
+                Ok(length) => content_length = Some(length),
+                Err(_) => return Ok(Err(Response::error(400, "invalid Content-Length"))),
+            }
+        } else if name.eq_ignore_ascii_case("content-type") {
+            content_type = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("expect") {
+            expect_continue = value.eq_ignore_ascii_case("100-continue");
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            return Ok(Err(Response::error(411, "send a Content-Length body")));
+        }
+    }
+
+    let content_length = content_length.unwrap_or(0);
+    if content_length > MAX_BODY_BYTES {
+        return Ok(Err(Response::error(413, "image too large")));
+    }
+    if expect_continue {
+        // Clients such as curl wait for this before sending large bodies.
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+    }
+    // Grown as the body arrives, so a client announcing a large body does
+    // not get the memory before sending it.
+    let mut body = Vec::new();
+    reader
+        .by_ref()
+        .take(content_length as u64)
+        .read_to_end(&mut body)?;
+    if body.len() < content_length {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    return Ok(Ok(Request {
+        method: method.to_string(),
+        path: target.split('?').next().unwrap_or("").to_string(),
+        content_type,
+        body,
+    }));
+}
+
+/// Extracts the boundary of a `multipart/form-data` content type.
+fn multipart_boundary(content_type: &str) -> Option<String> {
+    let (mime, params) = content_type.split_once(';')?;
+    if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    return params.split(';').find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("boundary") {
+            return None;
+        }
+        return Some(value.trim().trim_matches('"').to_string());
+    });
+}
+
+/// Returns the content of the first part of a multipart body that carries
+/// a file name, or the first part if none does.
+fn first_multipart_file<'a>(body: &'a [u8], boundary: &str) -> Option<&'a [u8]> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut parts = Vec::new();
+    let mut rest = &body[find(body, &delimiter)? + delimiter.len()..];
+    // Every part ends at the next "\r\n--boundary"; the last is followed by "--".
+    while !rest.starts_with(b"--") {
+        let part_end = find(rest, &delimiter)?;
+        let part = rest[..part_end]
+            .strip_suffix(b"\r\n")
+            .unwrap_or(&rest[..part_end]);
+        let part = part.strip_prefix(b"\r\n").unwrap_or(part);
+        let header_end = find(part, b"\r\n\r\n")?;
+        parts.push((&part[..header_end], &part[header_end + 4..]));
+        rest = &rest[part_end + delimiter.len()..];
+    }
+    let is_file = |headers: &[u8]| String::from_utf8_lossy(headers).contains("filename=");
+    return parts
+        .iter()
+        .find(|(headers, _)| is_file(headers))
+        .or(parts.first())
+        .map(|(_, content)| *content);
+}
+
+/// Position of the first occurrence of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    return haystack
+        .windows(needle.len())
+        .position(|window| window == needle);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::antbee::Preprocess;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use std::io::Cursor;
+    use std::net::Ipv4Addr;
+
+    /// A connection that reads `input` and records what is written to it.
+    struct Connection {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Connection {
+        fn new(input: impl Into<Vec<u8>>) -> Self {
+            return Self {
+                input: Cursor::new(input.into()),
+                output: Vec::new(),
+            };
+        }
+    }
+
+    impl Read for Connection {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            return self.input.read(buf);
+        }
+    }
+
+    impl Write for Connection {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            return self.output.write(buf);
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            return Ok(());
+        }
+    }
+
+    fn request(input: &[u8]) -> std::result::Result<Request, Response> {
+        return read_request(&mut Connection::new(input)).unwrap();
+    }
+
+    /// Status of the response `input` is rejected with.
+    fn rejection(input: &[u8]) -> u16 {
+        return match request(input) {
+            Ok(request) => panic!("accepted {} {}", request.method, request.path),
+            Err(response) => response.status,
+        };
+    }
+
+    #[test]
+    fn read_request_reads_head_and_body() {
+        let request = request(
+            b"POST /predict?tta=1 HTTP/1.1\r\nHost: x\r\ncontent-type: image/png\r\nContent-Length: 5\r\n\r\nhello trailing",
+        )
+        .unwrap_or_else(|response| panic!("rejected with {}", response.status));
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/predict");
+        assert_eq!(request.content_type.as_deref(), Some("image/png"));
+        assert_eq!(request.body, b"hello");
+
+        let request = self::request(b"GET / HTTP/1.1\n\n").unwrap_or_else(|_| panic!());
+        assert_eq!(request.path, "/");
+        assert!(request.body.is_empty());
+    }
+
+    #[test]
+    fn read_request_answers_continue() {
+        let mut connection = Connection::new(
+            b"POST /predict HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 2\r\n\r\nhi"
+                .to_vec(),
+        );
+        let request = read_request(&mut connection).unwrap();
+        assert!(request.is_ok());
+        assert_eq!(connection.output, b"HTTP/1.1 100 Continue\r\n\r\n");
+    }
+
+    #[test]
+    fn read_request_rejects_malformed_requests() {
+        assert_eq!(
+            rejection(b"POST /predict HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n"),
+            411
+        );
+        assert_eq!(
+            rejection(b"POST /predict HTTP/1.1\r\nContent-Length: lots\r\n\r\n"),
+            400
+        );
+        let too_large = format!(
+            "POST /predict HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_BYTES + 1
+        );
+        assert_eq!(rejection(too_large.as_bytes()), 413);
+        assert_eq!(rejection(b"GARBAGE\r\n\r\n"), 400);
+        // The connection closes before the headers end.
+        assert_eq!(rejection(b"GET / HTTP/1.1\r\nHost: x\r\n"), 400);
+
+        let mut oversized = b"GET / HTTP/1.1\r\nX-Padding: ".to_vec();
+        oversized.resize(MAX_HEADER_BYTES + 1, b'a');
+        oversized.extend_from_slice(b"\r\n\r\n");
+        assert_eq!(rejection(&oversized), 400);
+        // One long line is cut off at the limit as well.
+        assert_eq!(rejection(&vec![b'a'; 4 * MAX_HEADER_BYTES]), 400);
+    }
+
+    #[test]
+    fn read_request_fails_on_a_short_body() {
+        let err = read_request(&mut Connection::new(
+            b"POST /predict HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort".to_vec(),
+        ))
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn multipart_boundary_accepts_quoted_and_unquoted_values() {
+        assert_eq!(
+            multipart_boundary("multipart/form-data; boundary=abc123").as_deref(),
+            Some("abc123")
+        );
+        assert_eq!(
+            multipart_boundary("Multipart/Form-Data; charset=utf-8; BOUNDARY=\"a b\"").as_deref(),
+            Some("a b")
+        );
+        assert_eq!(
+            multipart_boundary("multipart/form-data;boundary=\"xyz\"").as_deref(),
+            Some("xyz")
+        );
+        assert_eq!(multipart_boundary("image/png"), None);
+        assert_eq!(multipart_boundary("multipart/form-data"), None);
+        assert_eq!(
+            multipart_boundary("multipart/form-data; charset=utf-8"),
+            None
+        );
+        assert_eq!(multipart_boundary("multipart/mixed; boundary=abc"), None);
+    }
+
+    #[test]
+    fn first_multipart_file_prefers_file_parts() {
+        let body = b"preamble\r\n--XyZ\r\n\
+Content-Disposition: form-data; name=\"note\"\r\n\r\n\
+text\r\n--XyZ\r\n\
+Content-Disposition: form-data; name=\"image\"; filename=\"a.png\"\r\n\
+Content-Type: image/png\r\n\r\n\
+\x89PNG\r\n\x1a\n\r\n--XyZ--\r\n";
+        assert_eq!(
+            first_multipart_file(body, "XyZ"),
+            Some(&b"\x89PNG\r\n\x1a\n"[..])
+        );
+
+        let without_file = b"--XyZ\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nfirst\r\n\
+--XyZ\r\nContent-Disposition: form-data; name=\"b\"\r\n\r\nsecond\r\n--XyZ--";
+        assert_eq!(
+            first_multipart_file(without_file, "XyZ"),
+            Some(&b"first"[..])
+        );
+        assert_eq!(first_multipart_file(b"--XyZ--\r\n", "XyZ"), None);
+    }
+
+    #[test]
+    fn first_multipart_file_rejects_broken_bodies() {
+        // No closing delimiter after the part.
+        let unterminated =
+            b"--XyZ\r\nContent-Disposition: form-data; filename=\"a.png\"\r\n\r\ndata";
+        assert_eq!(first_multipart_file(unterminated, "XyZ"), None);
+        // A part without the blank line ending its headers.
+        let headless = b"--XyZ\r\nContent-Disposition: form-data\r\ndata\r\n--XyZ--";
+        assert_eq!(first_multipart_file(headless, "XyZ"), None);
+        // No delimiter at all, or another boundary.
+        assert_eq!(first_multipart_file(b"raw image bytes", "XyZ"), None);
+        let other = b"--abc\r\nX: y\r\n\r\ndata\r\n--abc--";
+        assert_eq!(first_multipart_file(other, "XyZ"), None);
+    }
+
+    #[test]
+    fn serve_rejects_a_class_count_mismatch() {
+        let model = Model::from_rng(Preprocess::default(), 2, &mut ChaCha8Rng::seed_from_u64(0));
+        let classes = ["ants".to_string()];
+        let result = serve(&model, &classes, (Ipv4Addr::LOCALHOST, 0));
+        assert!(
+            matches!(result, Err(Error::InvalidFormat(_))),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn slots_are_limited_and_released() {
+        let in_flight = AtomicUsize::new(0);
+        let mut slots: Vec<Slot> = (0..MAX_CONNECTIONS)
+            .map(|_| Slot::take(&in_flight).unwrap())
+            .collect();
+        assert!(Slot::take(&in_flight).is_none());
+        slots.pop();
+        assert_eq!(in_flight.load(Ordering::Acquire), MAX_CONNECTIONS - 1);
+        slots.push(Slot::take(&in_flight).unwrap());
+        assert!(Slot::take(&in_flight).is_none());
+        drop(slots);
+        assert_eq!(in_flight.load(Ordering::Acquire), 0);
+    }
+
+    #[test]
+    fn reject_answers_503() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        let (server, _) = listener.accept().unwrap();
+        reject(server);
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(
+            response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"),
+            "{}",
+            response
+        );
+        assert!(response.ends_with("{\"error\":\"too many connections\"}"));
+    }
+}
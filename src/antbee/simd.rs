@@ -0,0 +1,124 @@
+//! Matrix products of the linear layer.
+//!
+//! By default these are ndarray's general matrix products. With the `simd`
+//! feature (nightly only, it uses `std::simd`) they are computed as explicit
+//! 8-lane dot products and axpy updates over contiguous rows instead, which
+//! is considerably faster for the shapes the model uses: a handful of
+//! classes against tens of thousands of features, where a general matrix
+//! product spends most of its time packing and padding.
+//!
+//! With the `gpu` feature and [`Backend::Gpu`](super::Backend::Gpu)
+//! selected, all three run on the GPU through OpenCL instead.
+
+#[cfg(all(feature = "gpu", unix))]
+use super::backend::Backend;
+#[cfg(all(feature = "gpu", unix))]
+use super::opencl;
+use ndarray::Array2;
+use ndarray::ArrayView2;
+
+/// Computes X·W^T for X of shape (n, d) and W of shape (k, d).
+pub(crate) fn matmul_transposed(x: ArrayView2<f32>, w: ArrayView2<f32>) -> Array2<f32> {
+    #[cfg(all(feature = "gpu", unix))]
+    if Backend::current() == Backend::Gpu {
+        return opencl::matmul(x, w.t());
+    }
+    #[cfg(feature = "simd")]
+    {
+        let (x, w) = (x.as_standard_layout(), w.as_standard_layout());
+        return Array2::from_shape_fn((x.nrows(), w.nrows()), |(i, k)| {
+            lanes::dot(row(&x, i), row(&w, k))
+        });
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        return x.dot(&w.t());
+    }
+}
+
+/// Computes A^T·X for A of shape (n, k) and X of shape (n, d).
+pub(crate) fn transposed_matmul(a: ArrayView2<f32>, x: ArrayView2<f32>) -> Array2<f32> {
+    #[cfg(all(feature = "gpu", unix))]
+    if Backend::current() == Backend::Gpu {
+        return opencl::matmul(a.t(), x);
+    }
+    #[cfg(feature = "simd")]
+    {
+        let x = x.as_standard_layout();
+        let mut out = Array2::zeros((a.ncols(), x.ncols()));
+        for (i, a_row) in a.outer_iter().enumerate() {
+            for (k, &alpha) in a_row.iter().enumerate() {
+                lanes::axpy(alpha, row(&x, i), out.row_mut(k).as_slice_mut().unwrap());
+            }
+        }
+        return out;
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        return a.t().dot(&x);
+    }
+}
+
+/// Computes A·W for A of shape (n, k) and W of shape (k, d).
+pub(crate) fn matmul(a: ArrayView2<f32>, w: ArrayView2<f32>) -> Array2<f32> {
+    #[cfg(all(feature = "gpu", unix))]
+    if Backend::current() == Backend::Gpu {
+        return opencl::matmul(a, w);
+    }
+    #[cfg(feature = "simd")]
+    {
+        let w = w.as_standard_layout();
+        let mut out = Array2::zeros((a.nrows(), w.ncols()));
+        for (a_row, mut out_row) in a.outer_iter().zip(out.outer_iter_mut()) {
+            let out_row = out_row.as_slice_mut().unwrap();
+            for (k, &alpha) in a_row.iter().enumerate() {
+                lanes::axpy(alpha, row(&w, k), out_row);
+            }
+        }
+        return out;
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        return a.dot(&w);
+    }
+}
+
+/// Row `i` of a matrix in standard layout as a slice.
+#[cfg(feature = "simd")]
+fn row<'a>(matrix: &'a ndarray::CowArray<f32, ndarray::Ix2>, i: usize) -> &'a [f32] {
+    let cols = matrix.ncols();
+    return &matrix.as_slice().unwrap()[i * cols..(i + 1) * cols];
+}
+
+#[cfg(feature = "simd")]
+mod lanes {
+    use std::simd::f32x8;
+    use std::simd::num::SimdFloat;
+
+    /// Sum of `a[i] * b[i]`.
+    pub(super) fn dot(a: &[f32], b: &[f32]) -> f32 {
+        assert_eq!(a.len(), b.len());
+        let (a_chunks, a_rest) = a.as_chunks::<8>();
+        let (b_chunks, b_rest) = b.as_chunks::<8>();
+        let mut acc = f32x8::splat(0.0);
+        for (a, b) in a_chunks.iter().zip(b_chunks) {
+            acc += f32x8::from_array(*a) * f32x8::from_array(*b);
+        }
+        let rest: f32 = a_rest.iter().zip(b_rest).map(|(a, b)| a * b).sum();
+        return acc.reduce_sum() + rest;
+    }
+
+    /// `y += alpha * x`.
+    pub(super) fn axpy(alpha: f32, x: &[f32], y: &mut [f32]) {
+        assert_eq!(x.len(), y.len());
+        let (x_chunks, x_rest) = x.as_chunks::<8>();
+        let (y_chunks, y_rest) = y.as_chunks_mut::<8>();
+        let alpha_lanes = f32x8::splat(alpha);
+        for (x, y) in x_chunks.iter().zip(y_chunks) {
+            *y = (alpha_lanes * f32x8::from_array(*x) + f32x8::from_array(*y)).to_array();
+        }
+        for (x, y) in x_rest.iter().zip(y_rest) {
+            *y += alpha * x;
+        }
+    }
+}
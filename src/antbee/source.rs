@@ -0,0 +1,102 @@
+use super::kind::ClassMap;
+use super::kind::Kind;
+use super::preprocess::Preprocess;
+use ndarray::Array2;
+use ndarray::Axis;
+use ndarray::CowArray;
+use ndarray::Ix1;
+use ndarray::Ix2;
+use rand::Rng;
+use rand::prelude::SliceRandom;
+use std::ops::Range;
+
+/// Labeled samples that a [`Model`](super::Model) can be trained and
+/// evaluated on.
+///
+/// Implemented by the in-memory [`Dataset`](super::Dataset) and by
+/// [`LazyDataset`](super::LazyDataset), which decodes images on demand.
+/// Labels are always kept in memory; only the feature vectors may be
+/// produced lazily.
+pub trait DatasetSource {
+    /// Number of samples.
+    fn len(&self) -> usize;
+
+    /// Class names of the labels.
+    fn class_map(&self) -> &ClassMap;
+
+    /// Names of the classes, indexed by `Kind::index`.
+    fn classes(&self) -> &[String] {
+        return self.class_map().names();
+    }
+
+    /// Preprocessing applied to every sample.
+    fn preprocess(&self) -> &Preprocess;
+
+    /// Labels of all samples, in storage order.
+    fn labels(&self) -> &[Kind];
+
+    /// Feature vector of the sample at `index`, of shape (input_dim,).
+    fn sample(&self, index: usize) -> CowArray<'_, f32, Ix1>;
+
+    /// Feature vectors of the samples in `range`, one per row.
+    fn batch(&self, range: Range<usize>) -> CowArray<'_, f32, Ix2>;
+
+    /// Feature vectors of the samples at `indices`, in that order, one per row.
+    fn select(&self, indices: &[usize]) -> Array2<f32> {
+        let mut rows = Array2::<f32>::zeros((indices.len(), self.preprocess().input_dim()));
+        for (mut row, &index) in rows.axis_iter_mut(Axis(0)).zip(indices) {
+            row.assign(&self.sample(index));
+        }
+        return rows;
+    }
+
+    fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    fn num_classes(&self) -> usize {
+        return self.classes().len();
+    }
+
+    /// Number of samples of each class, indexed by `Kind::index`.
+    fn class_counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.num_classes()];
+        for kind in self.labels() {
+            counts[kind.index()] += 1;
+        }
+        return counts;
+    }
+
+    /// Loss weights that make every class contribute equally overall.
+    ///
+    /// Class `k` gets `len / (num_classes * count_k)`, so a perfectly balanced
+    /// dataset gets all ones. Classes without samples get weight 0.
+    fn balanced_class_weights(&self) -> Vec<f32> {
+        let total = self.len() as f32;
+        let num_classes = self.num_classes() as f32;
+        return self
+            .class_counts()
+            .into_iter()
+            .map(|count| {
+                if count == 0 {
+                    0.0
+                } else {
+                    total / (num_classes * count as f32)
+                }
+            })
+            .collect();
+    }
+
+    /// Returns a fresh random permutation of the sample indices.
+    ///
+    /// The training loop draws a new order every epoch so that SGD does not
+    /// see the samples in the same sequence each time.
+    fn shuffled_indices(&self, rng: &mut impl Rng) -> Vec<usize>
+    where
+        Self: Sized,
+    {
+        let mut indices: Vec<usize> = (0..self.len()).collect();
+        indices.shuffle(rng);
+        return indices;
+    }
+}
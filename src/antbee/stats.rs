@@ -0,0 +1,167 @@
+//! Integrity report of an image dataset on disk.
+
+use super::dataset::Dataset;
+use super::error::Result;
+use image::ImageReader;
+use rayon::prelude::*;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Images with a side shorter than this many pixels are reported as
+/// suspicious: they carry little information once resized.
+const MIN_SIDE: u32 = 32;
+
+/// Images whose longer side exceeds the shorter one by more than this
+/// factor are reported as suspicious: resizing distorts them heavily.
+const MAX_ASPECT_RATIO: f32 = 4.0;
+
+/// Number of images of one class.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassCount {
+    /// Name of the class directory.
+    pub name: String,
+    /// Images found in the directory, including ones that fail to decode.
+    pub images: usize,
+}
+
+/// A file that failed a check, with the reason.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileIssue {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Summary of an ImageFolder-style dataset, see [`Dataset::stats`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatasetStats {
+    /// Image count of every class, in label order.
+    pub classes: Vec<ClassCount>,
+    /// Smallest channel value over all decoded images, in [0, 1].
+    pub min_pixel: f32,
+    /// Largest channel value over all decoded images, in [0, 1].
+    pub max_pixel: f32,
+    /// Mean channel value over all decoded images, in [0, 1].
+    pub mean_pixel: f32,
+    /// Files that could not be read or decoded.
+    pub failed: Vec<FileIssue>,
+    /// Decoded images that are tiny, extremely elongated or blank (a single
+    /// gray level), and may be mislabeled or broken.
+    pub suspicious: Vec<FileIssue>,
+}
+
+impl DatasetStats {
+    /// Total number of images over all classes.
+    pub fn total_images(&self) -> usize {
+        return self.classes.iter().map(|class| class.images).sum();
+    }
+}
+
+/// Pixel statistics of one decoded image.
+struct ImageSummary {
+    min: u8,
+    max: u8,
+    sum: u64,
+    values: u64,
+    issue: Option<String>,
+}
+
+impl Dataset {
+    /// Decodes every image of the dataset at `path` and reports class
+    /// counts, pixel value statistics and files that are likely broken.
+    ///
+    /// Images are listed as [`Dataset::from_dataset_path`] does, so the class
+    /// order matches the labels a loaded dataset would use. Unlike loading,
+    /// files that fail to decode do not abort the scan but are collected in
+    /// [`DatasetStats::failed`]. Pixel values are those of the original
+    /// images in RGB, scaled to [0, 1] like the model inputs.
+    pub fn stats(path: &Path) -> DatasetStats {
+        let (classes, images) = Self::list_images(path, None, true);
+        let mut counts: Vec<ClassCount> = classes
+            .names()
+            .iter()
+            .map(|name| ClassCount {
+                name: name.clone(),
+                images: 0,
+            })
+            .collect();
+        for (kind, _) in &images {
+            counts[kind.index()].images += 1;
+        }
+
+        let summaries: Vec<(&PathBuf, Result<ImageSummary>)> = images
+            .par_iter()
+            .map(|(_, path)| (path, summarize(path)))
+            .collect();
+
+        let (mut min, mut max, mut sum, mut values) = (u8::MAX, u8::MIN, 0u64, 0u64);
+        let mut failed = Vec::new();
+        let mut suspicious = Vec::new();
+        for (path, summary) in summaries {
+            match summary {
+                Ok(summary) => {
+                    min = min.min(summary.min);
+                    max = max.max(summary.max);
+                    sum += summary.sum;
+                    values += summary.values;
+                    if let Some(reason) = summary.issue {
+                        suspicious.push(FileIssue {
+                            path: path.clone(),
+                            reason,
+                        });
+                    }
+                }
+                Err(err) => failed.push(FileIssue {
+                    path: path.clone(),
+                    reason: err.to_string(),
+                }),
+            }
+        }
+        failed.sort_by(|a, b| a.path.cmp(&b.path));
+        suspicious.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let (min_pixel, max_pixel, mean_pixel) = if values == 0 {
+            (0.0, 0.0, 0.0)
+        } else {
+            (
+                min as f32 / 255.0,
+                max as f32 / 255.0,
+                (sum as f64 / values as f64 / 255.0) as f32,
+            )
+        };
+        return DatasetStats {
+            classes: counts,
+            min_pixel,
+            max_pixel,
+            mean_pixel,
+            failed,
+            suspicious,
+        };
+    }
+}
+
+/// Decodes the image at `path` and summarizes its pixels.
+fn summarize(path: &Path) -> Result<ImageSummary> {
+    let image = ImageReader::open(path)?.with_guessed_format()?.decode()?;
+    let (width, height) = (image.width(), image.height());
+    let pixels = image.to_rgb8().into_raw();
+    let min = pixels.iter().copied().min().unwrap_or(0);
+    let max = pixels.iter().copied().max().unwrap_or(0);
+
+    let (short, long) = (width.min(height), width.max(height));
+    let issue = if short < MIN_SIDE {
+        Some(format!("only {}x{} pixels", width, height))
+    } else if long as f32 > MAX_ASPECT_RATIO * short as f32 {
+        Some(format!("aspect ratio of {}x{}", width, height))
+    } else if min == max {
+        Some(format!("every pixel has the value {}", min))
+    } else {
+        None
+    };
+    return Ok(ImageSummary {
+        min,
+        max,
+        sum: pixels.iter().map(|&value| value as u64).sum(),
+        values: pixels.len() as u64,
+        issue,
+    });
+}
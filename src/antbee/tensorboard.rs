@@ -0,0 +1,120 @@
+//! TensorBoard event file export of the training metrics.
+//!
+//! An event file is a TFRecord stream of `Event` protocol buffers: every
+//! record is a little-endian u64 length, its masked CRC-32C, the payload and
+//! the payload's masked CRC-32C. Field numbers follow `event.proto` and
+//! `summary.proto` from the TensorFlow repository.
+
+use super::error::Result;
+use super::metrics::EpochMetrics;
+use super::protobuf::Message;
+use std::fs::File;
+use std::fs::create_dir_all;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+/// Writes one scalar per metric and epoch to an event file that TensorBoard
+/// (`tensorboard --logdir <dir>`) picks up while training is running.
+///
+/// The scalars are `loss/train`, `loss/val`, `accuracy/train` and
+/// `accuracy/val`, stepped by epoch. Every epoch is flushed immediately.
+pub struct TensorBoardWriter {
+    writer: BufWriter<File>,
+    path: PathBuf,
+}
+
+impl TensorBoardWriter {
+    /// Creates a new event file in `dir`, creating the directory if needed.
+    ///
+    /// Each call starts a new file, so a resumed run adds a file next to the
+    /// earlier ones and TensorBoard shows both as one run.
+    pub fn create(dir: &Path) -> Result<Self> {
+        create_dir_all(dir)?;
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let path = dir.join(format!(
+            "events.out.tfevents.{}.{}.{}",
+            seconds,
+            env!("CARGO_PKG_NAME"),
+            process::id()
+        ));
+        let mut writer = Self {
+            writer: BufWriter::new(File::create(&path)?),
+            path,
+        };
+        let mut event = Message::default();
+        event
+            .fixed64(1, wall_time().to_bits())
+            .string(3, "brain.Event:2");
+        writer.write_record(&event.bytes)?;
+        writer.writer.flush()?;
+        return Ok(writer);
+    }
+
+    /// Path of the event file.
+    pub fn path(&self) -> &Path {
+        return &self.path;
+    }
+
+    /// Writes the metrics of one epoch.
+    pub fn log(&mut self, metrics: &EpochMetrics) -> Result<()> {
+        let scalars = [
+            ("loss/train", metrics.train_loss),
+            ("loss/val", metrics.val_loss),
+            ("accuracy/train", metrics.train_acc),
+            ("accuracy/val", metrics.val_acc),
+        ];
+        let mut summary = Message::default();
+        for (tag, value) in scalars {
+            let mut entry = Message::default();
+            entry.string(1, tag).fixed32(2, value.to_bits());
+            summary.message(1, &entry);
+        }
+        let mut event = Message::default();
+        event
+            .fixed64(1, wall_time().to_bits())
+            .varint(2, metrics.epoch as u64)
+            .message(5, &summary);
+        self.write_record(&event.bytes)?;
+        self.writer.flush()?;
+        return Ok(());
+    }
+
+    fn write_record(&mut self, data: &[u8]) -> Result<()> {
+        let length = (data.len() as u64).to_le_bytes();
+        self.writer.write_all(&length)?;
+        self.writer
+            .write_all(&masked_crc32c(&length).to_le_bytes())?;
+        self.writer.write_all(data)?;
+        self.writer.write_all(&masked_crc32c(data).to_le_bytes())?;
+        return Ok(());
+    }
+}
+
+/// Seconds since the Unix epoch, as `Event.wall_time` expects.
+fn wall_time() -> f64 {
+    return SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |time| time.as_secs_f64());
+}
+
+/// CRC-32C (Castagnoli) of `data`, masked as TFRecord requires so that
+/// CRCs of data containing CRCs stay well distributed.
+fn masked_crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            // 0x82F63B78 is the Castagnoli polynomial, bit-reversed.
+            crc = (crc >> 1) ^ (0x82F6_3B78 & (crc & 1).wrapping_neg());
+        }
+    }
+    crc = !crc;
+    return crc.rotate_right(15).wrapping_add(0xA282_EAD8);
+}
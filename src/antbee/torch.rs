@@ -0,0 +1,312 @@
+//! Importing linear classifiers trained in PyTorch, from a `state_dict`
+//! saved as `.safetensors` or `.npz`.
+
+use super::error::Error;
+use super::error::Result;
+use super::model::Gradients;
+use super::model::Model;
+use super::npz::read_npz;
+use super::preprocess::Preprocess;
+use half::bf16;
+use half::f16;
+use ndarray::ArrayD;
+use ndarray::Ix2;
+use ndarray::IxDyn;
+use ndarray::s;
+use std::fs::read;
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::Chars;
+
+/// A JSON value of a safetensors header, as far as headers use them.
+#[derive(Debug)]
+enum Json {
+    Null,
+    Bool,
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        let Json::Object(entries) = self else {
+            return None;
+        };
+        return entries
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value);
+    }
+
+    /// The value as a list of non-negative integers, e.g. a shape.
+    fn integers(&self) -> Option<Vec<usize>> {
+        let Json::Array(items) = self else {
+            return None;
+        };
+        return items
+            .iter()
+            .map(|item| match item {
+                Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as usize),
+                _ => None,
+            })
+            .collect();
+    }
+}
+
+/// Parses the JSON text `chars` starts with, or returns `None` if it is
+/// not valid JSON.
+fn parse_json(chars: &mut Peekable<Chars>) -> Option<Json> {
+    let skip_space = |chars: &mut Peekable<Chars>| {
+        while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+    };
+    skip_space(chars);
+    let value = match chars.peek()? {
+        '{' => {
+            chars.next();
+            let mut entries = Vec::new();
+            skip_space(chars);
+            if chars.next_if_eq(&'}').is_none() {
+                loop {
+                    let Json::String(key) = parse_json(chars)? else {
+                        return None;
+                    };
+                    skip_space(chars);
+                    chars.next_if_eq(&':')?;
+                    entries.push((key, parse_json(chars)?));
+                    skip_space(chars);
+                    if chars.next_if_eq(&',').is_none() {
+                        chars.next_if_eq(&'}')?;
+                        break;
+                    }
+                }
+            }
+            Json::Object(entries)
+        }
+        '[' => {
+            chars.next();
+            let mut items = Vec::new();
+            skip_space(chars);
+            if chars.next_if_eq(&']').is_none() {
+                loop {
+                    items.push(parse_json(chars)?);
+                    skip_space(chars);
+                    if chars.next_if_eq(&',').is_none() {
+                        chars.next_if_eq(&']')?;
+                        break;
+                    }
+                }
+            }
+            Json::Array(items)
+        }
+        '"' => {
+            chars.next();
+            let mut string = String::new();
+            loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => match chars.next()? {
+                        'n' => string.push('\n'),
+                        't' => string.push('\t'),
+                        'r' => string.push('\r'),
+                        'b' => string.push('\u{8}'),
+                        'f' => string.push('\u{c}'),
+                        'u' => {
+                            let code: String =
+                                (0..4).map(|_| chars.next()).collect::<Option<_>>()?;
+                            // Surrogate pairs only occur in metadata, which is ignored.
+                            let code = u32::from_str_radix(&code, 16).ok()?;
+                            string.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                        }
+                        c => string.push(c),
+                    },
+                    c => string.push(c),
+                }
+            }
+            Json::String(string)
+        }
+        _ => {
+            let mut word = String::new();
+            while let Some(c) =
+                chars.next_if(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+            {
+                word.push(c);
+            }
+            match word.as_str() {
+                "null" => Json::Null,
+                "true" | "false" => Json::Bool,
+                number => Json::Number(number.parse().ok()?),
+            }
+        }
+    };
+    return Some(value);
+}
+
+/// Reads every tensor of the `.safetensors` file at `path`, in header
+/// order. Floating-point tensors of any width are converted to `f32`;
+/// other tensors are skipped.
+fn read_safetensors(path: &Path) -> Result<Vec<(String, ArrayD<f32>)>> {
+    let bytes = read(path)?;
+    let invalid =
+        |reason: String| Error::InvalidFormat(format!("invalid .safetensors file: {}", reason));
+    let header_len = bytes
+        .get(..8)
+        .map(|field| u64::from_le_bytes(field.try_into().unwrap()))
+        .ok_or_else(|| invalid("truncated".to_string()))?;
+    let data_start = usize::try_from(header_len)
+        .ok()
+        .and_then(|len| len.checked_add(8))
+        .ok_or_else(|| invalid("truncated header".to_string()))?;
+    let header = bytes
+        .get(8..data_start)
+        .and_then(|header| std::str::from_utf8(header).ok())
+        .ok_or_else(|| invalid("truncated header".to_string()))?;
+    let data = &bytes[data_start..];
+    let Some(Json::Object(entries)) = parse_json(&mut header.chars().peekable()) else {
+        return Err(invalid("header is not a JSON object".to_string()));
+    };
+
+    let mut tensors = Vec::with_capacity(entries.len());
+    for (name, info) in &entries {
+        if name == "__metadata__" {
+            continue;
+        }
+        let (Some(Json::String(dtype)), Some(shape), Some(offsets)) = (
+            info.get("dtype"),
+            info.get("shape").and_then(Json::integers),
+            info.get("data_offsets").and_then(Json::integers),
+        ) else {
+            return Err(invalid(format!("bad header entry for {}", name)));
+        };
+        let &[start, end] = offsets.as_slice() else {
+            return Err(invalid(format!("bad data offsets for {}", name)));
+        };
+        let tensor = data
+            .get(start..end.max(start))
+            .ok_or_else(|| invalid(format!("data of {} out of bounds", name)))?;
+        let width = match dtype.as_str() {
+            "F64" => 8,
+            "F32" => 4,
+            "F16" | "BF16" => 2,
+            // Integer tensors such as `num_batches_tracked` are no weights.
+            _ => continue,
+        };
+        let size = shape
+            .iter()
+            .try_fold(width, |size: usize, &dim| size.checked_mul(dim));
+        if size != Some(tensor.len()) {
+            return Err(invalid(format!(
+                "data of {} does not match its shape",
+                name
+            )));
+        }
+        let values: Vec<f32> = match dtype.as_str() {
+            "F64" => tensor
+                .chunks_exact(8)
+                .map(|c| f64::from_le_bytes(c.try_into().unwrap()) as f32)
+                .collect(),
+            "F32" => tensor
+                .chunks_exact(4)
+                .map(|c| f32::from_le_bytes(c.try_into().unwrap()))
+                .collect(),
+            "F16" => tensor
+                .chunks_exact(2)
+                .map(|c| f16::from_le_bytes(c.try_into().unwrap()).to_f32())
+                .collect(),
+            _ => tensor
+                .chunks_exact(2)
+                .map(|c| bf16::from_le_bytes(c.try_into().unwrap()).to_f32())
+                .collect(),
+        };
+        tensors.push((
+            name.clone(),
+            ArrayD::from_shape_vec(IxDyn(&shape), values).unwrap(),
+        ));
+    }
+    return Ok(tensors);
+}
+
+impl Model {
+    /// Imports a linear classifier (an `nn.Linear` on flattened images,
+    /// alone or inside a module) from a PyTorch `state_dict`, saved as
+    /// `.safetensors` or as `.npz`, e.g. with
+    ///
+    /// ```python
+    /// safetensors.torch.save_file(model.state_dict(), "linear.safetensors")
+    /// np.savez("linear.npz", **{k: v.numpy() for k, v in model.state_dict().items()})
+    /// ```
+    ///
+    /// The state dict must hold exactly one 2-D `weight` (or `*.weight`) of
+    /// shape (num_classes, INPUT_DIM), e.g. (2, 2352) for 28x28 RGB, and may
+    /// hold the `bias` next to it. A single output row is taken as the
+    /// logit of class 1 of a binary logistic regression, as trained with
+    /// `BCEWithLogitsLoss`: class 0 gets zero weights, so the softmax of the
+    /// model equals the sigmoid of the original.
+    ///
+    /// Inputs are expected like `transforms.ToTensor()` produces them: CHW
+    /// order, scaled to [0, 1], which is the layout of [`Preprocess::apply`].
+    /// A model trained on `transforms.Normalize(mean, std)` inputs needs the
+    /// same statistics set with [`Model::set_normalizer`]. The classes are
+    /// numbered; see [`Model::set_class_map`].
+    pub fn from_state_dict(path: &Path, preprocess: Preprocess) -> Result<Self> {
+        let tensors = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("npz") => read_npz(path)?,
+            _ => read_safetensors(path)?,
+        };
+        let weights: Vec<&(String, ArrayD<f32>)> = tensors
+            .iter()
+            .filter(|(name, tensor)| {
+                return tensor.ndim() == 2 && (name == "weight" || name.ends_with(".weight"));
+            })
+            .collect();
+        let &[(weight_name, weight)] = weights.as_slice() else {
+            let names: Vec<&str> = weights.iter().map(|(name, _)| name.as_str()).collect();
+            return Err(Error::InvalidFormat(format!(
+                "expected the weight of one linear layer, found [{}]",
+                names.join(", ")
+            )));
+        };
+        let (outputs, inputs) = (weight.shape()[0], weight.shape()[1]);
+        if outputs == 0 {
+            return Err(Error::InvalidFormat(format!(
+                "{} has no outputs",
+                weight_name
+            )));
+        }
+        if inputs != preprocess.input_dim() {
+            return Err(Error::InvalidFormat(format!(
+                "{} has {} inputs, but {} images have {}",
+                weight_name,
+                inputs,
+                preprocess,
+                preprocess.input_dim()
+            )));
+        }
+        let bias_name = format!("{}bias", weight_name.strip_suffix("weight").unwrap());
+        let bias = match tensors.iter().find(|(name, _)| *name == bias_name) {
+            Some((_, bias)) if bias.shape() == [outputs] => Some(bias),
+            Some((_, bias)) => {
+                return Err(Error::InvalidFormat(format!(
+                    "{} has shape {:?}, expected [{}]",
+                    bias_name,
+                    bias.shape(),
+                    outputs
+                )));
+            }
+            None => None,
+        };
+        let weight = weight.view().into_dimensionality::<Ix2>().unwrap();
+
+        let mut model = Self::new(preprocess, outputs.max(2));
+        let mut params = Gradients::zeros_like(&model);
+        // Binary logistic regression: the one logit becomes class 1's.
+        let first = if outputs == 1 { 1 } else { 0 };
+        params.w.slice_mut(s![first.., ..]).assign(&weight);
+        if let Some(bias) = bias {
+            params.b.slice_mut(s![first..]).assign(bias);
+        }
+        model.set_parameters(params);
+        return Ok(model);
+    }
+}
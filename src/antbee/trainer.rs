@@ -0,0 +1,607 @@
+use super::callback::BatchMetrics;
+use super::callback::Callback;
+use super::callback::Control;
+use super::checkpoint::Checkpoint;
+use super::config::DivergencePolicy;
+use super::config::TrainConfig;
+use super::error::Error;
+use super::error::Result;
+use super::interrupt;
+use super::kind::Kind;
+use super::metrics::EpochMetrics;
+use super::metrics::History;
+use super::metrics::MetricsLogger;
+use super::model::Gradients;
+use super::model::Model;
+use super::optimizer::GradientAccumulator;
+use super::optimizer::Sgd;
+use super::source::DatasetSource;
+use super::tensorboard::TensorBoardWriter;
+use indicatif::ProgressBar;
+use indicatif::ProgressStyle;
+use ndarray::ArrayView2;
+use ndarray::s;
+use rand::Rng;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use rayon::ThreadPool;
+use rayon::ThreadPoolBuilder;
+use rayon::prelude::*;
+
+/// Outcome of a call to [`Trainer::fit`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FitReport {
+    /// Number of completed epochs, including any run before a resume.
+    pub epochs_run: usize,
+    /// Zero-based epoch with the lowest validation loss.
+    pub best_epoch: usize,
+    /// Validation loss at `best_epoch`.
+    pub best_val_loss: f32,
+    /// Whether training stopped before `TrainConfig::epochs` because the
+    /// validation loss did not improve for `TrainConfig::patience` epochs,
+    /// because a [`Callback`] returned [`Control::Stop`], or because the run
+    /// was interrupted.
+    pub stopped_early: bool,
+    /// Whether training was stopped by SIGINT or SIGTERM, see
+    /// [`TrainConfig::handle_signals`].
+    pub interrupted: bool,
+    /// Metrics of every completed epoch, including any run before a resume.
+    pub history: History,
+}
+
+/// Drives the training loop over a train/validation pair.
+pub struct Trainer {
+    config: TrainConfig,
+    callbacks: Vec<Box<dyn Callback>>,
+}
+
+impl Trainer {
+    pub fn new(config: TrainConfig) -> Self {
+        return Self {
+            config,
+            callbacks: Vec::new(),
+        };
+    }
+
+    /// Adds `callback` to the hooks run during training, after those
+    /// added before it.
+    ///
+    /// Typically chained onto construction, e.g.
+    /// `Trainer::new(config).with_callback(ModelCheckpoint::best(path))`.
+    pub fn with_callback(mut self, callback: impl Callback + 'static) -> Self {
+        self.callbacks.push(Box::new(callback));
+        return self;
+    }
+
+    pub fn config(&self) -> &TrainConfig {
+        return &self.config;
+    }
+
+    /// Trains `model` on `train`, monitoring the loss on `val` after every epoch.
+    ///
+    /// When `patience` is set, training stops once the validation loss has not
+    /// improved by at least `min_delta` for that many consecutive epochs.
+    /// In every case `model` ends up holding the weights from the epoch
+    /// with the best validation loss.
+    ///
+    /// When `checkpoint_dir` is set, the configuration is saved there as
+    /// `config.toml` (see [`TrainConfig::to_toml`]) before the first epoch,
+    /// and a [`Checkpoint`] is written there every `checkpoint_every`
+    /// epochs; failing to write either aborts training.
+    ///
+    /// The [`Callback`]s of the trainer run at the start and end of every
+    /// epoch and after every batch, and may stop training early as well.
+    ///
+    /// With `handle_signals`, SIGINT (Ctrl+C) or SIGTERM stops training
+    /// after the current batch. The unfinished epoch is discarded rather
+    /// than evaluated, and the state at its start is written to
+    /// `checkpoint_dir` regardless of `checkpoint_every`, so that resuming
+    /// redoes the epoch and ends exactly like an uninterrupted run.
+    /// [`FitReport::interrupted`] tells the caller to wrap up. A
+    /// second signal aborts the process at once. Once interrupted, later
+    /// runs stop before their first epoch, which is why [`tune`] and
+    /// [`cross_validate`] end at the first interrupted run.
+    ///
+    /// [`tune`]: fn@super::tune
+    /// [`cross_validate`]: super::cross_validate
+    ///
+    /// # Errors
+    /// [`Error::InvalidFormat`] if `val` is empty or `class_weights` does not
+    /// have one weight per class of `train`. Otherwise fails if a checkpoint, metrics or
+    /// TensorBoard file cannot be written, a callback fails, or the loss
+    /// diverges (see [`TrainConfig::on_divergence`]).
+    pub fn fit(
+        &mut self,
+        model: &mut Model,
+        train: &impl DatasetSource,
+        val: &impl DatasetSource,
+    ) -> Result<FitReport> {
+        self.check_data(train, val)?;
+        let state = Checkpoint::start(model.clone(), &self.config);
+        let logger = match &self.config.metrics_path {
+            Some(path) => Some(MetricsLogger::create(path)?),
+            None => None,
+        };
+        let (best_model, report) = self.run(state, logger, train, val)?;
+        *model = best_model;
+        return Ok(report);
+    }
+
+    /// Continues a run from `checkpoint` as if it had never been interrupted.
+    ///
+    /// The trainer should be configured the same way as the run that wrote
+    /// the checkpoint. Returns the model with the best validation loss.
+    ///
+    /// # Errors
+    /// As for [`Trainer::fit`].
+    pub fn resume(
+        &mut self,
+        checkpoint: Checkpoint,
+        train: &impl DatasetSource,
+        val: &impl DatasetSource,
+    ) -> Result<(Model, FitReport)> {
+        self.check_data(train, val)?;
+        println!("resuming from epoch {}", checkpoint.epoch);
+        let logger = match &self.config.metrics_path {
+            Some(path) => Some(MetricsLogger::append(path)?),
+            None => None,
+        };
+        return self.run(checkpoint, logger, train, val);
+    }
+
+    /// Rejects data or a configuration that training cannot use, before any
+    /// file is written.
+    fn check_data(&self, train: &impl DatasetSource, val: &impl DatasetSource) -> Result<()> {
+        // The best model is picked by validation loss, which is undefined
+        // without samples.
+        if val.is_empty() {
+            return Err(Error::InvalidFormat(
+                "the validation set is empty".to_string(),
+            ));
+        }
+        if let Some(weights) = &self.config.class_weights
+            && weights.len() != train.num_classes()
+        {
+            return Err(Error::InvalidFormat(format!(
+                "{} class weights for {} classes",
+                weights.len(),
+                train.num_classes()
+            )));
+        }
+        return Ok(());
+    }
+
+    /// Creates the epoch progress bar, starting at `position` completed epochs.
+    fn progress_bar(&self, position: usize) -> ProgressBar {
+        if !self.config.show_progress {
+            return ProgressBar::hidden();
+        }
+        let bar = ProgressBar::new(self.config.epochs as u64);
+        bar.set_style(
+            ProgressStyle::with_template("{bar:40} {pos}/{len} epochs [{elapsed_precise}] {msg}")
+                .unwrap(),
+        );
+        bar.set_position(position as u64);
+        return bar;
+    }
+
+    fn run(
+        &mut self,
+        mut state: Checkpoint,
+        mut logger: Option<MetricsLogger>,
+        train: &impl DatasetSource,
+        val: &impl DatasetSource,
+    ) -> Result<(Model, FitReport)> {
+        assert_eq!(
+            state.model.preprocess(),
+            train.preprocess(),
+            "model and training data use different preprocessing"
+        );
+        // The labels the model learns are those of the training data.
+        state.model.set_class_map(train.class_map().clone());
+        state.best_model.set_class_map(train.class_map().clone());
+        let bar = self.progress_bar(state.epoch);
+        let config = &self.config;
+        let callbacks = &mut self.callbacks;
+        let mut stopped_early = false;
+        let mut tensorboard = match &config.tensorboard_dir {
+            Some(dir) => Some(TensorBoardWriter::create(dir)?),
+            None => None,
+        };
+        if let Some(dir) = &config.checkpoint_dir {
+            config.save_toml(&dir.join("config.toml"))?;
+        }
+        if config.handle_signals {
+            interrupt::install();
+        }
+        let interrupted = || config.handle_signals && interrupt::received().is_some();
+        let pool = thread_pool(config.num_threads);
+        for callback in callbacks.iter_mut() {
+            callback.on_train_start(&state.model, state.epoch)?;
+        }
+        // Factor on the scheduled learning rate, halved by every rollback.
+        let mut learning_rate_scale = 1.0;
+        let mut rollbacks = 0;
+
+        while state.epoch < config.epochs {
+            if interrupted() {
+                stopped_early = true;
+                break;
+            }
+            let epoch = state.epoch;
+            let mut learning_rate = config.learning_rate_at(epoch) * learning_rate_scale;
+            // Every callback sees every hook, even after one asked to stop.
+            let mut stop = false;
+            for callback in callbacks.iter_mut() {
+                stop |= callback.on_epoch_start(&state.model, epoch, &mut learning_rate)?
+                    == Control::Stop;
+            }
+            if stop {
+                stopped_early = true;
+                break;
+            }
+            let can_roll_back = matches!(
+                config.on_divergence,
+                DivergencePolicy::Rollback { max_retries } if rollbacks < max_retries
+            );
+            // The state at the start of the epoch, to retry it after a
+            // divergence or to checkpoint it when interrupted.
+            let snapshot = (can_roll_back || config.handle_signals).then(|| state.clone());
+            let result = train_epoch(
+                pool.as_ref(),
+                &mut state.model,
+                &mut state.optimizer,
+                &mut state.rng,
+                train,
+                config,
+                epoch,
+                learning_rate,
+                &mut |model, metrics| {
+                    let mut stop = false;
+                    for callback in callbacks.iter_mut() {
+                        stop |= callback.on_batch_end(model, metrics)? == Control::Stop;
+                    }
+                    return Ok(if stop || interrupted() {
+                        Control::Stop
+                    } else {
+                        Control::Continue
+                    });
+                },
+            );
+            let (total_loss, samples, control) = match (result, snapshot) {
+                (Err(Error::Diverged { batch, .. }), Some(snapshot)) if can_roll_back => {
+                    state = snapshot;
+                    learning_rate_scale *= 0.5;
+                    rollbacks += 1;
+                    bar.suspend(|| {
+                        println!(
+                            "Loss diverged in epoch {}, batch {}: retrying the epoch at half the learning rate",
+                            epoch, batch
+                        );
+                    });
+                    continue;
+                }
+                // The interrupted epoch is discarded, so that the checkpoint
+                // resumes it from its first batch.
+                (Ok(_), Some(snapshot)) if interrupted() => {
+                    state = snapshot;
+                    stopped_early = true;
+                    break;
+                }
+                (result, _) => result?,
+            };
+            let mut stop = control == Control::Stop;
+            state.epoch += 1;
+
+            let metrics = EpochMetrics {
+                epoch,
+                // A cut-short epoch averages over the samples it trained on.
+                train_loss: total_loss / samples.max(1) as f32,
+                train_acc: state.model.evaluate(train),
+                val_loss: state.model.loss(val),
+                val_acc: state.model.evaluate(val),
+            };
+            if metrics.val_loss < state.best_val_loss - config.min_delta {
+                state.best_val_loss = metrics.val_loss;
+                state.best_epoch = epoch;
+                state.best_model = state.model.clone();
+            }
+
+            state.history.push(metrics);
+            for callback in callbacks.iter_mut() {
+                stop |= callback.on_epoch_end(&state.model, &metrics)? == Control::Stop;
+            }
+            if let Some(logger) = &mut logger {
+                logger.log(&metrics)?;
+            }
+            if let Some(tensorboard) = &mut tensorboard {
+                tensorboard.log(&metrics)?;
+            }
+            bar.set_message(format!(
+                "loss={:.4} val_loss={:.4} val_acc={:.2}%",
+                metrics.train_loss,
+                metrics.val_loss,
+                metrics.val_acc * 100.0
+            ));
+            bar.inc(1);
+            if epoch.is_multiple_of(10) {
+                bar.suspend(|| {
+                    println!(
+                        "Epoch {:3}: loss={:.4}, acc={:.2}%, val_loss={:.4}, val_acc={:.2}%",
+                        epoch,
+                        metrics.train_loss,
+                        metrics.train_acc * 100.0,
+                        metrics.val_loss,
+                        metrics.val_acc * 100.0
+                    );
+                });
+            }
+
+            if let Some(dir) = &config.checkpoint_dir
+                && state.epoch.is_multiple_of(config.checkpoint_every.max(1))
+            {
+                state.save(&Checkpoint::path_in(dir, state.epoch))?;
+            }
+
+            if let Some(patience) = config.patience
+                && epoch - state.best_epoch >= patience
+            {
+                bar.suspend(|| {
+                    println!(
+                        "Early stopping at epoch {}: no improvement since epoch {}",
+                        epoch, state.best_epoch
+                    );
+                });
+                stopped_early = true;
+                break;
+            }
+            if stop {
+                stopped_early = true;
+                break;
+            }
+        }
+
+        let interrupted = interrupted();
+        if interrupted && let Some(dir) = &config.checkpoint_dir {
+            let path = Checkpoint::path_in(dir, state.epoch);
+            state.save(&path)?;
+            bar.suspend(|| {
+                println!(
+                    "Interrupted after {} epochs: saved checkpoint to {}",
+                    state.epoch,
+                    path.display()
+                );
+            });
+        }
+        bar.finish_and_clear();
+        let report = FitReport {
+            epochs_run: state.epoch,
+            best_epoch: state.best_epoch,
+            best_val_loss: state.best_val_loss,
+            stopped_early,
+            interrupted,
+            history: state.history,
+        };
+        return Ok((state.best_model, report));
+    }
+}
+
+impl Model {
+    /// Trains the model for one pass over `dataset`, continuing from its
+    /// current weights, and returns the mean training loss.
+    ///
+    /// This refines a trained model as new labeled images arrive, without
+    /// retraining from scratch:
+    ///
+    /// - `config` supplies the loss, batch size, accumulation, class
+    ///   weights, input noise and threads as for [`Trainer::fit`]. The
+    ///   learning rate is `config.learning_rate`, without the scheduler;
+    ///   epochs, validation and early stopping do not apply.
+    /// - `optimizer` carries the momentum between calls. Keep it alongside
+    ///   the model with [`Sgd::save`] and [`Sgd::load`], or start with
+    ///   `Sgd::new(config.momentum)`; a run interrupted mid-epoch is better
+    ///   continued with [`Trainer::resume`].
+    /// - `rng` decides the sample order (if `config.shuffle`) and the input
+    ///   noise, so a seeded generator makes the update reproducible.
+    ///
+    /// A model loaded with [`Model::load`] continues exactly as the one that
+    /// was saved: the normalizer, temperature and threshold are kept but not
+    /// refit, so recalibrate on held-out data after large updates.
+    ///
+    /// # Errors
+    /// Fails with [`Error::Diverged`] if a batch's loss is not finite. The
+    /// batches before it have already been applied, and
+    /// `config.on_divergence` does not apply.
+    ///
+    /// # Panics
+    /// Panics if `dataset` has a different preprocessing or class map than
+    /// the model. Load new images with
+    /// [`Dataset::from_dataset_path_mapped`](super::Dataset::from_dataset_path_mapped)
+    /// and the model's [`Model::class_map`] so their labels line up even if
+    /// a class is missing from the new batch.
+    pub fn partial_fit(
+        &mut self,
+        dataset: &impl DatasetSource,
+        config: &TrainConfig,
+        optimizer: &mut Sgd,
+        rng: &mut impl Rng,
+    ) -> Result<f32> {
+        assert_eq!(
+            self.preprocess(),
+            dataset.preprocess(),
+            "model and training data use different preprocessing"
+        );
+        assert_eq!(
+            self.class_map(),
+            dataset.class_map(),
+            "model and training data use different class maps"
+        );
+        if dataset.is_empty() {
+            return Ok(0.0);
+        }
+        let pool = thread_pool(config.num_threads);
+        let (total_loss, samples, _) = train_epoch(
+            pool.as_ref(),
+            self,
+            optimizer,
+            rng,
+            dataset,
+            config,
+            0,
+            config.learning_rate,
+            &mut |_, _| Ok(Control::Continue),
+        )?;
+        return Ok(total_loss / samples as f32);
+    }
+}
+
+/// Starts the threads batches are split across, none for `num_threads = 1`.
+fn thread_pool(num_threads: usize) -> Option<ThreadPool> {
+    return match num_threads {
+        1 => None,
+        threads => Some(
+            ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .expect("failed to start training threads"),
+        ),
+    };
+}
+
+/// Runs one epoch of mini-batch gradient descent over `train`, calling
+/// `on_batch_end` after every batch.
+///
+/// # Returns
+/// The summed loss of all samples trained on, their number, and
+/// [`Control::Stop`] if `on_batch_end` cut the epoch short.
+///
+/// # Errors
+/// [`Error::Diverged`] at the first batch with a non-finite loss, before
+/// its gradients are applied.
+#[allow(clippy::too_many_arguments)]
+fn train_epoch(
+    pool: Option<&ThreadPool>,
+    model: &mut Model,
+    optimizer: &mut Sgd,
+    rng: &mut impl Rng,
+    train: &impl DatasetSource,
+    config: &TrainConfig,
+    epoch: usize,
+    learning_rate: f32,
+    on_batch_end: &mut dyn FnMut(&Model, &BatchMetrics) -> Result<Control>,
+) -> Result<(f32, usize, Control)> {
+    let mut total_loss = 0.0;
+    let mut samples = 0;
+    let batches = config.sampler.batches(
+        train.labels(),
+        train.num_classes(),
+        config.batch_size.max(1),
+        config.shuffle,
+        rng,
+    );
+    let mut accumulator = GradientAccumulator::new(config.accumulation_steps.max(1));
+    let mut control = Control::Continue;
+    for (index, batch) in batches.iter().enumerate() {
+        let x = train.select(batch);
+        let labels: Vec<Kind> = batch.iter().map(|&i| train.labels()[i]).collect();
+        // One seed per batch keeps the noise reproducible across resumes.
+        let noise_seed =
+            (config.input_dropout > 0.0 || config.input_noise > 0.0).then(|| rng.random::<u64>());
+        let (loss, grads) = batch_gradients(pool, model, x.view(), &labels, config, noise_seed);
+        if !loss.is_finite() {
+            return Err(Error::Diverged {
+                epoch,
+                batch: index,
+            });
+        }
+        total_loss += loss;
+        samples += batch.len();
+        if accumulator.add(grads) {
+            let grads = accumulator.take().unwrap();
+            apply_gradients(optimizer, model, grads, learning_rate, config);
+        }
+        let metrics = BatchMetrics {
+            epoch,
+            batch: index,
+            samples: batch.len(),
+            loss: loss / batch.len() as f32,
+        };
+        control = on_batch_end(model, &metrics)?;
+        if control == Control::Stop {
+            break;
+        }
+    }
+    // Never carry gradients across epochs, so checkpoints stay complete.
+    if let Some(grads) = accumulator.take() {
+        apply_gradients(optimizer, model, grads, learning_rate, config);
+    }
+    return Ok((total_loss, samples, control));
+}
+
+/// Clips `grads` to `config.max_grad_norm`, if set, and applies them.
+fn apply_gradients(
+    optimizer: &mut Sgd,
+    model: &mut Model,
+    mut grads: Gradients,
+    learning_rate: f32,
+    config: &TrainConfig,
+) {
+    if let Some(max_norm) = config.max_grad_norm {
+        grads.clip_norm(max_norm);
+    }
+    optimizer.step(model, &grads, learning_rate);
+}
+
+/// Computes the loss and mean gradients of a batch like [`Model::gradients`],
+/// splitting it into one shard per thread of `pool` if there is one.
+///
+/// With a `noise_seed`, the inputs are corrupted as configured by
+/// [`Model::gradients_with_noise`], shard `i` drawing from the seed + `i`.
+fn batch_gradients(
+    pool: Option<&ThreadPool>,
+    model: &Model,
+    x: ArrayView2<f32>,
+    labels: &[Kind],
+    config: &TrainConfig,
+    noise_seed: Option<u64>,
+) -> (f32, Gradients) {
+    let shard_gradients = |shard: usize, x: ArrayView2<f32>, labels: &[Kind]| {
+        return match noise_seed {
+            Some(seed) => {
+                let mut rng = ChaCha8Rng::seed_from_u64(seed.wrapping_add(shard as u64));
+                model.gradients_with_noise(x, labels, config, &mut rng)
+            }
+            None => model.gradients(x, labels, config),
+        };
+    };
+    let Some(pool) = pool.filter(|_| labels.len() > 1) else {
+        return shard_gradients(0, x, labels);
+    };
+    let shard_size = labels.len().div_ceil(pool.current_num_threads());
+    let starts: Vec<usize> = (0..labels.len()).step_by(shard_size).collect();
+    let shards: Vec<(f32, Gradients, usize)> = pool.install(|| {
+        return starts
+            .into_par_iter()
+            .enumerate()
+            .map(|(shard, start)| {
+                let end = (start + shard_size).min(labels.len());
+                let (loss, grads) =
+                    shard_gradients(shard, x.slice(s![start..end, ..]), &labels[start..end]);
+                (loss, grads, end - start)
+            })
+            .collect();
+    });
+
+    // Every shard's gradients are a mean over its samples, so weighting them
+    // by shard size gives the mean over the batch. Summing in shard order
+    // keeps the result independent of which thread finished first.
+    let n = labels.len() as f32;
+    let mut total_loss = 0.0;
+    let mut total = Gradients::zeros_like(model);
+    for (loss, mut grads, len) in shards {
+        total_loss += loss;
+        grads.scale(len as f32 / n);
+        total.add_assign(&grads);
+    }
+    return (total_loss, total);
+}
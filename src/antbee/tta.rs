@@ -0,0 +1,156 @@
+//! Test-time augmentation: averaging predictions over transformed copies of
+//! an input.
+
+use super::kind::Kind;
+use super::kind::Prediction;
+use super::model::Model;
+use super::preprocess::Preprocess;
+use ndarray::Array1;
+use ndarray::Array2;
+use ndarray::ArrayView1;
+use ndarray::Axis;
+
+#[cfg(feature = "fs")]
+use super::error::Result;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+/// The augmented views [`Model::predict_tta`] averages over, besides the
+/// input itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TtaConfig {
+    /// Add the horizontally mirrored image.
+    pub flip: bool,
+    /// Add the four corner crops that leave out this fraction of the width
+    /// and height, each resized back to the full image. `0.0` adds none.
+    pub crop_fraction: f32,
+}
+
+impl Default for TtaConfig {
+    /// The image, its mirror image and four corner crops of 90%: six views.
+    fn default() -> Self {
+        return Self {
+            flip: true,
+            crop_fraction: 0.1,
+        };
+    }
+}
+
+impl TtaConfig {
+    /// Returns the number of views predicted on, including the input.
+    pub fn views(&self) -> usize {
+        return 1 + self.flip as usize + if self.crop_fraction > 0.0 { 4 } else { 0 };
+    }
+
+    /// Builds the views of the preprocessed image `x`, one per row.
+    fn augment(&self, x: ArrayView1<f32>, preprocess: &Preprocess) -> Array2<f32> {
+        assert!(
+            (0.0..1.0).contains(&self.crop_fraction),
+            "crop fraction must be in [0, 1)"
+        );
+        let (width, height) = (preprocess.width as usize, preprocess.height as usize);
+        let mut views = Array2::zeros((self.views(), x.len()));
+        views.row_mut(0).assign(&x);
+        let mut row = 1;
+        if self.flip {
+            views
+                .row_mut(row)
+                .assign(&crop_resized(x, preprocess, width as f32, 0.0, -1.0, 1.0));
+            row += 1;
+        }
+        if self.crop_fraction > 0.0 {
+            let scale = 1.0 - self.crop_fraction;
+            let right = width as f32 * self.crop_fraction;
+            let bottom = height as f32 * self.crop_fraction;
+            for (left, top) in [(0.0, 0.0), (right, 0.0), (0.0, bottom), (right, bottom)] {
+                views
+                    .row_mut(row)
+                    .assign(&crop_resized(x, preprocess, left, top, scale, scale));
+                row += 1;
+            }
+        }
+        return views;
+    }
+}
+
+/// Resamples the CHW image `x` bilinearly: the output pixel (u, v) is
+/// the input around (left + (u + 0.5) * x_step, top + (v + 0.5) * y_step),
+/// measured from the top left corner of the image in pixels and clamped
+/// to it. A negative `x_step` mirrors the image.
+fn crop_resized(
+    x: ArrayView1<f32>,
+    preprocess: &Preprocess,
+    left: f32,
+    top: f32,
+    x_step: f32,
+    y_step: f32,
+) -> Array1<f32> {
+    let (width, height) = (preprocess.width as usize, preprocess.height as usize);
+    let plane = width * height;
+    // Source pixel pair and weight of the second along one axis.
+    let taps = |size: usize, start: f32, step: f32| {
+        return (0..size)
+            .map(|i| {
+                // The center of output pixel i, as an input pixel index.
+                let position = start + (i as f32 + 0.5) * step - 0.5;
+                let position = position.clamp(0.0, (size - 1) as f32);
+                let low = position.floor() as usize;
+                return (low, (low + 1).min(size - 1), position - low as f32);
+            })
+            .collect::<Vec<_>>();
+    };
+    let columns = taps(width, left, x_step);
+    let rows = taps(height, top, y_step);
+    let mut out = Array1::zeros(x.len());
+    for channel in 0..preprocess.channels.channels() {
+        let source = |row: usize, column: usize| x[channel * plane + row * width + column];
+        for (v, &(y0, y1, wy)) in rows.iter().enumerate() {
+            for (u, &(x0, x1, wx)) in columns.iter().enumerate() {
+                let upper = source(y0, x0) * (1.0 - wx) + source(y0, x1) * wx;
+                let lower = source(y1, x0) * (1.0 - wx) + source(y1, x1) * wx;
+                out[channel * plane + v * width + u] = upper * (1.0 - wy) + lower * wy;
+            }
+        }
+    }
+    return out;
+}
+
+impl Model {
+    /// Computes the class probabilities of the input averaged over the
+    /// augmented views of `config`.
+    ///
+    /// # Arguments
+    /// * `x` - Preprocessed input of shape (INPUT_DIM,), as produced by
+    ///   [`Preprocess::apply`].
+    pub fn predict_probs_tta(&self, x: ArrayView1<f32>, config: TtaConfig) -> Array1<f32> {
+        let views = config.augment(x, self.preprocess());
+        return self
+            .predict_probs_batch(views.view())
+            .mean_axis(Axis(0))
+            .unwrap();
+    }
+
+    /// Predicts the class of the input like [`Model::predict_with_probability`],
+    /// from the probabilities averaged over the augmented views of `config`.
+    ///
+    /// The averaged probabilities are what the decision threshold is
+    /// applied to. Averaging over small shifts and a mirror image makes the
+    /// prediction less sensitive to framing, at the cost of one forward
+    /// pass per [view](TtaConfig::views).
+    pub fn predict_tta(&self, x: ArrayView1<f32>, config: TtaConfig) -> Prediction {
+        let probs = self.predict_probs_tta(x, config);
+        let k = self.decide(probs.view());
+        return Prediction {
+            kind: Kind(k),
+            probability: probs[k],
+        };
+    }
+
+    /// Classifies a single image file like [`Model::predict_image`], with
+    /// test-time augmentation (see [`Model::predict_tta`]).
+    #[cfg(feature = "fs")]
+    pub fn predict_image_tta(&self, path: &Path, config: TtaConfig) -> Result<Prediction> {
+        let x = self.preprocess().load_image(path)?;
+        return Ok(self.predict_tta(x.view(), config));
+    }
+}
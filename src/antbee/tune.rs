@@ -0,0 +1,258 @@
+use super::config::TrainConfig;
+use super::error::Result;
+use super::model::Model;
+use super::source::DatasetSource;
+use super::trainer::FitReport;
+use super::trainer::Trainer;
+use rand::SeedableRng;
+use rand::prelude::SliceRandom;
+use rand_chacha::ChaCha8Rng;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Candidate values for every tuned hyperparameter.
+///
+/// A trial picks one value from each list; the search space is their
+/// cartesian product.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchSpace {
+    pub learning_rates: Vec<f32>,
+    pub batch_sizes: Vec<usize>,
+    pub l2: Vec<f32>,
+}
+
+impl SearchSpace {
+    /// Number of distinct hyperparameter combinations.
+    pub fn len(&self) -> usize {
+        return self.learning_rates.len() * self.batch_sizes.len() * self.l2.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    /// Every combination, varying the L2 penalty fastest.
+    pub fn grid(&self) -> Vec<TrialParams> {
+        let mut grid = Vec::with_capacity(self.len());
+        for &learning_rate in &self.learning_rates {
+            for &batch_size in &self.batch_sizes {
+                for &l2 in &self.l2 {
+                    grid.push(TrialParams {
+                        learning_rate,
+                        batch_size,
+                        l2,
+                    });
+                }
+            }
+        }
+        return grid;
+    }
+}
+
+impl Default for SearchSpace {
+    /// Log-spaced learning rates and L2 penalties around the defaults of
+    /// [`TrainConfig`], and batch sizes from plain SGD up to 64.
+    fn default() -> Self {
+        return Self {
+            learning_rates: vec![1e-4, 3e-4, 1e-3, 3e-3, 1e-2, 3e-2],
+            batch_sizes: vec![1, 4, 16, 64],
+            l2: vec![0.0, 1e-5, 1e-4, 1e-3],
+        };
+    }
+}
+
+/// How trials are drawn from a [`SearchSpace`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchStrategy {
+    /// Try every combination in order.
+    Grid,
+    /// Try `trials` distinct combinations in random order (all of them if
+    /// the space is smaller).
+    Random { trials: usize },
+}
+
+/// Settings of a hyperparameter search.
+#[derive(Debug, Clone)]
+pub struct TuneConfig {
+    pub space: SearchSpace,
+    pub strategy: SearchStrategy,
+    /// Seed for drawing random trials.
+    pub seed: u64,
+    /// CSV file that every finished trial is written to as a row.
+    pub log_path: Option<PathBuf>,
+}
+
+impl Default for TuneConfig {
+    fn default() -> Self {
+        return Self {
+            space: SearchSpace::default(),
+            strategy: SearchStrategy::Random { trials: 20 },
+            seed: 0,
+            log_path: None,
+        };
+    }
+}
+
+/// Hyperparameters of a single trial.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrialParams {
+    pub learning_rate: f32,
+    pub batch_size: usize,
+    pub l2: f32,
+}
+
+impl TrialParams {
+    /// Returns `base` with these hyperparameters filled in.
+    pub fn apply(&self, base: &TrainConfig) -> TrainConfig {
+        return TrainConfig {
+            learning_rate: self.learning_rate,
+            batch_size: self.batch_size,
+            l2: self.l2,
+            ..base.clone()
+        };
+    }
+}
+
+/// Result of training with one [`TrialParams`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trial {
+    /// Zero-based trial number, in the order trials were run.
+    pub index: usize,
+    pub params: TrialParams,
+    /// Accuracy of the restored best weights on the validation split.
+    pub val_accuracy: f32,
+    pub fit: FitReport,
+}
+
+/// Outcome of [`tune`].
+#[derive(Debug, Clone)]
+pub struct TuneReport {
+    /// All trials, in the order they were run.
+    pub trials: Vec<Trial>,
+    /// Index into `trials` of the trial with the lowest validation loss.
+    pub best_trial: usize,
+    /// `base` with the hyperparameters of `best_trial`.
+    pub best_config: TrainConfig,
+    /// The model trained in `best_trial`.
+    pub best_model: Model,
+    /// Whether a signal stopped the search, see
+    /// [`TrainConfig::handle_signals`]. `trials` then ends with the
+    /// interrupted trial, and the remaining candidates were not tried.
+    pub interrupted: bool,
+}
+
+impl TuneReport {
+    pub fn best(&self) -> &Trial {
+        return &self.trials[self.best_trial];
+    }
+}
+
+/// Searches for the hyperparameters that minimize the validation loss.
+///
+/// Every trial trains a copy of `initial` on `train` with `base` overridden
+/// by the trial's [`TrialParams`], monitoring `val` for early stopping as
+/// [`Trainer::fit`] does. Starting all trials from the same weights keeps
+/// them comparable. Trials are ranked by their best validation loss.
+///
+/// Checkpointing and metrics logging are disabled for the trials, since
+/// they would overwrite each other's files. One line per trial is printed,
+/// and written to `tune.log_path` when set. An interrupted trial ends the
+/// search, see [`TuneReport::interrupted`].
+pub fn tune(
+    initial: &Model,
+    train: &impl DatasetSource,
+    val: &impl DatasetSource,
+    base: &TrainConfig,
+    tune: &TuneConfig,
+) -> Result<TuneReport> {
+    assert!(!tune.space.is_empty(), "search space is empty");
+    let trial_base = TrainConfig {
+        checkpoint_dir: None,
+        metrics_path: None,
+        tensorboard_dir: None,
+        ..base.clone()
+    };
+
+    let mut candidates = tune.space.grid();
+    if let SearchStrategy::Random { trials } = tune.strategy {
+        candidates.shuffle(&mut ChaCha8Rng::seed_from_u64(tune.seed));
+        candidates.truncate(trials.max(1));
+    }
+
+    let mut log = match &tune.log_path {
+        Some(path) => {
+            let mut writer = BufWriter::new(File::create(path)?);
+            writeln!(
+                writer,
+                "trial,learning_rate,batch_size,l2,epochs_run,best_epoch,val_loss,val_acc"
+            )?;
+            Some(writer)
+        }
+        None => None,
+    };
+
+    let mut trials = Vec::<Trial>::with_capacity(candidates.len());
+    let mut best: Option<(usize, Model)> = None;
+    let mut interrupted = false;
+    for (index, params) in candidates.into_iter().enumerate() {
+        let mut trainer = Trainer::new(params.apply(&trial_base));
+        let mut model = initial.clone();
+        let fit = trainer.fit(&mut model, train, val)?;
+        let trial = Trial {
+            index,
+            params,
+            val_accuracy: model.evaluate(val),
+            fit,
+        };
+        println!(
+            "trial {:3}: lr={}, batch_size={}, l2={} -> val_loss={:.4}, val_acc={:.2}%",
+            index,
+            params.learning_rate,
+            params.batch_size,
+            params.l2,
+            trial.fit.best_val_loss,
+            trial.val_accuracy * 100.0
+        );
+        if let Some(writer) = &mut log {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{}",
+                index,
+                params.learning_rate,
+                params.batch_size,
+                params.l2,
+                trial.fit.epochs_run,
+                trial.fit.best_epoch,
+                trial.fit.best_val_loss,
+                trial.val_accuracy
+            )?;
+            writer.flush()?;
+        }
+
+        let is_best = match &best {
+            Some((best_index, _)) => {
+                trial.fit.best_val_loss < trials[*best_index].fit.best_val_loss
+            }
+            None => true,
+        };
+        interrupted = trial.fit.interrupted;
+        trials.push(trial);
+        if is_best {
+            best = Some((index, model));
+        }
+        if interrupted {
+            break;
+        }
+    }
+
+    let (best_trial, best_model) = best.unwrap();
+    return Ok(TuneReport {
+        best_config: trials[best_trial].params.apply(base),
+        trials,
+        best_trial,
+        best_model,
+        interrupted,
+    });
+}
@@ -0,0 +1,103 @@
+//! wasm-bindgen bindings for classifying images in the browser.
+//!
+//! Build with `--no-default-features --features wasm` for
+//! `wasm32-unknown-unknown`. The model is passed in as the bytes of a file
+//! written by `Model::save`, e.g. fetched from the server, and images as
+//! raw RGB pixels, e.g. from a canvas with the alpha channel dropped.
+
+use super::model::Model;
+use wasm_bindgen::prelude::*;
+
+/// A trained model loaded for use from JavaScript.
+#[wasm_bindgen]
+pub struct Classifier {
+    model: Model,
+}
+
+/// The outcome of [`Classifier::predict_from_rgb`].
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy)]
+pub struct ClassPrediction {
+    /// Index of the predicted class.
+    pub class: usize,
+    /// The model's probability for `class`.
+    pub probability: f32,
+}
+
+#[wasm_bindgen]
+impl Classifier {
+    /// Loads a model from the contents of a model file.
+    #[wasm_bindgen(constructor)]
+    pub fn new(model_bytes: &[u8]) -> Result<Classifier, JsError> {
+        let model = Model::read_from(&mut &model_bytes[..])?;
+        return Ok(Self { model });
+    }
+
+    /// Number of classes the model distinguishes.
+    #[wasm_bindgen(getter, js_name = numClasses)]
+    pub fn num_classes(&self) -> usize {
+        return self.model.num_classes();
+    }
+
+    /// Name of class `index`, as saved with the model.
+    #[wasm_bindgen(js_name = className)]
+    pub fn class_name(&self, index: usize) -> Option<String> {
+        return self.model.class_map().names().get(index).cloned();
+    }
+
+    /// Classifies a raw RGB image of `width` x `height` pixels, three bytes
+    /// per pixel in row-major order.
+    ///
+    /// Throws if the image is empty or `rgb` does not hold exactly its
+    /// pixels.
+    #[wasm_bindgen(js_name = predictFromRgb)]
+    pub fn predict_from_rgb(
+        &self,
+        rgb: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<ClassPrediction, JsError> {
+        check_rgb(rgb.len(), width, height).map_err(|reason| JsError::new(&reason))?;
+        let prediction = self.model.predict_from_rgb(rgb, width, height)?;
+        return Ok(ClassPrediction {
+            class: prediction.kind.index(),
+            probability: prediction.probability,
+        });
+    }
+}
+
+/// Checks the image size JavaScript passed in before any of it is used, so
+/// a bad call throws instead of aborting the module. The size is computed
+/// in `u128`, which no `u32` dimensions overflow; `usize` is 32 bits wide
+/// on wasm32.
+fn check_rgb(len: usize, width: u32, height: u32) -> Result<(), String> {
+    if width == 0 || height == 0 {
+        return Err(format!("empty {}x{} image", width, height));
+    }
+    let expected = width as u128 * height as u128 * 3;
+    if len as u128 != expected {
+        return Err(format!(
+            "expected {} bytes for a {}x{} RGB image, got {}",
+            expected, width, height, len
+        ));
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_rgb_rejects_bad_sizes() {
+        assert!(check_rgb(0, 0, 0).is_err());
+        assert!(check_rgb(0, 0, 5).is_err());
+        assert!(check_rgb(0, 5, 0).is_err());
+        assert!(check_rgb(11, 2, 2).is_err());
+        // 65536 x 65536 x 3 bytes wrap around to 0 in 32 bits.
+        assert!(check_rgb(0, 1 << 16, 1 << 16).is_err());
+        assert!(check_rgb(u32::MAX as usize, u32::MAX, u32::MAX).is_err());
+        assert!(check_rgb(12, 2, 2).is_ok());
+        assert!(check_rgb(3, 1, 1).is_ok());
+    }
+}
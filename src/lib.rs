@@ -0,0 +1,3 @@
+#![cfg_attr(feature = "simd", feature(portable_simd))]
+
+pub mod antbee;
@@ -1,50 +1,1814 @@
-use std::path::PathBuf;
-mod antbee;
+use antbee::Backend;
+use antbee::BalancedSampler;
+use antbee::BinaryCrossEntropy;
+use antbee::ChannelMode;
+use antbee::Checkpoint;
+use antbee::ClassMap;
+use antbee::ConfusionMatrix;
+use antbee::CrossEntropy;
+use antbee::DataConfig;
 use antbee::Dataset;
+use antbee::DatasetSource;
+use antbee::DivergencePolicy;
+use antbee::DuplicateGroup;
+use antbee::Ensemble;
+use antbee::Focal;
+use antbee::Hinge;
+use antbee::Kind;
+use antbee::Loss;
 use antbee::Model;
+use antbee::ModelConfig;
+use antbee::Normalizer;
+use antbee::Precision;
+use antbee::Preprocess;
+use antbee::RandomSampler;
+use antbee::ReliabilityDiagram;
+use antbee::Report;
+use antbee::ResizeMode;
+use antbee::RocCurve;
+use antbee::RunConfig;
+use antbee::Sampler;
+use antbee::SearchStrategy;
+use antbee::StratifiedSampler;
+use antbee::ThresholdMetric;
+use antbee::TrainConfig;
+use antbee::Trainer;
+use antbee::TtaConfig;
+use antbee::TuneConfig;
+use antbee::copy_misclassified;
+use antbee_rs::antbee;
+use ndarray::Array2;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::fs::File;
+use std::fs::create_dir_all;
+use std::fs::metadata;
+use std::fs::remove_file;
+use std::io::BufWriter;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::io::stdin;
+use std::io::stdout;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::exit;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: antbee-rs [train] [--config <file>] [--resume <checkpoint>] [--init-model <path>] [--checkpoint-dir <dir>] [--save-model <path>] [--export-onnx <path>] [--export-npz <path>] [--review-dir <dir>] [--report <json>] [--metrics <file>] [--tensorboard <dir>] [--batch-size <n>] [--accumulate <steps>] [--threads <n>] [--balance-classes] [--loss <cross-entropy|bce|hinge|focal>] [--label-smoothing <e>] [--sampler <random|stratified|balanced>] [--clip-norm <max>] [--on-divergence <halt|rollback>] [--dropout <p>] [--input-noise <std>] [--normalize] [--conv <channels>] [--calibrate] [--tune-threshold] [--ensemble <members>] [--backend <cpu|gpu>] [data options]\n       antbee-rs tune [--trials <n>] [--grid] [--epochs <n>] [--log <file>] [--save-model <path>] [--normalize] [--conv <channels>] [data options]\n       antbee-rs saliency --model <path> --image <path> --output <png> [--class <index>]\n       antbee-rs predict-dir --model <path> --input <dir> --output <csv|json> [--classes <names>] [--tta]\n       antbee-rs evaluate --model <path> [--report <json>] [--cache-dir <dir>] [--backend <cpu|gpu>]\n       antbee-rs quantize --model <path> --output <path> [--cache-dir <dir>]\n       antbee-rs embed --model <path> --input <dir> --output <npy>\n       antbee-rs live --model <path> --width <pixels> --height <pixels> [--classes <names>]\n       antbee-rs pack --input <dir> --output <file> [data options]\n       antbee-rs unpack --input <file> --output <dir>\n       antbee-rs import --weights <safetensors|npz> --output <path> [--classes <names>] [--evaluate] [data options]\n       antbee-rs inspect <dir>\n       antbee-rs dedup <dir> [--max-distance <bits>] [--remove]\n       antbee-rs serve --model <path> [--host <addr>] [--port <n>] [--classes <names>]\n\ndata options: [--cache-dir <dir>] [--image-size <pixels>] [--grayscale] [--resize <stretch|crop|letterbox>] [--half-precision] [--mmap]"
+    );
+    exit(2);
+}
+
+/// Returns the value following a flag, or exits with the usage message.
+fn value(iter: &mut impl Iterator<Item = String>) -> String {
+    return iter.next().unwrap_or_else(|| usage());
+}
+
+/// Parses the value following a flag, or exits with the usage message.
+fn number<T: FromStr>(iter: &mut impl Iterator<Item = String>) -> T {
+    return value(iter).parse().unwrap_or_else(|_| usage());
+}
+
+/// Parses a comma-separated list of conv block channels such as `8,16`,
+/// or exits with the usage message.
+fn channels(iter: &mut impl Iterator<Item = String>) -> Vec<usize> {
+    let channels: Vec<usize> = value(iter)
+        .split(',')
+        .map(|c| c.trim().parse().unwrap_or_else(|_| usage()))
+        .collect();
+    if channels.contains(&0) {
+        usage();
+    }
+    return channels;
+}
 
-fn train_model(model: &mut Model, dataset: &Dataset) {
-    const EPOCHS: usize = 150;
-    let n = dataset.len() as f32;
+/// Parses a training loss name (`cross-entropy`, `bce`, `hinge` or
+/// `focal`), or exits with the usage message.
+fn loss(iter: &mut impl Iterator<Item = String>) -> Arc<dyn Loss> {
+    return match value(iter).as_str() {
+        "cross-entropy" => Arc::new(CrossEntropy),
+        "bce" => Arc::new(BinaryCrossEntropy),
+        "hinge" => Arc::new(Hinge::default()),
+        "focal" => Arc::new(Focal::default()),
+        _ => usage(),
+    };
+}
 
-    for epoch in 0..EPOCHS {
-        let mut total_loss = 0.0;
+/// Parses a batch sampler name (`random`, `stratified` or `balanced`), or
+/// exits with the usage message.
+fn sampler(iter: &mut impl Iterator<Item = String>) -> Arc<dyn Sampler> {
+    return match value(iter).as_str() {
+        "random" => Arc::new(RandomSampler),
+        "stratified" => Arc::new(StratifiedSampler),
+        "balanced" => Arc::new(BalancedSampler),
+        _ => usage(),
+    };
+}
 
-        for data in dataset.get_values() {
-            total_loss += model.train_step(data);
+/// Parses a divergence policy (`halt` or `rollback`, which retries up to
+/// three times), or exits with the usage message.
+fn divergence_policy(iter: &mut impl Iterator<Item = String>) -> DivergencePolicy {
+    return match value(iter).as_str() {
+        "halt" => DivergencePolicy::Halt,
+        "rollback" => DivergencePolicy::Rollback { max_retries: 3 },
+        _ => usage(),
+    };
+}
+
+/// Parses a compute backend (`cpu` or `gpu`), or exits with the usage
+/// message.
+fn backend(iter: &mut impl Iterator<Item = String>) -> Backend {
+    return match value(iter).as_str() {
+        "cpu" => Backend::Cpu,
+        "gpu" => Backend::Gpu,
+        _ => usage(),
+    };
+}
+
+/// Runs the linear layer on `backend`, or exits if it is unavailable.
+fn select_backend(backend: Backend) {
+    if let Err(err) = backend.select() {
+        eprintln!("cannot use the {:?} backend: {}", backend, err);
+        exit(2);
+    }
+}
+
+/// Parses a comma-separated list of class names, or exits with the usage
+/// message.
+fn class_names(iter: &mut impl Iterator<Item = String>) -> Vec<String> {
+    return value(iter).split(',').map(str::to_string).collect();
+}
+
+/// Returns `classes`, or the class names saved with `model` if not given.
+///
+/// Exits with the usage message if the number of names does not match.
+fn class_names_or_saved(classes: Option<Vec<String>>, model: &Model) -> Vec<String> {
+    return match classes {
+        Some(classes) if classes.len() == model.num_classes() => classes,
+        Some(_) => usage(),
+        None => model.class_map().names().to_vec(),
+    };
+}
+
+/// Options shared by every command that loads the dataset.
+///
+/// They are `None` or `false` unless given, so that they override the
+/// `[data]` table of a `--config` file rather than reset it.
+struct DataArgs {
+    /// Directory holding preprocessed dataset caches.
+    cache_dir: Option<PathBuf>,
+    /// Width and height images are resized to.
+    image_size: Option<u32>,
+    /// Keep a single luminance channel.
+    grayscale: bool,
+    /// How images of another aspect ratio are fit to the input.
+    resize: Option<ResizeMode>,
+    /// Store the loaded features as 16-bit floats.
+    half_precision: bool,
+    /// Memory-map dataset caches instead of reading them into memory.
+    mmap: bool,
+}
+
+impl DataArgs {
+    fn new() -> Self {
+        return Self {
+            cache_dir: None,
+            image_size: None,
+            grayscale: false,
+            resize: None,
+            half_precision: false,
+            mmap: false,
+        };
+    }
+
+    /// Applies `flag` if it is a data option; returns whether it was one.
+    fn parse_flag(&mut self, flag: &str, iter: &mut impl Iterator<Item = String>) -> bool {
+        match flag {
+            "--cache-dir" => self.cache_dir = Some(value(iter).into()),
+            "--image-size" => self.image_size = Some(number(iter)),
+            "--grayscale" => self.grayscale = true,
+            "--half-precision" => self.half_precision = true,
+            "--mmap" => self.mmap = true,
+            "--resize" => {
+                self.resize = Some(match value(iter).as_str() {
+                    "stretch" => ResizeMode::Stretch,
+                    "crop" => ResizeMode::CenterCrop,
+                    "letterbox" => ResizeMode::Letterbox,
+                    _ => usage(),
+                })
+            }
+            _ => return false,
         }
+        return true;
+    }
 
-        if epoch % 10 == 0 {
-            let avg_loss = total_loss / n;
-            let accuracy = model.evaluate(dataset);
-            println!(
-                "Epoch {:3}: loss={:.4}, acc={:.2}%",
-                epoch,
-                avg_loss,
-                accuracy * 100.0
-            );
+    /// Returns `data` with the options given on the command line applied.
+    fn apply(&self, mut data: DataConfig) -> DataConfig {
+        if let Some(dir) = &self.cache_dir {
+            data.cache_dir = Some(dir.clone());
+        }
+        if let Some(size) = self.image_size {
+            data.preprocess.width = size;
+            data.preprocess.height = size;
         }
+        if self.grayscale {
+            data.preprocess.channels = ChannelMode::Grayscale;
+        }
+        if let Some(resize) = self.resize {
+            data.preprocess.resize = resize;
+        }
+        if self.half_precision {
+            data.precision = Precision::F16;
+        }
+        data.mmap |= self.mmap;
+        return data;
+    }
+
+    /// Returns the default data settings with the command line options
+    /// applied.
+    fn config(&self) -> DataConfig {
+        return self.apply(DataConfig::default());
     }
 }
 
-fn test_model(model: &Model, dataset: &Dataset) {
-    let accuracy = model.evaluate(dataset);
-    println!("Test Accuracy: {:.2}%", accuracy * 100.0);
+/// Options of the `train` command.
+///
+/// The options that correspond to a [`TrainConfig`] field are `None` unless
+/// given, so that they override the `--config` file rather than reset it.
+struct TrainArgs {
+    data: DataArgs,
+    /// TOML file with the training, data and model configuration.
+    config: Option<PathBuf>,
+    /// Checkpoint to resume training from.
+    resume: Option<PathBuf>,
+    /// Saved model to fine-tune instead of starting from random weights.
+    init_model: Option<PathBuf>,
+    /// Directory to write periodic checkpoints to.
+    checkpoint_dir: Option<PathBuf>,
+    /// File to save the trained model to.
+    save_model: Option<PathBuf>,
+    /// File to export the trained model to in ONNX format.
+    export_onnx: Option<PathBuf>,
+    /// File to export the trained weights to as a NumPy archive.
+    export_npz: Option<PathBuf>,
+    /// Directory to copy the misclassified test images to.
+    review_dir: Option<PathBuf>,
+    /// JSON file to write the test predictions and metrics to.
+    report: Option<PathBuf>,
+    /// CSV or JSON Lines file to log per-epoch metrics to.
+    metrics: Option<PathBuf>,
+    /// Directory to write a TensorBoard event file to.
+    tensorboard: Option<PathBuf>,
+    /// Samples per gradient computation.
+    batch_size: Option<usize>,
+    /// Batches whose gradients are averaged per parameter update.
+    accumulation_steps: Option<usize>,
+    /// Threads every batch is split across; 0 for one per core.
+    threads: Option<usize>,
+    /// Weight the loss by inverse class frequency.
+    balance_classes: bool,
+    /// Dropout probability on the input features.
+    dropout: Option<f32>,
+    /// Standard deviation of Gaussian input noise.
+    input_noise: Option<f32>,
+    /// Training objective.
+    loss: Option<Arc<dyn Loss>>,
+    /// Label smoothing of the training targets.
+    label_smoothing: Option<f32>,
+    /// Groups the training samples into batches.
+    sampler: Option<Arc<dyn Sampler>>,
+    /// Largest gradient norm of an update.
+    clip_norm: Option<f32>,
+    /// Reaction to a NaN or infinite loss.
+    on_divergence: Option<DivergencePolicy>,
+    /// Standardize each channel with statistics of the training split.
+    normalize: bool,
+    /// Output channels of the conv blocks in front of the linear layer.
+    conv: Option<Vec<usize>>,
+    /// Fit a softmax temperature on the validation split.
+    calibrate: bool,
+    /// Pick the decision threshold that maximizes F1 on the validation split.
+    tune_threshold: bool,
+    /// Train this many models with different seeds and average them.
+    ensemble: Option<usize>,
+    /// Device the linear layer runs on.
+    backend: Backend,
 }
 
-fn main() {
-    let dataset_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("dataset");
+impl TrainArgs {
+    fn parse(mut iter: impl Iterator<Item = String>) -> Self {
+        let mut args = Self {
+            data: DataArgs::new(),
+            config: None,
+            resume: None,
+            init_model: None,
+            checkpoint_dir: None,
+            save_model: None,
+            export_onnx: None,
+            export_npz: None,
+            review_dir: None,
+            report: None,
+            metrics: None,
+            tensorboard: None,
+            batch_size: None,
+            accumulation_steps: None,
+            threads: None,
+            balance_classes: false,
+            dropout: None,
+            input_noise: None,
+            loss: None,
+            label_smoothing: None,
+            sampler: None,
+            clip_norm: None,
+            on_divergence: None,
+            normalize: false,
+            conv: None,
+            calibrate: false,
+            tune_threshold: false,
+            ensemble: None,
+            backend: Backend::Cpu,
+        };
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--config" => args.config = Some(value(&mut iter).into()),
+                "--resume" => args.resume = Some(value(&mut iter).into()),
+                "--init-model" => args.init_model = Some(value(&mut iter).into()),
+                "--checkpoint-dir" => args.checkpoint_dir = Some(value(&mut iter).into()),
+                "--save-model" => args.save_model = Some(value(&mut iter).into()),
+                "--export-onnx" => args.export_onnx = Some(value(&mut iter).into()),
+                "--export-npz" => args.export_npz = Some(value(&mut iter).into()),
+                "--review-dir" => args.review_dir = Some(value(&mut iter).into()),
+                "--report" => args.report = Some(value(&mut iter).into()),
+                "--metrics" => args.metrics = Some(value(&mut iter).into()),
+                "--tensorboard" => args.tensorboard = Some(value(&mut iter).into()),
+                "--batch-size" => args.batch_size = Some(number(&mut iter)),
+                "--accumulate" => args.accumulation_steps = Some(number(&mut iter)),
+                "--threads" => args.threads = Some(number(&mut iter)),
+                "--balance-classes" => args.balance_classes = true,
+                "--loss" => args.loss = Some(loss(&mut iter)),
+                "--label-smoothing" => args.label_smoothing = Some(number(&mut iter)),
+                "--sampler" => args.sampler = Some(sampler(&mut iter)),
+                "--clip-norm" => args.clip_norm = Some(number(&mut iter)),
+                "--on-divergence" => args.on_divergence = Some(divergence_policy(&mut iter)),
+                "--dropout" => args.dropout = Some(number(&mut iter)),
+                "--input-noise" => args.input_noise = Some(number(&mut iter)),
+                "--normalize" => args.normalize = true,
+                "--conv" => args.conv = Some(channels(&mut iter)),
+                "--calibrate" => args.calibrate = true,
+                "--tune-threshold" => args.tune_threshold = true,
+                "--ensemble" => args.ensemble = Some(number(&mut iter)),
+                "--backend" => args.backend = backend(&mut iter),
+                flag => {
+                    if !args.data.parse_flag(flag, &mut iter) {
+                        usage();
+                    }
+                }
+            }
+        }
+        if args.dropout.is_some_and(|p| !(0.0..1.0).contains(&p))
+            || args.input_noise.is_some_and(|std| std < 0.0)
+            || args
+                .label_smoothing
+                .is_some_and(|e| !(0.0..1.0).contains(&e))
+            || args.clip_norm.is_some_and(|max| max <= 0.0)
+        {
+            usage();
+        }
+        // A model to fine-tune brings its own architecture and normalizer.
+        if args.init_model.is_some()
+            && (args.resume.is_some()
+                || args.normalize
+                || args.conv.is_some()
+                || args.ensemble.is_some())
+        {
+            usage();
+        }
+        // Ensembles cannot be resumed, exported, calibrated or thresholded yet.
+        if args.ensemble.is_some_and(|size| {
+            size == 0
+                || args.resume.is_some()
+                || args.export_onnx.is_some()
+                || args.export_npz.is_some()
+                || args.review_dir.is_some()
+                || args.report.is_some()
+                || args.calibrate
+                || args.tune_threshold
+        }) {
+            usage();
+        }
+        return args;
+    }
+}
+
+/// Options of the `tune` command.
+struct TuneArgs {
+    data: DataArgs,
+    /// Number of random trials; ignored with `grid`.
+    trials: usize,
+    /// Run every combination of the search space instead of random trials.
+    grid: bool,
+    /// Maximum epochs per trial; defaults to `TrainConfig::default()`.
+    epochs: Option<usize>,
+    /// CSV file to log one row per trial to.
+    log: Option<PathBuf>,
+    /// File to save the best trial's model to.
+    save_model: Option<PathBuf>,
+    /// Standardize each channel with statistics of the training split.
+    normalize: bool,
+    /// Output channels of the conv blocks in front of the linear layer.
+    conv: Option<Vec<usize>>,
+}
+
+impl TuneArgs {
+    fn parse(mut iter: impl Iterator<Item = String>) -> Self {
+        let mut args = Self {
+            data: DataArgs::new(),
+            trials: 20,
+            grid: false,
+            epochs: None,
+            log: None,
+            save_model: None,
+            normalize: false,
+            conv: None,
+        };
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--trials" => args.trials = number(&mut iter),
+                "--grid" => args.grid = true,
+                "--epochs" => args.epochs = Some(number(&mut iter)),
+                "--log" => args.log = Some(value(&mut iter).into()),
+                "--save-model" => args.save_model = Some(value(&mut iter).into()),
+                "--normalize" => args.normalize = true,
+                "--conv" => args.conv = Some(channels(&mut iter)),
+                flag => {
+                    if !args.data.parse_flag(flag, &mut iter) {
+                        usage();
+                    }
+                }
+            }
+        }
+        return args;
+    }
+}
+
+/// Options of the `saliency` command.
+struct SaliencyArgs {
+    /// Trained model to explain.
+    model: PathBuf,
+    /// Image to explain the prediction for.
+    image: PathBuf,
+    /// PNG file to write the heatmap to.
+    output: PathBuf,
+    /// Class index to explain; defaults to the predicted class.
+    class: Option<usize>,
+}
+
+impl SaliencyArgs {
+    fn parse(mut iter: impl Iterator<Item = String>) -> Self {
+        let (mut model, mut image, mut output, mut class) = (None, None, None, None);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--model" => model = Some(value(&mut iter).into()),
+                "--image" => image = Some(value(&mut iter).into()),
+                "--output" => output = Some(value(&mut iter).into()),
+                "--class" => class = Some(number(&mut iter)),
+                _ => usage(),
+            }
+        }
+        let (Some(model), Some(image), Some(output)) = (model, image, output) else {
+            usage();
+        };
+        return Self {
+            model,
+            image,
+            output,
+            class,
+        };
+    }
+}
+
+/// Options of the `predict-dir` command.
+struct PredictDirArgs {
+    /// Trained model to classify with.
+    model: PathBuf,
+    /// Directory searched recursively for images.
+    input: PathBuf,
+    /// CSV file to write one row per image to, or a JSON report if it
+    /// ends in `.json`.
+    output: PathBuf,
+    /// Class names by index; defaults to the class indices.
+    classes: Option<Vec<String>>,
+    /// Average the predictions over augmented views of every image.
+    tta: Option<TtaConfig>,
+}
+
+impl PredictDirArgs {
+    fn parse(mut iter: impl Iterator<Item = String>) -> Self {
+        let (mut model, mut input, mut output, mut classes) = (None, None, None, None);
+        let mut tta = None;
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--model" => model = Some(value(&mut iter).into()),
+                "--input" => input = Some(value(&mut iter).into()),
+                "--output" => output = Some(value(&mut iter).into()),
+                "--classes" => classes = Some(class_names(&mut iter)),
+                "--tta" => tta = Some(TtaConfig::default()),
+                _ => usage(),
+            }
+        }
+        let (Some(model), Some(input), Some(output)) = (model, input, output) else {
+            usage();
+        };
+        return Self {
+            model,
+            input,
+            output,
+            classes,
+            tta,
+        };
+    }
+}
+
+/// Options of the `evaluate` command.
+struct EvaluateArgs {
+    /// Trained model to evaluate.
+    model: PathBuf,
+    /// JSON file to write the predictions and metrics to.
+    report: Option<PathBuf>,
+    /// Directory holding preprocessed dataset caches.
+    cache_dir: Option<PathBuf>,
+    /// Device the linear layer runs on.
+    backend: Backend,
+}
+
+impl EvaluateArgs {
+    fn parse(mut iter: impl Iterator<Item = String>) -> Self {
+        let (mut model, mut report, mut cache_dir) = (None, None, None);
+        let mut compute = Backend::Cpu;
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--model" => model = Some(value(&mut iter).into()),
+                "--report" => report = Some(value(&mut iter).into()),
+                "--cache-dir" => cache_dir = Some(value(&mut iter).into()),
+                "--backend" => compute = backend(&mut iter),
+                _ => usage(),
+            }
+        }
+        let Some(model) = model else {
+            usage();
+        };
+        return Self {
+            model,
+            report,
+            cache_dir,
+            backend: compute,
+        };
+    }
+}
+
+/// Options of the `quantize` command.
+struct QuantizeArgs {
+    /// Trained model to quantize.
+    model: PathBuf,
+    /// File to save the quantized model to.
+    output: PathBuf,
+    /// Directory holding preprocessed dataset caches.
+    cache_dir: Option<PathBuf>,
+}
+
+impl QuantizeArgs {
+    fn parse(mut iter: impl Iterator<Item = String>) -> Self {
+        let (mut model, mut output, mut cache_dir) = (None, None, None);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--model" => model = Some(value(&mut iter).into()),
+                "--output" => output = Some(value(&mut iter).into()),
+                "--cache-dir" => cache_dir = Some(value(&mut iter).into()),
+                _ => usage(),
+            }
+        }
+        let (Some(model), Some(output)) = (model, output) else {
+            usage();
+        };
+        return Self {
+            model,
+            output,
+            cache_dir,
+        };
+    }
+}
+
+/// Options of the `embed` command.
+struct EmbedArgs {
+    /// Trained model to embed with.
+    model: PathBuf,
+    /// Directory searched recursively for images.
+    input: PathBuf,
+    /// `.npy` file to write one embedding per row to.
+    output: PathBuf,
+}
+
+impl EmbedArgs {
+    fn parse(mut iter: impl Iterator<Item = String>) -> Self {
+        let (mut model, mut input, mut output) = (None, None, None);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--model" => model = Some(value(&mut iter).into()),
+                "--input" => input = Some(value(&mut iter).into()),
+                "--output" => output = Some(value(&mut iter).into()),
+                _ => usage(),
+            }
+        }
+        let (Some(model), Some(input), Some(output)) = (model, input, output) else {
+            usage();
+        };
+        return Self {
+            model,
+            input,
+            output,
+        };
+    }
+}
+
+/// Options of the `live` command.
+struct LiveArgs {
+    /// Trained model to classify with.
+    model: PathBuf,
+    /// Frame size of the raw RGB stream on stdin.
+    width: u32,
+    height: u32,
+    /// Class names by index; defaults to the class indices.
+    classes: Option<Vec<String>>,
+}
+
+impl LiveArgs {
+    fn parse(mut iter: impl Iterator<Item = String>) -> Self {
+        let (mut model, mut width, mut height, mut classes) = (None, None, None, None);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--model" => model = Some(value(&mut iter).into()),
+                "--width" => width = Some(number(&mut iter)),
+                "--height" => height = Some(number(&mut iter)),
+                "--classes" => classes = Some(class_names(&mut iter)),
+                _ => usage(),
+            }
+        }
+        let (Some(model), Some(width), Some(height)) = (model, width, height) else {
+            usage();
+        };
+        if width == 0 || height == 0 {
+            usage();
+        }
+        return Self {
+            model,
+            width,
+            height,
+            classes,
+        };
+    }
+}
 
+/// Options of the `pack` command.
+struct PackArgs {
+    /// Root of an ImageFolder-style dataset.
+    input: PathBuf,
+    /// Pack file to write.
+    output: PathBuf,
+    /// Resolution, channels and resizing the images are preprocessed with.
+    preprocess: Preprocess,
+}
+
+impl PackArgs {
+    fn parse(mut iter: impl Iterator<Item = String>) -> Self {
+        let (mut input, mut output) = (None, None);
+        let mut data = DataArgs::new();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--input" => input = Some(value(&mut iter).into()),
+                "--output" => output = Some(value(&mut iter).into()),
+                flag => {
+                    if !data.parse_flag(flag, &mut iter) {
+                        usage();
+                    }
+                }
+            }
+        }
+        let (Some(input), Some(output)) = (input, output) else {
+            usage();
+        };
+        return Self {
+            input,
+            output,
+            preprocess: data.config().preprocess,
+        };
+    }
+}
+
+/// Options of the `import` command.
+struct ImportArgs {
+    /// PyTorch state dict as `.safetensors` or `.npz`.
+    weights: PathBuf,
+    /// File to save the imported model to.
+    output: PathBuf,
+    /// Class names by index; defaults to those of the test images with
+    /// `--evaluate`, and to the class indices otherwise.
+    classes: Option<Vec<String>>,
+    /// Evaluate the imported model on the test images.
+    evaluate: bool,
+    data: DataArgs,
+}
+
+impl ImportArgs {
+    fn parse(mut iter: impl Iterator<Item = String>) -> Self {
+        let (mut weights, mut output, mut classes) = (None, None, None);
+        let mut evaluate = false;
+        let mut data = DataArgs::new();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--weights" => weights = Some(value(&mut iter).into()),
+                "--output" => output = Some(value(&mut iter).into()),
+                "--classes" => classes = Some(class_names(&mut iter)),
+                "--evaluate" => evaluate = true,
+                flag => {
+                    if !data.parse_flag(flag, &mut iter) {
+                        usage();
+                    }
+                }
+            }
+        }
+        let (Some(weights), Some(output)) = (weights, output) else {
+            usage();
+        };
+        return Self {
+            weights,
+            output,
+            classes,
+            evaluate,
+            data,
+        };
+    }
+}
+
+/// Options of the `unpack` command.
+struct UnpackArgs {
+    /// Pack file to read.
+    input: PathBuf,
+    /// Directory to write one folder of images per class to.
+    output: PathBuf,
+}
+
+impl UnpackArgs {
+    fn parse(mut iter: impl Iterator<Item = String>) -> Self {
+        let (mut input, mut output) = (None, None);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--input" => input = Some(value(&mut iter).into()),
+                "--output" => output = Some(value(&mut iter).into()),
+                _ => usage(),
+            }
+        }
+        let (Some(input), Some(output)) = (input, output) else {
+            usage();
+        };
+        return Self { input, output };
+    }
+}
+
+/// Options of the `inspect` command.
+struct InspectArgs {
+    /// Root of an ImageFolder-style dataset.
+    dir: PathBuf,
+}
+
+impl InspectArgs {
+    fn parse(mut iter: impl Iterator<Item = String>) -> Self {
+        let (Some(dir), None) = (iter.next(), iter.next()) else {
+            usage();
+        };
+        if dir.starts_with("--") {
+            usage();
+        }
+        return Self { dir: dir.into() };
+    }
+}
+
+/// Options of the `dedup` command.
+struct DedupArgs {
+    /// Directory searched for images, recursively.
+    dir: PathBuf,
+    /// Largest perceptual hash distance of near-duplicates.
+    max_distance: u32,
+    /// Delete all but the first file of every group.
+    remove: bool,
+}
+
+impl DedupArgs {
+    fn parse(mut iter: impl Iterator<Item = String>) -> Self {
+        let mut dir = None;
+        let mut max_distance = DuplicateGroup::DEFAULT_MAX_DISTANCE;
+        let mut remove = false;
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--max-distance" => max_distance = number(&mut iter),
+                "--remove" => remove = true,
+                flag if flag.starts_with("--") => usage(),
+                _ if dir.is_some() => usage(),
+                path => dir = Some(PathBuf::from(path)),
+            }
+        }
+        let Some(dir) = dir else {
+            usage();
+        };
+        return Self {
+            dir,
+            max_distance,
+            remove,
+        };
+    }
+}
+
+/// Options of the `serve` command.
+#[cfg(feature = "serve")]
+struct ServeArgs {
+    /// Trained model to classify with.
+    model: PathBuf,
+    /// Address to listen on.
+    host: String,
+    port: u16,
+    /// Class names by index; defaults to the class indices.
+    classes: Option<Vec<String>>,
+}
+
+#[cfg(feature = "serve")]
+impl ServeArgs {
+    fn parse(mut iter: impl Iterator<Item = String>) -> Self {
+        let (mut model, mut classes) = (None, None);
+        let (mut host, mut port) = ("127.0.0.1".to_string(), 8080);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--model" => model = Some(value(&mut iter).into()),
+                "--host" => host = value(&mut iter),
+                "--port" => port = number(&mut iter),
+                "--classes" => classes = Some(class_names(&mut iter)),
+                _ => usage(),
+            }
+        }
+        let Some(model) = model else {
+            usage();
+        };
+        return Self {
+            model,
+            host,
+            port,
+            classes,
+        };
+    }
+}
+
+enum Command {
+    Train(Box<TrainArgs>),
+    Tune(TuneArgs),
+    Saliency(SaliencyArgs),
+    PredictDir(PredictDirArgs),
+    Evaluate(EvaluateArgs),
+    Quantize(QuantizeArgs),
+    Embed(EmbedArgs),
+    Live(LiveArgs),
+    Pack(PackArgs),
+    Unpack(UnpackArgs),
+    Import(ImportArgs),
+    Inspect(InspectArgs),
+    Dedup(DedupArgs),
+    #[cfg(feature = "serve")]
+    Serve(ServeArgs),
+}
+
+impl Command {
+    /// Parses the command line. Without a command name, `train` is assumed.
+    fn parse() -> Self {
+        let mut iter = std::env::args().skip(1).peekable();
+        match iter.peek().map(String::as_str) {
+            Some("tune") => {
+                iter.next();
+                return Command::Tune(TuneArgs::parse(iter));
+            }
+            Some("saliency") => {
+                iter.next();
+                return Command::Saliency(SaliencyArgs::parse(iter));
+            }
+            Some("predict-dir") => {
+                iter.next();
+                return Command::PredictDir(PredictDirArgs::parse(iter));
+            }
+            Some("evaluate") => {
+                iter.next();
+                return Command::Evaluate(EvaluateArgs::parse(iter));
+            }
+            Some("quantize") => {
+                iter.next();
+                return Command::Quantize(QuantizeArgs::parse(iter));
+            }
+            Some("embed") => {
+                iter.next();
+                return Command::Embed(EmbedArgs::parse(iter));
+            }
+            Some("live") => {
+                iter.next();
+                return Command::Live(LiveArgs::parse(iter));
+            }
+            Some("pack") => {
+                iter.next();
+                return Command::Pack(PackArgs::parse(iter));
+            }
+            Some("unpack") => {
+                iter.next();
+                return Command::Unpack(UnpackArgs::parse(iter));
+            }
+            Some("import") => {
+                iter.next();
+                return Command::Import(ImportArgs::parse(iter));
+            }
+            Some("inspect") => {
+                iter.next();
+                return Command::Inspect(InspectArgs::parse(iter));
+            }
+            Some("dedup") => {
+                iter.next();
+                return Command::Dedup(DedupArgs::parse(iter));
+            }
+            #[cfg(feature = "serve")]
+            Some("serve") => {
+                iter.next();
+                return Command::Serve(ServeArgs::parse(iter));
+            }
+            #[cfg(not(feature = "serve"))]
+            Some("serve") => {
+                eprintln!("antbee-rs was built without the `serve` feature");
+                exit(2);
+            }
+            Some("train") => {
+                iter.next();
+                return Command::Train(Box::new(TrainArgs::parse(iter)));
+            }
+            _ => return Command::Train(Box::new(TrainArgs::parse(iter))),
+        }
+    }
+}
+
+/// Loads the `name` split of the dataset, going through `cache_dir` if given.
+///
+/// A missing cache file is created after decoding the images, so only the
+/// first run pays for JPEG decoding and resizing.
+///
+/// Cache files are keyed by the preprocessing and precision, so changing
+/// the image size or channel mode never picks up stale tensors, and full
+/// precision runs never read rounded ones. They are not keyed by the
+/// dataset folder, so give each dataset its own cache directory. With
+/// `--mmap`, caches are memory-mapped rather than read, see
+/// [`Dataset::map_cache`].
+fn load_dataset(name: &str, data: &DataConfig) -> Dataset {
+    let load = || {
+        let dataset_dir = match &data.dataset_dir {
+            Some(dir) => dir.clone(),
+            None => PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("dataset"),
+        };
+        let dataset = Dataset::from_dataset_path_with(&dataset_dir.join(name), data.preprocess)
+            .unwrap_or_else(|err| {
+                eprintln!("failed to load the {} dataset: {}", name, err);
+                exit(1);
+            });
+        return dataset.with_precision(data.precision);
+    };
+    let Some(cache_dir) = &data.cache_dir else {
+        return load();
+    };
+
+    let suffix = match data.precision {
+        Precision::F32 => "",
+        Precision::F16 => "-f16",
+    };
+    let cache_path = cache_dir.join(format!("{}_{}{}.bin", name, data.preprocess, suffix));
+    let read_cache = || {
+        let dataset = if data.mmap {
+            Dataset::map_cache(&cache_path)
+        } else {
+            Dataset::from_cache(&cache_path)
+        };
+        return dataset.expect("failed to read dataset cache");
+    };
+    if cache_path.exists() {
+        return read_cache();
+    }
+
+    let dataset = load();
+    create_dir_all(cache_dir).expect("failed to create cache directory");
+    dataset
+        .to_cache(&cache_path)
+        .expect("failed to write dataset cache");
+    if data.mmap {
+        // Release the decoded images in favor of the mapped cache.
+        return read_cache();
+    }
+    return dataset;
+}
+
+/// Training, validation and test splits of a dataset.
+struct Splits {
+    train: Dataset,
+    val: Dataset,
+    test: Dataset,
+}
+
+/// Loads the train and test folders and holds out part of the training
+/// folder (chosen by `seed`) for validation.
+fn load_splits(data: &DataConfig, seed: u64) -> Splits {
     println!("loading train dataset");
-    let train_dataset = antbee::Dataset::from_dataset_path(&dataset_dir.join("train"));
+    let full_train_dataset = load_dataset("train", data);
+
+    println!("loading test dataset");
+    let test_dataset = load_dataset("val", data);
+    assert_eq!(
+        full_train_dataset.classes(),
+        test_dataset.classes(),
+        "train and test datasets have different classes"
+    );
+    println!("classes: {}", full_train_dataset.classes().join(", "));
+
+    // Hold out part of the training folder for early stopping so that the
+    // test set stays unseen until the final evaluation.
+    let (train_dataset, val_dataset) = full_train_dataset.split(0.8, seed);
+    println!(
+        "split {} training images into {} train / {} validation",
+        full_train_dataset.len(),
+        train_dataset.len(),
+        val_dataset.len()
+    );
+    return Splits {
+        train: train_dataset,
+        val: val_dataset,
+        test: test_dataset,
+    };
+}
+
+/// Creates an untrained model for `train`, with conv blocks of the given
+/// channels if any, normalized by its statistics when `normalize` is set.
+///
+/// The weights are drawn from `seed`, so a config file's `seed` reproduces
+/// the initialization along with the rest of the run.
+fn initial_model(
+    preprocess: Preprocess,
+    train: &Dataset,
+    normalize: bool,
+    conv: Option<&[usize]>,
+    seed: u64,
+) -> Model {
+    let rng = &mut ChaCha8Rng::seed_from_u64(seed);
+    let mut model = match conv {
+        Some(channels) => Model::with_conv(preprocess, channels, train.num_classes(), rng),
+        None => Model::from_rng(preprocess, train.num_classes(), rng),
+    };
+    if normalize {
+        let normalizer = Normalizer::fit(train);
+        println!(
+            "normalizing with mean {:?}, std {:?}",
+            normalizer.mean(),
+            normalizer.std()
+        );
+        model.set_normalizer(Some(normalizer));
+    }
+    return model;
+}
+
+fn test_model(model: &Model, dataset: &Dataset) {
+    let roc = (model.num_classes() == 2).then(|| model.roc_curve(dataset, Kind(1)));
+    let reliability = model.reliability_diagram(dataset, ReliabilityDiagram::DEFAULT_BINS);
+    print_test_results(&model.confusion_matrix(dataset), roc, &reliability, dataset);
+}
+
+fn test_ensemble(ensemble: &Ensemble, dataset: &Dataset) {
+    let roc = (ensemble.num_classes() == 2).then(|| ensemble.roc_curve(dataset, Kind(1)));
+    let reliability = ensemble.reliability_diagram(dataset, ReliabilityDiagram::DEFAULT_BINS);
+    print_test_results(
+        &ensemble.confusion_matrix(dataset),
+        roc,
+        &reliability,
+        dataset,
+    );
+}
+
+fn print_test_results(
+    matrix: &ConfusionMatrix,
+    roc: Option<RocCurve>,
+    reliability: &ReliabilityDiagram,
+    dataset: &Dataset,
+) {
+    println!("Test Accuracy: {:.2}%", matrix.accuracy() * 100.0);
+    for (index, class) in dataset.classes().iter().enumerate() {
+        println!(
+            "  {:>10} recall: {:.2}%",
+            class,
+            matrix.recall(Kind(index)) * 100.0
+        );
+    }
+    if let Some(roc) = roc {
+        println!("Test AUC: {:.4}", roc.auc());
+    }
+    println!(
+        "Test ECE: {:.4} (mean confidence {:.2}%)",
+        reliability.expected_calibration_error(),
+        reliability.mean_confidence() * 100.0
+    );
+}
+
+fn save_report(report: &Report, path: &Path) {
+    report.save(path).expect("failed to write report");
+    println!(
+        "wrote {} predictions to {} ({} failed)",
+        report.samples.len(),
+        path.display(),
+        report.failures.len()
+    );
+}
+
+fn save_model(model: &Model, path: &Path) {
+    model.save(path).expect("failed to save model");
+    println!("saved model to {}", path.display());
+}
+
+fn train(args: TrainArgs) {
+    select_backend(args.backend);
+    let run = match &args.config {
+        Some(path) => RunConfig::load_toml(path).unwrap_or_else(|err| {
+            eprintln!("failed to load config: {}", err);
+            exit(2);
+        }),
+        // Ctrl+C finishes the batch and checkpoints instead of losing the
+        // run; a config file decides this with `handle_signals`.
+        None => RunConfig {
+            train: TrainConfig {
+                patience: Some(20),
+                handle_signals: true,
+                ..TrainConfig::default()
+            },
+            ..RunConfig::default()
+        },
+    };
+    // Options given on the command line override the config file.
+    let data = args.data.apply(run.data);
+    let model_config = ModelConfig {
+        conv: args.conv.clone().or(run.model.conv),
+        normalize: args.normalize || run.model.normalize,
+    };
+    if args.init_model.is_some() && model_config != ModelConfig::default() {
+        eprintln!("a model to fine-tune brings its own architecture, leave out the [model] table");
+        exit(2);
+    }
+    let mut config = run.train;
+    if let Some(dir) = &args.checkpoint_dir {
+        config.checkpoint_dir = Some(dir.clone());
+    }
+    if let Some(path) = &args.metrics {
+        config.metrics_path = Some(path.clone());
+    }
+    if let Some(dir) = &args.tensorboard {
+        config.tensorboard_dir = Some(dir.clone());
+    }
+    if let Some(batch_size) = args.batch_size {
+        config.batch_size = batch_size;
+    }
+    if let Some(steps) = args.accumulation_steps {
+        config.accumulation_steps = steps;
+    }
+    if let Some(threads) = args.threads {
+        config.num_threads = threads;
+    }
+    if let Some(loss) = &args.loss {
+        config.loss = loss.clone();
+    }
+    if let Some(smoothing) = args.label_smoothing {
+        config.label_smoothing = smoothing;
+    }
+    if let Some(sampler) = &args.sampler {
+        config.sampler = sampler.clone();
+    }
+    if let Some(max) = args.clip_norm {
+        config.max_grad_norm = Some(max);
+    }
+    if let Some(policy) = args.on_divergence {
+        config.on_divergence = policy;
+    }
+    if let Some(p) = args.dropout {
+        config.input_dropout = p;
+    }
+    if let Some(std) = args.input_noise {
+        config.input_noise = std;
+    }
+    let splits = load_splits(&data, config.seed);
+
+    if args.balance_classes {
+        config.class_weights = Some(splits.train.balanced_class_weights());
+    }
+
+    if let Some(size) = args.ensemble {
+        train_ensemble(&args, size, &splits, &config, &data, &model_config);
+        return;
+    }
 
     println!("starting training");
-    let mut model = antbee::Model::new();
-    train_model(&mut model, &train_dataset);
+    let mut trainer = Trainer::new(config);
+    let (mut model, report) = match args.resume {
+        Some(path) => {
+            let checkpoint = Checkpoint::load(&path).expect("failed to load checkpoint");
+            trainer.resume(checkpoint, &splits.train, &splits.val)
+        }
+        None => {
+            let mut model = match &args.init_model {
+                Some(path) => {
+                    let model = Model::load(path).expect("failed to load model");
+                    if *model.preprocess() != data.preprocess {
+                        eprintln!(
+                            "{} expects {} images, pass the same data options",
+                            path.display(),
+                            model.preprocess()
+                        );
+                        exit(2);
+                    }
+                    model
+                }
+                None => initial_model(
+                    data.preprocess,
+                    &splits.train,
+                    model_config.normalize,
+                    model_config.conv.as_deref(),
+                    trainer.config().seed,
+                ),
+            };
+            trainer
+                .fit(&mut model, &splits.train, &splits.val)
+                .map(|report| (model, report))
+        }
+    }
+    .expect("training failed");
+    if report.interrupted {
+        exit_interrupted(trainer.config());
+    }
+    println!(
+        "restored weights from epoch {} (val_loss={:.4})",
+        report.best_epoch, report.best_val_loss
+    );
 
-    println!("loading test dataset");
-    let test_dataset = antbee::Dataset::from_dataset_path(&dataset_dir.join("val"));
+    // Calibrate first: the threshold applies to the calibrated probabilities.
+    if args.calibrate {
+        let before = model.reliability_diagram(&splits.val, ReliabilityDiagram::DEFAULT_BINS);
+        let temperature = model.calibrate(&splits.val);
+        let after = model.reliability_diagram(&splits.val, ReliabilityDiagram::DEFAULT_BINS);
+        println!(
+            "calibrated temperature {:.4} (val ECE {:.4} -> {:.4})",
+            temperature,
+            before.expected_calibration_error(),
+            after.expected_calibration_error()
+        );
+    }
+
+    if args.tune_threshold {
+        let threshold = model.find_best_threshold(&splits.val, ThresholdMetric::F1);
+        model.set_threshold(Some(threshold));
+        println!("decision threshold tuned to {:.4}", threshold);
+    }
+
+    println!("starting testing");
+    test_model(&model, &splits.test);
+
+    if let Some(path) = &args.report {
+        let mut report = Report::evaluate(&model, &splits.test);
+        report.model_path = args.save_model.clone();
+        save_report(&report, path);
+    }
+
+    if let Some(dir) = args.review_dir {
+        let samples = model.misclassified(&splits.test);
+        let copied = copy_misclassified(&samples, model.class_map(), &dir)
+            .expect("failed to copy misclassified images");
+        println!(
+            "copied {} misclassified test images to {}",
+            copied,
+            dir.display()
+        );
+    }
+
+    if let Some(path) = args.save_model {
+        save_model(&model, &path);
+    }
+
+    if let Some(path) = args.export_onnx {
+        model
+            .export_onnx(&path)
+            .expect("failed to export ONNX model");
+        println!("exported ONNX model to {}", path.display());
+    }
+
+    if let Some(path) = args.export_npz {
+        model
+            .save_npz(&path)
+            .expect("failed to export NumPy weights");
+        println!("exported weights to {}", path.display());
+    }
+}
+
+/// Ends a training run stopped by Ctrl+C or SIGTERM, with the exit status
+/// of a shell after Ctrl+C.
+fn exit_interrupted(config: &TrainConfig) -> ! {
+    match &config.checkpoint_dir {
+        Some(dir) => eprintln!(
+            "training interrupted; continue with --resume and the last checkpoint in {}",
+            dir.display()
+        ),
+        None => eprintln!("training interrupted; pass --checkpoint-dir to be able to resume"),
+    }
+    exit(130);
+}
+
+fn train_ensemble(
+    args: &TrainArgs,
+    size: usize,
+    splits: &Splits,
+    config: &TrainConfig,
+    data: &DataConfig,
+    model_config: &ModelConfig,
+) {
+    println!("starting training of {} ensemble members", size);
+    let normalizer = model_config
+        .normalize
+        .then(|| Normalizer::fit(&splits.train));
+    let num_classes = splits.train.num_classes();
+    let (ensemble, reports) = Ensemble::fit(
+        size,
+        |rng| {
+            let mut model = match &model_config.conv {
+                Some(channels) => Model::with_conv(data.preprocess, channels, num_classes, rng),
+                None => Model::from_rng(data.preprocess, num_classes, rng),
+            };
+            model.set_normalizer(normalizer.clone());
+            model
+        },
+        &splits.train,
+        &splits.val,
+        config,
+    )
+    .expect("training failed");
+    if reports.iter().any(|report| report.interrupted) {
+        exit_interrupted(config);
+    }
+    println!(
+        "ensemble validation accuracy: {:.2}%",
+        ensemble.evaluate(&splits.val) * 100.0
+    );
 
     println!("starting testing");
-    test_model(&model, &test_dataset);
+    test_ensemble(&ensemble, &splits.test);
+
+    if let Some(path) = &args.save_model {
+        ensemble.save(path).expect("failed to save ensemble");
+        println!("saved ensemble to {}", path.display());
+    }
+}
+
+fn tune(args: TuneArgs) {
+    let mut config = TrainConfig {
+        patience: Some(20),
+        show_progress: false,
+        ..TrainConfig::default()
+    };
+    if let Some(epochs) = args.epochs {
+        config.epochs = epochs;
+    }
+    let data = args.data.config();
+    let splits = load_splits(&data, config.seed);
+    let initial = initial_model(
+        data.preprocess,
+        &splits.train,
+        args.normalize,
+        args.conv.as_deref(),
+        config.seed,
+    );
+
+    let tune_config = TuneConfig {
+        strategy: if args.grid {
+            SearchStrategy::Grid
+        } else {
+            SearchStrategy::Random {
+                trials: args.trials,
+            }
+        },
+        seed: config.seed,
+        log_path: args.log,
+        ..TuneConfig::default()
+    };
+    let report = antbee::tune(&initial, &splits.train, &splits.val, &config, &tune_config)
+        .expect("tuning failed");
+
+    let best = report.best();
+    println!(
+        "best trial {}: lr={}, batch_size={}, l2={} (val_loss={:.4}, val_acc={:.2}%)",
+        best.index,
+        best.params.learning_rate,
+        best.params.batch_size,
+        best.params.l2,
+        best.fit.best_val_loss,
+        best.val_accuracy * 100.0
+    );
+
+    println!("testing best model");
+    test_model(&report.best_model, &splits.test);
+
+    if let Some(path) = args.save_model {
+        save_model(&report.best_model, &path);
+    }
+}
+
+fn saliency(args: SaliencyArgs) {
+    let model = Model::load(&args.model).expect("failed to load model");
+    let x = model
+        .preprocess()
+        .load_image(&args.image)
+        .expect("failed to load image");
+    let class = match args.class {
+        Some(index) if index < model.num_classes() => Kind(index),
+        Some(_) => usage(),
+        None => model.predict_with_probability(x.view()).kind,
+    };
+    model
+        .saliency_map(x.view(), class)
+        .save(&args.output)
+        .expect("failed to write saliency map");
+    println!(
+        "wrote saliency map of class {} to {}",
+        class.index(),
+        args.output.display()
+    );
+}
+
+/// Quotes `field` for CSV if it contains a separator, quote or line break.
+fn csv_field(field: &str) -> String {
+    if !field.contains([',', '"', '\n', '\r']) {
+        return field.to_string();
+    }
+    return format!("\"{}\"", field.replace('"', "\"\""));
+}
+
+fn predict_dir(args: PredictDirArgs) {
+    let model = Model::load(&args.model).expect("failed to load model");
+    let classes = class_names_or_saved(args.classes, &model);
+    let paths = antbee::find_images(&args.input).expect("failed to list images");
+    println!("classifying {} images", paths.len());
+    if args.output.extension().is_some_and(|ext| ext == "json") {
+        let mut report = Report::predict_images(&model, &paths, args.tta);
+        report.model_path = Some(args.model);
+        report.classes = ClassMap::new(classes);
+        for failure in &report.failures {
+            eprintln!("skipping {}: {}", failure.path.display(), failure.reason);
+        }
+        save_report(&report, &args.output);
+        return;
+    }
+    let predictions = match args.tta {
+        Some(config) => model.predict_images_tta(&paths, config),
+        None => model.predict_images(&paths),
+    };
+
+    let mut writer = BufWriter::new(File::create(&args.output).expect("failed to create output"));
+    writeln!(writer, "path,class,probability").expect("failed to write output");
+    let mut failed = 0;
+    for (path, prediction) in paths.iter().zip(predictions) {
+        match prediction {
+            Ok(prediction) => writeln!(
+                writer,
+                "{},{},{}",
+                csv_field(&path.to_string_lossy()),
+                csv_field(&classes[prediction.kind.index()]),
+                prediction.probability
+            )
+            .expect("failed to write output"),
+            Err(err) => {
+                eprintln!("skipping {}: {}", path.display(), err);
+                failed += 1;
+            }
+        }
+    }
+    writer.flush().expect("failed to write output");
+    println!(
+        "wrote {} predictions to {} ({} failed)",
+        paths.len() - failed,
+        args.output.display(),
+        failed
+    );
+}
+
+fn evaluate(args: EvaluateArgs) {
+    select_backend(args.backend);
+    let model = Model::load(&args.model).expect("failed to load model");
+    let data = DataConfig {
+        cache_dir: args.cache_dir,
+        preprocess: *model.preprocess(),
+        ..DataConfig::default()
+    };
+    println!("loading test dataset");
+    let test = load_dataset("val", &data);
+    test_model(&model, &test);
+
+    if let Some(path) = &args.report {
+        let mut report = Report::evaluate(&model, &test);
+        report.model_path = Some(args.model);
+        save_report(&report, path);
+    }
+}
+
+fn quantize(args: QuantizeArgs) {
+    let model = Model::load(&args.model).expect("failed to load model");
+    let quantized = model.quantize();
+
+    let data = DataConfig {
+        cache_dir: args.cache_dir,
+        preprocess: *model.preprocess(),
+        ..DataConfig::default()
+    };
+    println!("loading test dataset");
+    let test = load_dataset("val", &data);
+    let report = quantized.compare(&model, &test);
+    println!(
+        "Test Accuracy: {:.2}% float, {:.2}% int8 ({:+.2} points)",
+        report.float_accuracy * 100.0,
+        report.quantized_accuracy * 100.0,
+        report.accuracy_delta() * 100.0
+    );
+    println!("Prediction agreement: {:.2}%", report.agreement * 100.0);
+    println!(
+        "Probability error: mean {:.5}, max {:.5}",
+        report.mean_probability_error, report.max_probability_error
+    );
+
+    quantized
+        .save(&args.output)
+        .expect("failed to save quantized model");
+    let size = |path: &Path| metadata(path).map_or(0, |metadata| metadata.len());
+    println!(
+        "saved quantized model to {} ({} bytes, float model {} bytes)",
+        args.output.display(),
+        size(&args.output),
+        size(&args.model)
+    );
+}
+
+/// Writes the embeddings of the images below `args.input` to `args.output`,
+/// and their paths, one per row, to the same path with a `.txt` extension.
+fn embed(args: EmbedArgs) {
+    let model = Model::load(&args.model).expect("failed to load model");
+    let paths = antbee::find_images(&args.input).expect("failed to list images");
+    println!("embedding {} images", paths.len());
+    let mut embedded = Vec::new();
+    let mut values = Vec::new();
+    for (path, embedding) in paths.iter().zip(model.embed_images(&paths)) {
+        match embedding {
+            Ok(embedding) => {
+                embedded.push(path);
+                values.extend(embedding);
+            }
+            Err(err) => eprintln!("skipping {}: {}", path.display(), err),
+        }
+    }
+    let embeddings = Array2::from_shape_vec((embedded.len(), model.feature_dim()), values)
+        .expect("embeddings have the feature dimension");
+    antbee::save_npy(&args.output, embeddings.view().into_dyn())
+        .expect("failed to write embeddings");
+
+    let paths_file = args.output.with_extension("txt");
+    let mut writer = BufWriter::new(File::create(&paths_file).expect("failed to create output"));
+    for path in &embedded {
+        writeln!(writer, "{}", path.display()).expect("failed to write output");
+    }
+    writer.flush().expect("failed to write output");
+    println!(
+        "wrote {} embeddings of dimension {} to {} (paths in {})",
+        embedded.len(),
+        model.feature_dim(),
+        args.output.display(),
+        paths_file.display()
+    );
+}
+
+/// Classifies a stream of raw RGB frames (rgb24, `args.width` x
+/// `args.height`) read from stdin, printing the prediction for each frame
+/// in place until the stream ends.
+///
+/// Any camera works through a capture tool writing raw video to stdout, e.g.
+/// `ffmpeg -f v4l2 -i /dev/video0 -vf scale=320:240 -pix_fmt rgb24 -f rawvideo -`
+/// for `--width 320 --height 240`.
+fn live(args: LiveArgs) {
+    let model = Model::load(&args.model).expect("failed to load model");
+    let classes = class_names_or_saved(args.classes, &model);
+    let mut frame = vec![0u8; args.width as usize * args.height as usize * 3];
+    let mut input = stdin().lock();
+    let mut stdout = stdout().lock();
+    let mut frames = 0u64;
+    let mut fps = 0.0;
+    let mut last = Instant::now();
+    loop {
+        match input.read_exact(&mut frame) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+            Err(err) => panic!("failed to read frame: {}", err),
+        }
+        let prediction = model
+            .predict_from_rgb(&frame, args.width, args.height)
+            .expect("frame has the size given on the command line");
+        // Smooth the frame rate over about ten frames.
+        let elapsed = last.elapsed().as_secs_f64();
+        last = Instant::now();
+        fps = if frames == 0 {
+            0.0
+        } else if fps == 0.0 {
+            1.0 / elapsed
+        } else {
+            0.9 * fps + 0.1 / elapsed
+        };
+        frames += 1;
+        write!(
+            stdout,
+            "\r{:<20} {:6.2}%  {:5.1} fps",
+            classes[prediction.kind.index()],
+            prediction.probability * 100.0,
+            fps
+        )
+        .and_then(|()| stdout.flush())
+        .expect("failed to write prediction");
+    }
+    println!("\nclassified {} frames", frames);
+}
+
+fn pack(args: PackArgs) {
+    let dataset =
+        Dataset::from_dataset_path_with(&args.input, args.preprocess).unwrap_or_else(|err| {
+            eprintln!("failed to load {}: {}", args.input.display(), err);
+            exit(1);
+        });
+    dataset.to_pack(&args.output).expect("failed to write pack");
+    println!(
+        "packed {} images ({}) of {} classes into {} ({} bytes)",
+        dataset.len(),
+        args.preprocess,
+        dataset.num_classes(),
+        args.output.display(),
+        metadata(&args.output).map_or(0, |metadata| metadata.len())
+    );
+}
+
+fn unpack(args: UnpackArgs) {
+    let dataset = Dataset::from_pack(&args.input).expect("failed to read pack");
+    let written = dataset
+        .save_images(&args.output)
+        .expect("failed to write images");
+    println!(
+        "unpacked {} images ({}) into {}",
+        written,
+        dataset.preprocess(),
+        args.output.display()
+    );
+}
+
+fn import(args: ImportArgs) {
+    let data = args.data.config();
+    let mut model = Model::from_state_dict(&args.weights, data.preprocess).unwrap_or_else(|err| {
+        eprintln!("failed to import {}: {}", args.weights.display(), err);
+        exit(1);
+    });
+    println!(
+        "imported a linear layer of {} classes on {} images",
+        model.num_classes(),
+        model.preprocess()
+    );
+    if let Some(names) = args.classes {
+        if names.len() != model.num_classes() {
+            eprintln!(
+                "{} class names given for {} classes",
+                names.len(),
+                model.num_classes()
+            );
+            exit(2);
+        }
+        model.set_class_map(ClassMap::new(names));
+    }
+    if args.evaluate {
+        let test = load_dataset("val", &data);
+        if model.class_map() == &ClassMap::indices(model.num_classes()) {
+            model.set_class_map(test.class_map().clone());
+        }
+        test_model(&model, &test);
+    }
+    save_model(&model, &args.output);
+}
+
+fn inspect(args: InspectArgs) {
+    if !args.dir.is_dir() {
+        eprintln!("{} is not a directory", args.dir.display());
+        exit(2);
+    }
+    let stats = Dataset::stats(&args.dir);
+    println!(
+        "{} images in {} classes",
+        stats.total_images(),
+        stats.classes.len()
+    );
+    for class in &stats.classes {
+        println!(
+            "  {:<20} {:>6} ({:.1}%)",
+            class.name,
+            class.images,
+            class.images as f32 / stats.total_images().max(1) as f32 * 100.0
+        );
+    }
+    println!(
+        "pixel values: min {:.3}, max {:.3}, mean {:.3}",
+        stats.min_pixel, stats.max_pixel, stats.mean_pixel
+    );
+    if !stats.suspicious.is_empty() {
+        println!("{} suspicious images:", stats.suspicious.len());
+        for issue in &stats.suspicious {
+            println!("  {}: {}", issue.path.display(), issue.reason);
+        }
+    }
+    if !stats.failed.is_empty() {
+        println!("{} files failed to decode:", stats.failed.len());
+        for issue in &stats.failed {
+            println!("  {}: {}", issue.path.display(), issue.reason);
+        }
+        exit(1);
+    }
+}
+
+fn dedup(args: DedupArgs) {
+    if !args.dir.is_dir() {
+        eprintln!("{} is not a directory", args.dir.display());
+        exit(2);
+    }
+    let paths = antbee::find_images(&args.dir).expect("failed to list images");
+    let groups =
+        antbee::find_duplicate_images(&paths, args.max_distance).expect("failed to hash images");
+    for group in &groups {
+        println!("{}:", if group.exact { "identical" } else { "similar" });
+        for (rank, &index) in group.indices.iter().enumerate() {
+            let action = match (rank, args.remove) {
+                (0, _) => "keep",
+                (_, true) => "remove",
+                (_, false) => "",
+            };
+            println!("  {:<6} {}", action, paths[index].display());
+        }
+    }
+    let duplicates: usize = groups.iter().map(|group| group.indices.len() - 1).sum();
+    println!(
+        "{} duplicates in {} groups among {} images",
+        duplicates,
+        groups.len(),
+        paths.len()
+    );
+    if args.remove {
+        for group in &groups {
+            for &index in &group.indices[1..] {
+                remove_file(&paths[index]).expect("failed to remove duplicate");
+            }
+        }
+        println!("removed {} files", duplicates);
+    }
+}
+
+#[cfg(feature = "serve")]
+fn serve(args: ServeArgs) {
+    let model = Model::load(&args.model).expect("failed to load model");
+    let classes = class_names_or_saved(args.classes, &model);
+    antbee::serve(&model, &classes, (args.host.as_str(), args.port)).expect("server failed");
+}
+
+fn main() {
+    match Command::parse() {
+        Command::Train(args) => train(*args),
+        Command::Tune(args) => tune(args),
+        Command::Saliency(args) => saliency(args),
+        Command::PredictDir(args) => predict_dir(args),
+        Command::Evaluate(args) => evaluate(args),
+        Command::Quantize(args) => quantize(args),
+        Command::Embed(args) => embed(args),
+        Command::Live(args) => live(args),
+        Command::Pack(args) => pack(args),
+        Command::Unpack(args) => unpack(args),
+        Command::Import(args) => import(args),
+        Command::Inspect(args) => inspect(args),
+        Command::Dedup(args) => dedup(args),
+        #[cfg(feature = "serve")]
+        Command::Serve(args) => serve(args),
+    }
 }
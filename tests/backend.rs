@@ -0,0 +1,20 @@
+use antbee_rs::antbee::Backend;
+use antbee_rs::antbee::Error;
+
+#[test]
+fn gpu_falls_back_to_cpu_when_unavailable() {
+    assert_eq!(Backend::current(), Backend::Cpu);
+    assert_eq!(Backend::default(), Backend::Cpu);
+
+    // Without the gpu feature, or without an OpenCL device, selecting the
+    // GPU fails and keeps the CPU backend.
+    match Backend::Gpu.select() {
+        Ok(()) => assert_eq!(Backend::current(), Backend::Gpu),
+        Err(err) => {
+            assert!(matches!(err, Error::Unsupported(_)), "{}", err);
+            assert_eq!(Backend::current(), Backend::Cpu);
+        }
+    }
+    Backend::Cpu.select().unwrap();
+    assert_eq!(Backend::current(), Backend::Cpu);
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use antbee_rs::antbee::Dataset;
+use antbee_rs::antbee::DatasetSource;
+use antbee_rs::antbee::Error;
+use antbee_rs::antbee::Kind;
+use common::TempFile;
+use half::f16;
+use std::fs::write;
+use std::path::PathBuf;
+
+/// Offset of the sample count: header, preprocessing and the two class
+/// names `ant` and `bee`.
+const LEN_AT: usize = 12 + 16 + 8 + 2 * (8 + 3);
+
+/// A version 5 cache of grayscale 2x1 images of two classes, with `len`
+/// and `dim` as the sample count and size, followed by `rest`.
+fn cache(len: u64, dim: u64, rest: &[u8]) -> Vec<u8> {
+    let mut bytes = b"ANTBEEDS".to_vec();
+    bytes.extend_from_slice(&5u32.to_le_bytes());
+    for value in [2u32, 1, 1, 0] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes.extend_from_slice(&2u64.to_le_bytes());
+    for name in ["ant", "bee"] {
+        bytes.extend_from_slice(&(name.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+    }
+    assert_eq!(bytes.len(), LEN_AT);
+    bytes.extend_from_slice(&len.to_le_bytes());
+    bytes.extend_from_slice(&dim.to_le_bytes());
+    bytes.extend_from_slice(rest);
+    return bytes;
+}
+
+/// Labels bee and ant, `paths` if given, and `f16` features with the
+/// stored value count `count`.
+fn samples(paths: Option<&[&str]>, count: u64) -> Vec<u8> {
+    let mut rest = Vec::new();
+    for label in [1u32, 0] {
+        rest.extend_from_slice(&label.to_le_bytes());
+    }
+    rest.extend_from_slice(&(paths.is_some() as u32).to_le_bytes());
+    for path in paths.unwrap_or(&[]) {
+        rest.extend_from_slice(&(path.len() as u64).to_le_bytes());
+        rest.extend_from_slice(path.as_bytes());
+    }
+    rest.extend_from_slice(&16u32.to_le_bytes());
+    rest.extend_from_slice(&count.to_le_bytes());
+    for value in [0.0, 0.25, 0.5, 1.0] {
+        rest.extend_from_slice(&f16::from_f32(value).to_le_bytes());
+    }
+    return rest;
+}
+
+fn valid() -> Vec<u8> {
+    return cache(2, 2, &samples(Some(&["b.png", "a.png"]), 4));
+}
+
+#[test]
+fn from_cache_reads_a_written_cache() {
+    let file = TempFile::new("cache-read", "bin");
+    write(&file.path, valid()).unwrap();
+    let dataset = Dataset::from_cache(&file.path).unwrap();
+    assert_eq!(dataset.labels(), &[Kind(1), Kind(0)]);
+    assert_eq!(dataset.path(1).unwrap(), PathBuf::from("a.png"));
+    assert_eq!(
+        dataset.features().as_slice().unwrap(),
+        &[0.0, 0.25, 0.5, 1.0]
+    );
+
+    dataset.to_cache(&file.path).unwrap();
+    let again = Dataset::from_cache(&file.path).unwrap();
+    assert_eq!(again.labels(), dataset.labels());
+    assert_eq!(again.features(), dataset.features());
+}
+
+#[test]
+fn from_cache_rejects_corrupted_headers() {
+    let file = TempFile::new("cache-corrupt", "bin");
+    let load = |bytes: Vec<u8>| {
+        write(&file.path, bytes).unwrap();
+        return Dataset::from_cache(&file.path);
+    };
+
+    let bytes = valid();
+    for len in (0..bytes.len()).step_by(3) {
+        assert!(load(bytes[..len].to_vec()).is_err(), "{} bytes loaded", len);
+    }
+    // A sample count whose feature count overflows.
+    assert!(matches!(
+        load(cache(u64::MAX / 2 + 1, 2, &[])),
+        Err(Error::InvalidFormat(_))
+    ));
+    // Counts and lengths far beyond the data fail at its end instead of
+    // being reserved up front.
+    assert!(load(cache(u64::MAX / 4, 2, &samples(None, 4))).is_err());
+    let mut rest = samples(Some(&["b.png", "a.png"]), 4);
+    rest[12..20].copy_from_slice(&(u64::MAX - 1).to_le_bytes());
+    assert!(matches!(load(cache(2, 2, &rest)), Err(Error::Io(_))));
+
+    assert!(load(valid()).is_ok());
+}
+
+#[test]
+fn map_cache_reads_a_written_cache() {
+    let file = TempFile::new("cache-map", "bin");
+    write(&file.path, valid()).unwrap();
+    let mapped = Dataset::map_cache(&file.path).unwrap();
+    assert!(mapped.is_mapped());
+    let loaded = Dataset::from_cache(&file.path).unwrap();
+    assert_eq!(mapped.labels(), loaded.labels());
+    assert_eq!(mapped.features(), loaded.features());
+}
+
+#[test]
+fn map_cache_rejects_corrupted_sizes() {
+    let file = TempFile::new("cache-map-corrupt", "bin");
+    let map = |bytes: Vec<u8>| {
+        write(&file.path, bytes).unwrap();
+        return Dataset::map_cache(&file.path);
+    };
+
+    // Every truncation is an error; ones inside the features are caught by
+    // the bounds check before any row is sliced from the map.
+    let bytes = valid();
+    for len in 0..bytes.len() {
+        assert!(map(bytes[..len].to_vec()).is_err(), "{} bytes mapped", len);
+    }
+    assert!(matches!(
+        map(bytes[..bytes.len() - 1].to_vec()),
+        Err(Error::InvalidFormat(_))
+    ));
+
+    // Stored value counts that disagree with the header, including ones
+    // that would wrap the bounds check.
+    for count in [3, 5, u64::MAX, u64::MAX / 2 + 2] {
+        assert!(
+            matches!(
+                map(cache(2, 2, &samples(None, count))),
+                Err(Error::InvalidFormat(_))
+            ),
+            "{} values",
+            count
+        );
+    }
+    // Sample counts whose feature size overflows.
+    assert!(matches!(
+        map(cache(u64::MAX / 2 + 1, 2, &[])),
+        Err(Error::InvalidFormat(_))
+    ));
+
+    assert!(map(valid()).is_ok());
+}
@@ -0,0 +1,57 @@
+//! Helpers shared by the integration tests.
+
+// Each test crate uses only some of them.
+#![allow(dead_code)]
+
+pub mod protobuf;
+
+use std::fs::create_dir_all;
+use std::fs::remove_dir_all;
+use std::fs::remove_file;
+use std::path::PathBuf;
+
+/// A file under the system temp dir, removed on drop.
+pub struct TempFile {
+    pub path: PathBuf,
+}
+
+impl TempFile {
+    /// `antbee-<name>-<pid>.<extension>`, unique per test and process.
+    pub fn new(name: &str, extension: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "antbee-{}-{}.{}",
+            name,
+            std::process::id(),
+            extension
+        ));
+        return Self { path };
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = remove_file(&self.path);
+    }
+}
+
+/// A directory under the system temp dir, removed with its contents on
+/// drop.
+pub struct TempDir {
+    pub path: PathBuf,
+}
+
+impl TempDir {
+    /// An empty `antbee-<name>-<pid>`, unique per test and process.
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("antbee-{}-{}", name, std::process::id()));
+        let _ = remove_dir_all(&path);
+        create_dir_all(&path).unwrap();
+        return Self { path };
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = remove_dir_all(&self.path);
+    }
+}
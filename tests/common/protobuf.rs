@@ -0,0 +1,150 @@
+//! Decoding of the Protocol Buffers messages the exporters write, to check
+//! them field by field.
+
+/// The value of one field, by wire type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+/// The fields of a message, in the order they were written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message<'a> {
+    pub fields: Vec<(u32, Value<'a>)>,
+}
+
+impl<'a> Message<'a> {
+    /// Decodes `bytes`, panicking on anything malformed or left over.
+    pub fn parse(bytes: &'a [u8]) -> Self {
+        let mut fields = Vec::new();
+        let mut at = 0;
+        while at < bytes.len() {
+            let key = varint(bytes, &mut at);
+            let field = u32::try_from(key >> 3).expect("field number too large");
+            let value = match key & 7 {
+                0 => Value::Varint(varint(bytes, &mut at)),
+                1 => {
+                    let value = u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+                    at += 8;
+                    Value::Fixed64(value)
+                }
+                2 => {
+                    let len = varint(bytes, &mut at) as usize;
+                    let value = &bytes[at..at + len];
+                    at += len;
+                    Value::Bytes(value)
+                }
+                5 => {
+                    let value = u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+                    at += 4;
+                    Value::Fixed32(value)
+                }
+                other => panic!("unexpected wire type {} of field {}", other, field),
+            };
+            fields.push((field, value));
+        }
+        return Self { fields };
+    }
+
+    fn values(&self, field: u32) -> impl Iterator<Item = Value<'a>> + '_ {
+        return self
+            .fields
+            .iter()
+            .filter(move |(number, _)| *number == field)
+            .map(|(_, value)| *value);
+    }
+
+    pub fn varints(&self, field: u32) -> Vec<u64> {
+        return self
+            .values(field)
+            .map(|value| match value {
+                Value::Varint(value) => value,
+                other => panic!("field {} is {:?}, not a varint", field, other),
+            })
+            .collect();
+    }
+
+    /// The only value of the varint `field`, if present.
+    pub fn varint(&self, field: u32) -> Option<u64> {
+        return single(self.varints(field), field);
+    }
+
+    pub fn bytes(&self, field: u32) -> Vec<&'a [u8]> {
+        return self
+            .values(field)
+            .map(|value| match value {
+                Value::Bytes(bytes) => bytes,
+                other => panic!("field {} is {:?}, not length-delimited", field, other),
+            })
+            .collect();
+    }
+
+    pub fn strings(&self, field: u32) -> Vec<&'a str> {
+        return self
+            .bytes(field)
+            .into_iter()
+            .map(|bytes| std::str::from_utf8(bytes).expect("string is not UTF-8"))
+            .collect();
+    }
+
+    /// The only value of the string `field`, if present.
+    pub fn string(&self, field: u32) -> Option<&'a str> {
+        return single(self.strings(field), field);
+    }
+
+    pub fn messages(&self, field: u32) -> Vec<Message<'a>> {
+        return self.bytes(field).into_iter().map(Message::parse).collect();
+    }
+
+    /// The only value of the message `field`, panicking if it is missing.
+    pub fn message(&self, field: u32) -> Message<'a> {
+        return single(self.messages(field), field).unwrap_or_else(|| {
+            panic!("message field {} is missing", field);
+        });
+    }
+
+    /// The only value of the 32-bit `field`, if present.
+    pub fn fixed32(&self, field: u32) -> Option<u32> {
+        let values = self
+            .values(field)
+            .map(|value| match value {
+                Value::Fixed32(value) => value,
+                other => panic!("field {} is {:?}, not 32-bit", field, other),
+            })
+            .collect();
+        return single(values, field);
+    }
+
+    /// The only value of the 64-bit `field`, if present.
+    pub fn fixed64(&self, field: u32) -> Option<u64> {
+        let values = self
+            .values(field)
+            .map(|value| match value {
+                Value::Fixed64(value) => value,
+                other => panic!("field {} is {:?}, not 64-bit", field, other),
+            })
+            .collect();
+        return single(values, field);
+    }
+}
+
+fn single<T>(mut values: Vec<T>, field: u32) -> Option<T> {
+    assert!(values.len() <= 1, "field {} is repeated", field);
+    return values.pop();
+}
+
+fn varint(bytes: &[u8], at: &mut usize) -> u64 {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = bytes[*at];
+        *at += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte < 0x80 {
+            return value;
+        }
+    }
+    panic!("varint longer than 10 bytes");
+}
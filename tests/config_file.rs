@@ -0,0 +1,385 @@
+use antbee_rs::antbee::BalancedSampler;
+#[cfg(feature = "fs")]
+use antbee_rs::antbee::ChannelMode;
+#[cfg(feature = "fs")]
+use antbee_rs::antbee::DataConfig;
+use antbee_rs::antbee::DivergencePolicy;
+use antbee_rs::antbee::Error;
+use antbee_rs::antbee::Focal;
+#[cfg(feature = "fs")]
+use antbee_rs::antbee::ModelConfig;
+#[cfg(feature = "fs")]
+use antbee_rs::antbee::Precision;
+#[cfg(feature = "fs")]
+use antbee_rs::antbee::Preprocess;
+#[cfg(feature = "fs")]
+use antbee_rs::antbee::ResizeMode;
+#[cfg(feature = "fs")]
+use antbee_rs::antbee::RunConfig;
+use antbee_rs::antbee::StepDecay;
+use antbee_rs::antbee::TrainConfig;
+use antbee_rs::antbee::Warmup;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Every key a config file can hold.
+const KEYS: [&str; 26] = [
+    "epochs",
+    "learning_rate",
+    "scheduler",
+    "momentum",
+    "loss",
+    "label_smoothing",
+    "l2",
+    "input_dropout",
+    "input_noise",
+    "class_weights",
+    "patience",
+    "min_delta",
+    "batch_size",
+    "accumulation_steps",
+    "num_threads",
+    "max_grad_norm",
+    "on_divergence",
+    "shuffle",
+    "sampler",
+    "seed",
+    "checkpoint_dir",
+    "checkpoint_every",
+    "metrics_path",
+    "tensorboard_dir",
+    "show_progress",
+    "handle_signals",
+];
+
+/// A configuration with every setting away from its default.
+fn custom_config() -> TrainConfig {
+    return TrainConfig {
+        epochs: 42,
+        learning_rate: 0.025,
+        scheduler: Arc::new(Warmup {
+            warmup_epochs: 3,
+            after: StepDecay {
+                step_size: 7,
+                gamma: 0.25,
+            },
+        }),
+        momentum: 0.9,
+        loss: Arc::new(Focal { gamma: 1.5 }),
+        label_smoothing: 0.1,
+        l2: 1e-4,
+        input_dropout: 0.2,
+        input_noise: 0.05,
+        class_weights: Some(vec![0.75, 1.5]),
+        patience: Some(6),
+        min_delta: 0.001,
+        batch_size: 32,
+        accumulation_steps: 4,
+        num_threads: 0,
+        max_grad_norm: Some(2.5),
+        on_divergence: DivergencePolicy::Rollback { max_retries: 5 },
+        shuffle: false,
+        sampler: Arc::new(BalancedSampler),
+        // Above i64::MAX, so it is written as its bit pattern.
+        seed: u64::MAX - 1,
+        checkpoint_dir: Some(PathBuf::from("runs/\"quoted\"\\dir")),
+        checkpoint_every: 3,
+        metrics_path: Some(PathBuf::from("runs/metrics.jsonl")),
+        tensorboard_dir: Some(PathBuf::from("runs/tb")),
+        show_progress: false,
+        handle_signals: true,
+    };
+}
+
+/// The reason of an `InvalidFormat` error, failing on anything else.
+fn rejection(text: &str) -> String {
+    return match TrainConfig::from_toml(text) {
+        Err(Error::InvalidFormat(reason)) => reason,
+        Err(err) => panic!("{:?}: unexpected error {}", text, err),
+        Ok(config) => panic!("{:?} was accepted as {:?}", text, config),
+    };
+}
+
+#[test]
+fn round_trip_keeps_every_setting() {
+    let config = custom_config();
+    let text = config.to_toml();
+    for key in KEYS {
+        assert!(
+            text.lines()
+                .any(|line| line.starts_with(&format!("{} = ", key))),
+            "`{}` missing from\n{}",
+            key,
+            text
+        );
+    }
+
+    let parsed = TrainConfig::from_toml(&text).unwrap();
+    assert_eq!(parsed.to_toml(), text);
+    assert_eq!(parsed.epochs, 42);
+    assert_eq!(parsed.learning_rate, 0.025);
+    assert_eq!(parsed.momentum, 0.9);
+    assert_eq!(parsed.label_smoothing, 0.1);
+    assert_eq!(parsed.l2, 1e-4);
+    assert_eq!(parsed.input_dropout, 0.2);
+    assert_eq!(parsed.input_noise, 0.05);
+    assert_eq!(parsed.class_weights, Some(vec![0.75, 1.5]));
+    assert_eq!(parsed.patience, Some(6));
+    assert_eq!(parsed.min_delta, 0.001);
+    assert_eq!(parsed.batch_size, 32);
+    assert_eq!(parsed.accumulation_steps, 4);
+    assert_eq!(parsed.num_threads, 0);
+    assert_eq!(parsed.max_grad_norm, Some(2.5));
+    assert_eq!(
+        parsed.on_divergence,
+        DivergencePolicy::Rollback { max_retries: 5 }
+    );
+    assert!(!parsed.shuffle);
+    assert_eq!(parsed.seed, u64::MAX - 1);
+    assert_eq!(parsed.checkpoint_dir, config.checkpoint_dir);
+    assert_eq!(parsed.checkpoint_every, 3);
+    assert_eq!(parsed.metrics_path, config.metrics_path);
+    assert_eq!(parsed.tensorboard_dir, config.tensorboard_dir);
+    assert!(!parsed.show_progress);
+    assert!(parsed.handle_signals);
+    assert_eq!(parsed.loss.to_toml(), config.loss.to_toml());
+    assert_eq!(parsed.scheduler.to_toml(), config.scheduler.to_toml());
+    assert_eq!(parsed.sampler.to_toml(), config.sampler.to_toml());
+    for epoch in 0..config.epochs {
+        assert_eq!(
+            parsed.learning_rate_at(epoch),
+            config.learning_rate_at(epoch)
+        );
+    }
+}
+
+#[test]
+fn default_config_round_trips() {
+    let text = TrainConfig::default().to_toml();
+    assert_eq!(TrainConfig::from_toml(&text).unwrap().to_toml(), text);
+    assert_eq!(
+        TrainConfig::from_toml("").unwrap().to_toml(),
+        text,
+        "an empty file gives the defaults"
+    );
+}
+
+#[test]
+fn components_can_be_written_as_tables() {
+    let tables = TrainConfig::from_toml(
+        "# components as tables\n\
+         epochs = 12\n\
+         \n\
+         [loss]\n\
+         name = \"hinge\"\n\
+         margin = 0.5\n\
+         \n\
+         [scheduler]\n\
+         name = \"warmup\"  # ramp up first\n\
+         warmup_epochs = 2\n\
+         after = { name = \"cosine\", min_lr = 0.0001 }\n\
+         \n\
+         [sampler]\n\
+         name = 'stratified'\n\
+         \n\
+         [on_divergence]\n\
+         name = \"rollback\"\n\
+         max_retries = 4\n",
+    )
+    .unwrap();
+    let inline = TrainConfig::from_toml(
+        "epochs = 12\n\
+         loss = { name = \"hinge\", margin = 0.5 }\n\
+         scheduler = { name = \"warmup\", warmup_epochs = 2, after = { name = \"cosine\", min_lr = 0.0001 } }\n\
+         sampler = \"stratified\"\n\
+         on_divergence = { name = \"rollback\", max_retries = 4 }\n",
+    )
+    .unwrap();
+    assert_eq!(tables.to_toml(), inline.to_toml());
+    assert_eq!(tables.epochs, 12);
+    assert_eq!(
+        tables.loss.to_toml().unwrap(),
+        "{ name = \"hinge\", margin = 0.5 }"
+    );
+    assert_eq!(
+        tables.scheduler.to_toml().unwrap(),
+        "{ name = \"warmup\", warmup_epochs = 2, after = { name = \"cosine\", min_lr = 0.0001 } }"
+    );
+    assert_eq!(tables.sampler.to_toml().unwrap(), "\"stratified\"");
+    assert_eq!(
+        tables.on_divergence,
+        DivergencePolicy::Rollback { max_retries: 4 }
+    );
+
+    // Parameters left out keep their defaults.
+    let defaults = TrainConfig::from_toml("[loss]\nname = \"focal\"\n").unwrap();
+    assert_eq!(
+        defaults.loss.to_toml(),
+        TrainConfig::from_toml("loss = \"focal\"")
+            .unwrap()
+            .loss
+            .to_toml()
+    );
+}
+
+#[test]
+fn rejects_unknown_keys() {
+    assert!(rejection("learning_rat = 0.1").contains("`learning_rat`"));
+    assert!(rejection("[loss]\nname = \"focal\"\nalpha = 0.25\n").contains("`loss.alpha`"));
+    assert!(rejection("scheduler = { name = \"step\", steps = 3 }").contains("`scheduler.steps`"));
+    assert!(
+        rejection("scheduler = { name = \"warmup\", after = { name = \"cosine\", max_lr = 1 } }")
+            .contains("`scheduler.after.max_lr`")
+    );
+    assert!(rejection("[optimizer]\nname = \"adam\"\n").contains("`optimizer`"));
+    assert!(rejection("loss = \"mse\"").contains("unknown loss `mse`"));
+    assert!(rejection("scheduler = \"linear\"").contains("unknown scheduler `linear`"));
+    assert!(rejection("sampler = { name = \"weighted\" }").contains("unknown sampler `weighted`"));
+    assert!(rejection("on_divergence = \"ignore\"").contains("unknown policy `ignore`"));
+    assert!(rejection("[loss]\ngamma = 2.0\n").contains("`loss.name`: missing"));
+}
+
+#[test]
+fn rejects_bad_values() {
+    for text in [
+        // Types.
+        "epochs = \"ten\"",
+        "epochs = 1.5",
+        "epochs = -1",
+        "learning_rate = true",
+        "shuffle = 1",
+        "seed = 1.0",
+        "checkpoint_dir = 3",
+        "class_weights = 1.0",
+        "class_weights = [1.0, \"two\"]",
+        "loss = 3",
+        // Ranges training would reject.
+        "input_dropout = 1.0",
+        "input_dropout = -0.1",
+        "label_smoothing = 1",
+        "input_noise = -0.5",
+        "input_noise = inf",
+        "learning_rate = -0.01",
+        "learning_rate = nan",
+        "learning_rate = 1e39",
+        "momentum = -0.9",
+        "momentum = 1.0",
+        "momentum = nan",
+        "l2 = -1e-4",
+        "l2 = inf",
+        "min_delta = -0.001",
+        "min_delta = -inf",
+        "max_grad_norm = 0",
+        "max_grad_norm = -1.0",
+        "batch_size = 0",
+        "accumulation_steps = 0",
+        "class_weights = []",
+        "class_weights = [1.0]",
+        "class_weights = [1.0, -2.0]",
+        // Syntax.
+        "epochs 10",
+        "epochs = ",
+        "epochs = 10 11",
+        "epochs = 1\nepochs = 2",
+        "checkpoint_dir = \"runs",
+        "checkpoint_dir = \"bad \\q escape\"",
+        "class_weights = [1.0, 2.0",
+        "[loss\nname = \"bce\"",
+    ] {
+        let reason = rejection(text);
+        assert!(reason.starts_with("config "), "{:?}: {}", text, reason);
+    }
+    assert_eq!(
+        rejection("epochs = 10\nbatch_size = 0"),
+        "config line 2: key `batch_size`: expected a positive integer, found 0"
+    );
+    assert!(rejection("epochs = 1\nepochs = 2").starts_with("config line 2:"));
+    assert_eq!(
+        rejection("epochs = 10\n\nlearning_rate = nan"),
+        "config line 3: key `learning_rate`: expected a finite number >= 0, found NaN"
+    );
+    // Keys in tables have their own line, keys in inline tables and
+    // missing keys that of the enclosing key.
+    assert!(rejection("[loss]\nname = \"focal\"\ngamma = true\n").starts_with("config line 3:"));
+    assert!(rejection("\n[loss]\ngamma = 2.0\n").starts_with("config line 2:"));
+    assert!(
+        rejection("epochs = 1\nscheduler = { name = \"warmup\", after = { name = \"step\", gamma = \"x\" } }")
+            .starts_with("config line 2: key `scheduler.after.gamma`:")
+    );
+}
+
+#[cfg(feature = "fs")]
+#[test]
+fn run_config_round_trips_data_and_model_tables() {
+    let config = RunConfig {
+        train: custom_config(),
+        data: DataConfig {
+            dataset_dir: Some(PathBuf::from("data/insects")),
+            cache_dir: Some(PathBuf::from("cache")),
+            preprocess: Preprocess {
+                width: 64,
+                height: 48,
+                channels: ChannelMode::Grayscale,
+                resize: ResizeMode::Letterbox,
+            },
+            precision: Precision::F16,
+            mmap: true,
+        },
+        model: ModelConfig {
+            conv: Some(vec![8, 16]),
+            normalize: true,
+        },
+    };
+    let text = config.to_toml();
+    let parsed = RunConfig::from_toml(&text).unwrap();
+    assert_eq!(parsed.to_toml(), text);
+    assert_eq!(parsed.train.to_toml(), config.train.to_toml());
+    assert_eq!(parsed.data, config.data);
+    assert_eq!(parsed.model, config.model);
+
+    let default = RunConfig::from_toml(&TrainConfig::default().to_toml()).unwrap();
+    assert_eq!(default.data, DataConfig::default());
+    assert_eq!(default.model, ModelConfig::default());
+    let inline = RunConfig::from_toml("data = { width = 32, height = 32 }").unwrap();
+    assert_eq!(inline.data.preprocess.width, 32);
+    assert_eq!(inline.data.preprocess.height, 32);
+}
+
+#[cfg(feature = "fs")]
+#[test]
+fn run_config_rejects_bad_data_and_model_settings() {
+    let rejection = |text: &str| {
+        return match RunConfig::from_toml(text) {
+            Err(Error::InvalidFormat(reason)) => reason,
+            Err(err) => panic!("{:?}: unexpected error {}", text, err),
+            Ok(config) => panic!("{:?} was accepted as {:?}", text, config),
+        };
+    };
+    for text in [
+        "data = 3",
+        "[data]\nsize = 28",
+        "[data]\nwidth = 0",
+        "[data]\nheight = 4294967296",
+        "[data]\nchannels = \"cmyk\"",
+        "[data]\nresize = \"pad\"",
+        "[data]\nprecision = \"f64\"",
+        "[data]\nmmap = \"yes\"",
+        "[data]\ndataset_dir = 1",
+        "[model]\nconv = 8",
+        "[model]\nconv = []",
+        "[model]\nconv = [8, 0]",
+        "[model]\nlayers = 2",
+        "[model]\nnormalize = 1",
+    ] {
+        let reason = rejection(text);
+        assert!(reason.starts_with("config line "), "{:?}: {}", text, reason);
+    }
+    assert_eq!(
+        rejection("epochs = 3\n[model]\nnormalize = true\nconv = [8, 0]"),
+        "config line 4: key `model.conv`: expected a positive integer, found 0"
+    );
+    assert!(rejection("momentum = 2.0\n[data]\n").contains("`momentum`"));
+
+    // Training config files keep rejecting the tables.
+    assert!(TrainConfig::from_toml("[data]\nwidth = 32").is_err());
+}
@@ -0,0 +1,131 @@
+mod common;
+
+use antbee_rs::antbee::ChannelMode;
+use antbee_rs::antbee::Dataset;
+use antbee_rs::antbee::DatasetSource;
+use antbee_rs::antbee::DuplicateGroup;
+use antbee_rs::antbee::Preprocess;
+use antbee_rs::antbee::ResizeMode;
+use antbee_rs::antbee::find_duplicate_images;
+use common::TempDir;
+use image::GrayImage;
+use image::ImageFormat;
+use image::Luma;
+use image::imageops::FilterType;
+use image::imageops::resize;
+use std::fs::copy;
+use std::fs::create_dir_all;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// A smooth 64x48 pattern, so that re-encoding and resizing keep the
+/// brightness of neighboring regions in order.
+fn pattern(phase: f32) -> GrayImage {
+    return GrayImage::from_fn(64, 48, |x, y| {
+        let value = (x as f32 / 6.0 + phase).sin() * (y as f32 / 9.0 - phase).cos();
+        return Luma([(128.0 + 100.0 * value) as u8]);
+    });
+}
+
+fn save(image: &GrayImage, path: &Path, format: ImageFormat) {
+    create_dir_all(path.parent().unwrap()).unwrap();
+    image.save_with_format(path, format).unwrap();
+}
+
+#[test]
+fn copies_are_found_across_formats_and_sizes() {
+    let dir = TempDir::new("dedup-files");
+    let original = pattern(0.0);
+    let paths: Vec<PathBuf> = ["a.png", "b.png", "c.jpg", "d.png", "e.png"]
+        .iter()
+        .map(|name| dir.path.join(name))
+        .collect();
+    save(&original, &paths[0], ImageFormat::Png);
+    copy(&paths[0], &paths[1]).unwrap();
+    save(&original, &paths[2], ImageFormat::Jpeg);
+    let larger = resize(&original, 96, 72, FilterType::Triangle);
+    save(&larger, &paths[3], ImageFormat::Png);
+    save(&pattern(2.0), &paths[4], ImageFormat::Png);
+
+    let groups = find_duplicate_images(&paths, DuplicateGroup::DEFAULT_MAX_DISTANCE).unwrap();
+    assert_eq!(
+        groups,
+        [DuplicateGroup {
+            indices: vec![0, 1, 2, 3],
+            exact: false,
+        }]
+    );
+    let exact = find_duplicate_images(&paths[..2], 0).unwrap();
+    assert_eq!(
+        exact,
+        [DuplicateGroup {
+            indices: vec![0, 1],
+            exact: true,
+        }]
+    );
+    assert!(find_duplicate_images(&paths[3..], 0).unwrap().is_empty());
+
+    let missing = dir.path.join("missing.png");
+    let Err(err) = find_duplicate_images(&[paths[0].clone(), missing.clone()], 0) else {
+        panic!("found duplicates among a missing file");
+    };
+    assert!(
+        err.to_string().contains(&missing.display().to_string()),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn dedup_keeps_the_first_of_every_group() {
+    let dir = TempDir::new("dedup-dataset");
+    let ants = dir.path.join("ants");
+    let bees = dir.path.join("bees");
+    save(&pattern(0.0), &ants.join("a.png"), ImageFormat::Png);
+    save(&pattern(0.0), &ants.join("b.jpg"), ImageFormat::Jpeg);
+    save(&pattern(1.0), &ants.join("c.png"), ImageFormat::Png);
+    save(&pattern(2.0), &bees.join("d.png"), ImageFormat::Png);
+    // A mislabeled copy in the other class.
+    copy(ants.join("c.png"), bees.join("e.png")).unwrap();
+
+    let preprocess = Preprocess {
+        width: 32,
+        height: 24,
+        channels: ChannelMode::Grayscale,
+        resize: ResizeMode::Stretch,
+    };
+    let dataset = Dataset::from_dataset_path_with(&dir.path, preprocess).unwrap();
+    let name = |dataset: &Dataset, index: usize| {
+        let path = dataset.path(index).unwrap();
+        return path.file_name().unwrap().to_string_lossy().into_owned();
+    };
+    // Loading shuffles the samples, so compare the groups by file name.
+    let names: Vec<String> = (0..dataset.len()).map(|i| name(&dataset, i)).collect();
+    let groups = dataset.duplicates(DuplicateGroup::DEFAULT_MAX_DISTANCE);
+    let mut named: Vec<(Vec<&str>, bool)> = groups
+        .iter()
+        .map(|group| {
+            let mut files: Vec<&str> = group.indices.iter().map(|&i| names[i].as_str()).collect();
+            files.sort();
+            return (files, group.exact);
+        })
+        .collect();
+    named.sort();
+    assert_eq!(
+        named,
+        [
+            (vec!["a.png", "b.jpg"], false),
+            (vec!["c.png", "e.png"], true)
+        ]
+    );
+
+    let deduped = dataset.dedup();
+    let kept: Vec<String> = (0..deduped.len()).map(|i| name(&deduped, i)).collect();
+    let expected: Vec<String> = (0..dataset.len())
+        .filter(|i| groups.iter().all(|group| !group.indices[1..].contains(i)))
+        .map(|i| names[i].clone())
+        .collect();
+    assert_eq!(kept, expected);
+    assert_eq!(kept.len(), 3);
+    assert!(kept.contains(&"d.png".to_string()));
+}
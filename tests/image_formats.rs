@@ -0,0 +1,125 @@
+mod common;
+
+use antbee_rs::antbee::ChannelMode;
+use antbee_rs::antbee::Dataset;
+use antbee_rs::antbee::DatasetSource;
+use antbee_rs::antbee::Error;
+use antbee_rs::antbee::Kind;
+use antbee_rs::antbee::LazyDataset;
+use antbee_rs::antbee::Preprocess;
+use antbee_rs::antbee::ResizeMode;
+use common::TempDir;
+use image::ImageFormat;
+use image::Rgb;
+use image::RgbImage;
+use std::fs::create_dir_all;
+use std::fs::write;
+use std::path::Path;
+
+/// A dataset directory with `ants` and `bees` classes, removed on drop.
+struct TempDataset(TempDir);
+
+impl TempDataset {
+    fn new(name: &str) -> Self {
+        let dir = TempDir::new(name);
+        create_dir_all(dir.path.join("ants")).unwrap();
+        create_dir_all(dir.path.join("bees")).unwrap();
+        return Self(dir);
+    }
+
+    fn root(&self) -> &Path {
+        return &self.0.path;
+    }
+
+    /// Writes a solid `color` image to `class/file` in `format`.
+    fn image(&self, class: &str, file: &str, color: [u8; 3], format: ImageFormat) {
+        let image = RgbImage::from_pixel(8, 8, Rgb(color));
+        image
+            .save_with_format(self.root().join(class).join(file), format)
+            .unwrap();
+    }
+}
+
+fn preprocess() -> Preprocess {
+    return Preprocess {
+        width: 4,
+        height: 4,
+        channels: ChannelMode::Rgb,
+        resize: ResizeMode::Stretch,
+    };
+}
+
+#[test]
+fn loads_mixed_formats_and_skips_other_files() {
+    let dir = TempDataset::new("mixed");
+    dir.image("ants", "a.jpg", [200, 0, 0], ImageFormat::Jpeg);
+    dir.image("ants", "b.png", [200, 0, 0], ImageFormat::Png);
+    dir.image("ants", "c.bmp", [200, 0, 0], ImageFormat::Bmp);
+    dir.image("bees", "d.webp", [0, 0, 200], ImageFormat::WebP);
+    dir.image("bees", "e.PNG", [0, 0, 200], ImageFormat::Png);
+    dir.image("bees", ".hidden.png", [0, 0, 200], ImageFormat::Png);
+    write(dir.root().join("bees").join("notes.txt"), "not an image").unwrap();
+    write(dir.root().join("ants").join(".DS_Store"), [0u8; 16]).unwrap();
+
+    let dataset = Dataset::from_dataset_path_with(dir.root(), preprocess()).unwrap();
+    assert_eq!(dataset.classes(), ["ants", "bees"]);
+    assert_eq!(dataset.class_counts(), [3, 2]);
+
+    let lazy = LazyDataset::from_dataset_path(dir.root(), preprocess(), 0);
+    assert_eq!(lazy.class_counts(), [3, 2]);
+}
+
+#[test]
+fn png_and_webp_decode_to_the_same_features() {
+    let dir = TempDataset::new("lossless");
+    dir.image("ants", "a.png", [255, 51, 0], ImageFormat::Png);
+    dir.image("bees", "b.webp", [255, 51, 0], ImageFormat::WebP);
+
+    let dataset = Dataset::from_dataset_path_with(dir.root(), preprocess()).unwrap();
+    let png = dataset
+        .iter()
+        .find(|data| data.get_kind() == Kind(0))
+        .unwrap();
+    let webp = dataset
+        .iter()
+        .find(|data| data.get_kind() == Kind(1))
+        .unwrap();
+
+    // Both formats are lossless here, so a solid color survives exactly
+    // (up to resampling error): red channel first, then green, then blue.
+    let pixels = 16;
+    for (i, (&a, &b)) in png.get_data().iter().zip(webp.get_data()).enumerate() {
+        let expected = [1.0, 0.2, 0.0][i / pixels];
+        assert!((a - expected).abs() < 1e-2, "png feature {} = {}", i, a);
+        assert!((b - expected).abs() < 1e-2, "webp feature {} = {}", i, b);
+    }
+}
+
+#[test]
+fn format_is_detected_from_contents() {
+    let dir = TempDataset::new("misnamed");
+    dir.image("ants", "a.jpg", [0, 255, 0], ImageFormat::Png);
+    dir.image("bees", "b.png", [0, 255, 0], ImageFormat::Jpeg);
+
+    let dataset = Dataset::from_dataset_path_with(dir.root(), preprocess()).unwrap();
+    assert_eq!(dataset.len(), 2);
+}
+
+#[test]
+fn undecodable_images_fail_with_their_path() {
+    let dir = TempDataset::new("corrupt");
+    dir.image("ants", "a.png", [200, 0, 0], ImageFormat::Png);
+    dir.image("bees", "b.png", [0, 0, 200], ImageFormat::Png);
+    let broken = dir.root().join("bees").join("broken.png");
+    write(&broken, b"\x89PNG\r\n\x1a\nnot really").unwrap();
+
+    let Err(err) = Dataset::from_dataset_path_with(dir.root(), preprocess()) else {
+        panic!("loaded a dataset with an undecodable image");
+    };
+    assert!(matches!(err, Error::InvalidFormat(_)), "{:?}", err);
+    assert!(
+        err.to_string().contains(&broken.display().to_string()),
+        "{}",
+        err
+    );
+}
@@ -0,0 +1,102 @@
+//! Signal handling across the runs of a sweep. The interrupt flag is
+//! global to the process, so everything runs in a single test.
+#![cfg(unix)]
+
+mod common;
+
+use antbee_rs::antbee::Callback;
+use antbee_rs::antbee::ChannelMode;
+use antbee_rs::antbee::Dataset;
+use antbee_rs::antbee::DatasetSource;
+use antbee_rs::antbee::Ensemble;
+use antbee_rs::antbee::Model;
+use antbee_rs::antbee::Preprocess;
+use antbee_rs::antbee::ResizeMode;
+use antbee_rs::antbee::Result;
+use antbee_rs::antbee::SearchStrategy;
+use antbee_rs::antbee::TrainConfig;
+use antbee_rs::antbee::Trainer;
+use antbee_rs::antbee::TuneConfig;
+use antbee_rs::antbee::cross_validate;
+use antbee_rs::antbee::tune;
+use common::TempDir;
+use image::Rgb;
+use image::RgbImage;
+use std::fs::create_dir_all;
+
+/// Sends the process a SIGINT as training starts, once the trainer has
+/// installed its handler.
+struct Interrupt;
+
+impl Callback for Interrupt {
+    fn on_train_start(&mut self, _model: &Model, _epoch: usize) -> Result<()> {
+        // SAFETY: raises a signal whose handler the trainer installed.
+        unsafe { libc::raise(libc::SIGINT) };
+        return Ok(());
+    }
+}
+
+/// Six solid red ants and six solid blue bees.
+fn dataset(dir: &TempDir) -> Dataset {
+    for (class, color) in [("ants", [200, 0, 0]), ("bees", [0, 0, 200])] {
+        create_dir_all(dir.path.join(class)).unwrap();
+        for index in 0..6 {
+            RgbImage::from_pixel(4, 4, Rgb(color))
+                .save(dir.path.join(class).join(format!("{}.png", index)))
+                .unwrap();
+        }
+    }
+    let preprocess = Preprocess {
+        width: 2,
+        height: 2,
+        channels: ChannelMode::Rgb,
+        resize: ResizeMode::Stretch,
+    };
+    return Dataset::from_dataset_path_with(&dir.path, preprocess).unwrap();
+}
+
+#[test]
+fn an_interrupt_ends_the_whole_sweep() {
+    let dir = TempDir::new("interrupt");
+    let dataset = dataset(&dir);
+    let (train, val) = dataset.split(0.5, 0);
+    let config = TrainConfig {
+        epochs: 3,
+        show_progress: false,
+        handle_signals: true,
+        ..TrainConfig::default()
+    };
+    let initial = Model::new(*dataset.preprocess(), dataset.num_classes());
+
+    let mut trainer = Trainer::new(config.clone()).with_callback(Interrupt);
+    let fit = trainer.fit(&mut initial.clone(), &train, &val).unwrap();
+    assert!(fit.interrupted);
+    assert_eq!(fit.epochs_run, 0);
+
+    let tune_config = TuneConfig {
+        strategy: SearchStrategy::Random { trials: 4 },
+        ..TuneConfig::default()
+    };
+    let report = tune(&initial, &train, &val, &config, &tune_config).unwrap();
+    assert!(report.interrupted);
+    assert_eq!(report.trials.len(), 1);
+    assert_eq!(report.best_trial, 0);
+
+    let crossval = cross_validate(&dataset, 3, &config).unwrap();
+    assert!(crossval.interrupted);
+    assert_eq!(crossval.folds.len(), 1);
+
+    let (ensemble, reports) = Ensemble::fit(3, |_| initial.clone(), &train, &val, &config).unwrap();
+    assert_eq!(ensemble.len(), 1);
+    assert_eq!(reports.len(), 1);
+    assert!(reports[0].interrupted);
+
+    // Runs that do not handle signals are unaffected.
+    let unhandled = TrainConfig {
+        handle_signals: false,
+        ..config
+    };
+    let report = tune(&initial, &train, &val, &unhandled, &tune_config).unwrap();
+    assert!(!report.interrupted);
+    assert_eq!(report.trials.len(), 4);
+}
@@ -0,0 +1,107 @@
+use antbee_rs::antbee::BinaryCrossEntropy;
+use antbee_rs::antbee::CrossEntropy;
+use antbee_rs::antbee::Focal;
+use antbee_rs::antbee::Hinge;
+use antbee_rs::antbee::Kind;
+use antbee_rs::antbee::Loss;
+use ndarray::Array1;
+use ndarray::array;
+
+fn assert_close(actual: f32, expected: f32) {
+    assert!(
+        (actual - expected).abs() < 1e-5,
+        "{} != {}",
+        actual,
+        expected
+    );
+}
+
+fn assert_all_close(actual: &Array1<f32>, expected: &[f32]) {
+    assert_eq!(actual.len(), expected.len());
+    for (&a, &e) in actual.iter().zip(expected) {
+        assert_close(a, e);
+    }
+}
+
+#[test]
+fn losses_match_known_answers() {
+    // Softmax of [0, ln 3] is [1/4, 3/4].
+    let z = array![0.0, 3f32.ln()];
+    assert_close(CrossEntropy.forward(z.view(), Kind(1)), (4f32 / 3.0).ln());
+    assert_all_close(&CrossEntropy.gradient(z.view(), Kind(1)), &[0.25, -0.25]);
+    let focal = Focal { gamma: 2.0 };
+    assert_close(focal.forward(z.view(), Kind(1)), 0.0625 * (4f32 / 3.0).ln());
+
+    // Every sigmoid is 1/2 at zero.
+    let z = array![0.0, 0.0];
+    let bce = BinaryCrossEntropy.forward(z.view(), Kind(0));
+    assert_close(bce, 2.0 * 2f32.ln());
+    assert_all_close(
+        &BinaryCrossEntropy.gradient(z.view(), Kind(0)),
+        &[-0.5, 0.5],
+    );
+
+    // Only class 1 is inside the margin of class 0.
+    let z = array![2.0, 1.5, 0.0];
+    let hinge = Hinge::default();
+    assert_close(hinge.forward(z.view(), Kind(0)), 0.5);
+    assert_all_close(&hinge.gradient(z.view(), Kind(0)), &[-1.0, 1.0, 0.0]);
+    let wide = Hinge { margin: 3.0 };
+    assert_close(wide.forward(z.view(), Kind(0)), 2.5 + 1.0);
+    assert_all_close(&wide.gradient(z.view(), Kind(0)), &[-2.0, 1.0, 1.0]);
+}
+
+#[test]
+fn focal_without_focus_is_cross_entropy() {
+    let z = array![0.3, -1.2, 2.0];
+    let focal = Focal { gamma: 0.0 };
+    for k in 0..3 {
+        let target = Kind(k);
+        assert_close(
+            focal.forward(z.view(), target),
+            CrossEntropy.forward(z.view(), target),
+        );
+        let expected = CrossEntropy.gradient(z.view(), target);
+        assert_all_close(
+            &focal.gradient(z.view(), target),
+            expected.as_slice().unwrap(),
+        );
+    }
+}
+
+#[test]
+fn gradients_match_finite_differences() {
+    // Away from the hinge's kinks, so every loss is smooth around z.
+    let z = array![0.4, -0.7, 1.1, 0.05];
+    let losses: [&dyn Loss; 5] = [
+        &CrossEntropy,
+        &BinaryCrossEntropy,
+        &Hinge::default(),
+        &Focal::default(),
+        &Focal { gamma: 0.5 },
+    ];
+    let h = 1e-2;
+    for loss in losses {
+        for k in 0..z.len() {
+            let target = Kind(k);
+            let gradient = loss.gradient(z.view(), target);
+            for i in 0..z.len() {
+                let mut up = z.clone();
+                up[i] += h;
+                let mut down = z.clone();
+                down[i] -= h;
+                let numeric = (loss.forward(up.view(), target) - loss.forward(down.view(), target))
+                    / (2.0 * h);
+                assert!(
+                    (gradient[i] - numeric).abs() < 1e-3,
+                    "{:?}, target {}, dz[{}]: {} vs {}",
+                    loss,
+                    k,
+                    i,
+                    gradient[i],
+                    numeric
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use antbee_rs::antbee::ChannelMode;
+use antbee_rs::antbee::Error;
+use antbee_rs::antbee::Model;
+use antbee_rs::antbee::Preprocess;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+/// Offset of the class count in a file of a model without conv blocks:
+/// the header, the preprocessing and the conv block count before it.
+const NUM_CLASSES_AT: usize = 12 + 16 + 4;
+
+fn preprocess() -> Preprocess {
+    return Preprocess {
+        width: 2,
+        height: 2,
+        channels: ChannelMode::Grayscale,
+        ..Preprocess::default()
+    };
+}
+
+fn bytes_of(model: &Model) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    model.write_to(&mut bytes).unwrap();
+    return bytes;
+}
+
+fn read(bytes: &[u8]) -> antbee_rs::antbee::Result<Model> {
+    return Model::read_from(&mut &bytes[..]);
+}
+
+fn set_u64(bytes: &mut [u8], at: usize, value: u64) {
+    bytes[at..at + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+#[test]
+fn read_from_restores_a_written_model() {
+    let model = Model::from_rng(preprocess(), 3, &mut ChaCha8Rng::seed_from_u64(0));
+    let loaded = read(&bytes_of(&model)).unwrap();
+    assert_eq!(loaded.weights(), model.weights());
+    assert_eq!(loaded.bias(), model.bias());
+
+    let conv = Model::with_conv(preprocess(), &[2], 2, &mut ChaCha8Rng::seed_from_u64(1));
+    let loaded = read(&bytes_of(&conv)).unwrap();
+    assert_eq!(loaded.weights(), conv.weights());
+}
+
+#[test]
+fn read_from_rejects_corrupted_sizes() {
+    let bytes = bytes_of(&Model::from_rng(
+        preprocess(),
+        2,
+        &mut ChaCha8Rng::seed_from_u64(0),
+    ));
+    for len in 0..bytes.len() {
+        assert!(read(&bytes[..len]).is_err(), "{} bytes loaded", len);
+    }
+
+    // A class count whose weight count overflows.
+    let mut corrupted = bytes.clone();
+    set_u64(&mut corrupted, NUM_CLASSES_AT, u64::MAX / 2);
+    assert!(matches!(read(&corrupted), Err(Error::InvalidFormat(_))));
+
+    // An image size whose input size overflows.
+    let mut corrupted = bytes.clone();
+    corrupted[12..20].copy_from_slice(&[0xff; 8]);
+    corrupted[20..24].copy_from_slice(&3u32.to_le_bytes());
+    assert!(matches!(read(&corrupted), Err(Error::InvalidFormat(_))));
+
+    // A huge but consistent class and weight count fails at the end of
+    // the file instead of being allocated up front.
+    let mut corrupted = bytes.clone();
+    set_u64(&mut corrupted, NUM_CLASSES_AT, 1 << 40);
+    set_u64(&mut corrupted, NUM_CLASSES_AT + 16, 4 << 40);
+    assert!(matches!(read(&corrupted), Err(Error::Io(_))));
+
+    assert!(read(&bytes).is_ok());
+}
@@ -0,0 +1,183 @@
+mod common;
+
+use antbee_rs::antbee::ChannelMode;
+use antbee_rs::antbee::Error;
+use antbee_rs::antbee::Kind;
+use antbee_rs::antbee::Model;
+use antbee_rs::antbee::Preprocess;
+use antbee_rs::antbee::Sgd;
+use antbee_rs::antbee::TrainConfig;
+use common::TempFile;
+use ndarray::Array2;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::fs::read;
+use std::fs::write;
+
+fn preprocess() -> Preprocess {
+    return Preprocess {
+        width: 8,
+        height: 6,
+        channels: ChannelMode::Rgb,
+        ..Preprocess::default()
+    };
+}
+
+/// A conv model with every parameter away from its initialization, so the
+/// biases are not all zero.
+fn trained_model(seed: u64) -> Model {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut model = Model::with_conv(preprocess(), &[2, 3], 3, &mut rng);
+    let x = Array2::from_shape_fn((3, preprocess().input_dim()), |(i, j)| {
+        return ((i * 7 + j * 3) % 11) as f32 / 11.0;
+    });
+    let labels = [Kind(0), Kind(1), Kind(2)];
+    let config = TrainConfig::default();
+    model.train_batch(x.view(), &labels, &mut Sgd::new(0.0), 0.5, &config);
+    return model;
+}
+
+/// Position of the first central directory header of a `.npz` archive.
+fn central_directory(bytes: &[u8]) -> usize {
+    return bytes
+        .windows(4)
+        .position(|window| window == [0x50, 0x4b, 0x01, 0x02])
+        .unwrap();
+}
+
+/// `bytes` with the first entry's uncompressed size, compressed size and
+/// local header offset replaced by the given values of a zip64 extra field,
+/// and its compression method set to `method`.
+fn with_zip64(bytes: &[u8], values: [u64; 3], method: u16) -> Vec<u8> {
+    let at = central_directory(bytes);
+    let mut bytes = bytes.to_vec();
+    bytes[at + 10..at + 12].copy_from_slice(&method.to_le_bytes());
+    for field in [24, 20, 42] {
+        bytes[at + field..at + field + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    }
+    let name_len = u16::from_le_bytes([bytes[at + 28], bytes[at + 29]]) as usize;
+    let extra_len = u16::from_le_bytes([bytes[at + 30], bytes[at + 31]]);
+    bytes[at + 30..at + 32].copy_from_slice(&(extra_len + 28).to_le_bytes());
+    let mut extra = vec![0x01, 0x00, 24, 0];
+    for value in values {
+        extra.extend_from_slice(&value.to_le_bytes());
+    }
+    let end = at + 46 + name_len + extra_len as usize;
+    bytes.splice(end..end, extra);
+    return bytes;
+}
+
+fn assert_same_parameters(actual: &Model, expected: &Model) {
+    assert_eq!(actual.weights(), expected.weights());
+    assert_eq!(actual.bias(), expected.bias());
+    let (actual, expected) = (actual.conv().unwrap(), expected.conv().unwrap());
+    assert_eq!(actual.layers().len(), expected.layers().len());
+    for (actual, expected) in actual.layers().iter().zip(expected.layers()) {
+        assert_eq!(actual.kernel(), expected.kernel());
+        assert_eq!(actual.bias(), expected.bias());
+    }
+}
+
+#[test]
+fn save_npz_then_load_npz_restores_the_weights() {
+    let file = TempFile::new("round-trip", "npz");
+    let saved = trained_model(1);
+    assert!(saved.bias().iter().any(|&b| b != 0.0));
+    saved.save_npz(&file.path).unwrap();
+
+    let mut loaded = trained_model(2);
+    assert_ne!(loaded.weights(), saved.weights());
+    loaded.load_npz(&file.path).unwrap();
+    assert_same_parameters(&loaded, &saved);
+
+    // The linear head alone round-trips as well.
+    let linear = Model::from_rng(preprocess(), 2, &mut ChaCha8Rng::seed_from_u64(3));
+    linear.save_npz(&file.path).unwrap();
+    let mut target = Model::new(preprocess(), 2);
+    target.load_npz(&file.path).unwrap();
+    assert_eq!(target.weights(), linear.weights());
+    assert_eq!(target.bias(), linear.bias());
+}
+
+#[test]
+fn load_npz_rejects_mismatched_architectures() {
+    let file = TempFile::new("mismatch", "npz");
+    trained_model(1).save_npz(&file.path).unwrap();
+
+    let mut linear = Model::new(preprocess(), 3);
+    let before = linear.clone();
+    assert!(matches!(
+        linear.load_npz(&file.path),
+        Err(Error::InvalidFormat(_))
+    ));
+    assert_eq!(linear.weights(), before.weights());
+
+    let mut wider = Model::with_conv(preprocess(), &[4, 3], 3, &mut ChaCha8Rng::seed_from_u64(0));
+    assert!(matches!(
+        wider.load_npz(&file.path),
+        Err(Error::InvalidFormat(_))
+    ));
+}
+
+#[test]
+fn load_npz_rejects_corrupted_archives() {
+    let file = TempFile::new("corrupt", "npz");
+    let model = trained_model(1);
+    model.save_npz(&file.path).unwrap();
+    let bytes = read(&file.path).unwrap();
+    let mut target = trained_model(2);
+
+    // Every truncation is an error, never a panic.
+    for len in (0..bytes.len()).step_by(7) {
+        write(&file.path, &bytes[..len]).unwrap();
+        assert!(target.load_npz(&file.path).is_err(), "{} bytes loaded", len);
+    }
+
+    // Sizes and offsets in the central directory pointing far outside the
+    // archive.
+    let directory = central_directory(&bytes);
+    for field in [20, 24, 42] {
+        let mut corrupted = bytes.clone();
+        corrupted[directory + field..directory + field + 4].copy_from_slice(&[0xfe; 4]);
+        write(&file.path, &corrupted).unwrap();
+        assert!(
+            matches!(target.load_npz(&file.path), Err(Error::InvalidFormat(_))),
+            "field at {}",
+            field
+        );
+    }
+    // The same through zip64 fields, which hold 64-bit values. A huge
+    // uncompressed size of a deflated entry must not be allocated up front.
+    let (size, offset) = (bytes.len() as u64, 0);
+    for (values, method) in [
+        ([size, u64::MAX - 8, offset], 0),
+        ([size, size, u64::MAX - 2], 0),
+        ([size, size, u64::MAX / 2], 0),
+        ([u64::MAX / 2, 64, offset], 8),
+        ([u64::MAX, 0, offset], 8),
+    ] {
+        write(&file.path, with_zip64(&bytes, values, method)).unwrap();
+        assert!(target.load_npz(&file.path).is_err(), "{:?}", values);
+    }
+    // An untouched zip64 entry still loads.
+    let stored = u32::from_le_bytes(bytes[directory + 20..directory + 24].try_into().unwrap());
+    write(
+        &file.path,
+        with_zip64(&bytes, [stored as u64, stored as u64, offset], 0),
+    )
+    .unwrap();
+    target.load_npz(&file.path).unwrap();
+
+    // A flipped data byte fails the checksum.
+    let mut corrupted = bytes.clone();
+    corrupted[directory - 1] ^= 0xff;
+    write(&file.path, &corrupted).unwrap();
+    assert!(matches!(
+        target.load_npz(&file.path),
+        Err(Error::InvalidFormat(_))
+    ));
+
+    write(&file.path, &bytes).unwrap();
+    target.load_npz(&file.path).unwrap();
+    assert_same_parameters(&target, &model);
+}
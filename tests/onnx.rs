@@ -0,0 +1,203 @@
+mod common;
+
+use antbee_rs::antbee::ChannelMode;
+use antbee_rs::antbee::Model;
+use antbee_rs::antbee::Normalizer;
+use antbee_rs::antbee::Preprocess;
+use common::TempFile;
+use common::protobuf::Message;
+use ndarray::Array1;
+use ndarray::Array2;
+use rand::Rng;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::fs::read;
+
+/// `TensorProto.DataType.FLOAT`.
+const FLOAT: u64 = 1;
+
+/// `TensorProto.DataType.INT64`.
+const INT64: u64 = 7;
+
+fn preprocess() -> Preprocess {
+    return Preprocess {
+        width: 4,
+        height: 3,
+        channels: ChannelMode::Rgb,
+        ..Preprocess::default()
+    };
+}
+
+/// The bytes `model` exports to.
+fn export(model: &Model, name: &str) -> Vec<u8> {
+    let file = TempFile::new(name, "onnx");
+    model.export_onnx(&file.path).unwrap();
+    return read(&file.path).unwrap();
+}
+
+/// An initializer of the graph: its dimensions and float values.
+fn float_tensor(graph: &Message, name: &str) -> (Vec<u64>, Vec<f32>) {
+    let tensor = graph
+        .messages(5)
+        .into_iter()
+        .find(|tensor| tensor.string(8) == Some(name))
+        .unwrap_or_else(|| panic!("no initializer `{}`", name));
+    assert_eq!(tensor.varint(2), Some(FLOAT), "`{}` is not float", name);
+    let values = tensor.bytes(9)[0]
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+        .collect();
+    return (tensor.varints(1), values);
+}
+
+/// Operator types of the graph's nodes, in order.
+fn op_types<'a>(graph: &Message<'a>) -> Vec<&'a str> {
+    return graph
+        .messages(1)
+        .iter()
+        .map(|node| node.string(4).unwrap())
+        .collect();
+}
+
+/// The (batch, dimension) shape of the graph input or output `info`.
+fn shape<'a>(info: &Message<'a>) -> (Option<&'a str>, Option<u64>) {
+    let dims = info.message(2).message(1).message(2).messages(1);
+    assert_eq!(dims.len(), 2);
+    return (dims[0].string(2), dims[1].varint(1));
+}
+
+#[test]
+fn linear_export_computes_the_model() {
+    let mut rng = ChaCha8Rng::seed_from_u64(7);
+    let mut model = Model::from_rng(preprocess(), 3, &mut rng);
+    model.set_normalizer(Some(Normalizer::new(
+        vec![0.5, 0.4, 0.3],
+        vec![0.2, 0.25, 0.3],
+    )));
+    model.set_temperature(1.5);
+
+    let bytes = export(&model, "onnx-linear");
+    let onnx = Message::parse(&bytes);
+    assert_eq!(onnx.varint(1), Some(8), "IR version");
+    assert_eq!(onnx.string(2), Some("antbee-rs"));
+    let opset = onnx.message(8);
+    assert_eq!(opset.string(1), Some(""));
+    assert_eq!(opset.varint(2), Some(13));
+
+    let graph = onnx.message(7);
+    assert_eq!(op_types(&graph), ["MatMul", "Add", "Div", "Softmax"]);
+    let softmax = &graph.messages(1)[3];
+    assert_eq!(softmax.strings(1), ["scaled_logits"]);
+    assert_eq!(softmax.string(2), Some("probabilities"));
+    let axis = softmax.message(5);
+    assert_eq!(axis.string(1), Some("axis"));
+    assert_eq!(axis.varint(3), Some(1));
+
+    let input = graph.message(11);
+    assert_eq!(input.string(1), Some("input"));
+    assert_eq!(shape(&input), (Some("batch"), Some(36)));
+    let output = graph.message(12);
+    assert_eq!(output.string(1), Some("probabilities"));
+    assert_eq!(shape(&output), (Some("batch"), Some(3)));
+
+    // Evaluate the graph by hand: the normalizer is folded into the
+    // parameters, so it runs on raw inputs.
+    let (weight_dims, weights) = float_tensor(&graph, "weight");
+    assert_eq!(weight_dims, [36, 3]);
+    let weights = Array2::from_shape_vec((36, 3), weights).unwrap();
+    let (bias_dims, bias) = float_tensor(&graph, "bias");
+    assert_eq!(bias_dims, [3]);
+    let (temperature_dims, temperature) = float_tensor(&graph, "temperature");
+    assert!(temperature_dims.is_empty());
+    assert_eq!(temperature, [1.5]);
+
+    for _ in 0..5 {
+        let x = Array1::from_shape_fn(36, |_| rng.random::<f32>());
+        let logits = (x.dot(&weights) + Array1::from(bias.clone())) / temperature[0];
+        let exp = logits.mapv(|logit| (logit - logits[0]).exp());
+        let probabilities = &exp / exp.sum();
+        let expected = model.predict_probs(x.view());
+        for (got, want) in probabilities.iter().zip(&expected) {
+            assert!(
+                (got - want).abs() < 1e-5,
+                "{} vs {}",
+                probabilities,
+                expected
+            );
+        }
+    }
+}
+
+#[test]
+fn conv_export_lists_every_layer() {
+    let mut rng = ChaCha8Rng::seed_from_u64(3);
+    let preprocess = Preprocess {
+        width: 8,
+        height: 6,
+        ..preprocess()
+    };
+    let model = Model::with_conv(preprocess, &[2, 4], 2, &mut rng);
+    let bytes = export(&model, "onnx-conv");
+    let graph = Message::parse(&bytes).message(7);
+    assert_eq!(
+        op_types(&graph),
+        [
+            "Reshape", "Conv", "Relu", "MaxPool", "Conv", "Relu", "MaxPool", "Flatten", "MatMul",
+            "Add", "Softmax"
+        ]
+    );
+
+    let nodes = graph.messages(1);
+    for (index, node) in nodes.iter().enumerate().skip(1) {
+        // Every node reads the output of the one before it.
+        let previous = nodes[index - 1].string(2).unwrap();
+        assert_eq!(node.strings(1)[0], previous, "input of node {}", index);
+    }
+    let conv = &nodes[1];
+    assert_eq!(conv.strings(1), ["image", "conv0_weight", "conv0_bias"]);
+    let attributes = conv.messages(5);
+    let names: Vec<&str> = attributes.iter().map(|a| a.string(1).unwrap()).collect();
+    assert_eq!(names, ["kernel_shape", "pads", "strides"]);
+    assert_eq!(attributes[0].varints(8), [3, 3]);
+    assert_eq!(attributes[1].varints(8), [1, 1, 1, 1]);
+    assert_eq!(attributes[2].varints(8), [1, 1]);
+
+    let image_shape = graph
+        .messages(5)
+        .into_iter()
+        .find(|tensor| tensor.string(8) == Some("image_shape"))
+        .unwrap();
+    assert_eq!(image_shape.varint(2), Some(INT64));
+    let image_shape: Vec<i64> = image_shape.bytes(9)[0]
+        .chunks_exact(8)
+        .map(|chunk| i64::from_le_bytes(chunk.try_into().unwrap()))
+        .collect();
+    assert_eq!(image_shape, [-1, 3, 6, 8]);
+
+    let conv = model.conv().unwrap();
+    for (index, layer) in conv.layers().iter().enumerate() {
+        let (dims, values) = float_tensor(&graph, &format!("conv{}_weight", index));
+        let kernel = layer.kernel();
+        let (out_channels, in_channels, k, _) = kernel.dim();
+        assert_eq!(
+            dims,
+            [out_channels as u64, in_channels as u64, k as u64, k as u64]
+        );
+        assert_eq!(values, kernel.iter().copied().collect::<Vec<f32>>());
+        let (_, bias) = float_tensor(&graph, &format!("conv{}_bias", index));
+        assert_eq!(bias, layer.bias().to_vec());
+    }
+    let (weight_dims, weights) = float_tensor(&graph, "weight");
+    assert_eq!(weight_dims, [model.feature_dim() as u64, 2]);
+    assert_eq!(
+        weights,
+        model.weights().t().iter().copied().collect::<Vec<f32>>()
+    );
+    // A temperature of 1 leaves out the Div.
+    assert!(
+        graph
+            .messages(5)
+            .iter()
+            .all(|t| t.string(8) != Some("temperature"))
+    );
+}
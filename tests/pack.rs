@@ -0,0 +1,116 @@
+mod common;
+
+use antbee_rs::antbee::Dataset;
+use antbee_rs::antbee::DatasetSource;
+use antbee_rs::antbee::Error;
+use antbee_rs::antbee::Kind;
+use common::TempFile;
+use flate2::Compression;
+use flate2::write::DeflateEncoder;
+use std::fs::write;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// A pack of grayscale `width`x`height` images of two classes, claiming
+/// `len` samples and followed by `rest` as written by `Dataset::to_pack`.
+fn pack(width: u32, height: u32, len: u64, rest: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for value in [width, height, 1, 0] {
+        body.extend_from_slice(&value.to_le_bytes());
+    }
+    body.extend_from_slice(&2u64.to_le_bytes());
+    for name in ["ant", "bee"] {
+        body.extend_from_slice(&(name.len() as u64).to_le_bytes());
+        body.extend_from_slice(name.as_bytes());
+    }
+    body.extend_from_slice(&len.to_le_bytes());
+    body.extend_from_slice(rest);
+
+    let mut bytes = b"ANTBEEPK".to_vec();
+    bytes.extend_from_slice(&1u32.to_le_bytes());
+    let mut encoder = DeflateEncoder::new(bytes, Compression::default());
+    encoder.write_all(&body).unwrap();
+    return encoder.finish().unwrap();
+}
+
+/// Two 2x1 samples labelled bee and ant, with an optional path table.
+fn samples(paths: Option<&[&str]>) -> Vec<u8> {
+    let mut rest = Vec::new();
+    for label in [1u32, 0] {
+        rest.extend_from_slice(&label.to_le_bytes());
+    }
+    rest.extend_from_slice(&(paths.is_some() as u32).to_le_bytes());
+    for path in paths.unwrap_or(&[]) {
+        rest.extend_from_slice(&(path.len() as u64).to_le_bytes());
+        rest.extend_from_slice(path.as_bytes());
+    }
+    rest.extend_from_slice(&4u64.to_le_bytes());
+    rest.extend_from_slice(&[0, 51, 204, 255]);
+    return rest;
+}
+
+#[test]
+fn from_pack_reads_a_written_pack() {
+    let file = TempFile::new("read", "pack");
+    write(
+        &file.path,
+        pack(2, 1, 2, &samples(Some(&["b.png", "a.png"]))),
+    )
+    .unwrap();
+    let dataset = Dataset::from_pack(&file.path).unwrap();
+    assert_eq!(dataset.len(), 2);
+    assert_eq!(dataset.labels(), &[Kind(1), Kind(0)]);
+    assert_eq!(dataset.path(1).unwrap(), PathBuf::from("a.png"));
+    assert_eq!(
+        dataset.features().as_slice().unwrap(),
+        &[0.0, 0.2, 0.8, 1.0]
+    );
+
+    // And round-trips through `to_pack`.
+    dataset.to_pack(&file.path).unwrap();
+    let again = Dataset::from_pack(&file.path).unwrap();
+    assert_eq!(again.labels(), dataset.labels());
+    assert_eq!(again.features(), dataset.features());
+}
+
+#[test]
+fn from_pack_rejects_corrupted_sizes() {
+    let file = TempFile::new("corrupt", "pack");
+    let load = |bytes: Vec<u8>| {
+        write(&file.path, bytes).unwrap();
+        return Dataset::from_pack(&file.path);
+    };
+
+    // Sample counts and image sizes whose products overflow.
+    for (width, height, len) in [(2, 1, u64::MAX / 2 + 1), (u32::MAX, u32::MAX, 2)] {
+        assert!(
+            matches!(
+                load(pack(width, height, len, &[])),
+                Err(Error::InvalidFormat(_))
+            ),
+            "{}x{}, {} samples",
+            width,
+            height,
+            len
+        );
+    }
+
+    // Counts and lengths far beyond the data fail at its end instead of
+    // being reserved up front.
+    assert!(load(pack(2, 1, u64::MAX / 4, &samples(None))).is_err());
+    let mut rest = samples(None);
+    rest[8..12].copy_from_slice(&1u32.to_le_bytes());
+    rest.splice(12..12, u64::MAX.to_le_bytes());
+    assert!(load(pack(2, 1, 2, &rest)).is_err());
+    let mut rest = samples(None);
+    rest[12..20].copy_from_slice(&(u64::MAX - 1).to_le_bytes());
+    assert!(matches!(
+        load(pack(2, 1, 2, &rest)),
+        Err(Error::InvalidFormat(_))
+    ));
+    let mut rest = samples(None);
+    rest.truncate(rest.len() - 1);
+    assert!(matches!(load(pack(2, 1, 2, &rest)), Err(Error::Io(_))));
+
+    assert!(load(pack(2, 1, 2, &samples(None))).is_ok());
+}
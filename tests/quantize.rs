@@ -0,0 +1,118 @@
+use antbee_rs::antbee::ChannelMode;
+use antbee_rs::antbee::Model;
+use antbee_rs::antbee::Preprocess;
+use antbee_rs::antbee::QuantizedModel;
+use ndarray::Array2;
+use ndarray::ArrayView1;
+use rand::Rng;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+fn preprocess() -> Preprocess {
+    return Preprocess {
+        width: 4,
+        height: 4,
+        channels: ChannelMode::Rgb,
+        ..Preprocess::default()
+    };
+}
+
+/// Step of an `i8` code for `values`, whose range is widened to include 0.
+fn step(values: ArrayView1<f32>) -> f32 {
+    let low = values.fold(0.0f32, |acc, &v| acc.min(v));
+    let high = values.fold(0.0f32, |acc, &v| acc.max(v));
+    return (high - low) / 255.0;
+}
+
+#[test]
+fn dequantized_weights_are_within_half_a_step() {
+    for seed in 0..4 {
+        let model = Model::from_rng(preprocess(), 3, &mut ChaCha8Rng::seed_from_u64(seed));
+        let quantized = model.quantize();
+        let dequantized = quantized.dequantized_weights();
+        assert_eq!(dequantized.dim(), model.weights().dim());
+        for (row, decoded) in model.weights().outer_iter().zip(dequantized.outer_iter()) {
+            let tolerance = step(row) / 2.0 + 1e-6;
+            for (&w, &d) in row.iter().zip(decoded) {
+                assert!((w - d).abs() <= tolerance, "{} decoded as {}", w, d);
+            }
+        }
+    }
+}
+
+#[test]
+fn logits_stay_within_the_rounding_error() {
+    let model = Model::from_rng(preprocess(), 4, &mut ChaCha8Rng::seed_from_u64(7));
+    let quantized = model.quantize();
+    let mut rng = ChaCha8Rng::seed_from_u64(8);
+    let x = Array2::from_shape_fn((16, preprocess().input_dim()), |_| {
+        rng.random_range(-2.0..2.0)
+    });
+
+    let exact = model.logits_batch(x.view());
+    let approx = quantized.logits_batch(x.view());
+    for (i, features) in x.outer_iter().enumerate() {
+        let x_step = step(features);
+        for (k, row) in model.weights().outer_iter().enumerate() {
+            let w_step = step(row);
+            // Each product w x is off by at most |w| dx + |x| dw + dw dx
+            // for rounding errors dw, dx of half a step.
+            let bound: f32 = row
+                .iter()
+                .zip(features)
+                .map(|(&w, &x)| {
+                    w.abs() * x_step / 2.0 + x.abs() * w_step / 2.0 + w_step * x_step / 4.0
+                })
+                .sum();
+            let error = (exact[[i, k]] - approx[[i, k]]).abs();
+            assert!(
+                error <= bound * 1.01 + 1e-5,
+                "logit {},{}: {} > {}",
+                i,
+                k,
+                error,
+                bound
+            );
+        }
+    }
+}
+
+#[test]
+fn zero_features_leave_only_the_bias() {
+    let model = Model::from_rng(preprocess(), 2, &mut ChaCha8Rng::seed_from_u64(3));
+    let quantized = model.quantize();
+    let x = Array2::zeros((2, preprocess().input_dim()));
+    // All-zero features quantize to the zero point, so only the bias remains.
+    let logits = quantized.logits_batch(x.view());
+    for row in logits.outer_iter() {
+        assert_eq!(row, model.bias());
+    }
+}
+
+#[test]
+fn read_from_restores_a_written_model() {
+    let model = Model::from_rng(preprocess(), 3, &mut ChaCha8Rng::seed_from_u64(5));
+    let quantized = model.quantize();
+    let mut bytes = Vec::new();
+    quantized.write_to(&mut bytes).unwrap();
+    let loaded = QuantizedModel::read_from(&mut &bytes[..]).unwrap();
+    assert_eq!(
+        loaded.dequantized_weights(),
+        quantized.dequantized_weights()
+    );
+
+    let mut rng = ChaCha8Rng::seed_from_u64(6);
+    let x = Array2::from_shape_fn((4, preprocess().input_dim()), |_| rng.random::<f32>());
+    assert_eq!(
+        loaded.logits_batch(x.view()),
+        quantized.logits_batch(x.view())
+    );
+
+    for len in 0..bytes.len() {
+        assert!(
+            QuantizedModel::read_from(&mut &bytes[..len]).is_err(),
+            "{} bytes loaded",
+            len
+        );
+    }
+}
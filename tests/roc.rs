@@ -0,0 +1,103 @@
+use antbee_rs::antbee::RocCurve;
+use antbee_rs::antbee::RocPoint;
+use antbee_rs::antbee::ThresholdMetric;
+
+/// The ten scores of Fawcett's "An introduction to ROC analysis", Fig. 3,
+/// which have an area under the curve of 18/25.
+fn fawcett() -> RocCurve {
+    return RocCurve::from_scores([
+        (0.9, true),
+        (0.8, true),
+        (0.7, false),
+        (0.6, true),
+        (0.55, false),
+        (0.54, true),
+        (0.53, false),
+        (0.52, false),
+        (0.51, true),
+        (0.505, false),
+    ]);
+}
+
+#[test]
+fn points_count_samples_at_or_above_each_threshold() {
+    let curve = fawcett();
+    assert_eq!(curve.positives, 5);
+    assert_eq!(curve.negatives, 5);
+    assert_eq!(curve.points.len(), 11);
+    assert_eq!(
+        curve.points[0],
+        RocPoint {
+            threshold: f32::INFINITY,
+            true_positives: 0,
+            false_positives: 0,
+        }
+    );
+    assert_eq!(
+        curve.points[4],
+        RocPoint {
+            threshold: 0.6,
+            true_positives: 3,
+            false_positives: 1,
+        }
+    );
+    let last = curve.points.last().unwrap();
+    assert_eq!((last.true_positives, last.false_positives), (5, 5));
+    assert_eq!(curve.tpr(&curve.points[4]), 0.6);
+    assert_eq!(curve.fpr(&curve.points[4]), 0.2);
+
+    // Tied scores form a single point, whatever their order.
+    let tied = RocCurve::from_scores([(0.5, true), (0.7, false), (0.5, false), (0.5, true)]);
+    let thresholds: Vec<f32> = tied.points.iter().map(|p| p.threshold).collect();
+    assert_eq!(thresholds, [f32::INFINITY, 0.7, 0.5]);
+    assert_eq!(tied.points[2].true_positives, 2);
+    assert_eq!(tied.points[2].false_positives, 2);
+}
+
+#[test]
+fn auc_matches_known_answers() {
+    assert!((fawcett().auc() - 0.72).abs() < 1e-6, "{}", fawcett().auc());
+
+    let perfect = RocCurve::from_scores([(0.9, true), (0.8, true), (0.2, false), (0.1, false)]);
+    assert_eq!(perfect.auc(), 1.0);
+    let inverted = RocCurve::from_scores([(0.9, false), (0.8, false), (0.2, true), (0.1, true)]);
+    assert_eq!(inverted.auc(), 0.0);
+    // A tie between a positive and a negative counts half.
+    let tie = RocCurve::from_scores([(0.5, true), (0.5, false)]);
+    assert_eq!(tie.auc(), 0.5);
+    let constant = RocCurve::from_scores((0..10).map(|i| (0.3, i % 3 == 0)));
+    assert_eq!(constant.auc(), 0.5);
+}
+
+#[test]
+fn best_threshold_maximizes_each_metric() {
+    let curve = fawcett();
+    // 7 of 10 correct at 0.8, 0.6 and 0.54; the strictest one wins.
+    assert_eq!(curve.best_threshold(ThresholdMetric::Accuracy), 0.8);
+    assert_eq!(
+        curve.score(&curve.points[2], ThresholdMetric::Accuracy),
+        0.7
+    );
+    // F1 is 8/11 at 0.54, with 4 true and 2 false positives.
+    assert_eq!(curve.best_threshold(ThresholdMetric::F1), 0.54);
+    let f1 = curve.score(&curve.points[6], ThresholdMetric::F1);
+    assert!((f1 - 8.0 / 11.0).abs() < 1e-6, "{}", f1);
+
+    let youden = RocCurve::from_scores([
+        (0.9, true),
+        (0.8, false),
+        (0.7, true),
+        (0.6, true),
+        (0.3, false),
+        (0.2, false),
+    ]);
+    // TPR 1 against FPR 1/3 at 0.6.
+    assert_eq!(youden.best_threshold(ThresholdMetric::Youden), 0.6);
+    let j = youden.score(&youden.points[4], ThresholdMetric::Youden);
+    assert!((j - 2.0 / 3.0).abs() < 1e-6, "{}", j);
+
+    // Without any finite threshold, the default of 0.5 remains.
+    let empty = RocCurve::from_scores([]);
+    assert_eq!(empty.best_threshold(ThresholdMetric::F1), 0.5);
+    assert_eq!(empty.auc(), 0.0);
+}
@@ -0,0 +1,114 @@
+use antbee_rs::antbee::BalancedSampler;
+use antbee_rs::antbee::Kind;
+use antbee_rs::antbee::RandomSampler;
+use antbee_rs::antbee::Sampler;
+use antbee_rs::antbee::StratifiedSampler;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+/// `counts[k]` samples of every class `k`, in blocks of one class each.
+fn labels(counts: &[usize]) -> Vec<Kind> {
+    return counts
+        .iter()
+        .enumerate()
+        .flat_map(|(k, &count)| std::iter::repeat_n(Kind(k), count))
+        .collect();
+}
+
+fn batches(
+    sampler: &dyn Sampler,
+    labels: &[Kind],
+    num_classes: usize,
+    batch_size: usize,
+    shuffle: bool,
+    seed: u64,
+) -> Vec<Vec<usize>> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    return sampler.batches(labels, num_classes, batch_size, shuffle, &mut rng);
+}
+
+/// Checks that the batches hold every sample exactly once, in batches of
+/// `batch_size` but for a shorter last one.
+fn assert_epoch(batches: &[Vec<usize>], len: usize, batch_size: usize) {
+    for batch in &batches[..batches.len() - 1] {
+        assert_eq!(batch.len(), batch_size);
+    }
+    let mut seen: Vec<usize> = batches.concat();
+    seen.sort();
+    assert_eq!(seen, (0..len).collect::<Vec<_>>());
+}
+
+#[test]
+fn random_sampler_cuts_a_permutation() {
+    let labels = labels(&[5, 2]);
+    let ordered = batches(&RandomSampler, &labels, 2, 3, false, 0);
+    assert_eq!(ordered, [vec![0, 1, 2], vec![3, 4, 5], vec![6]]);
+
+    let shuffled = batches(&RandomSampler, &labels, 2, 3, true, 0);
+    assert_epoch(&shuffled, labels.len(), 3);
+    assert_ne!(shuffled, ordered);
+    assert_eq!(shuffled, batches(&RandomSampler, &labels, 2, 3, true, 0));
+}
+
+#[test]
+fn stratified_sampler_interleaves_classes_evenly() {
+    // Class 0 at 1/8, 3/8, 5/8 and 7/8 of the epoch, class 1 at 1/4 and 3/4.
+    let labels = labels(&[4, 2]);
+    let ordered = batches(&StratifiedSampler, &labels, 2, 3, false, 0);
+    assert_eq!(ordered, [vec![0, 4, 1], vec![2, 5, 3]]);
+}
+
+#[test]
+fn stratified_batches_keep_the_class_proportions() {
+    for (counts, batch_size) in [
+        (vec![90, 10], 10),
+        (vec![37, 12, 5], 8),
+        (vec![3, 50, 0, 21], 7),
+        (vec![1, 1, 64], 4),
+    ] {
+        let labels = labels(&counts);
+        let n = labels.len() as f32;
+        let tolerance = if counts.len() == 2 { 1.0 } else { 2.0 };
+        for (shuffle, seed) in [(false, 0), (true, 1), (true, 2), (true, 3)] {
+            let batches = batches(
+                &StratifiedSampler,
+                &labels,
+                counts.len(),
+                batch_size,
+                shuffle,
+                seed,
+            );
+            assert_epoch(&batches, labels.len(), batch_size);
+            for batch in &batches {
+                for (k, &count) in counts.iter().enumerate() {
+                    let share = batch.len() as f32 * count as f32 / n;
+                    let in_batch = batch.iter().filter(|&&i| labels[i] == Kind(k)).count();
+                    assert!(
+                        (in_batch as f32 - share).abs() < tolerance,
+                        "{:?} batch {:?}: {} of class {}, share {}",
+                        counts,
+                        batch,
+                        in_batch,
+                        k,
+                        share
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn balanced_sampler_takes_classes_in_turn() {
+    let labels = labels(&[6, 2, 0]);
+    let ordered = batches(&BalancedSampler, &labels, 3, 4, false, 0);
+    // The empty class is left out and class 1 starts over when it runs out.
+    assert_eq!(ordered, [vec![0, 6, 1, 7], vec![2, 6, 3, 7]]);
+
+    let shuffled = batches(&BalancedSampler, &labels, 3, 4, true, 5);
+    assert_eq!(shuffled.concat().len(), labels.len());
+    for batch in &shuffled {
+        let minority = batch.iter().filter(|&&i| labels[i] == Kind(1)).count();
+        assert_eq!(minority, batch.len() / 2);
+    }
+}
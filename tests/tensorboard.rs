@@ -0,0 +1,119 @@
+mod common;
+
+use antbee_rs::antbee::EpochMetrics;
+use antbee_rs::antbee::TensorBoardWriter;
+use common::TempDir;
+use common::protobuf::Message;
+use std::fs::read;
+use std::fs::read_dir;
+
+/// CRC-32C, a reference for the writer's, computed with the polynomial
+/// in its usual (not bit-reversed) form.
+fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= (byte.reverse_bits() as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x1EDC_6F41
+            } else {
+                crc << 1
+            };
+        }
+    }
+    return !crc.reverse_bits();
+}
+
+fn masked(crc: u32) -> u32 {
+    return crc.rotate_right(15).wrapping_add(0xA282_EAD8);
+}
+
+/// Splits a TFRecord stream into its payloads, checking the length and
+/// payload CRC of every record.
+fn records(mut bytes: &[u8]) -> Vec<&[u8]> {
+    let mut records = Vec::new();
+    while !bytes.is_empty() {
+        let (length, rest) = bytes.split_at(8);
+        let (length_crc, rest) = rest.split_at(4);
+        assert_eq!(
+            u32::from_le_bytes(length_crc.try_into().unwrap()),
+            masked(crc32c(length))
+        );
+        let length = u64::from_le_bytes(length.try_into().unwrap()) as usize;
+        let (data, rest) = rest.split_at(length);
+        let (data_crc, rest) = rest.split_at(4);
+        assert_eq!(
+            u32::from_le_bytes(data_crc.try_into().unwrap()),
+            masked(crc32c(data))
+        );
+        records.push(data);
+        bytes = rest;
+    }
+    return records;
+}
+
+#[test]
+fn reference_crc_matches_known_answers() {
+    // Check values of RFC 3720, appendix B.4.
+    assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+    assert_eq!(crc32c(&[0; 32]), 0x8A91_36AA);
+    assert_eq!(crc32c(&[0xFF; 32]), 0x62A8_AB43);
+    let ascending: Vec<u8> = (0..32).collect();
+    assert_eq!(crc32c(&ascending), 0x46DD_794E);
+}
+
+#[test]
+fn event_file_holds_a_scalar_per_metric_and_epoch() {
+    let dir = TempDir::new("tensorboard");
+    let mut writer = TensorBoardWriter::create(&dir.path.join("run")).unwrap();
+    for epoch in 0..3 {
+        writer
+            .log(&EpochMetrics {
+                epoch,
+                train_loss: 1.0 / (epoch + 1) as f32,
+                train_acc: 0.5 + 0.1 * epoch as f32,
+                val_loss: 2.0 / (epoch + 1) as f32,
+                val_acc: 0.4 + 0.1 * epoch as f32,
+            })
+            .unwrap();
+    }
+    let path = writer.path().to_path_buf();
+    drop(writer);
+
+    let files: Vec<_> = read_dir(dir.path.join("run")).unwrap().collect();
+    assert_eq!(files.len(), 1);
+    let name = path.file_name().unwrap().to_string_lossy().into_owned();
+    assert!(name.starts_with("events.out.tfevents."), "{}", name);
+
+    let bytes = read(&path).unwrap();
+    let records = records(&bytes);
+    assert_eq!(records.len(), 4);
+
+    let header = Message::parse(records[0]);
+    assert_eq!(header.string(3), Some("brain.Event:2"));
+    let wall_time = f64::from_bits(header.fixed64(1).unwrap());
+    assert!(wall_time > 1.5e9, "{}", wall_time);
+
+    for (epoch, record) in records[1..].iter().enumerate() {
+        let event = Message::parse(record);
+        assert_eq!(event.varint(2), Some(epoch as u64));
+        assert!(f64::from_bits(event.fixed64(1).unwrap()) >= wall_time);
+        let values = event.message(5).messages(1);
+        let scalars: Vec<(&str, f32)> = values
+            .iter()
+            .map(|value| {
+                let simple_value = f32::from_bits(value.fixed32(2).unwrap());
+                return (value.string(1).unwrap(), simple_value);
+            })
+            .collect();
+        assert_eq!(
+            scalars,
+            [
+                ("loss/train", 1.0 / (epoch + 1) as f32),
+                ("loss/val", 2.0 / (epoch + 1) as f32),
+                ("accuracy/train", 0.5 + 0.1 * epoch as f32),
+                ("accuracy/val", 0.4 + 0.1 * epoch as f32),
+            ]
+        );
+    }
+}
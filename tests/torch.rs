@@ -0,0 +1,73 @@
+mod common;
+
+use antbee_rs::antbee::ChannelMode;
+use antbee_rs::antbee::Error;
+use antbee_rs::antbee::Model;
+use antbee_rs::antbee::Preprocess;
+use common::TempFile;
+use std::fs::write;
+
+/// 2x2 grayscale images, four inputs.
+fn preprocess() -> Preprocess {
+    return Preprocess {
+        width: 2,
+        height: 2,
+        channels: ChannelMode::Grayscale,
+        ..Preprocess::default()
+    };
+}
+
+/// A `.safetensors` file of a linear layer with two outputs, its header
+/// length field replaced by `header_len` if given.
+fn safetensors(weight_shape: &str, header_len: Option<u64>) -> Vec<u8> {
+    let header = format!(
+        "{{\"weight\":{{\"dtype\":\"F32\",\"shape\":{},\"data_offsets\":[0,32]}},\
+         \"bias\":{{\"dtype\":\"F32\",\"shape\":[2],\"data_offsets\":[32,40]}}}}",
+        weight_shape
+    );
+    let mut bytes = header_len
+        .unwrap_or(header.len() as u64)
+        .to_le_bytes()
+        .to_vec();
+    bytes.extend_from_slice(header.as_bytes());
+    for value in [1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 0.5, -0.5] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    return bytes;
+}
+
+#[test]
+fn from_state_dict_reads_safetensors() {
+    let file = TempFile::new("read", "safetensors");
+    write(&file.path, safetensors("[2,4]", None)).unwrap();
+    let model = Model::from_state_dict(&file.path, preprocess()).unwrap();
+    assert_eq!(model.num_classes(), 2);
+    assert_eq!(model.bias().to_vec(), vec![0.5, -0.5]);
+    assert_eq!(model.weights().sum(), 36.0);
+}
+
+#[test]
+fn from_state_dict_rejects_corrupted_sizes() {
+    let file = TempFile::new("corrupt", "safetensors");
+    for (shape, header_len) in [
+        // Header lengths past the end of the file, or overflowing with the
+        // length field before them.
+        ("[2,4]", Some(u64::MAX)),
+        ("[2,4]", Some(u64::MAX - 7)),
+        ("[2,4]", Some(1 << 20)),
+        // A shape whose size overflows.
+        ("[4611686018427387904,4]", None),
+        ("[2,5]", None),
+    ] {
+        write(&file.path, safetensors(shape, header_len)).unwrap();
+        assert!(
+            matches!(
+                Model::from_state_dict(&file.path, preprocess()),
+                Err(Error::InvalidFormat(_))
+            ),
+            "{} {:?}",
+            shape,
+            header_len
+        );
+    }
+}